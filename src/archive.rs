@@ -0,0 +1,218 @@
+use crate::clip::parse_timestamp;
+use crate::download::download_file;
+use crate::ffmpeg::extract_audio;
+use crate::pipeline::Video;
+use crate::Result;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::path::Path;
+
+const AUDIO_EXTENSIONS: &[&str] = &["aac", "flac", "m4a", "mp3", "ogg", "opus", "wav"];
+const VIDEO_EXTENSIONS: &[&str] = &["avi", "mkv", "mov", "mp4", "mpeg", "ogv", "webm"];
+
+/// An Internet Archive item, with only its audio and video files.
+#[derive(Clone, Debug)]
+pub struct ArchiveItem {
+    pub identifier: String,
+    pub title: String,
+    pub creator: Option<String>,
+    pub date: Option<String>,
+    pub description: Option<String>,
+    pub files: Vec<ArchiveFile>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ArchiveFile {
+    pub name: String,
+    /// The format as named by the archive, e.g. "VBR MP3".
+    pub format: String,
+    pub size: Option<u64>,
+    /// The length in seconds.
+    pub length: Option<f64>,
+    pub is_audio: bool,
+}
+
+#[derive(Deserialize)]
+struct MetadataResponse {
+    #[serde(default)]
+    metadata: Option<ItemMetadata>,
+    #[serde(default)]
+    files: Vec<FileMetadata>,
+}
+
+/// Fields the archive returns as either a string or a list of strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_string(self) -> String {
+        match self {
+            OneOrMany::One(s) => s,
+            OneOrMany::Many(v) => v.join(", "),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ItemMetadata {
+    title: Option<OneOrMany>,
+    creator: Option<OneOrMany>,
+    date: Option<OneOrMany>,
+    description: Option<OneOrMany>,
+}
+
+#[derive(Deserialize)]
+struct FileMetadata {
+    name: String,
+    #[serde(default)]
+    format: String,
+    size: Option<String>,
+    length: Option<String>,
+}
+
+/// Returns the item identifier if `url` points at an archive.org item, e.g.
+/// `https://archive.org/details/<identifier>`.
+pub fn parse_item_url(url: &str) -> Option<String> {
+    let url = url.parse::<Url>().ok()?;
+    let host = url.host_str()?;
+    if host != "archive.org" && !host.ends_with(".archive.org") {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    match segments.next()? {
+        "details" | "download" | "embed" => segments
+            .next()
+            .filter(|id| !id.is_empty())
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Lists the audio and video files of the item with `identifier`.
+pub async fn fetch_archive_item(client: &Client, identifier: &str) -> Result<ArchiveItem> {
+    let response: MetadataResponse = client
+        .get(format!("https://archive.org/metadata/{}", identifier))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let metadata = response
+        .metadata
+        .ok_or_else(|| format!("No archive.org item named '{}'", identifier))?;
+
+    let files = response
+        .files
+        .into_iter()
+        .filter_map(|file| {
+            let ext = Path::new(&file.name).extension()?.to_str()?.to_lowercase();
+            let is_audio = AUDIO_EXTENSIONS.contains(&ext.as_str());
+            if !is_audio && !VIDEO_EXTENSIONS.contains(&ext.as_str()) {
+                return None;
+            }
+            Some(ArchiveFile {
+                size: file.size.and_then(|size| size.parse().ok()),
+                length: file.length.and_then(|length| parse_timestamp(&length).ok()),
+                name: file.name,
+                format: file.format,
+                is_audio,
+            })
+        })
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        return Err("The archive.org item has no audio or video files".into());
+    }
+
+    Ok(ArchiveItem {
+        identifier: identifier.to_string(),
+        title: metadata
+            .title
+            .map(OneOrMany::into_string)
+            .unwrap_or_else(|| identifier.to_string()),
+        creator: metadata.creator.map(OneOrMany::into_string),
+        date: metadata.date.map(OneOrMany::into_string),
+        description: metadata.description.map(OneOrMany::into_string),
+        files,
+    })
+}
+
+impl ArchiveItem {
+    /// The file most worth transcribing: the smallest audio file, since the archive's derived
+    /// MP3s and originals have the same speech, or the smallest video file if there is no audio.
+    pub fn best_file(&self) -> &ArchiveFile {
+        self.files
+            .iter()
+            .min_by_key(|file| (!file.is_audio, file.size.unwrap_or(u64::MAX)))
+            .expect("archive items have at least one file")
+    }
+
+    /// The video file to burn subtitles into: the smallest one, which is usually the MP4 the
+    /// archive derives from the original upload.
+    pub fn best_video_file(&self) -> Option<&ArchiveFile> {
+        self.files
+            .iter()
+            .filter(|file| !file.is_audio)
+            .min_by_key(|file| file.size.unwrap_or(u64::MAX))
+    }
+
+    /// The URL `file` is downloaded from, with each segment of its name percent-encoded, as
+    /// names may contain spaces, `#` or `?`. Names of files in subdirectories of the item keep
+    /// their `/`s.
+    pub(crate) fn download_url(&self, file: &ArchiveFile) -> String {
+        let mut url = Url::parse("https://archive.org/download").expect("valid base URL");
+        url.path_segments_mut()
+            .expect("base URL has a path")
+            .push(&self.identifier)
+            .extend(file.name.split('/'));
+        url.to_string()
+    }
+}
+
+/// Prepares `file` of `item` for transcription. m4a files are downloaded by the pipeline like
+/// any other audio track; other formats are downloaded into `dir` and their audio extracted
+/// with `ffmpeg`, reusing the extracted audio of an earlier run.
+pub async fn fetch_archive_video(
+    client: &Client,
+    item: &ArchiveItem,
+    file: &ArchiveFile,
+    dir: &Path,
+) -> Result<Video> {
+    let mut video = Video {
+        id: format!("{}_{}", item.identifier, file.name.replace('/', "_")),
+        title: item.title.clone(),
+        url: format!("https://archive.org/details/{}", item.identifier),
+        channel: item.creator.clone(),
+        album: None,
+        upload_date: item.date.clone(),
+        duration: file.length,
+        description: item.description.clone(),
+        thumbnail_url: Some(format!(
+            "https://archive.org/services/img/{}",
+            item.identifier
+        )),
+        audio_url: item.download_url(file),
+        audio_file_size: file.size.unwrap_or_default() as f64,
+        info: None,
+    };
+    if file.name.to_lowercase().ends_with(".m4a") {
+        return Ok(video);
+    }
+
+    tokio::fs::create_dir_all(dir).await?;
+    let audio_path = dir.join(format!("{}.m4a", video.id));
+    if !audio_path.exists() {
+        let media = download_file(client, &video.audio_url, |_, _| {}).await?;
+        let media_path = dir.join(&video.id);
+        tokio::fs::write(&media_path, media).await?;
+        let extracted = extract_audio(&media_path, &audio_path).await;
+        let _ = tokio::fs::remove_file(&media_path).await;
+        extracted?;
+    }
+    video.audio_url = format!("file://{}", audio_path.display());
+    video.audio_file_size = tokio::fs::metadata(&audio_path).await?.len() as f64;
+    Ok(video)
+}
@@ -0,0 +1,105 @@
+use crate::download::DownloadStrategy;
+use crate::Result;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// The most download chunks fetched at once in the background, which leaves the connection
+/// usable for everything else.
+const BACKGROUND_DOWNLOADS: usize = 2;
+
+/// How often to check whether the machine is back on mains power while paused.
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Drops this process to the lowest CPU priority and the idle I/O class, which the ffmpeg,
+/// yt-dlp, and whisper.cpp processes it starts inherit, so that it only uses what nothing else
+/// wants. This shells out to `renice`, and to `ionice` on Linux or `taskpolicy` on macOS.
+pub async fn lower_priority() -> Result<()> {
+    // Linux sets priorities per thread, and threads inherit them from the thread that starts
+    // them, so every thread running so far is lowered.
+    let ids = match std::fs::read_dir("/proc/self/task") {
+        Ok(tasks) => tasks
+            .filter_map(|task| task.ok())
+            .map(|task| task.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => vec![std::process::id().to_string()],
+    };
+    run(Command::new("renice").args(["-n", "19", "-p"]).args(&ids)).await?;
+    if cfg!(target_os = "linux") {
+        run(Command::new("ionice").args(["-c", "3", "-p"]).args(&ids)).await?;
+    } else if cfg!(target_os = "macos") {
+        run(Command::new("taskpolicy").args(["-b", "-p"]).args(&ids)).await?;
+    }
+    Ok(())
+}
+
+async fn run(command: &mut Command) -> Result<()> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let output = command
+        .output()
+        .await
+        .map_err(|err| format!("Failed to run {}: {}", program, err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Caps `strategy` at [`BACKGROUND_DOWNLOADS`] chunks at a time.
+pub fn background_downloads(strategy: DownloadStrategy) -> DownloadStrategy {
+    DownloadStrategy {
+        max_in_flight: strategy.max_in_flight.min(BACKGROUND_DOWNLOADS),
+        ..strategy
+    }
+}
+
+/// Whether the machine is running on battery, going by `/sys/class/power_supply` on Linux and
+/// `pmset` on macOS. Machines without a battery, or whose power source cannot be told, are
+/// taken to be on mains power.
+pub async fn on_battery() -> bool {
+    if cfg!(target_os = "macos") {
+        return Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .await
+            .is_ok_and(|output| {
+                String::from_utf8_lossy(&output.stdout).contains("'Battery Power'")
+            });
+    }
+    let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path).map_or(String::new(), |value| value.trim().to_string())
+    };
+    let mut battery = false;
+    for supply in supplies.filter_map(|supply| supply.ok()) {
+        let path = supply.path();
+        match read(path.join("type")).as_str() {
+            // Any adapter that is plugged in means mains power, even while a battery charges.
+            "Mains" | "USB" if read(path.join("online")) == "1" => return false,
+            "Battery" => battery |= read(path.join("status")) == "Discharging",
+            _ => {}
+        }
+    }
+    battery
+}
+
+/// Waits until the machine is on mains power, calling `on_pause` first if it is on battery.
+pub async fn wait_for_mains(on_pause: impl FnOnce()) {
+    if !on_battery().await {
+        return;
+    }
+    on_pause();
+    while on_battery().await {
+        tokio::time::sleep(POWER_POLL_INTERVAL).await;
+    }
+}
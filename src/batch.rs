@@ -0,0 +1,139 @@
+use crate::clip::format_timestamp;
+use crate::throughput::format_eta;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The widest a video's title is shown in the summary table.
+const MAX_TITLE_WIDTH: usize = 40;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    Done,
+    /// Written from a cached transcript, which cost nothing.
+    Cached,
+    Failed,
+}
+
+impl ItemStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ItemStatus::Done => "done",
+            ItemStatus::Cached => "cached",
+            ItemStatus::Failed => "failed",
+        }
+    }
+}
+
+/// The outcome of one video of a batch run.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchItem {
+    pub url: String,
+    pub title: Option<String>,
+    pub status: ItemStatus,
+    /// The length of audio transcribed, in seconds.
+    pub duration: Option<f64>,
+    /// The estimated cost in US dollars at list price.
+    pub cost: f64,
+    pub output: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+impl BatchItem {
+    pub fn failed(url: &str, error: String) -> Self {
+        BatchItem {
+            url: url.to_string(),
+            title: None,
+            status: ItemStatus::Failed,
+            duration: None,
+            cost: 0.0,
+            output: None,
+            error: Some(error),
+        }
+    }
+
+    fn row(&self) -> [String; 5] {
+        let video = self.title.as_deref().unwrap_or(&self.url);
+        let video = match video.chars().count() > MAX_TITLE_WIDTH {
+            true => {
+                let title = video.chars().take(MAX_TITLE_WIDTH - 1).collect::<String>();
+                format!("{}…", title)
+            }
+            false => video.to_string(),
+        };
+        let output = match (&self.output, &self.error) {
+            (_, Some(error)) => error.clone(),
+            (Some(output), None) => output.display().to_string(),
+            (None, None) => String::new(),
+        };
+        let cost = match self.status {
+            ItemStatus::Done => format!("${:.2}", self.cost),
+            ItemStatus::Cached | ItemStatus::Failed => "-".to_string(),
+        };
+        let duration = self.duration.map_or("-".to_string(), |duration| {
+            format_timestamp(duration.round())
+        });
+        [
+            self.status.as_str().to_string(),
+            duration,
+            cost,
+            video,
+            output,
+        ]
+    }
+}
+
+/// What a batch run did, printed as a table when it finishes and optionally written as JSON.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+    /// The seconds of audio transcribed, counting cached transcripts.
+    pub total_duration: f64,
+    pub total_cost: f64,
+    pub wall_clock_seconds: f64,
+}
+
+impl BatchReport {
+    pub fn new(items: Vec<BatchItem>, wall_clock: Duration) -> Self {
+        BatchReport {
+            total_duration: items.iter().filter_map(|item| item.duration).sum(),
+            total_cost: items.iter().map(|item| item.cost).sum(),
+            wall_clock_seconds: wall_clock.as_secs_f64(),
+            items,
+        }
+    }
+
+    /// Renders a row per video, with its output directory or, if it failed, the error, and a
+    /// line of totals.
+    pub fn render_table(&self) -> String {
+        let header = ["Status", "Duration", "Cost", "Video", "Output"].map(String::from);
+        let rows = std::iter::once(header)
+            .chain(self.items.iter().map(BatchItem::row))
+            .collect::<Vec<_>>();
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut table = String::new();
+        for row in &rows {
+            let mut line = String::new();
+            for (cell, width) in row.iter().zip(widths) {
+                line.push_str(cell);
+                line.push_str(&" ".repeat(width - cell.chars().count() + 2));
+            }
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        table.push_str(&format!(
+            "Transcribed {} for ${:.2} in {}.\n",
+            format_timestamp(self.total_duration.round()),
+            self.total_cost,
+            format_eta(Duration::from_secs_f64(self.wall_clock_seconds))
+        ));
+        table
+    }
+}
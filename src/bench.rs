@@ -0,0 +1,85 @@
+use crate::clip::Clip;
+use crate::ffmpeg::{ffmpeg, temp_path};
+use crate::openai;
+use crate::pipeline::TranscriptionOptions;
+use crate::provider::Provider;
+use crate::Result;
+use futures::future::try_join_all;
+use futures::StreamExt;
+use reqwest::Client;
+use std::ffi::OsStr;
+use std::time::{Duration, Instant};
+
+/// The file downloaded to measure download throughput when no other is given, 25 MB of
+/// Cloudflare's speed test.
+pub const DEFAULT_DOWNLOAD_URL: &str = "https://speed.cloudflare.com/__down?bytes=25000000";
+
+/// Encodes `seconds` of pink noise as an m4a audio track the way downloaded audio is prepared,
+/// returning it with how long encoding took. Noise is used rather than silence so that
+/// encoding does the same work as it does for speech.
+pub async fn synthesize_audio(seconds: f64) -> Result<(Vec<u8>, Duration)> {
+    let path = temp_path("m4a");
+    let started = Instant::now();
+    let source = format!("anoisesrc=color=pink:amplitude=0.1:duration={}", seconds);
+    let result = ffmpeg([
+        OsStr::new("-y"),
+        OsStr::new("-f"),
+        OsStr::new("lavfi"),
+        OsStr::new("-i"),
+        OsStr::new(&source),
+        OsStr::new("-c:a"),
+        OsStr::new("aac"),
+        OsStr::new("-b:a"),
+        OsStr::new("64k"),
+        path.as_os_str(),
+    ])
+    .await;
+    let elapsed = started.elapsed();
+    let audio = match result {
+        Ok(()) => tokio::fs::read(&path).await.map_err(Into::into),
+        Err(err) => Err(err),
+    };
+    let _ = tokio::fs::remove_file(&path).await;
+    Ok((audio?, elapsed))
+}
+
+/// Cuts the second half out of `audio`, `seconds` long, as clips are cut before transcription,
+/// returning how long it took.
+pub async fn measure_cut(audio: &[u8], seconds: f64) -> Result<Duration> {
+    let started = Instant::now();
+    Clip::new(Some(seconds / 2.0), None)?.cut(audio).await?;
+    Ok(started.elapsed())
+}
+
+/// Downloads `url` in a single stream, returning the number of bytes received and how long it
+/// took.
+pub async fn measure_download(client: &Client, url: &str) -> Result<(u64, Duration)> {
+    let started = Instant::now();
+    let response = client.get(url).send().await?.error_for_status()?;
+    let mut body = response.bytes_stream();
+    let mut bytes = 0;
+    while let Some(chunk) = body.next().await {
+        bytes += chunk?.len() as u64;
+    }
+    Ok((bytes, started.elapsed()))
+}
+
+/// Sends `concurrency` requests at once to transcribe `audio` on `provider`, bypassing the pool
+/// so that each provider is measured on its own, and returns how long each took.
+pub async fn measure_provider(
+    provider: &Provider,
+    audio: &[u8],
+    options: &TranscriptionOptions,
+    concurrency: usize,
+) -> Result<Vec<Duration>> {
+    let request = options.request(audio.to_vec()).for_provider(provider);
+    let requests = (0..concurrency.max(1)).map(|_| {
+        let request = request.clone();
+        async move {
+            let started = Instant::now();
+            openai::create_transcription(&provider.endpoint, request).await?;
+            Ok::<_, crate::Error>(started.elapsed())
+        }
+    });
+    try_join_all(requests).await
+}
@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+use transcribe::{
+    clip::format_timestamp,
+    digest::{collect_entries, DigestEntry},
+    lock::FileLock,
+    output::OutputDir,
+    pipeline::slug,
+    transcript::Format,
+    Result,
+};
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const REVERSE: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+const HELP: &str =
+    "↑↓ move  PgUp/PgDn scroll  / search  t tags  e export  o open video  d open folder  q quit";
+
+/// A video of the archive, as listed in the browser.
+struct Entry {
+    video: DigestEntry,
+    dir: OutputDir,
+    tags: Vec<String>,
+}
+
+impl Entry {
+    /// What the search is matched against.
+    fn haystack(&self) -> String {
+        let channel = self.video.channel.as_deref().unwrap_or_default();
+        format!(
+            "{} {} {} {}",
+            self.video.title,
+            channel,
+            self.video.id,
+            self.tags.join(" ")
+        )
+    }
+}
+
+enum Mode {
+    Browse,
+    Search,
+    /// Editing the tags of the selected video, as typed so far.
+    Tags(String),
+}
+
+/// A key pressed in the browser.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    Backspace,
+    /// Ctrl-U, which clears what is being typed.
+    ClearLine,
+    /// Ctrl-C, which quits from anywhere.
+    Interrupt,
+    Char(char),
+    Other,
+}
+
+/// The videos of an archive, narrowed down by a fuzzy search, with a preview of the selected
+/// one's transcript.
+struct Browser {
+    entries: Vec<Entry>,
+    /// The entries matching the query, best match first.
+    matches: Vec<usize>,
+    query: String,
+    /// The position in `matches` of the selected entry.
+    selected: usize,
+    /// The first match shown in the list, which follows the selection.
+    list_offset: usize,
+    /// How far the preview is scrolled down, in lines.
+    scroll: usize,
+    mode: Mode,
+    /// What the last action did, shown in place of the help until the next key.
+    status: Option<String>,
+    read_only: bool,
+    format: Format,
+    /// The lines of the transcripts previewed so far, by video ID.
+    previews: HashMap<String, Vec<String>>,
+}
+
+/// Browses the archive of output directories at `root` in the terminal until quit, searching
+/// the videos in it, previewing their transcripts, editing their tags unless `read_only` is
+/// set, exporting their transcripts in `format` into the working directory, and opening them.
+pub fn browse(root: &Path, read_only: bool, format: Format) -> Result<()> {
+    let entries = collect_entries(root, UNIX_EPOCH)?
+        .into_iter()
+        .map(|video| {
+            let dir = OutputDir::open(&video.dir);
+            let tags = dir.tags().unwrap_or_default();
+            Entry { video, dir, tags }
+        })
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return Err(format!("No transcribed videos in {}", root.display()).into());
+    }
+
+    let terminal = Terminal::enter()?;
+    let mut browser = Browser {
+        entries,
+        matches: vec![],
+        query: String::new(),
+        selected: 0,
+        list_offset: 0,
+        scroll: 0,
+        mode: Mode::Browse,
+        status: None,
+        read_only,
+        format,
+        previews: HashMap::new(),
+    };
+    browser.search();
+    // The screen is redrawn after each key press, and whenever the terminal is resized.
+    let mut drawn = None;
+    loop {
+        let (rows, cols) = terminal.size();
+        if drawn != Some((rows, cols)) {
+            terminal.draw(&browser.render(rows, cols))?;
+            drawn = Some((rows, cols));
+        }
+        for key in terminal.read_keys()? {
+            if !browser.handle(key, rows) {
+                return Ok(());
+            }
+            drawn = None;
+        }
+    }
+}
+
+impl Browser {
+    /// Narrows the list down to the videos matching the query, keeping the selected one
+    /// selected if it still matches.
+    fn search(&mut self) {
+        let selected = self.matches.get(self.selected).copied();
+        let mut scored = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((fuzzy_score(&self.query, &entry.haystack())?, i)))
+            .collect::<Vec<_>>();
+        // Ties keep the archive's order, newest first.
+        scored.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), i));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        let position = selected.and_then(|i| self.matches.iter().position(|&m| m == i));
+        if position != Some(self.selected) {
+            self.selected = position.unwrap_or(0);
+            self.scroll = 0;
+        }
+    }
+
+    fn current(&self) -> Option<&Entry> {
+        self.matches.get(self.selected).map(|&i| &self.entries[i])
+    }
+
+    fn select(&mut self, selected: usize) {
+        let selected = selected.min(self.matches.len().saturating_sub(1));
+        if selected != self.selected {
+            self.selected = selected;
+            self.scroll = 0;
+        }
+    }
+
+    /// Handles a key press, returning whether to carry on.
+    fn handle(&mut self, key: Key, rows: usize) -> bool {
+        self.status = None;
+        let page = (rows / 2).max(1);
+        match (&mut self.mode, key) {
+            (_, Key::Interrupt) => return false,
+            (Mode::Tags(_), Key::Escape) => self.mode = Mode::Browse,
+            (Mode::Tags(input), Key::Enter) => {
+                let input = std::mem::take(input);
+                self.mode = Mode::Browse;
+                self.save_tags(&input);
+            }
+            (Mode::Tags(input), key) => {
+                edit(input, key);
+            }
+            (Mode::Search, Key::Escape) => {
+                self.mode = Mode::Browse;
+                self.query.clear();
+                self.search();
+            }
+            (Mode::Search, Key::Enter) => self.mode = Mode::Browse,
+            (Mode::Search, Key::Up) => self.select(self.selected.saturating_sub(1)),
+            (Mode::Search, Key::Down) => self.select(self.selected + 1),
+            (Mode::Search, key) => {
+                if edit(&mut self.query, key) {
+                    self.search();
+                }
+            }
+            (Mode::Browse, Key::Char('q')) => return false,
+            (Mode::Browse, Key::Escape) if !self.query.is_empty() => {
+                self.query.clear();
+                self.search();
+            }
+            (Mode::Browse, Key::Up | Key::Char('k')) => {
+                self.select(self.selected.saturating_sub(1))
+            }
+            (Mode::Browse, Key::Down | Key::Char('j')) => self.select(self.selected + 1),
+            (Mode::Browse, Key::PageDown | Key::Char(' ')) => self.scroll += page,
+            (Mode::Browse, Key::PageUp | Key::Char('b')) => {
+                self.scroll = self.scroll.saturating_sub(page)
+            }
+            (Mode::Browse, Key::Char('/')) => self.mode = Mode::Search,
+            (Mode::Browse, Key::Char('t')) => match self.current() {
+                _ if self.read_only => {
+                    self.status = Some("The archive is read-only, so tags cannot be edited".into())
+                }
+                Some(entry) => self.mode = Mode::Tags(entry.tags.join(", ")),
+                None => {}
+            },
+            (Mode::Browse, Key::Char('e')) => self.export(),
+            (Mode::Browse, Key::Char('o')) => {
+                if let Some(entry) = self.current() {
+                    let url = entry.video.url.clone();
+                    self.status = Some(match open(&url) {
+                        Ok(()) => format!("Opened {}", url),
+                        Err(err) => err.to_string(),
+                    });
+                }
+            }
+            (Mode::Browse, Key::Char('d')) => {
+                if let Some(entry) = self.current() {
+                    let dir = entry.dir.path().display().to_string();
+                    self.status = Some(match open(&dir) {
+                        Ok(()) => format!("Opened {}", dir),
+                        Err(err) => err.to_string(),
+                    });
+                }
+            }
+            (Mode::Browse, _) => {}
+        }
+        true
+    }
+
+    /// Replaces the tags of the selected video with the comma-separated ones in `input`.
+    fn save_tags(&mut self, input: &str) {
+        let Some(&i) = self.matches.get(self.selected) else {
+            return;
+        };
+        let mut tags: Vec<String> = vec![];
+        for tag in input
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+        {
+            if !tags.iter().any(|other| other == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+        let entry = &mut self.entries[i];
+        // A run writing the video's output is not waited for, since the browser would hang.
+        let lock = futures::executor::block_on(FileLock::acquire(entry.dir.path(), false, |_| {}))
+            .map_err(|_| "another run is writing the video's output".into());
+        match lock.and_then(|_lock| entry.dir.write_tags(&tags)) {
+            Ok(()) => {
+                entry.tags = tags;
+                self.status = Some(format!("Saved the tags of {}", entry.video.id));
+                self.search();
+            }
+            Err(err) => self.status = Some(format!("Failed to save tags: {}", err)),
+        }
+    }
+
+    /// Writes the selected video's transcript in the browser's format into the working
+    /// directory, or as plain text if it has no timestamps for subtitles.
+    fn export(&mut self) {
+        let Some(entry) = self.current() else {
+            return;
+        };
+        let result = entry
+            .dir
+            .read_latest_transcript("transcript")
+            .and_then(|transcript| {
+                let format = match self.format.is_subtitles() && transcript.segments.is_empty() {
+                    true => Format::Text,
+                    false => self.format,
+                };
+                let name = Some(slug(&entry.video.title))
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or_else(|| entry.video.id.clone());
+                let file_name = format!("{}.{}", name, format.extension());
+                std::fs::write(&file_name, transcript.render(format))?;
+                Ok(file_name)
+            });
+        self.status = Some(match result {
+            Ok(file_name) => format!("Wrote {}", file_name),
+            Err(err) => format!("Failed to export: {}", err),
+        });
+    }
+
+    /// The lines previewing the selected video's transcript, a line per segment if it has
+    /// timestamps, before wrapping.
+    fn preview(&mut self) -> &[String] {
+        let Some(entry) = self.current() else {
+            return &[];
+        };
+        let id = entry.video.id.clone();
+        if !self.previews.contains_key(&id) {
+            let lines = match entry.dir.read_latest_transcript("transcript") {
+                Ok(transcript) if !transcript.segments.is_empty() => transcript
+                    .segments
+                    .iter()
+                    .map(|segment| {
+                        let start = format_timestamp(segment.start.floor());
+                        format!("[{}] {}", start, segment.text.trim())
+                    })
+                    .collect(),
+                Ok(transcript) => transcript.text.lines().map(str::to_string).collect(),
+                Err(err) => vec![err.to_string()],
+            };
+            self.previews.insert(id.clone(), lines);
+        }
+        &self.previews[&id]
+    }
+
+    /// Lays out the screen: the search, the list of matching videos, the preview of the
+    /// selected one, and the help or status line.
+    fn render(&mut self, rows: usize, cols: usize) -> Vec<String> {
+        let rows = rows.max(8);
+        let list_height = ((rows - 4) * 2 / 5).max(3);
+        let mut lines = vec![];
+
+        lines.push(format!(
+            "{}{}{}",
+            BOLD,
+            fit(
+                &format!(
+                    " transcribe browse  {} of {} videos",
+                    self.matches.len(),
+                    self.entries.len()
+                ),
+                cols
+            ),
+            RESET
+        ));
+        lines.push(match self.mode {
+            Mode::Search => fit(&format!(" / {}_", self.query), cols),
+            _ if !self.query.is_empty() => fit(&format!(" / {}", self.query), cols),
+            _ => format!("{}{}{}", DIM, fit(" / to search", cols), RESET),
+        });
+
+        if self.selected < self.list_offset {
+            self.list_offset = self.selected;
+        } else if self.selected >= self.list_offset + list_height {
+            self.list_offset = self.selected + 1 - list_height;
+        }
+        for row in 0..list_height {
+            let Some(&i) = self.matches.get(self.list_offset + row) else {
+                lines.push(String::new());
+                continue;
+            };
+            let entry = &self.entries[i];
+            let mut line = format!("  {}", entry.video.title);
+            if let Some(channel) = &entry.video.channel {
+                line.push_str(&format!("  · {}", channel));
+            }
+            if !entry.tags.is_empty() {
+                line.push_str(&format!("  [{}]", entry.tags.join(", ")));
+            }
+            lines.push(match self.list_offset + row == self.selected {
+                true => format!("{}{}{}", REVERSE, fit(&line, cols), RESET),
+                false => fit(&line, cols),
+            });
+        }
+        lines.push(format!("{}{}{}", DIM, "─".repeat(cols), RESET));
+
+        let preview_height = rows - lines.len() - 1;
+        let mut preview = vec![];
+        if let Some(entry) = self.current() {
+            preview.push(format!(
+                "{}{}{}",
+                BOLD,
+                fit(&entry.video.title, cols),
+                RESET
+            ));
+            let mut details = vec![entry.video.url.clone()];
+            details.extend(entry.video.channel.clone());
+            if !entry.tags.is_empty() {
+                details.push(format!("tags: {}", entry.tags.join(", ")));
+            }
+            preview.push(format!(
+                "{}{}{}",
+                DIM,
+                fit(&details.join("  ·  "), cols),
+                RESET
+            ));
+        }
+        let header = preview.len();
+        let body = self
+            .preview()
+            .iter()
+            .flat_map(|line| wrap(line, cols.saturating_sub(1).max(10)))
+            .collect::<Vec<_>>();
+        let visible = preview_height.saturating_sub(header);
+        self.scroll = self.scroll.min(body.len().saturating_sub(visible));
+        preview.extend(body.into_iter().skip(self.scroll).take(visible));
+        preview.resize(preview_height, String::new());
+        lines.extend(preview);
+
+        lines.push(match (&self.mode, &self.status) {
+            (Mode::Tags(input), _) => fit(
+                &format!(
+                    " Tags: {}_  (comma-separated, Enter to save, Esc to cancel)",
+                    input
+                ),
+                cols,
+            ),
+            (_, Some(status)) => fit(&format!(" {}", status), cols),
+            (_, None) => format!("{}{}{}", DIM, fit(&format!(" {}", HELP), cols), RESET),
+        });
+        lines
+    }
+}
+
+/// Applies a key to the text being typed, returning whether it changed.
+fn edit(input: &mut String, key: Key) -> bool {
+    match key {
+        Key::Char(c) => input.push(c),
+        Key::Backspace => return input.pop().is_some(),
+        Key::ClearLine if !input.is_empty() => input.clear(),
+        _ => return false,
+    }
+    true
+}
+
+/// Scores how well `query` matches `text`, or `None` if it does not. Each word of the query
+/// must appear in `text` as a subsequence of its characters, ignoring case, and scores more
+/// the more of its characters are next to each other or start words.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    for word in query.to_lowercase().split_whitespace() {
+        let mut position = 0;
+        let mut previous: Option<usize> = None;
+        for c in word.chars() {
+            let i = (position..text.len()).find(|&i| text[i] == c)?;
+            score += 1;
+            if previous.is_some_and(|previous| previous + 1 == i) {
+                score += 4;
+            }
+            if i == 0 || !text[i - 1].is_alphanumeric() {
+                score += 2;
+            }
+            previous = Some(i);
+            position = i + 1;
+        }
+    }
+    Some(score)
+}
+
+/// Cuts `text` to `width` characters, ending it with an ellipsis if it was longer.
+fn fit(text: &str, width: usize) -> String {
+    match text.chars().count() > width {
+        true => {
+            let mut fitted = text
+                .chars()
+                .take(width.saturating_sub(1))
+                .collect::<String>();
+            fitted.push('…');
+            fitted
+        }
+        false => text.to_string(),
+    }
+}
+
+/// Wraps `text` into lines of at most `width` characters, between words where it can.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let length = line.chars().count();
+        if length > 0 && length + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+        while line.chars().count() > width {
+            let rest = line.chars().skip(width).collect::<String>();
+            lines.push(line.chars().take(width).collect());
+            line = rest;
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Opens `target`, a URL or a directory, in the application the desktop opens it with.
+fn open(target: &str) -> Result<()> {
+    let mut command = match () {
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    command
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("Failed to run {}: {}", program, err))?;
+    Ok(())
+}
+
+/// How long to wait for a key press before checking whether the terminal was resized.
+#[cfg(unix)]
+const RESIZE_POLL_MS: libc::c_int = 100;
+
+/// The terminal, switched to its alternate screen and to reading key presses as they are made
+/// for as long as it is held, through termios, so the browser only runs on Unix.
+struct Terminal {
+    /// The settings the terminal is returned to.
+    #[cfg(unix)]
+    saved: libc::termios,
+}
+
+impl Terminal {
+    #[cfg(not(unix))]
+    fn enter() -> Result<Self> {
+        Err("Browsing needs a Unix terminal".into())
+    }
+
+    #[cfg(unix)]
+    fn enter() -> Result<Self> {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            return Err("Browsing needs a terminal".into());
+        }
+        // SAFETY: `termios` is plain data, which `tcgetattr` fills in for the terminal on stdin.
+        let mut saved = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_iflag &= !libc::IXON;
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: `raw` is a valid `termios`, copied from the terminal's own settings.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut stdout = std::io::stdout();
+        write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+        Ok(Terminal { saved })
+    }
+
+    /// The rows and columns of the terminal.
+    fn size(&self) -> (usize, usize) {
+        #[cfg(unix)]
+        {
+            // SAFETY: `winsize` is plain data, which `TIOCGWINSZ` fills in.
+            let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+            let read = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+            if read == 0 && size.ws_row > 0 && size.ws_col > 0 {
+                return (size.ws_row as usize, size.ws_col as usize);
+            }
+        }
+        (24, 80)
+    }
+
+    fn draw(&self, lines: &[String]) -> Result<()> {
+        let mut frame = String::from("\x1b[H");
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                frame.push_str("\r\n");
+            }
+            frame.push_str(line);
+            frame.push_str("\x1b[K");
+        }
+        // Whatever is left below from before the terminal was resized is cleared.
+        frame.push_str("\x1b[J");
+        let mut stdout = std::io::stdout();
+        stdout.write_all(frame.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Waits briefly for keys to be pressed, returning all of them that arrived together, e.g.
+    /// when pasting, or none if there were none, so that a resize can be noticed in between.
+    fn read_keys(&self) -> Result<Vec<Key>> {
+        #[cfg(unix)]
+        {
+            let mut stdin = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: `stdin` is a single valid `pollfd`.
+            match unsafe { libc::poll(&mut stdin, 1, RESIZE_POLL_MS) } {
+                0 => return Ok(vec![]),
+                ready if ready < 0 => {
+                    let err = std::io::Error::last_os_error();
+                    return match err.kind() {
+                        std::io::ErrorKind::Interrupted => Ok(vec![]),
+                        _ => Err(err.into()),
+                    };
+                }
+                _ => {}
+            }
+        }
+        let mut buffer = [0; 64];
+        let read = std::io::stdin().read(&mut buffer)?;
+        if read == 0 {
+            return Ok(vec![Key::Interrupt]);
+        }
+        Ok(parse_keys(&buffer[..read]))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        // SAFETY: `saved` holds the settings `tcgetattr` returned for the same terminal.
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+/// Parses the bytes the terminal sent into keys, as an escape sequence or as the characters
+/// typed.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    if bytes.first() == Some(&0x1b) {
+        let key = match &bytes[1..] {
+            [] => Key::Escape,
+            [b'[' | b'O', b'A'] => Key::Up,
+            [b'[' | b'O', b'B'] => Key::Down,
+            [b'[', b'5', b'~'] => Key::PageUp,
+            [b'[', b'6', b'~'] => Key::PageDown,
+            _ => Key::Other,
+        };
+        return vec![key];
+    }
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .map(|c| match c {
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x03' => Key::Interrupt,
+            '\x15' => Key::ClearLine,
+            c if c.is_control() => Key::Other,
+            c => Key::Char(c),
+        })
+        .collect()
+}
@@ -0,0 +1,140 @@
+use crate::archive::{fetch_archive_item, parse_item_url};
+use crate::direct::is_media_url;
+use crate::download::download_file;
+use crate::p2p::{fetch_p2p_file, P2pSource};
+use crate::pipeline::SourceOptions;
+use crate::source::Source;
+use crate::ytdlp;
+use crate::Result;
+use reqwest::Client;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Fetches the whole file of `source` into `dir`, a temporary directory, for its picture to
+/// have subtitles burned into, returning where it is. Local files are used where they are, and
+/// pages other than archive.org items, magnet links, IPFS files and links straight to a media
+/// file are downloaded with [`download_video`].
+pub async fn fetch_video_file(
+    source: &Source,
+    client: &Client,
+    options: &SourceOptions,
+    dir: &Path,
+    on_progress: impl FnMut(&str),
+) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join("input");
+    match source {
+        Source::LocalFile(path) => Ok(path.clone()),
+        Source::Stdin => {
+            let mut file = tokio::fs::File::create(&path).await?;
+            tokio::io::copy(&mut tokio::io::stdin(), &mut file).await?;
+            file.flush().await?;
+            if tokio::fs::metadata(&path).await?.len() == 0 {
+                return Err("Nothing was piped in on standard input".into());
+            }
+            Ok(path)
+        }
+        Source::RemoteVideo(url) => {
+            if let Some(identifier) = parse_item_url(url) {
+                let item = fetch_archive_item(client, &identifier).await?;
+                let file = item
+                    .best_video_file()
+                    .ok_or_else(|| format!("{} has no video files", url))?;
+                let data = download_file(client, &item.download_url(file), |_, _| {}).await?;
+                tokio::fs::write(&path, data).await?;
+                Ok(path)
+            } else if let Some(p2p) = P2pSource::parse(url) {
+                fetch_p2p_file(client, &p2p, dir).await
+            } else if is_media_url(url) {
+                tokio::fs::write(&path, download_file(client, url, |_, _| {}).await?).await?;
+                Ok(path)
+            } else {
+                download_video(url, options, None, dir, on_progress).await
+            }
+        }
+        Source::Microphone(_) | Source::LiveStream(_) => {
+            Err("Only video files and pages can have subtitles burned in".into())
+        }
+    }
+}
+
+/// Downloads the video at `url` with its audio, merged into `<dir>/video.mp4`. If `max_size` is
+/// given, the best video stream no larger than that many bytes is picked, falling back to the
+/// smallest one if none is. `on_progress` is called with how far yt-dlp has got, as for
+/// [`ytdlp::download`].
+pub async fn download_video(
+    url: &str,
+    options: &SourceOptions,
+    max_size: Option<u64>,
+    dir: &Path,
+    on_progress: impl FnMut(&str),
+) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let format = match max_size {
+        None => "bv*[ext=mp4]+ba[ext=m4a]/bv*+ba/b".to_string(),
+        Some(max) => format!(
+            "bv*[ext=mp4][filesize<?{max}]+ba[ext=m4a]/bv*[filesize<?{max}]+ba/b[filesize<?{max}]/wv*+wa/w",
+            max = max
+        ),
+    };
+    let mut args = options.ytdlp_args();
+    args.extend(
+        [
+            "-f",
+            &format,
+            "--merge-output-format",
+            "mp4",
+            "-o",
+            "video.%(ext)s",
+            url,
+        ]
+        .map(str::to_string),
+    );
+    ytdlp::download(&args, dir, on_progress).await?;
+    let path = dir.join("video.mp4");
+    match path.exists() {
+        true => Ok(path),
+        false => Err("yt-dlp did not produce an mp4 video".into()),
+    }
+}
+
+/// Renders a copy of `video` to `output` with `subtitles`, an SRT, WebVTT, or ASS file, burned
+/// into the picture, working in `dir`, a temporary directory. The audio is copied as is.
+pub async fn burn_subtitles(
+    video: &Path,
+    subtitles: &Path,
+    output: &Path,
+    dir: &Path,
+) -> Result<()> {
+    // The subtitles filter takes its file name inside the filter graph, where paths would need
+    // several layers of escaping, so ffmpeg is run in `dir` next to a copy with a plain name.
+    let extension = subtitles
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("srt");
+    let name = format!("burn-subtitles.{}", extension);
+    tokio::fs::copy(subtitles, dir.join(&name)).await?;
+
+    let result = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+        .arg(std::path::absolute(video)?)
+        .args(["-vf", &format!("subtitles={}", name)])
+        .args(["-c:a", "copy"])
+        .arg(std::path::absolute(output)?)
+        .current_dir(dir)
+        .output()
+        .await
+        .map_err(|err| format!("Failed to run ffmpeg: {}", err));
+    let _ = tokio::fs::remove_file(dir.join(&name)).await;
+
+    let output = result?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use crate::download::Validators;
+use crate::transcript::Transcript;
+use crate::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A content-addressable store under the user's cache directory.
+///
+/// Blobs are stored by their SHA-256 digest under `blobs/`, and small JSON index entries map a
+/// video ID to the blob holding its audio track under `audio/`, and a [`TranscriptKey`] to the
+/// blob holding the finished transcript under `transcripts/`.
+pub struct Cache {
+    root: PathBuf,
+}
+
+/// The cached audio track of a video, along with the validators it was served with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AudioEntry {
+    pub hash: String,
+    pub validators: Validators,
+}
+
+/// Everything that determines the transcript produced for a video.
+#[derive(Clone, Debug, Serialize)]
+pub struct TranscriptKey<'a> {
+    pub video_id: &'a str,
+    pub model: &'a str,
+    pub language: Option<&'a str>,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub temperature: f32,
+    pub clip: Option<String>,
+    /// Whether this is an English translation rather than a transcript.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub translate: bool,
+    /// The prompt the model was given to steer its spelling and style.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<&'a str>,
+    /// Whether the transcript has word timings.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub word_timestamps: bool,
+    /// The shortest silence left out of the audio before it was transcribed, if any were.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_silence: Option<f64>,
+}
+
+fn is_zero(value: &f32) -> bool {
+    *value == 0.0
+}
+
+impl TranscriptKey<'_> {
+    fn digest(&self) -> String {
+        let key = serde_json::to_vec(self).expect("Failed to serialize transcript key");
+        format!("{:x}", Sha256::digest(key))
+    }
+}
+
+impl Cache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Cache { root: root.into() }
+    }
+
+    /// Opens the cache at `$XDG_CACHE_HOME/transcribe` (or the platform equivalent).
+    pub fn open_default() -> Result<Self> {
+        let root = dirs::cache_dir().ok_or("Missing cache directory")?;
+        Ok(Cache::new(root.join("transcribe")))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Stores `data`, returning the hex-encoded SHA-256 digest it can be retrieved by.
+    pub fn put_blob(&self, data: &[u8]) -> Result<String> {
+        let hash = format!("{:x}", Sha256::digest(data));
+        let path = self.blob_path(&hash);
+        if !path.exists() {
+            write_atomic(&path, data)?;
+        }
+        Ok(hash)
+    }
+
+    pub fn get_blob(&self, hash: &str) -> Option<Vec<u8>> {
+        fs::read(self.blob_path(hash)).ok()
+    }
+
+    pub fn audio_entry(&self, video_id: &str) -> Option<AudioEntry> {
+        self.read_json(&self.root.join("audio").join(format!("{}.json", video_id)))
+    }
+
+    pub fn put_audio_entry(&self, video_id: &str, entry: &AudioEntry) -> Result<()> {
+        self.write_json(
+            &self.root.join("audio").join(format!("{}.json", video_id)),
+            entry,
+        )
+    }
+
+    pub fn transcript(&self, key: &TranscriptKey) -> Option<Transcript> {
+        let hash: String = self.read_json(&self.transcript_path(key))?;
+        serde_json::from_slice(&self.get_blob(&hash)?).ok()
+    }
+
+    pub fn put_transcript(&self, key: &TranscriptKey, transcript: &Transcript) -> Result<()> {
+        let hash = self.put_blob(&serde_json::to_vec(transcript)?)?;
+        self.write_json(&self.transcript_path(key), &hash)
+    }
+
+    fn transcript_path(&self, key: &TranscriptKey) -> PathBuf {
+        self.root
+            .join("transcripts")
+            .join(format!("{}.json", key.digest()))
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.root.join("blobs").join(&hash[..2]).join(hash)
+    }
+
+    fn read_json<T: DeserializeOwned>(&self, path: &Path) -> Option<T> {
+        let json = fs::read(path).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    fn write_json<T: Serialize>(&self, path: &Path, value: &T) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(value)?)
+    }
+}
+
+/// Writes to a temporary file first so that an interrupted write never leaves a truncated entry.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
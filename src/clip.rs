@@ -0,0 +1,153 @@
+use crate::ffmpeg;
+use crate::Result;
+use std::fmt;
+use std::str::FromStr;
+
+/// A time range within a video, in seconds, with either end left open.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clip {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl Clip {
+    pub fn new(start: Option<f64>, end: Option<f64>) -> std::result::Result<Self, String> {
+        if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                return Err("Clip start must be before its end".to_string());
+            }
+        }
+        Ok(Clip { start, end })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+
+    /// The position of the clip in the original video, which its timestamps are offset by.
+    pub fn offset(&self) -> f64 {
+        self.start.unwrap_or(0.0)
+    }
+
+    /// The length of the clip, given the length of the whole video.
+    pub fn length(&self, duration: f64) -> f64 {
+        self.end.unwrap_or(duration).min(duration) - self.offset()
+    }
+
+    /// Splits the clip into consecutive parts at most `max_length` long, given the length of
+    /// the whole video. `max_length` must be positive.
+    pub fn split(&self, duration: f64, max_length: f64) -> Vec<Clip> {
+        assert!(
+            max_length > 0.0,
+            "Clips can only be split into positive lengths"
+        );
+        let end = self.end.unwrap_or(duration).min(duration);
+        let mut parts = vec![];
+        let mut start = self.offset();
+        while start < end {
+            let part_end = (start + max_length).min(end);
+            parts.push(Clip {
+                start: Some(start),
+                end: Some(part_end),
+            });
+            start = part_end;
+        }
+        parts
+    }
+
+    /// Cuts the clip out of an m4a audio track without re-encoding it.
+    pub async fn cut(&self, audio: &[u8]) -> Result<Vec<u8>> {
+        if self.is_empty() {
+            return Ok(audio.to_vec());
+        }
+        let start = self.offset().to_string();
+        let end = self.end.map(|end| end.to_string());
+        let mut input_args = vec!["-ss", &start];
+        if let Some(end) = &end {
+            input_args.extend(["-to", end]);
+        }
+        ffmpeg::transform(audio, &input_args, &["-vn", "-c", "copy"], "m4a").await
+    }
+}
+
+/// Parses `START-END`, where either side may be omitted, e.g. `12:00-25:00` or `1:30:00-`.
+impl FromStr for Clip {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Expected START-END, got '{}'", s))?;
+        let parse = |s: &str| match s.trim() {
+            "" => Ok(None),
+            s => parse_timestamp(s).map(Some),
+        };
+        Clip::new(parse(start)?, parse(end)?)
+    }
+}
+
+/// Parses a timestamp of the form `[[HH:]MM:]SS[.fff]` into seconds.
+pub fn parse_timestamp(s: &str) -> std::result::Result<f64, String> {
+    let invalid = || format!("Invalid timestamp '{}', expected [[HH:]MM:]SS", s);
+    let parts = s.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    parts.iter().try_fold(0.0, |total, part| {
+        let value = part.parse::<f64>().map_err(|_| invalid())?;
+        if value < 0.0 || !value.is_finite() {
+            return Err(invalid());
+        }
+        Ok(total * 60.0 + value)
+    })
+}
+
+/// Parses a length such as `10m`, `90s`, or `1h30m` into seconds, or else a timestamp as
+/// accepted by [`parse_timestamp`].
+pub fn parse_duration(s: &str) -> std::result::Result<f64, String> {
+    if !s.ends_with(['h', 'm', 's']) {
+        return parse_timestamp(s);
+    }
+    let invalid = || format!("Invalid length '{}', expected e.g. 10m, 90s, or 1h30m", s);
+    let mut total = 0.0;
+    let mut number = String::new();
+    for c in s.chars() {
+        let unit = match c {
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => {
+                number.push(c);
+                continue;
+            }
+        };
+        let value = number.parse::<f64>().map_err(|_| invalid())?;
+        if value < 0.0 || !value.is_finite() {
+            return Err(invalid());
+        }
+        total += value * unit;
+        number.clear();
+    }
+    Ok(total)
+}
+
+/// Formats seconds as `[HH:]MM:SS[.fff]`, the inverse of [`parse_timestamp`].
+pub fn format_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let (hours, minutes, secs) = (millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60);
+    let fraction = match millis % 1000 {
+        0 => String::new(),
+        ms => format!(".{:03}", ms).trim_end_matches('0').to_string(),
+    };
+    match hours {
+        0 => format!("{:02}:{:02}{}", minutes, secs, fraction),
+        _ => format!("{}:{:02}:{:02}{}", hours, minutes, secs, fraction),
+    }
+}
+
+impl fmt::Display for Clip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = |time: Option<f64>| time.map(format_timestamp).unwrap_or_default();
+        write!(f, "{}-{}", format(self.start), format(self.end))
+    }
+}
@@ -0,0 +1,124 @@
+use crate::clip::format_timestamp;
+use crate::pipeline::Video;
+use crate::transcript::Transcript;
+use serde::{Deserialize, Serialize};
+
+/// The shortest pause in dialogue, in seconds, worth inserting a description into.
+pub const DEFAULT_MIN_GAP: f64 = 3.0;
+
+/// How many words of description fit in a second, at a typical narration pace.
+const WORDS_PER_SECOND: f64 = 2.5;
+
+/// A chapter of a video, as reported by yt-dlp, or of an audiobook, as read from its tags.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Chapter {
+    pub start_time: f64,
+    pub end_time: f64,
+    pub title: String,
+}
+
+/// The chapters of `video`, or none if it has none or its info JSON was not kept.
+pub fn chapters(video: &Video) -> Vec<Chapter> {
+    video
+        .info
+        .as_ref()
+        .and_then(|info| info.get("chapters"))
+        .and_then(|chapters| serde_json::from_value::<Vec<Chapter>>(chapters.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// A pause in dialogue that an audio description can be inserted into, with `start` and `end` in
+/// seconds.
+#[derive(Clone, Debug, Serialize)]
+pub struct Gap {
+    pub start: f64,
+    pub end: f64,
+    /// The title of the chapter the gap starts in, if the video has chapters.
+    pub chapter: Option<String>,
+    /// The dialogue just before the gap.
+    pub before: Option<String>,
+    /// The dialogue just after the gap.
+    pub after: Option<String>,
+}
+
+impl Gap {
+    /// Roughly how many words of description can be narrated within the gap.
+    pub fn word_budget(&self) -> usize {
+        ((self.end - self.start) * WORDS_PER_SECOND).floor() as usize
+    }
+}
+
+/// Finds the pauses of at least `min_gap` seconds between the segments of `transcript`,
+/// including before the first segment and, if the video's duration is known, after the last.
+pub fn find_gaps(video: &Video, transcript: &Transcript, min_gap: f64) -> Vec<Gap> {
+    let chapters = chapters(video);
+    let chapter_at = |time: f64| {
+        chapters
+            .iter()
+            .find(|chapter| chapter.start_time <= time && time < chapter.end_time)
+            .map(|chapter| chapter.title.clone())
+    };
+
+    let segments = &transcript.segments;
+    let mut bounds = vec![];
+    let mut previous_end = 0.0;
+    for (i, segment) in segments.iter().enumerate() {
+        bounds.push((previous_end, segment.start, i.checked_sub(1), Some(i)));
+        previous_end = segment.end;
+    }
+    if let Some(duration) = video.duration.or(transcript.duration) {
+        bounds.push((previous_end, duration, segments.len().checked_sub(1), None));
+    }
+
+    let text = |i: Option<usize>| i.map(|i| segments[i].text.trim().to_string());
+    bounds
+        .into_iter()
+        .filter(|(start, end, _, _)| end - start >= min_gap)
+        .map(|(start, end, before, after)| Gap {
+            start,
+            end,
+            chapter: chapter_at(start),
+            before: text(before),
+            after: text(after),
+        })
+        .collect()
+}
+
+/// Drafts an audio description script listing each gap with its insertion timestamp, the
+/// dialogue around it, and how many words fit, for a describer to fill in. Gaps that start a new
+/// chapter are pre-filled with the chapter's title as a starting point.
+pub fn draft_script(video: &Video, gaps: &[Gap]) -> String {
+    let mut script = format!(
+        "Audio description draft: {}\n{}\n\n{} gaps in dialogue to describe.\n",
+        video.title,
+        video.url,
+        gaps.len()
+    );
+    let mut chapter = None;
+    for (i, gap) in gaps.iter().enumerate() {
+        script.push_str(&format!(
+            "\n[{}] {} - {} ({:.1} s, about {} words)\n",
+            i + 1,
+            format_timestamp(gap.start),
+            format_timestamp(gap.end),
+            gap.end - gap.start,
+            gap.word_budget()
+        ));
+        if let Some(title) = &gap.chapter {
+            script.push_str(&format!("Chapter: {}\n", title));
+        }
+        if let Some(before) = &gap.before {
+            script.push_str(&format!("Before: \"{}\"\n", before));
+        }
+        if let Some(after) = &gap.after {
+            script.push_str(&format!("After: \"{}\"\n", after));
+        }
+        let draft = match &gap.chapter {
+            Some(title) if chapter != Some(title) => format!("{}.", title),
+            _ => String::new(),
+        };
+        chapter = gap.chapter.as_ref();
+        script.push_str(&format!("Description: {}\n", draft));
+    }
+    script
+}
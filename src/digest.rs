@@ -0,0 +1,182 @@
+use crate::import::parse_transcript;
+use crate::Result;
+use serde::Deserialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+/// The length in characters, roughly, of the transcript excerpt shown for each video.
+const EXCERPT_LENGTH: usize = 400;
+
+/// A video transcribed into an output directory, as listed in a digest.
+#[derive(Clone, Debug)]
+pub struct DigestEntry {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub channel: Option<String>,
+    /// The output directory the video was transcribed into.
+    pub dir: PathBuf,
+    /// When the video's metadata sidecar was last written.
+    pub transcribed: SystemTime,
+    /// The start of the transcript, cut at a word boundary.
+    pub excerpt: String,
+}
+
+/// The fields of `metadata.json` that digests use.
+#[derive(Deserialize)]
+struct MetadataFields {
+    id: String,
+    title: String,
+    url: String,
+    channel: Option<String>,
+}
+
+/// Lists the videos in the output directory `root` that were transcribed since `since`, newest
+/// first. Subdirectories without a readable `metadata.json` and transcript are skipped.
+pub fn collect_entries(root: &Path, since: SystemTime) -> Result<Vec<DigestEntry>> {
+    let mut entries = vec![];
+    for dir in fs::read_dir(root)? {
+        let dir = dir?.path();
+        let metadata_path = dir.join("metadata.json");
+        let Ok(transcribed) = fs::metadata(&metadata_path).and_then(|m| m.modified()) else {
+            continue;
+        };
+        if transcribed < since {
+            continue;
+        }
+        let metadata = fs::read(&metadata_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<MetadataFields>(&data).ok());
+        let Some(metadata) = metadata else {
+            continue;
+        };
+        let Some(text) = read_transcript_text(&dir) else {
+            continue;
+        };
+        entries.push(DigestEntry {
+            id: metadata.id,
+            title: metadata.title,
+            url: metadata.url,
+            channel: metadata.channel,
+            dir,
+            transcribed,
+            excerpt: excerpt(&text, EXCERPT_LENGTH),
+        });
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.transcribed));
+    Ok(entries)
+}
+
+fn read_transcript_text(dir: &Path) -> Option<String> {
+    if let Ok(text) = fs::read_to_string(dir.join("transcript.txt")) {
+        return Some(text);
+    }
+    ["transcript.json", "transcript.srt"]
+        .iter()
+        .find_map(|name| {
+            let contents = fs::read_to_string(dir.join(name)).ok()?;
+            parse_transcript(&contents)
+                .ok()
+                .map(|transcript| transcript.text)
+        })
+}
+
+/// Collapses the whitespace in `text` and cuts it after about `length` characters, at the end of
+/// a word.
+fn excerpt(text: &str, length: usize) -> String {
+    let mut excerpt = String::new();
+    for word in text.split_whitespace() {
+        if excerpt.len() >= length {
+            excerpt.push('…');
+            break;
+        }
+        if !excerpt.is_empty() {
+            excerpt.push(' ');
+        }
+        excerpt.push_str(word);
+    }
+    excerpt
+}
+
+/// Renders the digest as plain text, suitable for the body of an email.
+pub fn render_text(entries: &[DigestEntry]) -> String {
+    if entries.is_empty() {
+        return "No videos were transcribed in this period.\n".to_string();
+    }
+    entries
+        .iter()
+        .map(|entry| {
+            let channel = entry
+                .channel
+                .as_deref()
+                .map(|channel| format!(" ({})", channel))
+                .unwrap_or_default();
+            format!(
+                "{}{}\n{}\n\nExcerpt: {}\n",
+                entry.title, channel, entry.url, entry.excerpt
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders the digest as an RSS 2.0 feed with an item per video, whose GUID is the video ID so
+/// that feed readers only show each video once across regenerated feeds.
+pub fn render_rss(entries: &[DigestEntry], title: &str) -> String {
+    let items = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "    <item>\n      <title>{}</title>\n      <link>{}</link>\n      \
+                 <guid isPermaLink=\"false\">{}</guid>\n      <pubDate>{}</pubDate>\n      \
+                 <description>{}</description>\n    </item>\n",
+                escape_xml(&entry.title),
+                escape_xml(&entry.url),
+                escape_xml(&entry.id),
+                httpdate::fmt_http_date(entry.transcribed),
+                escape_xml(&entry.excerpt)
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n  <channel>\n    \
+         <title>{}</title>\n    <description>Newly transcribed videos</description>\n    \
+         <lastBuildDate>{}</lastBuildDate>\n{}  </channel>\n</rss>\n",
+        escape_xml(title),
+        httpdate::fmt_http_date(SystemTime::now()),
+        items
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Emails `body` to `to` with `sendmail`, which must be installed and configured.
+pub fn send_email(to: &str, subject: &str, body: &str) -> Result<()> {
+    let mut sendmail = Command::new("sendmail")
+        .args(["-t", "-i"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Failed to run sendmail: {}", err))?;
+    let message = format!(
+        "To: {}\nSubject: {}\nContent-Type: text/plain; charset=utf-8\n\n{}",
+        to, subject, body
+    );
+    sendmail
+        .stdin
+        .take()
+        .ok_or("Failed to open sendmail's input")?
+        .write_all(message.as_bytes())?;
+    let status = sendmail.wait()?;
+    if !status.success() {
+        return Err(format!("sendmail failed with {}", status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use crate::download::download_file;
+use crate::ffmpeg::extract_audio;
+use crate::p2p::MEDIA_EXTENSIONS;
+use crate::pipeline::Video;
+use crate::tags::{
+    find_moov, id3_size, parse_moov, MediaTags, MoovSearch, MAX_ATOM_HOPS, MAX_TAG_SIZE,
+    TAG_PROBE_SIZE,
+};
+use crate::Result;
+use reqwest::{header, Client, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Whether `url` points straight at an audio or video file over HTTP, going by its extension,
+/// e.g. a podcast episode's enclosure, which is fetched without yt-dlp.
+pub fn is_media_url(url: &str) -> bool {
+    let Some(rest) = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    else {
+        return false;
+    };
+    let path = rest.split(['?', '#']).next().unwrap_or(rest);
+    path.contains('/')
+        && Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| MEDIA_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// What the server says about a file without sending it.
+struct Head {
+    content_type: Option<String>,
+    size: Option<u64>,
+    last_modified: Option<String>,
+    /// The URL of the file after redirects, which podcast hosts chain through trackers.
+    url: String,
+}
+
+impl Head {
+    fn from_response(response: &Response) -> Self {
+        let value = |name| {
+            let value = response.headers().get(name)?.to_str().ok()?;
+            Some(value.to_string())
+        };
+        // A ranged response gives the size of the whole file after the slash.
+        let size = match response.status() {
+            StatusCode::PARTIAL_CONTENT => value(header::CONTENT_RANGE)
+                .and_then(|range| range.rsplit('/').next()?.parse().ok()),
+            // The body of a response to a HEAD request is empty, whatever `Content-Length`
+            // says.
+            _ => value(header::CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+        };
+        Head {
+            content_type: value(header::CONTENT_TYPE),
+            size,
+            last_modified: value(header::LAST_MODIFIED),
+            url: response.url().to_string(),
+        }
+    }
+
+    fn is_media(&self) -> bool {
+        self.content_type.as_deref().is_some_and(|content_type| {
+            content_type.starts_with("audio/") || content_type.starts_with("video/")
+        })
+    }
+}
+
+/// Fetches the metadata of the audio or video file at `url` from the server and the tags in
+/// the file, without yt-dlp, and extracts its audio track as m4a into `dir` unless it is m4a
+/// already. The title, channel, and date come from the file's ID3 tags or MP4 metadata where
+/// it has them, and from its name and `Last-Modified` otherwise.
+///
+/// Fails if the server does not report the file as audio or video, unless `url` has the
+/// extension of one.
+pub async fn fetch_direct_video(client: &Client, url: &str, dir: &Path) -> Result<Video> {
+    // Some servers refuse HEAD requests, in which case the ranged request for the tags says as
+    // much.
+    let head = match client.head(url).send().await {
+        Ok(response) if response.status().is_success() => Some(Head::from_response(&response)),
+        _ => None,
+    };
+    let is_media = |head: &Head| head.is_media() || is_media_url(&head.url) || is_media_url(url);
+    if head.as_ref().is_some_and(|head| !is_media(head)) {
+        return Err(format!("{} is not an audio or video file", url).into());
+    }
+    let fetch_url = head.as_ref().map_or(url, |head| head.url.as_str());
+    let (start, range_head) = fetch_range(client, fetch_url, 0, TAG_PROBE_SIZE).await?;
+    let head = head.unwrap_or(range_head);
+    if !is_media(&head) {
+        return Err(format!("{} is not an audio or video file", url).into());
+    }
+    let tags = read_tags(client, &head, &start).await;
+
+    let name = head
+        .url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(url);
+    let name = percent_decode(name);
+    let stem = Path::new(&name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| name.clone());
+    let mut video = Video {
+        id: format!("direct_{}", &format!("{:x}", Sha256::digest(url))[..16]),
+        title: tags.title.clone().unwrap_or(stem),
+        url: url.to_string(),
+        channel: tags.artist.clone(),
+        album: tags.album.clone(),
+        upload_date: tags
+            .upload_date()
+            .or_else(|| last_modified_date(head.last_modified.as_deref()?)),
+        duration: tags.duration,
+        description: None,
+        thumbnail_url: None,
+        audio_url: head.url.clone(),
+        audio_file_size: head.size.unwrap_or_default() as f64,
+        info: None,
+    };
+    let m4a = name.to_lowercase().ends_with(".m4a")
+        || head.content_type.as_deref() == Some("audio/mp4")
+        || head.content_type.as_deref() == Some("audio/x-m4a");
+    if m4a && head.size.is_some() {
+        return Ok(video);
+    }
+
+    tokio::fs::create_dir_all(dir).await?;
+    let audio_path = dir.join(format!("{}.m4a", video.id));
+    if !audio_path.exists() {
+        let media = download_file(client, &head.url, |_, _| {}).await?;
+        let media_path = dir.join(&video.id);
+        tokio::fs::write(&media_path, media).await?;
+        let extracted = extract_audio(&media_path, &audio_path).await;
+        let _ = tokio::fs::remove_file(&media_path).await;
+        extracted?;
+    }
+    video.audio_url = format!("file://{}", audio_path.display());
+    video.audio_file_size = tokio::fs::metadata(&audio_path).await?.len() as f64;
+    Ok(video)
+}
+
+/// Fetches `len` bytes of the file from `offset`, or fewer if the file ends first. Servers
+/// that ignore the range send the whole file, of which only as much as asked for is read.
+async fn fetch_range(client: &Client, url: &str, offset: u64, len: u64) -> Result<(Vec<u8>, Head)> {
+    let mut response = client
+        .get(url)
+        .header(
+            header::RANGE,
+            format!("bytes={}-{}", offset, offset + len - 1),
+        )
+        .send()
+        .await?
+        .error_for_status()?;
+    let head = Head::from_response(&response);
+    if offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err("Server does not support range requests".into());
+    }
+    let mut data = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        data.extend_from_slice(&chunk);
+        if data.len() as u64 >= len {
+            data.truncate(len as usize);
+            break;
+        }
+    }
+    Ok((data, head))
+}
+
+/// Reads the tags of the file whose first bytes are `start`, fetching more of it where they
+/// run past `start` or, for MP4 files not written for streaming, lie at its end. Files without
+/// tags, or whose tags cannot be fetched, have none.
+async fn read_tags(client: &Client, head: &Head, start: &[u8]) -> MediaTags {
+    let tags = async {
+        if let Some(size) = id3_size(start) {
+            if size as usize <= start.len() {
+                return MediaTags::parse(start);
+            }
+            let (tag, _) = fetch_range(client, &head.url, 0, size.min(MAX_TAG_SIZE))
+                .await
+                .ok()?;
+            return MediaTags::parse(&tag);
+        }
+
+        if let Some(mut search) = find_moov(start, 0) {
+            for _ in 0..MAX_ATOM_HOPS {
+                match search {
+                    MoovSearch::Found(from, to) if (to as usize) <= start.len() => {
+                        return parse_moov(&start[from as usize..to as usize]);
+                    }
+                    MoovSearch::Found(from, to) => {
+                        let len = (to - from).min(MAX_TAG_SIZE);
+                        let (moov, _) = fetch_range(client, &head.url, from, len).await.ok()?;
+                        return parse_moov(&moov);
+                    }
+                    MoovSearch::Next(offset) if head.size.is_some_and(|size| offset >= size) => {
+                        return None;
+                    }
+                    MoovSearch::Next(offset) => {
+                        let (header, _) = fetch_range(client, &head.url, offset, 16).await.ok()?;
+                        search = find_moov(&header, offset)?;
+                    }
+                }
+            }
+            return None;
+        }
+
+        // MP3s without an ID3v2 tag may still have an ID3v1 tag at their end.
+        let size = head.size.filter(|size| *size >= 128)?;
+        let (tail, _) = fetch_range(client, &head.url, size - 128, 128).await.ok()?;
+        MediaTags::parse_id3v1(&tail)
+    };
+    tags.await.unwrap_or_default()
+}
+
+/// Converts a `Last-Modified` date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, into YYYYMMDD.
+fn last_modified_date(value: &str) -> Option<String> {
+    // Normalizing the date first accepts the obsolete formats HTTP allows too.
+    let date = httpdate::fmt_http_date(httpdate::parse_http_date(value).ok()?);
+    let mut parts = date.split(' ').skip(1);
+    let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+    let months = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = months.iter().position(|name| *name == month)? + 1;
+    Some(format!("{}{:02}{}", year, month, day))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
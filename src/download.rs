@@ -0,0 +1,150 @@
+use crate::pause::wait_while_paused;
+use crate::Result;
+use futures::stream::{FuturesUnordered, StreamExt};
+use reqwest::{header, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+pub const CHUNK_SIZE: usize = 10 * 1024 * 1024; // 10 MB
+
+/// How a file is split into ranged requests, and how many of them are made at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DownloadStrategy {
+    pub chunk_size: usize,
+    pub max_in_flight: usize,
+}
+
+impl Default for DownloadStrategy {
+    /// Every chunk at once, which is fastest when memory is plentiful.
+    fn default() -> Self {
+        DownloadStrategy {
+            chunk_size: CHUNK_SIZE,
+            max_in_flight: usize::MAX,
+        }
+    }
+}
+
+/// Describes how far a download has got, e.g. `42% of 120.5 MB`, or `12.3 MB` if its size is
+/// unknown.
+pub fn format_progress(received: u64, total: Option<u64>) -> String {
+    let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+    match total.filter(|total| *total > 0) {
+        Some(total) => format!(
+            "{}% of {:.1} MB",
+            received.min(total) * 100 / total,
+            megabytes(total)
+        ),
+        None => format!("{:.1} MB", megabytes(received)),
+    }
+}
+
+/// The cache validators a server returned for a file, used to make later requests conditional.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+pub enum Download {
+    /// The file is unchanged since the validators it was requested with were issued.
+    NotModified,
+    Modified {
+        data: Vec<u8>,
+        validators: Validators,
+    },
+}
+
+/// Downloads `url` in parallel ranged chunks, calling `on_progress` with the number of bytes
+/// received so far and the total size each time a chunk completes.
+pub async fn download_file(
+    client: &Client,
+    url: &str,
+    on_progress: impl Fn(u64, u64),
+) -> Result<Vec<u8>> {
+    let strategy = DownloadStrategy::default();
+    match download_file_if_modified(client, url, None, strategy, on_progress).await? {
+        Download::Modified { data, .. } => Ok(data),
+        Download::NotModified => unreachable!("unconditional requests are never 304"),
+    }
+}
+
+/// Like [`download_file`], but sends `If-None-Match`/`If-Modified-Since` from `validators` and
+/// skips the download entirely if the server reports the file as unchanged, and downloads with
+/// `strategy` rather than every chunk at once.
+pub async fn download_file_if_modified(
+    client: &Client,
+    url: &str,
+    validators: Option<&Validators>,
+    strategy: DownloadStrategy,
+    on_progress: impl Fn(u64, u64),
+) -> Result<Download> {
+    let mut request = client.get(url);
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Download::NotModified);
+    }
+    let response = response.error_for_status()?;
+    let total_size = response.content_length().unwrap_or(0);
+    let header_value = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let validators = Validators {
+        etag: header_value(header::ETAG),
+        last_modified: header_value(header::LAST_MODIFIED),
+    };
+    drop(response);
+
+    let mut starts = (0..total_size).step_by(strategy.chunk_size);
+    let mut handles = FuturesUnordered::new();
+
+    // Chunks finish in any order, so each one is copied into place by its offset.
+    let mut data = vec![0; total_size as usize];
+    let mut downloaded = 0;
+    loop {
+        while handles.len() < strategy.max_in_flight.max(1) {
+            let Some(start) = starts.next() else {
+                break;
+            };
+            wait_while_paused().await;
+            let end = std::cmp::min(start + strategy.chunk_size as u64 - 1, total_size - 1);
+            let range = format!("bytes={}-{}", start, end);
+
+            let client = client.clone();
+            let url = url.to_string();
+
+            handles.push(tokio::spawn(async move {
+                let chunk = client
+                    .get(&url)
+                    .header("Range", range)
+                    .send()
+                    .await?
+                    .bytes()
+                    .await?;
+                Ok::<_, crate::Error>((start as usize, chunk))
+            }));
+        }
+
+        let Some(handle) = handles.next().await else {
+            break;
+        };
+        let (start, chunk) = handle??;
+        data.get_mut(start..start + chunk.len())
+            .ok_or("Server returned more data than requested")?
+            .copy_from_slice(&chunk);
+        downloaded += chunk.len() as u64;
+        on_progress(downloaded, total_size);
+    }
+
+    Ok(Download::Modified { data, validators })
+}
@@ -0,0 +1,267 @@
+use crate::live::{LiveStream, Sink};
+use crate::pipeline::Video;
+use crate::transcript::{Segment, Transcript};
+use crate::Result;
+use futures::future::BoxFuture;
+use percent_encoding::percent_decode_str;
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many segments are indexed per bulk request.
+const BULK_BATCH: usize = 500;
+
+/// An Elasticsearch or OpenSearch index that transcript segments are indexed into, one document
+/// per segment with the video's metadata, for searching in Kibana or OpenSearch Dashboards.
+#[derive(Clone, Debug)]
+pub struct Elasticsearch {
+    client: Client,
+    base_url: String,
+    index: String,
+    username: Option<String>,
+    password: Option<String>,
+    api_key: Option<String>,
+}
+
+impl Elasticsearch {
+    /// Parses `http(s)://[user:password@]host:9200/<index>`, reading an API key from
+    /// `ELASTICSEARCH_API_KEY` if set.
+    pub fn parse(url: &str) -> Result<Self> {
+        let mut url = url.parse::<Url>()?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(format!("Unsupported Elasticsearch URL scheme: {}", url.scheme()).into());
+        }
+        let index = url.path().trim_matches('/').to_string();
+        if index.is_empty() || index.contains('/') {
+            return Err("Expected an index, e.g. http://localhost:9200/transcripts".into());
+        }
+        if index.starts_with(['_', '-', '+']) || index.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(format!("Invalid index name '{}'", index).into());
+        }
+
+        // Credentials containing `@`, `:` or `%` have to be percent-encoded in the URL.
+        let decode = |s: &str| -> Result<String> {
+            Ok(percent_decode_str(s)
+                .decode_utf8()
+                .map_err(|_| "Elasticsearch credentials are not valid UTF-8")?
+                .into_owned())
+        };
+        let username = Some(decode(url.username())?).filter(|username| !username.is_empty());
+        let password = url.password().map(decode).transpose()?;
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+        url.set_path("");
+        Ok(Elasticsearch {
+            client: Client::new(),
+            base_url: url.as_str().trim_end_matches('/').to_string(),
+            index,
+            username,
+            password,
+            api_key: std::env::var("ELASTICSEARCH_API_KEY").ok(),
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}/{}{}", self.base_url, self.index, path));
+        match (&self.api_key, &self.username) {
+            (Some(key), _) => request.header("Authorization", format!("ApiKey {}", key)),
+            (None, Some(username)) => request.basic_auth(username, self.password.as_ref()),
+            (None, None) => request,
+        }
+    }
+
+    /// Indexes each segment of `transcript` with the video's metadata, returning how many were
+    /// indexed. The video's previously indexed segments are deleted first, so re-indexing a video
+    /// replaces its segments rather than duplicating them. Transcripts without timestamps are
+    /// indexed as a single document.
+    pub async fn index_transcript(&self, video: &Video, transcript: &Transcript) -> Result<usize> {
+        if !self.ensure_index().await? {
+            self.delete_video(&video.id).await?;
+        }
+
+        let indexed_at = now_millis();
+        let whole = [Segment {
+            start: 0.0,
+            end: transcript.duration.unwrap_or_default(),
+            text: transcript.text.clone(),
+            avg_logprob: None,
+            compression_ratio: None,
+            no_speech_prob: None,
+            speaker: None,
+            words: vec![],
+        }];
+        let segments = match transcript.segments.is_empty() {
+            true => &whole[..],
+            false => &transcript.segments[..],
+        };
+        let metadata = json!({
+            "video_id": video.id,
+            "title": video.title,
+            "url": video.url,
+            "channel": video.channel,
+            "album": video.album,
+            "upload_date": video.upload_date,
+            "language": transcript.language,
+        });
+
+        for (batch, segments) in segments.chunks(BULK_BATCH).enumerate() {
+            let documents = segments.iter().enumerate().map(|(i, segment)| {
+                let number = batch * BULK_BATCH + i + 1;
+                let id = format!("{}-{}", video.id, number);
+                (Some(id), document(&metadata, number, segment, indexed_at))
+            });
+            self.bulk(documents).await?;
+        }
+        Ok(segments.len())
+    }
+
+    /// A sink that indexes the segments of `stream`, found at `url`, as they are transcribed.
+    pub fn live_sink(&self, stream: &LiveStream, url: &str) -> LiveIndexer {
+        LiveIndexer {
+            elasticsearch: self.clone(),
+            metadata: json!({
+                "video_id": stream.id,
+                "title": stream.title,
+                "url": url,
+            }),
+            ensured: false,
+            count: 0,
+        }
+    }
+
+    /// Creates the index with mappings for the segment documents unless it already exists,
+    /// returning whether it was created.
+    async fn ensure_index(&self) -> Result<bool> {
+        let res = self.request(Method::HEAD, "").send().await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            res.error_for_status()?;
+            return Ok(false);
+        }
+        let mappings = json!({
+            "mappings": {
+                "properties": {
+                    "video_id": { "type": "keyword" },
+                    "title": {
+                        "type": "text",
+                        "fields": { "keyword": { "type": "keyword", "ignore_above": 256 } }
+                    },
+                    "url": { "type": "keyword" },
+                    "channel": { "type": "keyword" },
+                    "album": { "type": "keyword" },
+                    "upload_date": { "type": "date", "format": "basic_date" },
+                    "language": { "type": "keyword" },
+                    "segment": { "type": "integer" },
+                    "start": { "type": "double" },
+                    "end": { "type": "double" },
+                    "speaker": { "type": "keyword" },
+                    "text": { "type": "text" },
+                    "indexed_at": { "type": "date", "format": "epoch_millis" }
+                }
+            }
+        });
+        let res = self.request(Method::PUT, "").json(&mappings).send().await?;
+        res.error_for_status()?;
+        Ok(true)
+    }
+
+    async fn delete_video(&self, video_id: &str) -> Result<()> {
+        let query = json!({ "query": { "term": { "video_id": video_id } } });
+        let res = self
+            .request(
+                Method::POST,
+                "/_delete_by_query?refresh=true&conflicts=proceed",
+            )
+            .json(&query)
+            .send()
+            .await?;
+        res.error_for_status()?;
+        Ok(())
+    }
+
+    /// Indexes `documents` in one bulk request, with their IDs if given.
+    async fn bulk(&self, documents: impl Iterator<Item = (Option<String>, Value)>) -> Result<()> {
+        let mut body = String::new();
+        for (id, document) in documents {
+            let action = match id {
+                Some(id) => json!({ "index": { "_id": id } }),
+                None => json!({ "index": {} }),
+            };
+            body.push_str(&format!("{}\n{}\n", action, document));
+        }
+        let res = self
+            .request(Method::POST, "/_bulk")
+            .header("Content-Type", "application/x-ndjson")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // Bulk requests succeed as a whole even if some documents fail, which is reported per
+        // item instead.
+        let response = res.json::<Value>().await?;
+        if response["errors"].as_bool().unwrap_or(false) {
+            let reason = response["items"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find_map(|item| item["index"]["error"]["reason"].as_str())
+                .unwrap_or("unknown error");
+            return Err(format!("Failed to index segments: {}", reason).into());
+        }
+        Ok(())
+    }
+}
+
+/// Indexes live segments into Elasticsearch as they are transcribed, with IDs left to
+/// Elasticsearch so that restarting a stream's transcription never overwrites earlier segments.
+pub struct LiveIndexer {
+    elasticsearch: Elasticsearch,
+    metadata: Value,
+    ensured: bool,
+    count: usize,
+}
+
+impl Sink for LiveIndexer {
+    fn write<'a>(&'a mut self, segments: &'a [Segment]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if segments.is_empty() {
+                return Ok(());
+            }
+            if !self.ensured {
+                self.elasticsearch.ensure_index().await?;
+                self.ensured = true;
+            }
+            let indexed_at = now_millis();
+            let first = self.count + 1;
+            let documents = segments.iter().enumerate().map(|(i, segment)| {
+                (
+                    None,
+                    document(&self.metadata, first + i, segment, indexed_at),
+                )
+            });
+            self.elasticsearch.bulk(documents).await?;
+            self.count += segments.len();
+            Ok(())
+        })
+    }
+}
+
+fn document(metadata: &Value, number: usize, segment: &Segment, indexed_at: u64) -> Value {
+    let mut document = metadata.clone();
+    document["segment"] = json!(number);
+    document["start"] = json!(segment.start);
+    document["end"] = json!(segment.end);
+    document["speaker"] = json!(segment.speaker);
+    document["text"] = json!(segment.text.trim());
+    document["indexed_at"] = json!(indexed_at);
+    document
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,81 @@
+use crate::Result;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::process::Command;
+
+/// Runs `ffmpeg` over `input`, returning what it writes to an output file with extension `ext`.
+///
+/// `input_args` go before `-i` and `output_args` before the output path. ffmpeg needs to seek in
+/// both m4a inputs and outputs, so the data is passed through temporary files rather than pipes.
+pub async fn transform(
+    input: &[u8],
+    input_args: &[&str],
+    output_args: &[&str],
+    ext: &str,
+) -> Result<Vec<u8>> {
+    let input_path = temp_path("m4a");
+    let output_path = temp_path(ext);
+    tokio::fs::write(&input_path, input).await?;
+
+    let mut args = vec![OsStr::new("-y")];
+    args.extend(input_args.iter().map(OsStr::new));
+    args.extend([OsStr::new("-i"), input_path.as_os_str()]);
+    args.extend(output_args.iter().map(OsStr::new));
+    args.push(output_path.as_os_str());
+
+    let result = match ffmpeg(args).await {
+        Ok(()) => tokio::fs::read(&output_path).await.map_err(Into::into),
+        Err(err) => Err(err),
+    };
+
+    let _ = tokio::fs::remove_file(&input_path).await;
+    let _ = tokio::fs::remove_file(&output_path).await;
+    result
+}
+
+/// Extracts the audio track of the media file at `input` into an m4a file at `output`,
+/// re-encoding it at a bitrate that is plenty for speech.
+pub async fn extract_audio(input: &Path, output: &Path) -> Result<()> {
+    ffmpeg([
+        OsStr::new("-y"),
+        OsStr::new("-i"),
+        input.as_os_str(),
+        OsStr::new("-vn"),
+        OsStr::new("-c:a"),
+        OsStr::new("aac"),
+        OsStr::new("-b:a"),
+        OsStr::new("64k"),
+        output.as_os_str(),
+    ])
+    .await
+}
+
+/// Runs `ffmpeg` with `args`, failing with its stderr if it exits unsuccessfully.
+pub async fn ffmpeg<I, S>(args: I) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error"])
+        .args(args)
+        .output()
+        .await
+        .map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Returns a path in the system temporary directory that is unique to this process and call.
+pub fn temp_path(ext: &str) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("transcribe-{}-{}.{}", std::process::id(), n, ext))
+}
@@ -0,0 +1,375 @@
+use crate::transcript::Transcript;
+use crate::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How likely, at least, the model must have thought a segment was to contain no speech for
+/// blocked phrases to be stripped from it by default. Phrases in segments the model was sure
+/// held speech are left alone, as people do say "thank you".
+pub const DEFAULT_HALLUCINATION_THRESHOLD: f64 = 0.3;
+
+/// The phrases Whisper is known to make up in silence, picked up from the subtitles of the
+/// videos it was trained on, by ISO-639-1 code. `hallucinations.txt` starts out with these.
+const DEFAULT_PHRASES: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "thanks for watching",
+            "thank you for watching",
+            "please subscribe",
+            "subscribe to my channel",
+            "like and subscribe",
+            "see you in the next video",
+            "subtitles by the amara.org community",
+            "transcription by castingwords",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "sous-titres réalisés par la communauté d'amara.org",
+            "sous-titrage st' 501",
+            "sous-titres par",
+            "merci d'avoir regardé",
+            "abonnez-vous",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "untertitel der amara.org-community",
+            "untertitel im auftrag des zdf",
+            "vielen dank fürs zuschauen",
+            "bis zum nächsten mal",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "subtítulos realizados por la comunidad de amara.org",
+            "gracias por ver el video",
+            "suscríbete",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "sottotitoli creati dalla comunità amara.org",
+            "grazie per la visione",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "legendas pela comunidade amara.org",
+            "obrigado por assistir",
+            "inscreva-se no canal",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "ondertiteling door de amara.org gemeenschap",
+            "bedankt voor het kijken",
+        ],
+    ),
+    ("ru", &["редактор субтитров", "продолжение следует"]),
+    (
+        "ja",
+        &["ご視聴ありがとうございました", "チャンネル登録お願いします"],
+    ),
+    (
+        "zh",
+        &[
+            "请不吝点赞 订阅 转发 打赏支持明镜与点点栏目",
+            "字幕由amara.org社区提供",
+        ],
+    ),
+    ("ko", &["시청해주셔서 감사합니다", "구독과 좋아요"]),
+];
+
+/// The languages the API names in its transcripts, e.g. `english`, by ISO-639-1 code.
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("english", "en"),
+    ("french", "fr"),
+    ("german", "de"),
+    ("spanish", "es"),
+    ("italian", "it"),
+    ("portuguese", "pt"),
+    ("dutch", "nl"),
+    ("russian", "ru"),
+    ("japanese", "ja"),
+    ("chinese", "zh"),
+    ("korean", "ko"),
+];
+
+/// Phrases that are stripped from segments the model thought were silent, by language, as
+/// listed in a file of `<language>: <phrase>` lines, with `*` for phrases of every language.
+#[derive(Clone, Debug, Default)]
+pub struct Blocklist {
+    /// The phrases, normalized, with the ISO-639-1 code of their language or `*`.
+    phrases: Vec<(String, String)>,
+}
+
+impl Blocklist {
+    /// The blocklist in `hallucinations.txt` in the configuration directory, which is written
+    /// with the built-in phrases the first time it is needed, for editing from then on.
+    pub fn load_default() -> Result<Self> {
+        let path = Self::default_path()?;
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, default_file())?;
+        }
+        Self::load(&path)
+    }
+
+    pub fn default_path() -> Result<PathBuf> {
+        let root = dirs::config_dir().ok_or("Missing configuration directory")?;
+        Ok(root.join("transcribe").join("hallucinations.txt"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        Self::parse(&contents)
+    }
+
+    /// Parses a blocklist file. Blank lines and lines starting with `#` are skipped.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut phrases = vec![];
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (language, phrase) = line.split_once(':').ok_or_else(|| {
+                format!(
+                    "Line {} of the hallucination blocklist is not <language>: <phrase>",
+                    i + 1
+                )
+            })?;
+            let phrase = normalize(phrase);
+            if !phrase.is_empty() {
+                phrases.push((language.trim().to_lowercase(), phrase));
+            }
+        }
+        Ok(Blocklist { phrases })
+    }
+
+    /// Strips the sentences containing a blocked phrase of the transcript's language from
+    /// its segments at least `threshold` likely to contain no speech, along with their words,
+    /// narrowing the segments to the words left, then drops segments left empty and rebuilds the
+    /// text. Segments without a no-speech probability are kept as they are. Returns how many
+    /// sentences were stripped.
+    pub fn strip(&self, transcript: &mut Transcript, threshold: f64) -> usize {
+        let language = transcript.language.as_deref().map(language_code);
+        let phrases = self
+            .phrases
+            .iter()
+            .filter(|(code, _)| code == "*" || language.is_none_or(|language| code == language))
+            .map(|(_, phrase)| phrase.as_str())
+            .collect::<Vec<_>>();
+        if phrases.is_empty() {
+            return 0;
+        }
+
+        let mut stripped = 0;
+        for segment in &mut transcript.segments {
+            if segment.no_speech_prob.is_none_or(|p| p < threshold) {
+                continue;
+            }
+            let sentences = sentences(&segment.text)
+                .into_iter()
+                .map(|sentence| {
+                    let normalized = normalize(sentence);
+                    let blocked = phrases.iter().any(|phrase| normalized.contains(phrase));
+                    (sentence.to_string(), blocked)
+                })
+                .collect::<Vec<_>>();
+            let blocked = sentences.iter().filter(|(_, blocked)| *blocked).count();
+            if blocked == 0 {
+                continue;
+            }
+            stripped += blocked;
+            // The model times each word of the text, so the words of each sentence follow on
+            // from those of the one before. If they do not add up, none of them can be trusted
+            // to be free of the stripped phrases.
+            let words = sentences
+                .iter()
+                .map(|(sentence, _)| sentence.split_whitespace().count())
+                .sum::<usize>();
+            let mut kept_words = vec![];
+            if words == segment.words.len() {
+                let mut words = std::mem::take(&mut segment.words).into_iter();
+                for (sentence, blocked) in &sentences {
+                    let sentence_words = words.by_ref().take(sentence.split_whitespace().count());
+                    match blocked {
+                        true => sentence_words.for_each(drop),
+                        false => kept_words.extend(sentence_words),
+                    }
+                }
+            }
+            if let (Some(first), Some(last)) = (kept_words.first(), kept_words.last()) {
+                segment.start = first.start;
+                segment.end = last.end;
+            }
+            segment.words = kept_words;
+            segment.text = sentences
+                .into_iter()
+                .filter(|(_, blocked)| !blocked)
+                .map(|(sentence, _)| sentence)
+                .collect();
+        }
+        if stripped > 0 {
+            transcript
+                .segments
+                .retain(|segment| !normalize(&segment.text).is_empty());
+            transcript.text = transcript
+                .segments
+                .iter()
+                .map(|segment| segment.text.as_str())
+                .collect();
+        }
+        stripped
+    }
+}
+
+/// Splits `text` into sentences, each with the punctuation and space after it, at the end of
+/// sentences rather than at every full stop, such as those in `amara.org`.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let ends = match c {
+            '。' | '！' | '？' | '\n' => true,
+            '.' | '!' | '?' => chars.peek().is_none_or(|(_, next)| next.is_whitespace()),
+            _ => false,
+        };
+        if ends {
+            // The space after the sentence stays with it.
+            let mut end = i + c.len_utf8();
+            while let Some(&(j, next)) = chars.peek() {
+                if next != ' ' {
+                    break;
+                }
+                end = j + next.len_utf8();
+                chars.next();
+            }
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+/// The ISO-639-1 code of `language`, which the API gives by name, e.g. `English`.
+fn language_code(language: &str) -> &str {
+    let name = language.trim().to_lowercase();
+    LANGUAGE_NAMES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map_or(language.trim(), |(_, code)| code)
+}
+
+/// Lowercases `text` and reduces everything but letters, digits, and the punctuation inside
+/// words and domains to single spaces, so that phrases match however they were punctuated.
+fn normalize(text: &str) -> String {
+    let text = text
+        .to_lowercase()
+        .chars()
+        .map(
+            |c| match c.is_alphanumeric() || matches!(c, '\'' | '’' | '-' | '.') {
+                true => c,
+                false => ' ',
+            },
+        )
+        .collect::<String>();
+    text.split_whitespace()
+        .map(|word| word.trim_matches(['.', '-', '\'', '’']))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The blocklist file `hallucinations.txt` starts out as.
+fn default_file() -> String {
+    let mut file = String::from(
+        "# Phrases Whisper makes up in silence, stripped from segments it thought held no speech.\n\
+         # One per line as <language>: <phrase>, by ISO-639-1 code, or * for every language.\n\
+         # Case and punctuation are ignored, and sentences containing a phrase are removed.\n",
+    );
+    for (language, phrases) in DEFAULT_PHRASES {
+        file.push('\n');
+        for phrase in *phrases {
+            file.push_str(&format!("{}: {}\n", language, phrase));
+        }
+    }
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn splits_sentences_at_their_end_rather_than_at_every_full_stop() {
+        assert_eq!(
+            sentences(" Thanks for watching! Subtitles by the Amara.org community. Bye"),
+            [
+                " Thanks for watching! ",
+                "Subtitles by the Amara.org community. ",
+                "Bye"
+            ]
+        );
+        assert_eq!(
+            sentences("ご視聴ありがとうございました。またね"),
+            ["ご視聴ありがとうございました。", "またね"]
+        );
+        assert_eq!(sentences("Wait... what?"), ["Wait... ", "what?"]);
+    }
+
+    #[test]
+    fn normalizes_case_and_punctuation_but_keeps_domains() {
+        assert_eq!(
+            normalize(" Subtitles by the Amara.org community."),
+            "subtitles by the amara.org community"
+        );
+        assert_eq!(normalize("THANKS, for watching!!"), "thanks for watching");
+        assert_eq!(normalize("'Sous-titres' par..."), "sous-titres par");
+        assert_eq!(normalize(" -- . "), "");
+    }
+
+    #[test]
+    fn strips_the_words_of_blocked_sentences() {
+        let mut transcript: Transcript = serde_json::from_value(json!({
+            "text": " Okay. Thanks for watching!",
+            "language": "english",
+            "segments": [{
+                "start": 10.0,
+                "end": 14.0,
+                "text": " Okay. Thanks for watching!",
+                "no_speech_prob": 0.9,
+                "words": [
+                    { "word": "Okay", "start": 10.0, "end": 10.5 },
+                    { "word": "Thanks", "start": 12.0, "end": 12.5 },
+                    { "word": "for", "start": 12.5, "end": 13.0 },
+                    { "word": "watching", "start": 13.0, "end": 14.0 }
+                ]
+            }]
+        }))
+        .unwrap();
+        let blocklist = Blocklist::parse("en: thanks for watching").unwrap();
+        assert_eq!(blocklist.strip(&mut transcript, 0.3), 1);
+        let segment = &transcript.segments[0];
+        assert_eq!(segment.text, " Okay. ");
+        assert_eq!(segment.words.len(), 1);
+        assert_eq!((segment.start, segment.end), (10.0, 10.5));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::clip::format_timestamp;
+use crate::transcript::{Format, Transcript};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The subdirectory of an output directory that replaced transcripts are kept in, as
+/// `version-0001.json` and so on, oldest first.
+pub const VERSIONS_DIR: &str = "versions";
+
+/// How many unchanged lines are shown around each change in a diff.
+const DIFF_CONTEXT: usize = 1;
+
+/// A transcript that was replaced by transcribing the video again, or by importing a
+/// corrected transcript over it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Version {
+    /// The version's number, counting from 1 for the first transcript of the video.
+    pub number: usize,
+    /// When the transcript was written, in seconds since the Unix epoch.
+    pub written_at: u64,
+    /// When the transcript was replaced, in seconds since the Unix epoch.
+    pub replaced_at: u64,
+    /// The model and provider that made the transcript, as `manifest.json` recorded them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// The file the transcript was imported from, if it was made by another tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_from: Option<String>,
+    pub transcript: Transcript,
+}
+
+impl Version {
+    /// Describes where the version came from, e.g. `whisper-1 via openai`.
+    pub fn origin(&self) -> String {
+        match (&self.imported_from, &self.model, &self.provider) {
+            (Some(file), _, _) => format!("imported from {}", file),
+            (None, Some(model), Some(provider)) => format!("{} via {}", model, provider),
+            (None, Some(model), None) => format!("{}, from the cache", model),
+            (None, None, _) => "unknown origin".to_string(),
+        }
+    }
+}
+
+/// The versions of the transcript in the output directory `dir`, oldest first, ending with
+/// the current transcript, which has yet to be replaced.
+pub fn versions(dir: &Path, current: Option<Transcript>) -> Result<Vec<Version>> {
+    let mut versions = vec![];
+    if let Ok(entries) = fs::read_dir(dir.join(VERSIONS_DIR)) {
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                versions.push(serde_json::from_slice::<Version>(&fs::read(&path)?)?);
+            }
+        }
+    }
+    versions.sort_by_key(|version| version.number);
+    if let Some(transcript) = current {
+        let number = versions.last().map_or(1, |version| version.number + 1);
+        versions.push(describe_current(dir, transcript, number));
+    }
+    Ok(versions)
+}
+
+/// Keeps `previous`, the transcript in the output directory `dir`, as its next version before
+/// `transcript` replaces it, unless the two read the same. Returns the path it was kept at.
+pub fn keep_version(
+    dir: &Path,
+    previous: Transcript,
+    transcript: &Transcript,
+) -> Result<Option<PathBuf>> {
+    let unchanged = match previous.segments.is_empty() {
+        true => previous.text.trim() == transcript.text.trim(),
+        false => previous.render(Format::Srt) == transcript.render(Format::Srt),
+    };
+    if unchanged {
+        return Ok(None);
+    }
+    let number = versions(dir, None)?
+        .last()
+        .map_or(1, |version| version.number + 1);
+    let mut version = describe_current(dir, previous, number);
+    version.replaced_at = unix_seconds(SystemTime::now());
+    let versions_dir = dir.join(VERSIONS_DIR);
+    fs::create_dir_all(&versions_dir)?;
+    let path = versions_dir.join(format!("version-{:04}.json", number));
+    fs::write(&path, serde_json::to_string_pretty(&version)?)?;
+    Ok(Some(path))
+}
+
+/// Describes the transcript the output directory `dir` holds as version `number`, going by
+/// its sidecars.
+fn describe_current(dir: &Path, transcript: Transcript, number: usize) -> Version {
+    let read_json = |name: &str| -> Option<Value> {
+        serde_json::from_slice(&fs::read(dir.join(name)).ok()?).ok()
+    };
+    let manifest = read_json("manifest.json");
+    let metadata = read_json("metadata.json");
+    let field =
+        |json: &Option<Value>, name: &str| json.as_ref()?.get(name)?.as_str().map(str::to_string);
+    // The sidecars are written after the transcript, so the newest of them is when the
+    // transcript was finished.
+    let written_at = ["metadata.json", "manifest.json"]
+        .into_iter()
+        .filter_map(|name| fs::metadata(dir.join(name)).and_then(|m| m.modified()).ok())
+        .max()
+        .map_or(0, unix_seconds);
+    Version {
+        number,
+        written_at,
+        replaced_at: 0,
+        model: field(&manifest, "model"),
+        provider: field(&manifest, "provider"),
+        imported_from: field(&metadata, "imported_from"),
+        transcript,
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats seconds since the Unix epoch as an HTTP date, e.g. `Tue, 14 Oct 2025 09:30:00 GMT`.
+pub fn format_time(seconds: u64) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// A line of a transcript as compared in a diff, with when it was said, if known.
+struct Line<'a> {
+    start: Option<f64>,
+    text: &'a str,
+}
+
+/// The lines of `transcript` compared in a diff: its segments, or for transcripts without
+/// timestamps, its sentences.
+fn lines(transcript: &Transcript) -> Vec<Line<'_>> {
+    if !transcript.segments.is_empty() {
+        return transcript
+            .segments
+            .iter()
+            .map(|segment| Line {
+                start: Some(segment.start),
+                text: segment.text.trim(),
+            })
+            .collect();
+    }
+    transcript
+        .text
+        .split_inclusive(['.', '?', '!', '\n'])
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(|text| Line { start: None, text })
+        .collect()
+}
+
+/// Compares the text of two transcripts line by line, ignoring changes to timestamps alone,
+/// and renders the lines removed from `old` with `-` and added in `new` with `+`, each with
+/// when it was said, along with the unchanged lines next to them. Returns `None` if the
+/// texts are the same.
+pub fn diff(old: &Transcript, new: &Transcript) -> Option<String> {
+    let (old, new) = (lines(old), lines(new));
+    let changes = diff_lines(&old, &new);
+    if changes
+        .iter()
+        .all(|change| matches!(change, Change::Same(..)))
+    {
+        return None;
+    }
+
+    let render = |sign: char, line: &Line| match line.start {
+        Some(start) => format!(
+            "{} [{}] {}",
+            sign,
+            format_timestamp(start.floor()),
+            line.text
+        ),
+        None => format!("{} {}", sign, line.text),
+    };
+    let near_change = |i: usize| {
+        let from = i.saturating_sub(DIFF_CONTEXT);
+        let to = (i + DIFF_CONTEXT + 1).min(changes.len());
+        changes[from..to]
+            .iter()
+            .any(|change| !matches!(change, Change::Same(..)))
+    };
+    let mut out = vec![];
+    let mut skipped = false;
+    for (i, change) in changes.iter().enumerate() {
+        let line = match *change {
+            Change::Same(j) if near_change(i) => render(' ', &new[j]),
+            Change::Same(..) => {
+                if !skipped && !out.is_empty() {
+                    out.push("  ...".to_string());
+                }
+                skipped = true;
+                continue;
+            }
+            Change::Removed(j) => render('-', &old[j]),
+            Change::Added(j) => render('+', &new[j]),
+        };
+        skipped = false;
+        out.push(line);
+    }
+    Some(out.join("\n"))
+}
+
+/// A line of a diff, by its index in the transcript it is shown from, which for unchanged
+/// lines is the new one.
+enum Change {
+    Same(usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Diffs two sequences of lines by their longest common subsequence, after setting aside the
+/// lines they start and end with in common, which are most of them between versions.
+fn diff_lines(old: &[Line], new: &[Line]) -> Vec<Change> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(a, b)| a.text == b.text)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a.text == b.text)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // lengths[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let width = b.len() + 1;
+    let mut lengths = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * width + j] = match a[i].text == b[j].text {
+                true => lengths[(i + 1) * width + j + 1] + 1,
+                false => lengths[(i + 1) * width + j].max(lengths[i * width + j + 1]),
+            };
+        }
+    }
+
+    let mut changes = (0..prefix).map(Change::Same).collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].text == b[j].text {
+            changes.push(Change::Same(prefix + j));
+            i += 1;
+            j += 1;
+        } else if i < a.len()
+            && (j == b.len() || lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            changes.push(Change::Removed(prefix + i));
+            i += 1;
+        } else {
+            changes.push(Change::Added(prefix + j));
+            j += 1;
+        }
+    }
+    let new_end = new.len() - suffix;
+    changes.extend((0..suffix).map(|k| Change::Same(new_end + k)));
+    changes
+}
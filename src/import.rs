@@ -0,0 +1,165 @@
+use crate::clip::parse_timestamp;
+use crate::transcript::{Segment, Transcript};
+use crate::Result;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// The `verbose_json` of the API, which the `whisper` command line tool's JSON output is a
+/// superset of.
+#[derive(Deserialize)]
+struct WhisperOutput {
+    #[serde(default)]
+    text: String,
+    language: Option<String>,
+    duration: Option<f64>,
+    #[serde(default)]
+    segments: Vec<Segment>,
+}
+
+/// The JSON whisper.cpp writes with `--output-json`.
+#[derive(Deserialize)]
+struct WhisperCppOutput {
+    #[serde(default)]
+    result: Option<WhisperCppResult>,
+    transcription: Vec<WhisperCppSegment>,
+}
+
+#[derive(Deserialize)]
+struct WhisperCppResult {
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WhisperCppSegment {
+    /// The start and end in milliseconds.
+    offsets: WhisperCppOffsets,
+    text: String,
+    /// The decoded tokens, written with `--output-json-full`.
+    #[serde(default)]
+    tokens: Vec<WhisperCppToken>,
+}
+
+#[derive(Deserialize)]
+struct WhisperCppToken {
+    text: String,
+    /// The probability of the token.
+    p: f64,
+}
+
+#[derive(Deserialize)]
+struct WhisperCppOffsets {
+    from: u64,
+    to: u64,
+}
+
+/// Parses a transcript produced by another tool: the JSON of the OpenAI API, the `whisper`
+/// command line tool, or whisper.cpp, or an SRT file.
+pub fn parse_transcript(contents: &str) -> Result<Transcript> {
+    let contents = contents.trim_start_matches('\u{feff}');
+    match contents.trim_start().starts_with('{') {
+        true => parse_json(contents),
+        false => parse_srt(contents),
+    }
+}
+
+fn parse_json(contents: &str) -> Result<Transcript> {
+    let value: Value = serde_json::from_str(contents)?;
+    if value.get("transcription").is_some() {
+        let output: WhisperCppOutput = serde_json::from_value(value)?;
+        let segments = output
+            .transcription
+            .into_iter()
+            .map(|segment| {
+                // Special tokens such as `[_BEG_]` and timestamps are left out, like the API
+                // leaves them out of a segment's `avg_logprob`.
+                let logprobs = segment
+                    .tokens
+                    .iter()
+                    .filter(|token| !token.text.starts_with("[_"))
+                    .map(|token| token.p.max(f64::MIN_POSITIVE).ln())
+                    .collect::<Vec<_>>();
+                Segment {
+                    avg_logprob: Some(logprobs.iter().sum::<f64>() / logprobs.len() as f64)
+                        .filter(|_| !logprobs.is_empty()),
+                    ..plain_segment(
+                        segment.offsets.from as f64 / 1000.0,
+                        segment.offsets.to as f64 / 1000.0,
+                        segment.text,
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        return Ok(from_segments(
+            segments,
+            output.result.and_then(|result| result.language),
+        ));
+    }
+
+    let output: WhisperOutput = serde_json::from_value(value)
+        .map_err(|err| format!("Unrecognized transcript JSON: {}", err))?;
+    let text = match output.text.is_empty() {
+        true => output.segments.iter().map(|s| s.text.as_str()).collect(),
+        false => output.text,
+    };
+    Ok(Transcript {
+        text,
+        language: output.language,
+        duration: output.duration,
+        segments: output.segments,
+    })
+}
+
+fn parse_srt(contents: &str) -> Result<Transcript> {
+    let mut segments = vec![];
+    for cue in contents.replace("\r\n", "\n").split("\n\n") {
+        let mut lines = cue.lines().skip_while(|line| line.trim().is_empty());
+        let Some(first) = lines.next() else {
+            continue;
+        };
+        // The cue index is optional in practice, so the timing may be on the first line.
+        let timing = match first.contains("-->") {
+            true => first,
+            false => lines
+                .next()
+                .ok_or_else(|| format!("Invalid SRT cue: {}", cue))?,
+        };
+        let (start, end) = timing
+            .split_once("-->")
+            .ok_or_else(|| format!("Invalid SRT timing: {}", timing))?;
+        let text = lines.collect::<Vec<_>>().join(" ");
+        segments.push(plain_segment(
+            parse_srt_timestamp(start)?,
+            parse_srt_timestamp(end)?,
+            format!(" {}", text.trim()),
+        ));
+    }
+    Ok(from_segments(segments, None))
+}
+
+fn parse_srt_timestamp(s: &str) -> Result<f64> {
+    // Strip position settings some tools append after the end time.
+    let s = s.split_whitespace().next().unwrap_or_default();
+    Ok(parse_timestamp(&s.replace(',', "."))?)
+}
+
+fn plain_segment(start: f64, end: f64, text: String) -> Segment {
+    Segment {
+        start,
+        end,
+        text,
+        avg_logprob: None,
+        compression_ratio: None,
+        no_speech_prob: None,
+        speaker: None,
+        words: Vec::new(),
+    }
+}
+
+fn from_segments(segments: Vec<Segment>, language: Option<String>) -> Transcript {
+    Transcript {
+        text: segments.iter().map(|s| s.text.as_str()).collect(),
+        language,
+        duration: segments.last().map(|s| s.end),
+        segments,
+    }
+}
@@ -0,0 +1,57 @@
+pub mod archive;
+pub mod background;
+pub mod batch;
+pub mod bench;
+pub mod burn;
+pub mod cache;
+pub mod clip;
+pub mod describe;
+pub mod digest;
+pub mod direct;
+pub mod download;
+pub mod elasticsearch;
+pub mod events;
+pub mod ffmpeg;
+pub mod hallucinations;
+pub mod history;
+pub mod import;
+pub mod limits;
+pub mod live;
+pub mod llm;
+pub mod local;
+pub mod lock;
+pub mod mirror;
+pub mod models;
+pub mod mqtt;
+pub mod obs;
+pub mod openai;
+pub mod output;
+pub mod p2p;
+pub mod pause;
+pub mod pipeline;
+pub mod plan;
+pub mod probe;
+pub mod provider;
+pub mod queue;
+pub mod readability;
+pub mod report;
+pub mod sections;
+pub mod server;
+pub mod silence;
+pub mod slides;
+pub mod source;
+pub mod sql;
+pub mod tags;
+pub mod telegram;
+#[cfg(feature = "test-support")]
+pub mod testing;
+pub mod throughput;
+pub mod timeline;
+pub mod timings;
+pub mod transcript;
+pub mod vector;
+pub mod voices;
+pub mod ytdlp;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,121 @@
+use crate::download::{DownloadStrategy, CHUNK_SIZE};
+use crate::Result;
+
+const MB: u64 = 1000 * 1000;
+
+/// Below this much memory, audio is downloaded one small chunk at a time rather than all chunks
+/// at once.
+const LOW_MEMORY: u64 = 512 * MB;
+
+/// The chunk size downloads switch to below [`LOW_MEMORY`].
+const SMALL_CHUNK_SIZE: usize = 1024 * 1024; // 1 MB
+
+/// How many copies of the audio track are held in memory at worst: the download, the clip cut
+/// from it, and the body of the request it is sent in.
+const AUDIO_COPIES: f64 = 3.0;
+
+/// The size in bytes of a second of the WAV audio whisper.cpp reads, 16-bit mono at 16 kHz.
+const WAV_BYTES_PER_SECOND: f64 = 32_000.0;
+
+/// Caps on the memory and temporary disk space a transcription may use, for small machines.
+/// Within them, the pipeline falls back to slower strategies that need less of either.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ResourceLimits {
+    pub max_memory: Option<u64>,
+    pub max_temp_disk: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// How audio is downloaded within the memory limit. Chunks in flight are buffered on top of
+    /// the file itself, so a quarter of the limit is spent on them, and on small limits they are
+    /// fetched one at a time.
+    pub fn download_strategy(&self) -> DownloadStrategy {
+        match self.max_memory {
+            None => DownloadStrategy::default(),
+            Some(max) if max < LOW_MEMORY => DownloadStrategy {
+                chunk_size: SMALL_CHUNK_SIZE,
+                max_in_flight: 1,
+            },
+            Some(max) => DownloadStrategy {
+                chunk_size: CHUNK_SIZE,
+                max_in_flight: (max / 4 / CHUNK_SIZE as u64).max(1) as usize,
+            },
+        }
+    }
+
+    /// Fails if an audio track of `size` bytes cannot be transcribed within the limits, before
+    /// it is downloaded. Cutting a clip writes the track and the clip to temporary files.
+    pub fn check_audio(&self, size: f64) -> Result<()> {
+        if let Some(max) = self.max_memory {
+            if size * AUDIO_COPIES > max as f64 {
+                return Err(format!(
+                    "The audio track needs about {} of memory, more than --max-memory {}",
+                    format_size((size * AUDIO_COPIES) as u64),
+                    format_size(max)
+                )
+                .into());
+            }
+        }
+        if let Some(max) = self.max_temp_disk {
+            if size * 2.0 > max as f64 {
+                return Err(format!(
+                    "The audio track needs about {} of temporary disk, more than --max-temp-disk {}",
+                    format_size((size * 2.0) as u64),
+                    format_size(max)
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The length in seconds of the chunks to run a local model on, so that the WAV file of
+    /// each fits in half the temporary disk limit, or `None` if `duration` seconds of audio fit
+    /// in one go. The other half is left for the compressed chunk it is converted from.
+    pub fn local_chunk_length(&self, duration: f64) -> Option<f64> {
+        let max = self.max_temp_disk? as f64 / 2.0;
+        let length = max / WAV_BYTES_PER_SECOND;
+        (length < duration).then_some(length.floor().max(1.0))
+    }
+
+    /// The largest video file, in bytes, to download for burning in subtitles or reading slides.
+    /// yt-dlp writes the video and audio streams before merging them, so half the temporary disk
+    /// limit is left for the merged file.
+    pub fn max_video_size(&self) -> Option<u64> {
+        self.max_temp_disk.map(|max| max / 2)
+    }
+}
+
+/// Parses a size in bytes with an optional decimal unit, e.g. `512M`, `2GB`, or `1.5G`.
+pub fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let multiplier = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1000,
+        "M" => MB,
+        "G" => 1000 * MB,
+        "T" => 1000 * 1000 * MB,
+        _ => {
+            return Err(format!(
+                "Unknown size unit in '{}', expected K, M, G, or T",
+                s
+            ))
+        }
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number > 0.0 => Ok((number * multiplier as f64) as u64),
+        _ => Err(format!("Expected a size like 512M or 2G, got '{}'", s)),
+    }
+}
+
+/// Formats a size in bytes in MB or GB, e.g. `512 MB`, as sizes are given on the command line.
+pub fn format_size(bytes: u64) -> String {
+    match bytes >= 1000 * MB {
+        true => format!("{:.1} GB", bytes as f64 / (1000 * MB) as f64),
+        false => format!("{} MB", bytes / MB),
+    }
+}
@@ -0,0 +1,149 @@
+use crate::ffmpeg;
+use crate::pipeline::{transcribe, SourceOptions, TranscriptionOptions};
+use crate::provider::ProviderPool;
+use crate::transcript::Segment;
+use crate::Result;
+use futures::future::BoxFuture;
+use itertools::Itertools;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use youtube_dl::SingleVideo;
+
+/// A live stream whose audio can be read continuously by ffmpeg.
+#[derive(Clone, Debug)]
+pub struct LiveStream {
+    pub id: String,
+    pub title: String,
+    pub stream_url: String,
+    /// The ffmpeg input format to read `stream_url` with, for devices such as microphones
+    /// rather than URLs.
+    pub input_format: Option<String>,
+}
+
+/// Fetches the metadata of the live stream at `url` and picks its lowest-bitrate format that
+/// carries audio, preferring audio-only formats.
+pub async fn fetch_live_stream(url: &str, options: &SourceOptions) -> Result<LiveStream> {
+    let info = options.ytdlp_info(url, &[], |_| {}).await?;
+    if info.get("_type").is_some_and(|t| t == "playlist") {
+        return Err("URL does not point to a single video".into());
+    }
+    let video: SingleVideo = serde_json::from_value(info)?;
+    if video.is_live != Some(true) {
+        return Err("Video is not a live stream".into());
+    }
+
+    let has = |codec: &Option<String>| codec.as_deref().is_some_and(|c| c != "none");
+    let stream_url = video
+        .formats
+        .ok_or("Missing video formats")?
+        .into_iter()
+        .filter(|f| has(&f.acodec) && f.url.is_some())
+        .sorted_by(|a, b| {
+            let key = |f: &youtube_dl::Format| (has(&f.vcodec), f.tbr.unwrap_or(f64::MAX));
+            let (a, b) = (key(a), key(b));
+            a.0.cmp(&b.0).then(a.1.total_cmp(&b.1))
+        })
+        .next()
+        .and_then(|f| f.url)
+        .ok_or("No suitable audio streams found")?;
+
+    Ok(LiveStream {
+        id: video.id,
+        title: video.title.ok_or("Missing video title")?,
+        stream_url,
+        input_format: None,
+    })
+}
+
+/// A destination that live transcription results are pushed to as they are finalized.
+pub trait Sink: Send {
+    fn write<'a>(&'a mut self, segments: &'a [Segment]) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Records `stream` in chunks of `chunk_length` seconds, transcribing each chunk as soon as it
+/// is complete and writing its segments, timed from the start of the recording, to every sink
+/// in `sinks` in order. Runs until the stream ends or an error occurs.
+pub async fn transcribe_live(
+    pool: &ProviderPool,
+    stream: &LiveStream,
+    options: &TranscriptionOptions,
+    chunk_length: u32,
+    sinks: &mut [Box<dyn Sink>],
+) -> Result<()> {
+    let dir = ffmpeg::temp_path("live");
+    tokio::fs::create_dir_all(&dir).await?;
+    let list = dir.join("chunks.csv");
+
+    // The segment muxer only adds a chunk to the list once it has been fully written, which
+    // makes the list a reliable signal of which chunks are ready to transcribe.
+    let mut ffmpeg = Command::new("ffmpeg");
+    ffmpeg.args(["-hide_banner", "-loglevel", "error"]);
+    if let Some(format) = &stream.input_format {
+        ffmpeg.args(["-f", format]);
+    }
+    let mut ffmpeg = ffmpeg
+        .args(["-i", &stream.stream_url])
+        .args(["-vn", "-ac", "1", "-c:a", "aac", "-b:a", "64k"])
+        .args([
+            "-f",
+            "segment",
+            "-segment_format",
+            "mp4",
+            "-reset_timestamps",
+            "1",
+        ])
+        .args(["-segment_time", &chunk_length.to_string()])
+        .args(["-segment_list_type", "csv", "-segment_list"])
+        .arg(&list)
+        .arg(dir.join("chunk%06d.m4a"))
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+
+    let result = async {
+        let mut transcribed = 0;
+        loop {
+            let status = ffmpeg.try_wait()?;
+            let chunks = read_chunk_list(&list, &dir).await;
+            for (path, start) in chunks.into_iter().skip(transcribed) {
+                let audio = tokio::fs::read(&path).await?;
+                let _ = tokio::fs::remove_file(&path).await;
+                let mut transcript = transcribe(pool, audio, options).await?;
+                transcript.offset(start);
+                for sink in sinks.iter_mut() {
+                    sink.write(&transcript.segments).await?;
+                }
+                transcribed += 1;
+            }
+            match status {
+                Some(status) if !status.success() => {
+                    return Err(format!("ffmpeg exited with {}", status).into())
+                }
+                Some(_) => return Ok(()),
+                None => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+    }
+    .await;
+
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+/// Reads the chunks ffmpeg has finished writing, with their start times in seconds.
+async fn read_chunk_list(list: &Path, dir: &Path) -> Vec<(PathBuf, f64)> {
+    let Ok(csv) = tokio::fs::read_to_string(list).await else {
+        return vec![];
+    };
+    csv.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(',');
+            let file = fields.next()?;
+            let start = fields.next()?.parse().ok()?;
+            Some((dir.join(file), start))
+        })
+        .collect()
+}
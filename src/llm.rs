@@ -0,0 +1,180 @@
+use crate::transcript::Transcript;
+use serde::Serialize;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Estimates how many tokens `text` is for OpenAI's tokenizers from the kinds of characters in
+/// it, erring high rather than low, since `--chunk-for-llm` treats the estimate as a limit.
+/// English words are mostly a token each, while punctuation, runs of indentation, numbers of
+/// more than three digits, and words in other scripts take more, and CJK characters take up to
+/// two each. Exact counts need the model's own tokenizer, so a tenth is added as slack.
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0usize;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        tokens += match c {
+            c if c.is_ascii_alphabetic() => {
+                let len = run(&mut chars, char::is_ascii_alphabetic);
+                1 + len.saturating_sub(6).div_ceil(4)
+            }
+            c if c.is_ascii_digit() => run(&mut chars, char::is_ascii_digit).div_ceil(3),
+            // A single space goes with the word after it, but longer runs, such as the
+            // indentation of code, take tokens of their own.
+            ' ' => (run(&mut chars, |c| *c == ' ') - 1).div_ceil(4),
+            c if is_kana(c) => 1,
+            c if is_cjk(c) => 2,
+            c if c.is_alphabetic() => {
+                let len = run(&mut chars, |c| {
+                    c.is_alphabetic() && !c.is_ascii() && !is_cjk(*c) && !is_kana(*c)
+                });
+                len.div_ceil(2)
+            }
+            _ => 1,
+        };
+    }
+    tokens + tokens.div_ceil(10)
+}
+
+/// The length of the run of characters starting with the one just taken from `chars`, taking
+/// the rest of the run that `f` matches from it too.
+fn run(chars: &mut Peekable<Chars>, f: impl Fn(&char) -> bool) -> usize {
+    let mut len = 1;
+    while chars.next_if(&f).is_some() {
+        len += 1;
+    }
+    len
+}
+
+/// Whether `c` is Japanese hiragana or katakana.
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}')
+}
+
+/// Whether `c` is a Chinese, Japanese, or Korean ideograph or a Hangul syllable.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{ac00}'..='\u{d7af}'
+            | '\u{20000}'..='\u{2ffff}'
+    )
+}
+
+/// A piece of a transcript sized for an LLM's context, with `start` and `end` in seconds if the
+/// transcript has timestamps.
+#[derive(Clone, Debug, Serialize)]
+pub struct LlmChunk {
+    pub index: usize,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub tokens: usize,
+    #[serde(skip)]
+    pub text: String,
+}
+
+/// A run of text that is kept whole where possible: a segment, or a sentence of transcripts
+/// without segments.
+struct Unit {
+    start: Option<f64>,
+    end: Option<f64>,
+    text: String,
+    tokens: usize,
+}
+
+/// Splits `transcript` into chunks of at most about `max_tokens` tokens, each repeating the last
+/// `overlap` tokens or so of the previous chunk, so that passages cut at a chunk boundary are
+/// still whole in one of them. Chunks break between segments, or between words of segments that
+/// are too long to fit on their own.
+pub fn chunk_for_llm(transcript: &Transcript, max_tokens: usize, overlap: usize) -> Vec<LlmChunk> {
+    let overlap = overlap.min(max_tokens / 2);
+    let units = units(transcript, max_tokens);
+
+    let mut chunks = vec![];
+    let mut first = 0;
+    while first < units.len() {
+        let mut last = first;
+        let mut tokens = units[first].tokens;
+        while last + 1 < units.len() && tokens + units[last + 1].tokens <= max_tokens {
+            last += 1;
+            tokens += units[last].tokens;
+        }
+        let slice = &units[first..=last];
+        chunks.push(LlmChunk {
+            index: chunks.len() + 1,
+            start: slice.first().and_then(|unit| unit.start),
+            end: slice.last().and_then(|unit| unit.end),
+            tokens,
+            text: slice
+                .iter()
+                .map(|unit| unit.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        });
+        if last + 1 == units.len() {
+            break;
+        }
+
+        // Start the next chunk early enough to repeat up to `overlap` tokens, while always
+        // making progress.
+        let mut next = last + 1;
+        let mut repeated = 0;
+        while next > first + 1 && repeated + units[next - 1].tokens <= overlap {
+            next -= 1;
+            repeated += units[next].tokens;
+        }
+        first = next;
+    }
+    chunks
+}
+
+fn units(transcript: &Transcript, max_tokens: usize) -> Vec<Unit> {
+    let units: Vec<(Option<f64>, Option<f64>, String)> = match transcript.segments.is_empty() {
+        false => transcript
+            .segments
+            .iter()
+            .map(|s| (Some(s.start), Some(s.end), s.text.trim().to_string()))
+            .collect(),
+        true => transcript
+            .text
+            .split_inclusive(['.', '!', '?'])
+            .map(|sentence| (None, None, sentence.trim().to_string()))
+            .collect(),
+    };
+
+    let mut split = vec![];
+    for (start, end, text) in units.into_iter().filter(|(_, _, text)| !text.is_empty()) {
+        let tokens = estimate_tokens(&text);
+        if tokens <= max_tokens {
+            split.push(Unit {
+                start,
+                end,
+                text,
+                tokens,
+            });
+            continue;
+        }
+        let mut piece = String::new();
+        for word in text.split_whitespace() {
+            if !piece.is_empty() && estimate_tokens(&piece) + estimate_tokens(word) >= max_tokens {
+                split.push(Unit {
+                    start,
+                    end,
+                    tokens: estimate_tokens(&piece),
+                    text: std::mem::take(&mut piece),
+                });
+            }
+            if !piece.is_empty() {
+                piece.push(' ');
+            }
+            piece.push_str(word);
+        }
+        split.push(Unit {
+            start,
+            end,
+            tokens: estimate_tokens(&piece),
+            text: piece,
+        });
+    }
+    split
+}
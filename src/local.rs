@@ -0,0 +1,504 @@
+use crate::clip::Clip;
+use crate::ffmpeg::{temp_path, transform};
+use crate::import::parse_transcript;
+use crate::output::Rerun;
+use crate::pipeline::{transcribe, TranscriptionOptions};
+use crate::provider::ProviderPool;
+use crate::transcript::Transcript;
+use crate::Result;
+use clap::ValueEnum;
+use reqwest::multipart::{Form, Part};
+use reqwest::Client;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+
+/// The number of threads whisper.cpp decodes with in deterministic mode. The order floating
+/// point sums are reduced in depends on how the work is split between threads, so it is pinned
+/// rather than left to the number of cores.
+const DETERMINISTIC_THREADS: u32 = 4;
+
+/// The length in seconds of the chunks whose confidence decides whether they are escalated.
+pub const ESCALATION_CHUNK_LENGTH: f64 = 30.0;
+
+/// What whisper.cpp runs the model on. GPU backends are compiled into whisper.cpp, so `cuda` and
+/// `metal` only take effect if `whisper-cli` was built with them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Device {
+    /// The best accelerator detected on this machine.
+    #[default]
+    Auto,
+    Cpu,
+    Cuda,
+    Metal,
+}
+
+impl std::fmt::Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Device::Auto => "auto",
+            Device::Cpu => "CPU",
+            Device::Cuda => "CUDA",
+            Device::Metal => "Metal",
+        })
+    }
+}
+
+/// The accelerators and CPU features of this machine that whisper.cpp can use.
+#[derive(Clone, Debug)]
+pub struct Hardware {
+    /// Whether an NVIDIA GPU is present, going by its driver.
+    pub cuda: bool,
+    /// Whether this is an Apple Silicon Mac, whose GPU whisper.cpp runs on with Metal.
+    pub metal: bool,
+    pub avx512: bool,
+    pub avx2: bool,
+    /// The number of threads the CPU can run at once.
+    pub threads: usize,
+}
+
+impl Hardware {
+    pub fn detect() -> Self {
+        let cuda = Path::new("/dev/nvidia0").exists()
+            || std::process::Command::new("nvidia-smi")
+                .arg("-L")
+                .output()
+                .is_ok_and(|output| output.status.success() && !output.stdout.is_empty());
+        #[cfg(target_arch = "x86_64")]
+        let (avx512, avx2) = (
+            std::arch::is_x86_feature_detected!("avx512f"),
+            std::arch::is_x86_feature_detected!("avx2"),
+        );
+        #[cfg(not(target_arch = "x86_64"))]
+        let (avx512, avx2) = (false, false);
+        Hardware {
+            cuda,
+            metal: cfg!(all(target_os = "macos", target_arch = "aarch64")),
+            avx512,
+            avx2,
+            threads: std::thread::available_parallelism().map_or(4, |threads| threads.get()),
+        }
+    }
+
+    /// The device to run on when none is chosen: the GPU if there is one, else the CPU.
+    pub fn best_device(&self) -> Device {
+        match (self.cuda, self.metal) {
+            (true, _) => Device::Cuda,
+            (_, true) => Device::Metal,
+            _ => Device::Cpu,
+        }
+    }
+
+    /// What was detected, e.g. `CUDA, AVX-512, 16 threads`.
+    pub fn summary(&self) -> String {
+        let mut features = vec![];
+        if self.cuda {
+            features.push("CUDA".to_string());
+        }
+        if self.metal {
+            features.push("Metal".to_string());
+        }
+        match (self.avx512, self.avx2) {
+            (true, _) => features.push("AVX-512".to_string()),
+            (_, true) => features.push("AVX2".to_string()),
+            _ => {}
+        }
+        features.push(match self.threads {
+            1 => "1 thread".to_string(),
+            threads => format!("{} threads", threads),
+        });
+        features.join(", ")
+    }
+}
+
+/// How whisper.cpp decodes, beyond the options shared with the API.
+#[derive(Clone, Debug, Default)]
+pub struct Decoding {
+    /// The number of beams searched at temperature 0, 5 by default.
+    pub beam_size: Option<u32>,
+    /// The number of candidates sampled at higher temperatures, 5 by default.
+    pub best_of: Option<u32>,
+    /// Whether to decode so that repeated runs on the same audio yield identical output: greedy
+    /// or beam search at temperature 0 with no fallback, a fixed number of threads, and the CPU
+    /// only, since GPU kernels are not bitwise reproducible.
+    pub deterministic: bool,
+}
+
+/// A Whisper model in the ggml format run locally with whisper.cpp's `whisper-cli`, found on
+/// the `PATH` or at `WHISPER_CPP_BIN`.
+#[derive(Clone, Debug)]
+pub struct LocalModel {
+    pub binary: PathBuf,
+    pub model: PathBuf,
+    pub decoding: Decoding,
+    /// The device to run on, which is never [`Device::Auto`].
+    pub device: Device,
+    /// The number of threads to decode with on the CPU.
+    pub threads: usize,
+}
+
+impl LocalModel {
+    /// A model run on `device`, or on the best device of `hardware` for [`Device::Auto`].
+    /// Deterministic decoding always runs on the CPU.
+    pub fn new(
+        model: PathBuf,
+        decoding: Decoding,
+        device: Device,
+        hardware: &Hardware,
+    ) -> Result<Self> {
+        if !model.is_file() {
+            return Err(format!("Model file not found: {}", model.display()).into());
+        }
+        let binary = std::env::var_os("WHISPER_CPP_BIN").map_or("whisper-cli".into(), Into::into);
+        let device = match (decoding.deterministic, device) {
+            (true, _) => Device::Cpu,
+            (false, Device::Auto) => hardware.best_device(),
+            (false, device) => device,
+        };
+        Ok(LocalModel {
+            binary,
+            model,
+            decoding,
+            device,
+            threads: hardware.threads,
+        })
+    }
+
+    /// The model as it is recorded in the cache, e.g. `local:ggml-base.en`, with the decoding
+    /// settings that change its output.
+    pub fn cache_name(&self) -> String {
+        let stem = self.model.file_stem().unwrap_or_default().to_string_lossy();
+        let mut name = format!("local:{}", stem);
+        if let Some(beam_size) = self.decoding.beam_size {
+            name.push_str(&format!(":beam-size={}", beam_size));
+        }
+        if let Some(best_of) = self.decoding.best_of {
+            name.push_str(&format!(":best-of={}", best_of));
+        }
+        if self.decoding.deterministic {
+            name.push_str(":deterministic");
+        }
+        name
+    }
+
+    /// Transcribes an m4a audio track, or translates it into English if `translate` is set.
+    pub async fn transcribe(
+        &self,
+        audio: &[u8],
+        options: &TranscriptionOptions,
+        translate: bool,
+    ) -> Result<Transcript> {
+        let wav = to_wav(audio, options).await?;
+        let wav_path = temp_path("wav");
+        tokio::fs::write(&wav_path, wav).await?;
+        let output_path = wav_path.with_extension("");
+        let result = self.run(&wav_path, &output_path, options, translate).await;
+        let _ = tokio::fs::remove_file(&wav_path).await;
+        let _ = tokio::fs::remove_file(output_path.with_extension("json")).await;
+        result
+    }
+
+    /// Like [`LocalModel::transcribe`], but runs the model on each of `chunks` of the audio in
+    /// turn and joins their transcripts, which keeps the WAV files it reads small.
+    pub async fn transcribe_in_chunks(
+        &self,
+        audio: &[u8],
+        options: &TranscriptionOptions,
+        translate: bool,
+        chunks: &[Clip],
+    ) -> Result<Transcript> {
+        let mut transcript: Option<Transcript> = None;
+        for chunk in chunks {
+            let mut part = match chunk.is_empty() {
+                true => self.transcribe(audio, options, translate).await?,
+                false => {
+                    let clipped = chunk.cut(audio).await?;
+                    self.transcribe(&clipped, options, translate).await?
+                }
+            };
+            part.offset(chunk.offset());
+            match &mut transcript {
+                Some(transcript) => transcript.splice(chunk.offset(), f64::INFINITY, part),
+                None => transcript = Some(part),
+            }
+        }
+        transcript.ok_or_else(|| "No audio to transcribe".into())
+    }
+
+    /// The arguments to `whisper-cli` or `whisper-server` that set up decoding and the device.
+    fn decoding_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(beam_size) = self.decoding.beam_size {
+            args.extend(["--beam-size".to_string(), beam_size.to_string()]);
+        }
+        if let Some(best_of) = self.decoding.best_of {
+            args.extend(["--best-of".to_string(), best_of.to_string()]);
+        }
+        // whisper.cpp defaults to at most 4 threads, which leaves most of a larger CPU idle.
+        let threads = match (self.decoding.deterministic, self.device) {
+            (true, _) => {
+                args.extend(["--temperature", "0", "--no-fallback"].map(str::to_string));
+                Some(DETERMINISTIC_THREADS as usize)
+            }
+            (false, Device::Cpu) => Some(self.threads),
+            (false, Device::Auto | Device::Cuda | Device::Metal) => None,
+        };
+        if let Some(threads) = threads {
+            args.extend(["--no-gpu".to_string(), "--threads".to_string()]);
+            args.push(threads.to_string());
+        }
+        args
+    }
+
+    async fn run(
+        &self,
+        wav_path: &Path,
+        output_path: &Path,
+        options: &TranscriptionOptions,
+        translate: bool,
+    ) -> Result<Transcript> {
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("--model")
+            .arg(&self.model)
+            .arg("--file")
+            .arg(wav_path)
+            .args(["--output-json-full", "--no-prints"])
+            .arg("--output-file")
+            .arg(output_path)
+            .args(["--language", options.language.as_deref().unwrap_or("auto")]);
+        if let Some(prompt) = &options.prompt {
+            command.args(["--prompt", prompt]);
+        }
+        if translate {
+            command.arg("--translate");
+        }
+        if !self.decoding.deterministic {
+            command.args(["--temperature", &options.temperature.to_string()]);
+        }
+        command.args(self.decoding_args());
+
+        let output = command
+            .output()
+            .await
+            .map_err(|err| format!("Failed to run {}: {}", self.binary.display(), err))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed: {}",
+                self.binary.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let json = tokio::fs::read_to_string(output_path.with_extension("json")).await?;
+        parse_transcript(&json)
+    }
+}
+
+/// Converts an m4a audio track to the 16 kHz WAV that whisper.cpp reads.
+async fn to_wav(audio: &[u8], options: &TranscriptionOptions) -> Result<Vec<u8>> {
+    if options.word_timestamps {
+        return Err("Word timestamps are not supported with a local model".into());
+    }
+    transform(
+        audio,
+        &[],
+        &["-ar", "16000", "-ac", "1", "-c:a", "pcm_s16le"],
+        "wav",
+    )
+    .await
+}
+
+/// How long to wait for `whisper-server` to load a model, which takes a while for large models
+/// on slow disks.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How many ports to try `whisper-server` on before giving up, if other processes keep taking
+/// the free port picked for it before it listens on it.
+const PORT_ATTEMPTS: usize = 3;
+
+/// A local model kept loaded in memory between transcriptions by whisper.cpp's `whisper-server`,
+/// found on the `PATH` or at `WHISPER_SERVER_BIN`, which saves loading the model for every job.
+/// The server is started on first use, and again if it exits.
+pub struct WarmModel {
+    model: LocalModel,
+    server: tokio::sync::Mutex<Option<WarmServer>>,
+    client: Client,
+}
+
+struct WarmServer {
+    /// Killed when the model is dropped.
+    child: Child,
+    url: String,
+}
+
+impl WarmModel {
+    pub fn new(model: LocalModel) -> Self {
+        WarmModel {
+            model,
+            server: tokio::sync::Mutex::new(None),
+            client: Client::new(),
+        }
+    }
+
+    pub fn model(&self) -> &LocalModel {
+        &self.model
+    }
+
+    /// Starts `whisper-server` and waits for it to load the model, unless it is already running,
+    /// returning how long loading took, or `None` if the model was already loaded.
+    pub async fn warm_up(&self) -> Result<Option<Duration>> {
+        let mut server = self.server.lock().await;
+        self.ensure_started(&mut server).await
+    }
+
+    async fn ensure_started(&self, server: &mut Option<WarmServer>) -> Result<Option<Duration>> {
+        if let Some(running) = server {
+            if running.child.try_wait()?.is_none() {
+                return Ok(None);
+            }
+        }
+        *server = None;
+
+        let started = Instant::now();
+        let binary =
+            std::env::var_os("WHISPER_SERVER_BIN").map_or("whisper-server".into(), PathBuf::from);
+        for _ in 0..PORT_ATTEMPTS {
+            if let Some(running) = self.start(&binary, started).await? {
+                *server = Some(running);
+                return Ok(Some(started.elapsed()));
+            }
+        }
+        Err(format!("Could not find a free port for {}", binary.display()).into())
+    }
+
+    /// Starts `binary` on a free port and waits for it to load the model, returning `None` if
+    /// another process took the port before it could listen on it.
+    async fn start(&self, binary: &Path, started: Instant) -> Result<Option<WarmServer>> {
+        // whisper-server only takes a port number, so a free one is found by binding to any, and
+        // the server is started again on another if something else takes it in the meantime.
+        let port = std::net::TcpListener::bind(("127.0.0.1", 0))?
+            .local_addr()?
+            .port();
+        let mut child = Command::new(binary)
+            .arg("--model")
+            .arg(&self.model.model)
+            .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+            .args(self.model.decoding_args())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| format!("Failed to run {}: {}", binary.display(), err))?;
+        let url = format!("http://127.0.0.1:{}", port);
+        while self.client.get(&url).send().await.is_err() {
+            if let Some(status) = child.try_wait()? {
+                if std::net::TcpListener::bind(("127.0.0.1", port)).is_err() {
+                    return Ok(None);
+                }
+                return Err(format!(
+                    "{} exited while loading the model: {}",
+                    binary.display(),
+                    status
+                )
+                .into());
+            }
+            if started.elapsed() > LOAD_TIMEOUT {
+                return Err(format!(
+                    "Timed out waiting for {} to load the model",
+                    binary.display()
+                )
+                .into());
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Ok(Some(WarmServer { child, url }))
+    }
+
+    /// Transcribes an m4a audio track with the loaded model, or translates it into English if
+    /// `translate` is set, loading the model first if needed.
+    pub async fn transcribe(
+        &self,
+        audio: &[u8],
+        options: &TranscriptionOptions,
+        translate: bool,
+    ) -> Result<Transcript> {
+        let wav = to_wav(audio, options).await?;
+        let url = {
+            let mut server = self.server.lock().await;
+            self.ensure_started(&mut server).await?;
+            server
+                .as_ref()
+                .map(|server| server.url.clone())
+                .unwrap_or_default()
+        };
+        let temperature = match self.model.decoding.deterministic {
+            true => 0.0,
+            false => options.temperature,
+        };
+        let mut form = Form::new()
+            .part("file", Part::bytes(wav).file_name("audio.wav"))
+            .text("response_format", "verbose_json")
+            .text("temperature", temperature.to_string())
+            .text(
+                "language",
+                options.language.clone().unwrap_or("auto".to_string()),
+            )
+            .text("translate", translate.to_string());
+        if self.model.decoding.deterministic {
+            form = form.text("temperature_inc", "0");
+        }
+        if let Some(prompt) = &options.prompt {
+            form = form.text("prompt", prompt.clone());
+        }
+        let json = self
+            .client
+            .post(format!("{}/inference", url))
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        parse_transcript(&json)
+    }
+}
+
+/// Re-transcribes the chunks of about `chunk_length` seconds of a local `transcript` whose
+/// confidence is below `threshold` with the API, splicing the results in, so that only the audio
+/// the local model struggled with is paid for. `audio` is the audio the transcript was made
+/// from, starting at `offset` seconds into the video. Chunks of unknown confidence are kept.
+/// Returns the chunks that were escalated, along with how many chunks there were.
+pub async fn escalate_chunks(
+    transcript: &mut Transcript,
+    audio: &[u8],
+    offset: f64,
+    pool: &ProviderPool,
+    options: &TranscriptionOptions,
+    threshold: f64,
+    chunk_length: f64,
+) -> Result<(Vec<Rerun>, usize)> {
+    let chunks = transcript.chunks(chunk_length);
+    let mut escalated = vec![];
+    for chunk in &chunks {
+        if !chunk
+            .confidence()
+            .is_some_and(|confidence| confidence < threshold)
+        {
+            continue;
+        }
+        let clip = Clip::new(Some(chunk.start - offset), Some(chunk.end - offset))?;
+        let audio = clip.cut(audio).await?;
+        let mut replacement = transcribe(pool, audio, options).await?;
+        replacement.offset(chunk.start);
+        transcript.splice(chunk.start, chunk.end, replacement);
+        escalated.push(Rerun {
+            start: chunk.start,
+            end: chunk.end,
+            model: options.model,
+            prompt: options.prompt.clone(),
+        });
+    }
+    Ok((escalated, chunks.len()))
+}
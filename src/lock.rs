@@ -0,0 +1,153 @@
+use crate::Result;
+use std::ffi::OsString;
+use std::fs::{File, TryLockError};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often to check whether another run has released a lock that is being waited for.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A lock on an output file or directory, held on `<path>.lock` next to it for as long as this
+/// value lives, so that two runs writing the same output do not interleave their writes. The
+/// operating system releases the lock when the run holding it exits, even if it crashed, and
+/// the lock file holds the ID of that run's process, for saying who is being waited for.
+pub struct FileLock {
+    path: PathBuf,
+    /// The open lock file, which the lock is held on.
+    file: File,
+}
+
+impl FileLock {
+    /// Locks `target`. If another run holds the lock, this waits for it to be released if
+    /// `wait` is set, calling `on_wait` once with that run's process ID first, and fails
+    /// otherwise.
+    pub async fn acquire(target: &Path, wait: bool, on_wait: impl FnOnce(u32)) -> Result<Self> {
+        let mut name = target
+            .file_name()
+            .map_or_else(|| OsString::from("output"), |name| name.to_os_string());
+        name.push(".lock");
+        let path = target.with_file_name(name);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut on_wait = Some(on_wait);
+        loop {
+            let mut file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+            match file.try_lock() {
+                // The run that held the lock deletes the file as it releases it, so if that
+                // happened after it was opened here, it is opened again to lock the new one.
+                Ok(()) if !is_current(&file, &path) => continue,
+                Ok(()) => {
+                    file.set_len(0)?;
+                    write!(file, "{}", std::process::id())?;
+                    return Ok(FileLock { path, file });
+                }
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+            }
+
+            // The run holding the lock may not have written its ID yet.
+            let mut holder = String::new();
+            let holder = file
+                .read_to_string(&mut holder)
+                .ok()
+                .and_then(|_| holder.trim().parse::<u32>().ok());
+            if !wait {
+                let holder = holder.map_or(String::new(), |pid| format!(" (process {})", pid));
+                return Err(format!(
+                    "{} is being written by another run{}, pass --wait-for-lock to wait for it",
+                    target.display(),
+                    holder
+                )
+                .into());
+            }
+            if let Some(pid) = holder {
+                if let Some(on_wait) = on_wait.take() {
+                    on_wait(pid);
+                }
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // The file is deleted before it is unlocked, so that no other run can lock it in
+        // between and then lose it.
+        let _ = std::fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Whether `file` is still the file at `path`, rather than one deleted since it was opened.
+#[cfg(unix)]
+fn is_current(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Whether `file` is still the file at `path`. Elsewhere files cannot be deleted while another
+/// process has them open, so it always is if it is there.
+#[cfg(not(unix))]
+fn is_current(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use std::fs;
+
+    #[tokio::test]
+    async fn takes_over_a_lock_left_behind_by_a_run_that_exited() {
+        let dir = temp_dir("lock").unwrap();
+        let target = dir.join("video");
+        // A run that crashed leaves its lock file behind, but no longer holds the lock on it.
+        fs::write(dir.join("video.lock"), "4294967295").unwrap();
+
+        let lock = FileLock::acquire(&target, false, |_| {}).await.unwrap();
+        let holder = fs::read_to_string(dir.join("video.lock")).unwrap();
+        assert_eq!(holder, std::process::id().to_string());
+        drop(lock);
+        assert!(!dir.join("video.lock").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_or_waits_while_another_run_holds_the_lock() {
+        let dir = temp_dir("lock").unwrap();
+        let target = dir.join("video");
+        let lock = FileLock::acquire(&target, false, |_| {}).await.unwrap();
+
+        let err = FileLock::acquire(&target, false, |_| {})
+            .await
+            .err()
+            .unwrap();
+        let pid = std::process::id().to_string();
+        assert!(err.to_string().contains(&pid), "{}", err);
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(lock);
+        });
+        let mut waited_for = None;
+        let lock = FileLock::acquire(&target, true, |pid| waited_for = Some(pid))
+            .await
+            .unwrap();
+        release.await.unwrap();
+        assert_eq!(waited_for, Some(std::process::id()));
+        drop(lock);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
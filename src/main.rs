@@ -1,164 +1,3172 @@
-use clap::Parser;
+use clap::{builder::ArgPredicate, Args, Parser, Subcommand};
 use dotenvy::dotenv;
 use expanduser::expanduser;
-use itertools::Itertools;
+use futures::future::BoxFuture;
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    env::var,
+    fs::File,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+mod browse;
+mod preflight;
+mod review;
+mod speakers;
+
+use browse::browse;
+use preflight::{pick_archive_file, Preflight};
 use reqwest::Client;
-use rs_openai::{
-    audio::{AudioModel, CreateTranscriptionRequestBuilder, Language, ResponseFormat},
-    shared::types::FileMeta,
-    OpenAI,
+use review::review;
+use sha2::{Digest, Sha256};
+use speakers::{name_speakers, parse_names};
+use transcribe::{
+    background::{background_downloads, lower_priority, wait_for_mains},
+    batch::{BatchItem, BatchReport, ItemStatus},
+    bench::{
+        measure_cut, measure_download, measure_provider, synthesize_audio, DEFAULT_DOWNLOAD_URL,
+    },
+    burn::{burn_subtitles, download_video, fetch_video_file},
+    cache::{Cache, TranscriptKey},
+    clip::{format_timestamp, parse_duration, parse_timestamp, Clip},
+    describe::{chapters, draft_script, find_gaps, DEFAULT_MIN_GAP},
+    digest::{collect_entries, render_rss, render_text, send_email},
+    download::{format_progress, DownloadStrategy},
+    elasticsearch::Elasticsearch,
+    events::EventPublisher,
+    ffmpeg,
+    hallucinations::{Blocklist, DEFAULT_HALLUCINATION_THRESHOLD},
+    history::{diff, format_time, versions},
+    import::parse_transcript,
+    limits::{parse_size, ResourceLimits},
+    live::{transcribe_live, Sink},
+    llm::{chunk_for_llm, estimate_tokens},
+    local::{
+        escalate_chunks, Decoding, Device, Hardware, LocalModel, WarmModel, ESCALATION_CHUNK_LENGTH,
+    },
+    lock::FileLock,
+    mirror::{is_archive_url, open_archive},
+    models::{ModelStore, MODELS},
+    mqtt::MqttPublisher,
+    obs::{CaptionFile, ObsWebSocket},
+    output::{sign, Manifest, Metadata, OutputDir, SegmentAppender},
+    pause,
+    pipeline::{
+        check_audio_size, check_temperature, download_audio, fetch_playlist, slug, transcribe,
+        transcribe_with_fallback, translate, Model, SourceOptions, TranscriptionOptions, Video,
+        LYRICS_PROMPT, LYRICS_TEMPERATURES,
+    },
+    plan::Preference,
+    probe::probe_audio,
+    provider::ProviderPool,
+    queue::{DiskQueue, ItemState},
+    readability::readability,
+    report::confidence_report,
+    sections::{
+        combine_chapters, detect_scene_changes, propose_sections, render_sections, Boundaries,
+        Section, DEFAULT_MIN_SECTION,
+    },
+    server::{serve, Backend, Retention, ServeOptions},
+    silence::{strip_silences, DEFAULT_MIN_SILENCE},
+    slides::{extract_slides, lecture_notes, DEFAULT_SLIDE_INTERVAL},
+    source::Source,
+    sql::SqlDatabase,
+    tags::embed_lyrics,
+    telegram::TelegramBot,
+    throughput::ThroughputHistory,
+    timeline::Timeline,
+    timings::{Stage, Timings},
+    transcript::{
+        Bilingual, Color, Format, LowConfidence, Segment, SegmentFilter, SegmentMerge,
+        SubtitleStyle, Transcript,
+    },
+    vector::VectorStore,
+    voices::{
+        known_voices, match_voices, parse_embeddings, write_voices, Voice, DEFAULT_VOICE_THRESHOLD,
+    },
+    ytdlp::Retries,
 };
-use std::sync::Arc;
-use std::{env::var, fs::File, io::Write, process::exit};
-use tokio::sync::Mutex;
-use youtube_dl::YoutubeDl;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// The URL of the YouTube video to transcribe
-    #[arg(name = "URL")]
-    url: String,
-    /// The OpenAI API key to use for the Whisper V2 model
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    transcribe: TranscribeArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serve a REST API that queues and runs transcription jobs
+    Serve(ServeArgs),
+    /// Import a transcript made by another tool (Whisper or whisper.cpp JSON, or SRT)
+    Import(ImportArgs),
+    /// Transcribe every video of a channel or playlist, keeping track of progress on disk
+    Backfill(BackfillArgs),
+    /// List the videos recently transcribed into an output directory, as text, email, or RSS
+    Digest(DigestArgs),
+    /// Run a Telegram bot that replies to voice notes, videos, and video links with transcripts
+    Bot(BotArgs),
+    /// Render a copy of a video with its subtitles burned in, transcribing it first if needed
+    Burn(BurnArgs),
+    /// Regenerate the transcripts in an output directory whose format has changed, or in new formats
+    ReExport(ReExportArgs),
+    /// List the versions of a video's transcript in an output directory, and what changed between them
+    History(HistoryArgs),
+    /// Search, preview, tag, export, and open the videos of an output directory in the terminal
+    Browse(BrowseArgs),
+    /// Create or update the schema of a database that transcripts are stored in with --sql
+    Migrate(MigrateArgs),
+    /// List, download, or remove the whisper.cpp models used with --local-model
+    Models(ModelsArgs),
+    /// Measure download throughput, audio processing speed, and provider latency on this machine
+    Bench(BenchArgs),
+}
+
+#[derive(Args, Debug)]
+struct TranscribeArgs {
+    /// The URL of the YouTube video to transcribe, the path to a local audio or video file, `-`
+    /// to read one from stdin, or `mic` or `mic:<device>` to record from a microphone
+    #[arg(name = "URL", required = true)]
+    url: Option<String>,
+    /// The OpenAI API key to use
     #[arg(short = 'k', long = "api-key")]
     api_key: Option<String>,
+    /// A JSON file of OpenAI-compatible providers to spread requests across, instead of OpenAI
+    #[arg(long = "providers", conflicts_with = "api_key")]
+    providers: Option<String>,
     /// The path to the output file
     #[arg(short = 'o', long = "output")]
     output_path: Option<String>,
+    /// The ISO-639-1 code of the spoken language, detected automatically if omitted
+    #[arg(short = 'l', long = "language")]
+    language: Option<String>,
+    /// The transcription model, only whisper-1 supports timestamps
+    #[arg(short = 'm', long = "model", default_value = "whisper-1")]
+    model: Model,
+    /// Transcribe locally with whisper.cpp instead of the API, with this ggml model file or downloaded model
+    #[arg(long = "local-model", conflicts_with_all = ["plan", "live", "review", "vector_store", "karaoke", "lyrics"])]
+    local_model: Option<String>,
+    /// Re-transcribe chunks the local model is less confident than this about with the API, between 0 and 1 (e.g. 0.6)
+    #[arg(long = "escalate-below", requires = "local_model", value_parser = parse_confidence)]
+    escalate_below: Option<f64>,
+    /// What to run the local model on, detected automatically by default
+    #[arg(long = "device", value_enum, default_value_t = Device::Auto, requires = "local_model")]
+    device: Device,
+    /// The number of beams whisper.cpp searches, 5 by default
+    #[arg(long = "beam-size", requires = "local_model")]
+    beam_size: Option<u32>,
+    /// The number of candidates whisper.cpp samples when falling back to higher temperatures
+    #[arg(long = "best-of", requires = "local_model")]
+    best_of: Option<u32>,
+    /// Decode locally so that repeated runs on the same audio yield identical output
+    #[arg(
+        long = "deterministic",
+        requires = "local_model",
+        conflicts_with_all = ["temperature", "device"]
+    )]
+    deterministic: bool,
+    /// Choose the model automatically once the audio is available, optimizing for this
+    #[arg(long = "plan", value_enum, conflicts_with = "model")]
+    plan: Option<Preference>,
+    /// The sampling temperature between 0 and 1, higher is more random
+    #[arg(short = 't', long = "temperature", default_value_t = 0.0, value_parser = parse_temperature)]
+    temperature: f32,
+    /// Text to steer the model with, e.g. the spelling of names and jargon in the video
+    #[arg(long = "prompt")]
+    prompt: Option<String>,
+    /// The format of the transcript
+    #[arg(
+        short = 'f',
+        long = "format",
+        default_value = "text",
+        default_value_if("lyrics", ArgPredicate::IsPresent, "lrc")
+    )]
+    format: Format,
+    /// WebVTT cue settings added to every cue, e.g. "line:85% align:center"
+    #[arg(long = "vtt-settings")]
+    vtt_settings: Option<String>,
+    /// The font of ASS subtitles, Arial by default
+    #[arg(long = "font")]
+    font: Option<String>,
+    /// The font size of ASS subtitles, for a 1080p video, 64 by default
+    #[arg(long = "font-size")]
+    font_size: Option<u32>,
+    /// The color of each speaker's subtitles as #RRGGBB, in order of appearance (repeatable)
+    #[arg(long = "speaker-color")]
+    speaker_colors: Vec<Color>,
+    /// Highlight each word of ASS subtitles as it is spoken, using word timestamps
+    #[arg(long = "karaoke")]
+    karaoke: bool,
+    /// The directory to write the transcript and metadata sidecar to, under a subdirectory per video
+    #[arg(short = 'd', long = "output-dir")]
+    output_dir: Option<String>,
+    /// Also download the video thumbnail into the output directory
+    #[arg(long = "thumbnail", requires = "output_dir")]
+    thumbnail: bool,
+    /// Also save the full video description into the output directory
+    #[arg(long = "description", requires = "output_dir")]
+    description: bool,
+    /// Only transcribe from this timestamp onwards, as [[HH:]MM:]SS
+    #[arg(long = "start", value_parser = parse_timestamp, conflicts_with = "clip")]
+    start: Option<f64>,
+    /// Only transcribe up to this timestamp, as [[HH:]MM:]SS
+    #[arg(long = "end", value_parser = parse_timestamp, conflicts_with = "clip")]
+    end: Option<f64>,
+    /// Only transcribe this time range, as START-END, e.g. 12:00-25:00
+    #[arg(long = "clip")]
+    clip: Option<Clip>,
+    /// Also translate the audio into English, pairing the translation with the transcript
+    #[arg(long = "bilingual")]
+    bilingual: bool,
+    /// Also write an HTML report shading each word by confidence into the output directory
+    #[arg(long = "confidence-report", requires = "output_dir")]
+    confidence_report: bool,
+    /// Print an estimate of the transcript's length in LLM tokens
+    #[arg(long = "token-count")]
+    token_count: bool,
+    /// Print how long each stage of the job took, which -d also records in metadata.json
+    #[arg(long = "timings", conflicts_with = "live")]
+    timings: bool,
+    /// Also write the transcript split into chunks of at most this many tokens, for LLM ingestion
+    #[arg(long = "chunk-for-llm", requires = "output_dir")]
+    chunk_for_llm: Option<usize>,
+    /// How many tokens each LLM chunk repeats from the end of the previous one
+    #[arg(
+        long = "chunk-overlap",
+        default_value_t = 200,
+        requires = "chunk_for_llm"
+    )]
+    chunk_overlap: usize,
+    /// Also write a reading level report, for deciding whether a plain-language summary is needed
+    #[arg(long = "readability-report")]
+    readability_report: bool,
+    /// Also draft an audio description script, with the gaps in dialogue to describe, into the output directory
+    #[arg(long = "audio-description", requires = "output_dir")]
+    audio_description: bool,
+    /// Also OCR the slides shown in the video and write lecture notes interleaving them with the transcript
+    #[arg(long = "slides", requires = "output_dir")]
+    slides: bool,
+    /// How often, in seconds, frames are sampled to look for slides
+    #[arg(long = "slide-interval", default_value_t = DEFAULT_SLIDE_INTERVAL, requires = "slides")]
+    slide_interval: f64,
+    /// Also propose sections for videos without chapters, at scene changes or long pauses
+    #[arg(long = "sections", requires = "output_dir")]
+    sections: bool,
+    /// The shortest section to propose, e.g. 2:00
+    #[arg(long = "min-section", value_parser = parse_timestamp, default_value_t = DEFAULT_MIN_SECTION, requires = "sections")]
+    min_section: f64,
+    /// The shortest gap in dialogue, in seconds, listed in the audio description draft
+    #[arg(long = "min-gap", default_value_t = DEFAULT_MIN_GAP, requires = "audio_description")]
+    min_gap: f64,
+    /// Drop segments more likely than this to contain no speech, between 0 and 1 (e.g. 0.6)
+    #[arg(long = "no-speech-threshold")]
+    no_speech_threshold: Option<f64>,
+    /// Drop segments whose text compression ratio exceeds this, catching repetition loops (e.g. 2.4)
+    #[arg(long = "compression-ratio-threshold")]
+    compression_ratio_threshold: Option<f64>,
+    /// A file of phrases Whisper makes up in silence to strip, instead of hallucinations.txt in the configuration directory
+    #[arg(long = "hallucinations")]
+    hallucinations: Option<String>,
+    /// Keep phrases Whisper is known to make up in silence, such as "Thanks for watching!"
+    #[arg(long = "keep-hallucinations", conflicts_with = "hallucinations")]
+    keep_hallucinations: bool,
+    /// How likely, between 0 and 1, a segment must be to contain no speech for blocked phrases to be stripped from it
+    #[arg(long = "hallucination-threshold", default_value_t = DEFAULT_HALLUCINATION_THRESHOLD)]
+    hallucination_threshold: f64,
+    /// Merge short segments into subtitle cues of readable length and reading speed
+    #[arg(long = "merge-segments", conflicts_with = "live")]
+    merge_segments: bool,
+    /// The most characters in a merged cue, about two lines of subtitles
+    #[arg(long = "max-cue-chars", default_value_t = SegmentMerge::default().max_chars, requires = "merge_segments")]
+    max_cue_chars: usize,
+    /// The longest a merged cue may stay on screen, in seconds
+    #[arg(long = "max-cue-duration", default_value_t = SegmentMerge::default().max_duration, requires = "merge_segments")]
+    max_cue_duration: f64,
+    /// The fastest reading speed, in characters per second, that cues are left at without merging them further
+    #[arg(long = "max-cps", default_value_t = SegmentMerge::default().max_cps, requires = "merge_segments")]
+    max_cps: f64,
+    /// Leave long silences out of the audio sent to the model, reporting the minutes and cost saved
+    #[arg(long = "strip-silence", conflicts_with_all = ["live", "review", "escalate_below"])]
+    strip_silence: bool,
+    /// The shortest silence, in seconds, left out by --strip-silence
+    #[arg(long = "min-silence", default_value_t = DEFAULT_MIN_SILENCE, requires = "strip_silence")]
+    min_silence: f64,
+    /// Tune transcription for songs, retrying repetition loops hotter and writing LRC by default
+    #[arg(long = "lyrics", conflicts_with_all = ["no_speech_threshold", "compression_ratio_threshold", "live"])]
+    lyrics: bool,
+    /// Transcribe a live stream continuously in chunks until it ends, appending to the output file
+    #[arg(long = "live", conflicts_with_all = ["clip", "start", "end", "bilingual", "confidence_report", "output_dir"])]
+    live: bool,
+    /// The length in seconds of each chunk transcribed in live mode
+    #[arg(long = "chunk-length", default_value_t = 30, requires = "live")]
+    chunk_length: u32,
+    /// In live mode, keep this file containing only the latest caption, for an OBS text source
+    #[arg(long = "obs-caption-file", requires = "live")]
+    obs_caption_file: Option<PathBuf>,
+    /// In live mode, send captions to the stream through obs-websocket (e.g. ws://localhost:4455)
+    #[arg(long = "obs-websocket", requires = "live")]
+    obs_websocket: Option<String>,
+    /// The obs-websocket server password, defaulting to OBS_WEBSOCKET_PASSWORD
+    #[arg(long = "obs-password", requires = "obs_websocket")]
+    obs_password: Option<String>,
+    /// Embed the transcript and upsert its chunks into Qdrant (qdrant://host:6333/collection) or pgvector (postgres://...?table=name)
+    #[arg(long = "vector-store", value_parser = VectorStore::parse, conflicts_with = "live")]
+    vector_store: Option<VectorStore>,
+    /// Index the transcript's segments, or each live segment, into Elasticsearch or OpenSearch (http://[user:pass@]host:9200/index)
+    #[arg(long = "elasticsearch", value_parser = Elasticsearch::parse)]
+    elasticsearch: Option<Elasticsearch>,
+    /// Store the transcript and its segments in a Postgres, MySQL, or SQLite database (postgres://..., mysql://..., sqlite://path)
+    #[arg(long = "sql", value_parser = SqlDatabase::parse, conflicts_with = "live")]
+    sql: Option<SqlDatabase>,
+    /// Publish the transcript, or each live segment, to an MQTT topic (mqtt://[user:pass@]host[:port]/topic)
+    #[arg(long = "mqtt")]
+    mqtt: Option<String>,
+    /// Write the transcript into the tags of the local MP3 or MP4 file transcribed, as lyrics players show
+    #[arg(long = "embed-lyrics", conflicts_with_all = ["live", "split"])]
+    embed_lyrics: bool,
+    /// Embed the lyrics timed to each segment, as ID3 synchronized lyrics or LRC in MP4 files
+    #[arg(long = "synced-lyrics", requires = "embed_lyrics")]
+    synced_lyrics: bool,
+    /// In live mode, also publish segments mentioning this keyword to <topic>/alert (repeatable)
+    #[arg(long = "mqtt-keyword", requires_all = ["live", "mqtt"])]
+    mqtt_keywords: Vec<String>,
+    /// Write the SHA-256 sums of all files in the output directory to SHA256SUMS
+    #[arg(long = "checksums", requires = "output_dir")]
+    checksums: bool,
+    /// Sign SHA256SUMS with this minisign secret key (implies --checksums)
+    #[arg(long = "sign-key", requires = "output_dir")]
+    sign_key: Option<String>,
+    /// Refuse to transcribe clips longer than this, e.g. 4:00:00, to guard against huge bills
+    #[arg(long = "max-duration", value_parser = parse_max_duration)]
+    max_duration: Option<f64>,
+    /// Split clips longer than --max-duration into parts transcribed as separate jobs instead
+    #[arg(long = "split", requires = "max_duration")]
+    split: bool,
+    /// Transcribe each chapter of the video or audiobook as a separate job, combining them into one transcript headed by chapter
+    #[arg(long = "by-chapter", conflicts_with_all = ["split", "live", "embed_lyrics"])]
+    by_chapter: bool,
+    /// With --by-chapter, also write each chapter's transcript to its own file and output directory
+    #[arg(long = "chapter-files", requires = "by_chapter")]
+    chapter_files: bool,
+    /// If another run is writing the same output file or directory, wait for it instead of failing
+    #[arg(long = "wait-for-lock")]
+    wait_for_lock: bool,
+    /// Stay within this much memory, e.g. 256M, downloading audio in fewer and smaller chunks
+    #[arg(long = "max-memory", value_parser = parse_size)]
+    max_memory: Option<u64>,
+    /// Stay within this much temporary disk space, e.g. 1G, running local models and downloading videos in smaller pieces
+    #[arg(long = "max-temp-disk", value_parser = parse_size)]
+    max_temp_disk: Option<u64>,
+    /// Run at the lowest CPU and I/O priority with fewer downloads and threads at once, to keep the machine usable
+    #[arg(long = "background")]
+    background: bool,
+    /// With --background, wait while the machine is on battery power before each part
+    #[arg(long = "pause-on-battery", requires = "background")]
+    pause_on_battery: bool,
+    /// After transcribing, show the confidence of each chunk and pick chunks to re-transcribe
+    #[arg(long = "review", conflicts_with = "live")]
+    review: bool,
+    /// The length in seconds of the chunks shown by --review
+    #[arg(
+        long = "review-chunk-length",
+        default_value_t = 60,
+        requires = "review"
+    )]
+    review_chunk_length: u32,
+    /// Refuse transcripts less confident than this, between 0 and 1 (e.g. 0.85)
+    #[arg(long = "min-confidence", conflicts_with = "live", value_parser = parse_confidence)]
+    min_confidence: Option<f64>,
+    /// What to do with transcripts below --min-confidence
+    #[arg(
+        long = "on-low-confidence",
+        value_enum,
+        default_value_t = LowConfidence::Fail,
+        requires = "min_confidence"
+    )]
+    on_low_confidence: LowConfidence,
+    /// The model to retry transcripts below --min-confidence with, the -m model by default for local transcriptions
+    #[arg(long = "retry-model", requires = "min_confidence")]
+    retry_model: Option<Model>,
+    /// Neither read from nor write to the audio and transcript caches
+    #[arg(long = "no-cache", conflicts_with = "refresh")]
+    no_cache: bool,
+    /// Re-transcribe even if a cached transcript exists, replacing it
+    #[arg(long = "refresh")]
+    refresh: bool,
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+#[derive(Args, Debug, Default)]
+struct SourceArgs {
+    /// Load cookies from this Netscape-format file, for yt-dlp and the audio download
+    #[arg(long = "cookies")]
+    cookies: Option<String>,
+    /// Load cookies from this browser for yt-dlp, e.g. firefox or chrome:Profile 1
+    #[arg(long = "cookies-from-browser")]
+    cookies_from_browser: Option<String>,
+    /// Route yt-dlp and the audio download through this proxy, e.g. socks5://127.0.0.1:1080
+    #[arg(long = "proxy")]
+    proxy: Option<String>,
+    /// Pass an extra argument through to yt-dlp, may be repeated
+    #[arg(long = "ytdlp-arg", allow_hyphen_values = true)]
+    ytdlp_args: Vec<String>,
+    /// Also load this yt-dlp config file, on top of the user's own, may be repeated
+    #[arg(long = "ytdlp-config")]
+    ytdlp_configs: Vec<String>,
+    /// Also load yt-dlp plugins from this directory, may be repeated
+    #[arg(long = "ytdlp-plugin-dir")]
+    ytdlp_plugin_dirs: Vec<String>,
+    /// Ignore the user's yt-dlp config files, e.g. for runs that must not depend on this machine
+    #[arg(long = "no-ytdlp-config")]
+    no_ytdlp_config: bool,
+    /// Retry yt-dlp this many times when it fails to fetch a video's metadata
+    #[arg(long = "ytdlp-retries", default_value_t = 2)]
+    ytdlp_retries: u32,
+    /// How long to wait before retrying yt-dlp, e.g. 5s, doubling after each retry
+    #[arg(long = "ytdlp-retry-delay", value_parser = parse_duration, default_value = "2s")]
+    ytdlp_retry_delay: f64,
+}
+
+/// Expands the paths given to `flag`, exiting if any of them cannot be expanded or does not
+/// exist, so that a mistyped path is not silently left out.
+fn expand_paths(flag: &str, paths: Vec<String>) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .map(|path| match expanduser(path) {
+            Ok(expanded) if expanded.exists() => expanded,
+            Ok(_) => {
+                eprintln!("{} {} does not exist", flag, path);
+                exit(1);
+            }
+            Err(err) => {
+                eprintln!("Invalid {} path {}: {}", flag, path, err);
+                exit(1);
+            }
+        })
+        .collect()
+}
+
+impl SourceArgs {
+    fn into_options(self) -> SourceOptions {
+        SourceOptions {
+            cookies: self.cookies.and_then(|path| expanduser(&path).ok()),
+            cookies_from_browser: self.cookies_from_browser,
+            proxy: self.proxy,
+            ytdlp_args: self.ytdlp_args,
+            ytdlp_configs: expand_paths("--ytdlp-config", self.ytdlp_configs),
+            ytdlp_plugin_dirs: expand_paths("--ytdlp-plugin-dir", self.ytdlp_plugin_dirs),
+            ignore_ytdlp_config: self.no_ytdlp_config,
+            ytdlp_retries: Retries {
+                attempts: self.ytdlp_retries,
+                delay: Duration::from_secs_f64(self.ytdlp_retry_delay),
+            },
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// The port to listen on
+    #[arg(short = 'p', long = "port", default_value_t = 8080)]
+    port: u16,
+    /// The OpenAI API key to use
+    #[arg(short = 'k', long = "api-key")]
+    api_key: Option<String>,
+    /// A JSON file of OpenAI-compatible providers to spread requests across, instead of OpenAI
+    #[arg(long = "providers", conflicts_with = "api_key")]
+    providers: Option<String>,
+    /// The maximum number of jobs waiting in the queue
+    #[arg(long = "queue-size", default_value_t = 64)]
+    queue_size: usize,
+    /// The number of jobs to process at a time
+    #[arg(long = "workers", default_value_t = 1)]
+    workers: usize,
+    /// How long to keep finished jobs and their transcripts, e.g. 1h
+    #[arg(long = "keep-jobs-for", value_parser = parse_duration, default_value = "24h")]
+    keep_jobs_for: f64,
+    /// The most finished jobs to keep, removing the oldest beyond that
+    #[arg(long = "max-finished-jobs", default_value_t = 1000)]
+    max_finished_jobs: usize,
+    /// Fail jobs for videos longer than this, e.g. 4:00:00, to guard against huge bills
+    #[arg(long = "max-duration", value_parser = parse_max_duration)]
+    max_duration: Option<f64>,
+    /// Publish segment and job completion events to NATS (nats://host:4222/prefix) or a Kafka REST Proxy (kafka-rest+http://host:8082/prefix)
+    #[arg(long = "events", value_parser = EventPublisher::parse)]
+    events: Option<EventPublisher>,
+    /// Serve the output directories in this directory read-only under /archive, for digest, history and re-export to read over HTTP
+    #[arg(long = "archive")]
+    archive: Option<String>,
+    /// Transcribe locally with a ggml model file or downloaded model, kept loaded by whisper-server between jobs
+    #[arg(long = "local-model", conflicts_with_all = ["api_key", "providers"])]
+    local_model: Option<String>,
+    /// What to run the local model on, detected automatically by default
+    #[arg(long = "device", value_enum, default_value_t = Device::Auto, requires = "local_model")]
+    device: Device,
+    /// The number of beams whisper.cpp searches, 5 by default
+    #[arg(long = "beam-size", requires = "local_model")]
+    beam_size: Option<u32>,
+    /// The number of candidates whisper.cpp samples when falling back to higher temperatures
+    #[arg(long = "best-of", requires = "local_model")]
+    best_of: Option<u32>,
+    /// Decode locally so that repeated runs on the same audio yield identical output
+    #[arg(
+        long = "deterministic",
+        requires = "local_model",
+        conflicts_with = "device"
+    )]
+    deterministic: bool,
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+#[derive(Args, Debug)]
+struct ImportArgs {
+    /// The transcript file to import
+    #[arg(name = "FILE")]
+    file: String,
+    /// The video the transcript is of, given as for `transcribe`: a URL, a local file, or - for stdin
+    #[arg(long = "video")]
+    video: String,
+    /// The directory to write the transcript and its sidecars to, under <dir>/<video id>/
+    #[arg(short = 'd', long = "output-dir")]
+    output_dir: String,
+    /// The format to write the transcript in
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Name the speakers of a diarized transcript in order of appearance, e.g. "Alice,Bob", or by label, e.g. "SPEAKER_01=Bob"
+    #[arg(long = "speakers")]
+    speakers: Option<String>,
+    /// Ask for the name of each speaker of a diarized transcript, showing and playing some of their lines
+    #[arg(long = "name-speakers", conflicts_with = "speakers")]
+    name_speakers: bool,
+    /// How similar, between -1 and 1, a speaker's voice embedding must be to a voice named in another video to be given its name
+    #[arg(long = "voice-threshold", default_value_t = DEFAULT_VOICE_THRESHOLD)]
+    voice_threshold: f32,
+    /// If another run is writing the video's output directory, wait for it instead of failing
+    #[arg(long = "wait-for-lock")]
+    wait_for_lock: bool,
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+#[derive(Args, Debug)]
+struct BackfillArgs {
+    /// The URL of the channel or playlist to transcribe
+    #[arg(name = "URL")]
+    url: String,
+    /// The OpenAI API key to use
+    #[arg(short = 'k', long = "api-key")]
+    api_key: Option<String>,
+    /// A JSON file of OpenAI-compatible providers to spread requests across, instead of OpenAI
+    #[arg(long = "providers", conflicts_with = "api_key")]
+    providers: Option<String>,
+    /// The directory to write the transcripts and their sidecars to, under <dir>/<video id>/
+    #[arg(short = 'd', long = "output-dir")]
+    output_dir: String,
+    /// The file to keep the queue of videos in, defaulting to <dir>/queue.json
+    #[arg(long = "queue")]
+    queue: Option<String>,
+    /// The ISO-639-1 code of the spoken language, detected automatically if omitted
+    #[arg(short = 'l', long = "language")]
+    language: Option<String>,
+    /// The transcription model, only whisper-1 supports timestamps
+    #[arg(short = 'm', long = "model", default_value = "whisper-1")]
+    model: Model,
+    /// The format to write the transcripts in
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Fail videos longer than this, e.g. 4:00:00, to guard against huge bills
+    #[arg(long = "max-duration", value_parser = parse_max_duration)]
+    max_duration: Option<f64>,
+    /// Also retry the videos that failed in earlier runs
+    #[arg(long = "retry-failed")]
+    retry_failed: bool,
+    /// Carry on after a video fails, exiting with an error at the end if any did. This is the default, so the flag only overrides an earlier --fail-fast, e.g. one in a shell alias
+    // Never read: clap clears `fail_fast` when this comes after it, which is all it does.
+    #[arg(long = "keep-going", overrides_with = "fail_fast")]
+    keep_going: bool,
+    /// Stop at the first video that fails, leaving the rest queued
+    #[arg(long = "fail-fast", overrides_with = "keep_going")]
+    fail_fast: bool,
+    /// If another backfill is using the same queue, or another run is writing a video's output directory, wait for it instead of failing
+    #[arg(long = "wait-for-lock")]
+    wait_for_lock: bool,
+    /// Run at the lowest CPU and I/O priority with fewer downloads at once, to keep the machine usable
+    #[arg(long = "background")]
+    background: bool,
+    /// With --background, wait while the machine is on battery power before each video
+    #[arg(long = "pause-on-battery", requires = "background")]
+    pause_on_battery: bool,
+    /// Also write the summary of the run as JSON to this file
+    #[arg(long = "report")]
+    report: Option<String>,
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+#[derive(Args, Debug)]
+struct DigestArgs {
+    /// The output directory the videos were transcribed into, or the URL of a server sharing it with serve --archive
+    #[arg(name = "DIR")]
+    output_dir: String,
+    /// List the videos transcribed in this many past days
+    #[arg(long = "days", default_value_t = 1)]
+    days: u64,
+    /// Email the digest to this address with sendmail, instead of printing it
+    #[arg(long = "email")]
+    email: Option<String>,
+    /// Write the digest as an RSS feed to this file, instead of printing it
+    #[arg(long = "rss")]
+    rss: Option<String>,
+    /// The title of the email or feed
+    #[arg(long = "title", default_value = "Transcribe digest")]
+    title: String,
+}
+
+#[derive(Args, Debug)]
+struct BotArgs {
+    /// The bot token from @BotFather, defaulting to TELEGRAM_BOT_TOKEN
+    #[arg(long = "telegram-token")]
+    telegram_token: Option<String>,
+    /// Only answer the chat with this ID, may be repeated; either this or --allow-any-chat is required
+    #[arg(long = "allow-chat", required_unless_present = "allow_any_chat")]
+    allowed_chats: Vec<i64>,
+    /// Answer every chat, billing anyone who finds the bot's transcriptions to the API key
+    #[arg(long = "allow-any-chat", conflicts_with = "allowed_chats")]
+    allow_any_chat: bool,
+    /// The OpenAI API key to use
+    #[arg(short = 'k', long = "api-key")]
+    api_key: Option<String>,
+    /// A JSON file of OpenAI-compatible providers to spread requests across, instead of OpenAI
+    #[arg(long = "providers", conflicts_with = "api_key")]
+    providers: Option<String>,
+    /// The ISO-639-1 code of the spoken language, detected automatically if omitted
+    #[arg(short = 'l', long = "language")]
+    language: Option<String>,
+    /// The transcription model
+    #[arg(short = 'm', long = "model", default_value = "whisper-1")]
+    model: Model,
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+#[derive(Args, Debug)]
+struct BurnArgs {
+    /// The URL of the video, the path to a local video file, or `-` to read one from stdin
+    #[arg(name = "INPUT")]
+    input: String,
+    /// The path to write the captioned video to
+    #[arg(short = 'o', long = "output")]
+    output_path: String,
+    /// Burn in this SRT, WebVTT, or ASS file instead of transcribing the video
+    #[arg(long = "subtitles")]
+    subtitles: Option<String>,
+    /// The OpenAI API key to use
+    #[arg(short = 'k', long = "api-key")]
+    api_key: Option<String>,
+    /// A JSON file of OpenAI-compatible providers to spread requests across, instead of OpenAI
+    #[arg(long = "providers", conflicts_with = "api_key")]
+    providers: Option<String>,
+    /// The ISO-639-1 code of the spoken language, detected automatically if omitted
+    #[arg(short = 'l', long = "language")]
+    language: Option<String>,
+    /// The font of the subtitles, Arial by default
+    #[arg(long = "font")]
+    font: Option<String>,
+    /// The font size of the subtitles, for a 1080p video, 64 by default
+    #[arg(long = "font-size")]
+    font_size: Option<u32>,
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+#[derive(Args, Debug)]
+struct ReExportArgs {
+    /// The output directory the videos were transcribed into, or the URL of a server sharing it with serve --archive
+    #[arg(name = "DIR")]
+    output_dir: String,
+    /// Write the rendered transcripts into this directory instead, leaving DIR untouched; required for URLs
+    #[arg(long = "into")]
+    into: Option<String>,
+    /// Also render the stored transcripts in this format (repeatable)
+    #[arg(short = 'f', long = "format", value_enum)]
+    formats: Vec<Format>,
+    /// Regenerate every rendered transcript, even if its format has not changed
+    #[arg(long = "force")]
+    force: bool,
+    /// If another run is writing a video's output directory, wait for it instead of skipping the video
+    #[arg(long = "wait-for-lock")]
+    wait_for_lock: bool,
+}
+
+#[derive(Args, Debug)]
+struct HistoryArgs {
+    /// The ID of the video, or e.g. `<id>/part-2` for a part of a video that was split
+    #[arg(name = "VIDEO_ID")]
+    video_id: String,
+    /// The output directory the video was transcribed into, or the URL of a server sharing it with serve --archive
+    #[arg(short = 'd', long = "output-dir")]
+    output_dir: String,
+    /// The version to show the changes from, the one before --to by default
+    #[arg(long = "from")]
+    from: Option<usize>,
+    /// The version to show the changes to, the current transcript by default
+    #[arg(long = "to")]
+    to: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct BrowseArgs {
+    /// The output directory the videos were transcribed into, or the URL of a server sharing it with serve --archive
+    #[arg(name = "DIR")]
+    output_dir: String,
+    /// The format transcripts are exported in, into the working directory
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = Format::Srt)]
+    format: Format,
+}
+
+#[derive(Args, Debug)]
+struct MigrateArgs {
+    /// The database to migrate (postgres://..., mysql://..., or sqlite://path)
+    #[arg(name = "DATABASE", value_parser = SqlDatabase::parse)]
+    database: SqlDatabase,
+    /// Print the SQL of every migration instead of running the pending ones, e.g. for a DBA to review
+    #[arg(long = "print")]
+    print: bool,
+}
+
+#[derive(Args, Debug)]
+struct BenchArgs {
+    /// The length of the synthetic audio to process and transcribe, e.g. 10m
+    #[arg(long = "synthetic", value_parser = parse_duration, default_value = "10m")]
+    synthetic: f64,
+    /// The file to download to measure download throughput
+    #[arg(long = "download-url", default_value = DEFAULT_DOWNLOAD_URL)]
+    download_url: String,
+    /// The number of transcription requests sent to each provider at once
+    #[arg(long = "concurrency", default_value_t = 1)]
+    concurrency: usize,
+    /// Skip measuring provider latency, which is billed like any transcription
+    #[arg(long = "no-api")]
+    no_api: bool,
+    /// The OpenAI API key to use
+    #[arg(short = 'k', long = "api-key")]
+    api_key: Option<String>,
+    /// A JSON file of OpenAI-compatible providers to measure, instead of OpenAI
+    #[arg(long = "providers", conflicts_with = "api_key")]
+    providers: Option<String>,
+    /// The transcription model
+    #[arg(short = 'm', long = "model", default_value = "whisper-1")]
+    model: Model,
+}
+
+#[derive(Args, Debug)]
+struct ModelsArgs {
+    #[command(subcommand)]
+    command: ModelsCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ModelsCommand {
+    /// List the models available for download and those downloaded, with their disk usage
+    List,
+    /// Download a model, resuming an interrupted download and verifying its checksum
+    Download {
+        /// The model, e.g. base.en-q5_1
+        #[arg(name = "NAME")]
+        name: String,
+        /// The expected SHA-256 of the model file, instead of the one Hugging Face lists
+        #[arg(long = "sha256")]
+        sha256: Option<String>,
+    },
+    /// Delete a downloaded model, or a partial download of one
+    Remove {
+        /// The model, e.g. base.en-q5_1
+        #[arg(name = "NAME")]
+        name: String,
+    },
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    let args = Args::parse();
-    let url = args.url;
-    let api_key = args
-        .api_key
-        .unwrap_or(var("OPENAI_API_KEY").expect("Missing API key"));
-
-    let output_file = args
-        .output_path
-        .map(|path| expanduser(&path).ok())
-        .flatten()
-        .map(|path| {
-            File::create(path)
-                .ok()
-                .expect("Failed to create output file")
+    let cli = Cli::parse();
+    pause::listen_for_signals();
+    match cli.command {
+        Some(Command::Serve(args)) => {
+            let backend = match args.local_model {
+                Some(path) => {
+                    let decoding = Decoding {
+                        beam_size: args.beam_size,
+                        best_of: args.best_of,
+                        deterministic: args.deterministic,
+                    };
+                    let model = resolve_local_model(&path, decoding, args.device, false);
+                    Backend::Local(Box::new(WarmModel::new(model)))
+                }
+                None => Backend::Api(resolve_providers(args.providers, args.api_key)),
+            };
+            if let Err(err) = serve(
+                args.port,
+                backend,
+                ServeOptions {
+                    queue_size: args.queue_size,
+                    workers: args.workers,
+                    max_duration: args.max_duration,
+                    archive: args.archive.and_then(|dir| expanduser(dir).ok()),
+                    retention: Retention {
+                        ttl: Duration::from_secs_f64(args.keep_jobs_for),
+                        max_jobs: args.max_finished_jobs,
+                    },
+                },
+                args.source.into_options(),
+                args.events,
+            )
+            .await
+            {
+                eprintln!("Server error: {}", err);
+                exit(1);
+            }
+        }
+        Some(Command::Import(args)) => run_import(args).await,
+        Some(Command::Backfill(args)) => run_backfill(args).await,
+        Some(Command::Digest(args)) => run_digest(args).await,
+        Some(Command::Bot(args)) => run_bot(args).await,
+        Some(Command::Burn(args)) => run_burn(args).await,
+        Some(Command::ReExport(args)) => run_re_export(args).await,
+        Some(Command::History(args)) => run_history(args).await,
+        Some(Command::Browse(args)) => run_browse(args).await,
+        Some(Command::Migrate(args)) => run_migrate(args),
+        Some(Command::Models(args)) => run_models(args).await,
+        Some(Command::Bench(args)) => run_bench(args).await,
+        None => run(cli.transcribe).await,
+    }
+}
+
+fn parse_temperature(s: &str) -> Result<f32, String> {
+    let temperature = s
+        .parse::<f32>()
+        .map_err(|_| format!("Expected a number between 0 and 1, got '{}'", s))?;
+    check_temperature(temperature)
+}
+
+/// Parses a `--max-duration`, which must be longer than nothing for clips to be split by it.
+fn parse_max_duration(s: &str) -> Result<f64, String> {
+    match parse_timestamp(s)? {
+        duration if duration > 0.0 => Ok(duration),
+        _ => Err(format!("Expected a length greater than zero, got '{}'", s)),
+    }
+}
+
+fn parse_confidence(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(confidence) if (0.0..=1.0).contains(&confidence) => Ok(confidence),
+        _ => Err(format!("Expected a number between 0 and 1, got '{}'", s)),
+    }
+}
+
+fn resolve_api_key(api_key: Option<String>) -> String {
+    api_key.unwrap_or_else(|| var("OPENAI_API_KEY").expect("Missing API key"))
+}
+
+/// Loads the providers file if one was given, and otherwise uses OpenAI with the API key.
+fn resolve_providers(providers: Option<String>, api_key: Option<String>) -> ProviderPool {
+    match providers.and_then(|path| expanduser(&path).ok()) {
+        Some(path) => ProviderPool::load(&path).unwrap_or_else(|err| {
+            eprintln!("Failed to load providers: {}", err);
+            exit(1);
+        }),
+        None => ProviderPool::openai(resolve_api_key(api_key)),
+    }
+}
+
+/// Locks the output file or directory `target` for as long as the lock is held, as
+/// [`FileLock::acquire`] does, saying which run is being waited for.
+async fn lock_output(target: &Path, wait: bool) -> transcribe::Result<FileLock> {
+    let on_wait = |pid| {
+        println!(
+            "Waiting for the run writing {} (process {}) to finish...",
+            target.display(),
+            pid
+        )
+    };
+    FileLock::acquire(target, wait, on_wait).await
+}
+
+/// Loads the local model at `path`, or downloaded with `models download` under that name, to run
+/// on `device` or on the best one detected, leaving half the CPU free if `background` is set.
+fn resolve_local_model(
+    path: &str,
+    decoding: Decoding,
+    device: Device,
+    background: bool,
+) -> LocalModel {
+    let mut hardware = Hardware::detect();
+    let summary = hardware.summary();
+    if background {
+        hardware.threads = (hardware.threads / 2).max(1);
+    }
+    let downloaded = ModelStore::open_default()
+        .ok()
+        .and_then(|store| store.find(path));
+    let local = downloaded
+        .map_or_else(|| expanduser(path).map_err(Into::into), Ok)
+        .and_then(|path| LocalModel::new(path, decoding, device, &hardware))
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
         });
+    println!(
+        "Detected {}, transcribing locally on the {}.",
+        summary, local.device
+    );
+    local
+}
 
-    print!("Fetching video metadata... ");
-    std::io::stdout().flush().unwrap();
-    let output = YoutubeDl::new(url).run_async().await.unwrap();
+async fn run(mut args: TranscribeArgs) {
+    let url = args.url.take().expect("Missing URL");
+    if args.background {
+        if let Err(err) = lower_priority().await {
+            eprintln!("Failed to lower priority: {}", err);
+        }
+    }
+    let retries = args.min_confidence.is_some() && args.on_low_confidence == LowConfidence::Retry;
+    if retries && args.retry_model.is_none() && args.local_model.is_none() {
+        eprintln!("--on-low-confidence retry needs a --retry-model to retry with");
+        exit(1);
+    }
+    let needs_api = args.local_model.is_none() || args.escalate_below.is_some() || retries;
+    let pool = match !needs_api && args.providers.is_none() {
+        // Nothing is sent to the API with a local model, so no API key is needed.
+        true => ProviderPool::openai(args.api_key.take().unwrap_or_default()),
+        false => resolve_providers(args.providers.take(), args.api_key.take()),
+    };
+    let local = args.local_model.take().map(|path| {
+        let decoding = Decoding {
+            beam_size: args.beam_size,
+            best_of: args.best_of,
+            deterministic: args.deterministic,
+        };
+        resolve_local_model(&path, decoding, args.device, args.background)
+    });
+    // The blocklist is read before anything is transcribed, so that mistakes in it are caught
+    // before paying for the transcription.
+    let blocklist = match &args.hallucinations {
+        _ if args.keep_hallucinations => None,
+        Some(path) => {
+            let path = expanduser(path).expect("Invalid blocklist path");
+            Some(Blocklist::load(&path).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            }))
+        }
+        None => Blocklist::load_default()
+            .map_err(|err| eprintln!("Not stripping hallucinations: {}", err))
+            .ok(),
+    };
+    let clip = args
+        .clip
+        .map_or_else(|| Clip::new(args.start, args.end), Ok)
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+
+    let mut timestamp_features = vec![];
+    match args.format {
+        Format::Srt => timestamp_features.push("SRT output"),
+        Format::Vtt => timestamp_features.push("WebVTT output"),
+        Format::Ass => timestamp_features.push("ASS output"),
+        Format::Lrc => timestamp_features.push("LRC output"),
+        Format::Text | Format::Json | Format::Html => {}
+    }
+    if args.bilingual {
+        timestamp_features.push("Translation");
+    }
+    if args.confidence_report {
+        timestamp_features.push("The confidence report");
+    }
+    if args.no_speech_threshold.is_some() || args.compression_ratio_threshold.is_some() {
+        timestamp_features.push("Segment filtering");
+    }
+    if args.merge_segments {
+        timestamp_features.push("Segment merging");
+    }
+    if args.min_confidence.is_some() {
+        timestamp_features.push("Confidence gating");
+    }
+    if args.review {
+        timestamp_features.push("Reviewing chunks");
+    }
+    if args.escalate_below.is_some() {
+        timestamp_features.push("Escalating chunks");
+    }
+    if args.karaoke {
+        timestamp_features.push("Karaoke timing");
+    }
+    if args.audio_description {
+        timestamp_features.push("Audio description drafts");
+    }
+    if args.slides {
+        timestamp_features.push("Lecture notes");
+    }
+    if args.sections {
+        timestamp_features.push("Proposing sections");
+    }
+    if args.synced_lyrics {
+        timestamp_features.push("Synced lyrics");
+    }
+    // Confidence is reported with timestamps. A model without them can still be gated if its
+    // transcripts, which then always fall short, are retried with one that reports it.
+    if retries && args.retry_model.unwrap_or(args.model).supports_timestamps() {
+        timestamp_features.retain(|feature| *feature != "Confidence gating");
+    } else if args.min_confidence.is_some()
+        && args.plan.is_none()
+        && !args.model.supports_timestamps()
+    {
+        eprintln!(
+            "--min-confidence needs a model that reports confidence, which {} does not, use \
+             whisper-1 or --on-low-confidence retry with a --retry-model that does",
+            args.model
+        );
+        exit(1);
+    }
+    for feature in timestamp_features.iter().filter(|_| args.plan.is_none()) {
+        if let Err(err) = args.model.require_timestamps(feature) {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    }
+    // A retry model that only returns text is fine for gating, which trusts it, but not for
+    // the rest.
+    let retry_features = timestamp_features
+        .iter()
+        .filter(|feature| **feature != "Confidence gating");
+    for feature in retry_features.filter(|_| retries) {
+        if let Err(err) = args
+            .retry_model
+            .unwrap_or(args.model)
+            .require_timestamps(feature)
+        {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    }
+
+    let source = std::mem::take(&mut args.source).into_options();
+    let client = source.http_client().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    });
+
+    if args.live {
+        let options = TranscriptionOptions {
+            model: args.model,
+            language: args.language.take(),
+            temperature: args.temperature,
+            prompt: args.prompt.take(),
+            word_timestamps: false,
+        };
+        let output_path = args
+            .output_path
+            .take()
+            .and_then(|path| expanduser(&path).ok());
+        let live = LiveArgs {
+            output_path,
+            format: args.format,
+            chunk_length: args.chunk_length,
+            obs_caption_file: args.obs_caption_file.take(),
+            mqtt: args.mqtt.take(),
+            mqtt_keywords: std::mem::take(&mut args.mqtt_keywords),
+            elasticsearch: args.elasticsearch.take(),
+            obs_websocket: args.obs_websocket.take(),
+            obs_password: args
+                .obs_password
+                .take()
+                .or_else(|| var("OBS_WEBSOCKET_PASSWORD").ok()),
+        };
+        return run_live(&url, &pool, options, live, &source).await;
+    }
+
+    let input = Source::parse(&url, false);
+    if args.review && input.consumes_stdin() {
+        eprintln!("--review reads its answers from stdin, which the audio is piped in on");
+        exit(1);
+    }
+    let status = StatusLine::new(match input {
+        Source::Microphone(_) => "Recording... ",
+        _ => "Fetching video metadata... ",
+    });
+    // Picking an archive.org file waits on the user, which is left out of the timings.
+    let started = Instant::now();
+    let picking = Cell::new(Duration::ZERO);
+    let video = input
+        .resolve(
+            &client,
+            &source,
+            |item| {
+                status.clear();
+                println!("done.");
+                let picked = Instant::now();
+                let file = pick_archive_file(item);
+                picking.set(picked.elapsed());
+                if file.is_some() {
+                    print!("Fetching audio file... ");
+                    std::io::stdout().flush().unwrap();
+                }
+                file
+            },
+            |line| status.show(line),
+        )
+        .await
+        .unwrap_or_else(|err| {
+            status.clear();
+            println!("failed.");
+            eprintln!("{}", err);
+            exit(1);
+        });
+    let Some(video) = video else {
+        return;
+    };
+    let metadata_time = started.elapsed().saturating_sub(picking.get());
+    status.clear();
     println!("done.");
 
-    let video = output.into_single_video().unwrap();
-    let (audio_file_size, audio_url) = video
-        .formats
-        .expect("Missing video formats")
+    if args.embed_lyrics && !video.url.starts_with("file://") {
+        eprintln!("--embed-lyrics needs the path to a local audio file");
+        exit(1);
+    }
+
+    let mut preflight = Preflight {
+        model: args.model,
+        language: args.language.take(),
+        output_path: args.output_path.take(),
+        clip,
+        timestamp_features,
+        plan: args.plan,
+        max_duration: args.max_duration,
+        split: args.split,
+        history: ThroughputHistory::open_default().ok(),
+        local_model: local.as_ref().map(LocalModel::cache_name),
+        chapters: match args.by_chapter {
+            true => chapters(&video),
+            false => vec![],
+        },
+        unattended: input.consumes_stdin(),
+    };
+    if args.by_chapter && preflight.chapters.is_empty() {
+        println!(
+            "'{}' has no chapters, transcribing it as a whole.",
+            video.title
+        );
+    }
+    if !preflight.confirm(&video) {
+        return;
+    }
+    let limits = ResourceLimits {
+        max_memory: args.max_memory,
+        max_temp_disk: args.max_temp_disk,
+    };
+    if let Err(err) = limits.check_audio(video.audio_file_size) {
+        eprintln!("{}", err);
+        exit(1);
+    }
+    let parts = preflight.parts(&video);
+    let chapters = preflight
+        .chapter_parts()
         .into_iter()
-        .filter(|f| f.ext.as_ref().map_or(false, |ext| ext == "m4a"))
-        .map(|f| (f.filesize.map(|v| v as f64).or(f.filesize_approx), f.url))
-        .filter(|(size, url)| size.is_some() && url.is_some())
-        .map(|(size, url)| (size.unwrap(), url.unwrap()))
-        .sorted_by(|a, b| f64::total_cmp(&a.0, &b.0))
-        .next()
-        .expect("No suitable audio tracks found");
-
-    if audio_file_size >= 25.0 * 1000.0 * 1000.0 {
+        .map(|(clip, chapter)| (clip, chapter.title.clone()))
+        .collect::<Vec<_>>();
+    let Preflight {
+        model,
+        language,
+        output_path,
+        clip,
+        timestamp_features,
+        plan,
+        ..
+    } = preflight;
+    let options = TranscriptionOptions {
+        model,
+        language,
+        temperature: args.temperature,
+        prompt: args
+            .prompt
+            .clone()
+            .or_else(|| Some(LYRICS_PROMPT.to_string()).filter(|_| args.lyrics)),
+        word_timestamps: args.karaoke || (args.lyrics && args.format == Format::Lrc),
+    };
+    let mut targets = vec![];
+    if let Some(path) = output_path
+        .as_deref()
+        .and_then(|path| expanduser(path).ok())
+    {
+        targets.push(path);
+    }
+    if let Some(root) = args
+        .output_dir
+        .as_ref()
+        .and_then(|path| expanduser(path).ok())
+    {
+        targets.push(root.join(&video.id));
+    }
+    // Held until the run finishes, so that another run writing the same output waits or fails.
+    let mut locks = vec![];
+    for target in targets {
+        match lock_output(&target, args.wait_for_lock).await {
+            Ok(lock) => locks.push(lock),
+            Err(err) => {
+                eprintln!("{}", err);
+                drop(locks);
+                exit(1);
+            }
+        }
+    }
+
+    let cache = Cache::open_default().ok().filter(|_| !args.no_cache);
+    let job = Job {
+        pool: &pool,
+        client: &client,
+        source: &source,
+        local: local.as_ref(),
+        cache: cache.as_ref(),
+        limits,
+        options,
+        plan,
+        needs_timestamps: !timestamp_features.is_empty(),
+        blocklist,
+        metadata_time,
+        store: chapters.is_empty(),
+    };
+    let root = args
+        .output_dir
+        .as_ref()
+        .and_then(|path| expanduser(path).ok());
+    // A failed job leaves the locks to be released, and the output directory created for it
+    // removed if nothing was written to it, before exiting.
+    let fail = |err: transcribe::Error, locks: Vec<FileLock>| -> ! {
+        eprintln!("{}", err);
+        if let Some(root) = &root {
+            remove_if_empty(&root.join(&video.id));
+        }
+        drop(locks);
+        exit(1);
+    };
+    if !chapters.is_empty() {
+        let book = ChapterJob {
+            chapters,
+            clip,
+            output_path,
+            root: root.clone(),
+        };
+        if let Err(err) = run_chapters(&args, &job, &video, book).await {
+            fail(err, locks);
+        }
+        return;
+    }
+    if parts.len() == 1 {
+        let dir = root.as_ref().map(|root| {
+            OutputDir::create(root, &video).expect("Failed to create output directory")
+        });
+        if let Err(err) = run_job(&args, &job, &video, parts[0], output_path, dir).await {
+            fail(err, locks);
+        }
+        return;
+    }
+    for (i, clip) in parts.iter().enumerate() {
+        println!("Transcribing part {} of {} ({})", i + 1, parts.len(), clip);
+        let output_path = output_path.as_deref().map(|path| part_path(path, i + 1));
+        let dir = root.as_ref().map(|root| {
+            OutputDir::create_part(root, &video, i + 1).expect("Failed to create output directory")
+        });
+        let part_dir = dir.as_ref().map(|dir| dir.path().to_path_buf());
+        if let Err(err) = run_job(&args, &job, &video, *clip, output_path, dir).await {
+            if let Some(dir) = part_dir {
+                remove_if_empty(&dir);
+            }
+            fail(err, locks);
+        }
+    }
+}
+
+/// Removes the directory at `path` if it is empty, e.g. one created for output that was never
+/// written.
+fn remove_if_empty(path: &Path) {
+    let _ = std::fs::remove_dir(path);
+}
+
+/// The chapters of a video transcribed by chapter, and where the combined transcript goes.
+struct ChapterJob {
+    /// The part of the clip in each chapter, with the chapter's title.
+    chapters: Vec<(Clip, String)>,
+    clip: Clip,
+    output_path: Option<String>,
+    root: Option<PathBuf>,
+}
+
+/// Transcribes each chapter of `video` as its own job, then writes the chapters combined into
+/// one transcript, headed by the chapter titles in text output, with the chapters as its
+/// sections. With `--chapter-files`, each chapter's transcript is also written to its own file
+/// and output directory.
+async fn run_chapters(
+    args: &TranscribeArgs,
+    job: &Job<'_>,
+    video: &Video,
+    book: ChapterJob,
+) -> transcribe::Result<()> {
+    let ChapterJob {
+        chapters,
+        clip,
+        output_path,
+        root,
+    } = book;
+    let mut transcripts = vec![];
+    for (i, (chapter_clip, title)) in chapters.iter().enumerate() {
+        println!(
+            "Transcribing chapter {} of {}: {} ({})",
+            i + 1,
+            chapters.len(),
+            title,
+            chapter_clip
+        );
+        let (path, dir) = match args.chapter_files {
+            true => (
+                output_path
+                    .as_deref()
+                    .map(|path| chapter_path(path, i + 1, title)),
+                root.as_ref().map(|root| {
+                    OutputDir::create_chapter(root, video, i + 1, title)
+                        .expect("Failed to create output directory")
+                }),
+            ),
+            false => (None, None),
+        };
+        let chapter_dir = dir.as_ref().map(|dir| dir.path().to_path_buf());
+        let transcript = match run_job(args, job, video, *chapter_clip, path, dir).await {
+            Ok(transcript) => transcript,
+            Err(err) => {
+                if let Some(dir) = chapter_dir {
+                    remove_if_empty(&dir);
+                }
+                return Err(err);
+            }
+        };
+        let section = Section {
+            start: chapter_clip.offset(),
+            end: chapter_clip.end.unwrap_or(chapter_clip.offset()),
+            title: title.clone(),
+        };
+        transcripts.push((section, transcript));
+    }
+
+    let transcript = combine_chapters(&transcripts);
+    store_transcript(args, job.pool, video, &transcript).await;
+    let style = subtitle_style(args);
+    if let Some(path) = output_path.and_then(|path| expanduser(&path).ok()) {
+        std::fs::write(&path, transcript.render_styled(args.format, &style))
+            .expect("Failed to write to output file");
+        println!("Wrote {} chapters to {}", transcripts.len(), path.display());
+    }
+    if let Some(root) = root {
+        let sections = transcripts
+            .into_iter()
+            .map(|(section, _)| section)
+            .collect::<Vec<_>>();
+        let json = serde_json::json!({ "boundaries": Boundaries::Chapters, "sections": sections });
+        let dir = OutputDir::create(&root, video).expect("Failed to create output directory");
+        let written = dir
+            .replace_transcript(&transcript, args.format, &style)
+            .and_then(|_| dir.write_manifest(&Manifest::new(video, &job.options, &clip)))
+            .and_then(|_| dir.write_metadata(&Metadata::from(video)))
+            .and_then(|_| serde_json::to_string_pretty(&json).map_err(Into::into))
+            .and_then(|json| dir.write("sections.json", json))
+            .and_then(|_| dir.write("sections.txt", render_sections(&sections)));
+        match written {
+            Ok(_) => println!(
+                "Wrote {} chapters to {}",
+                sections.len(),
+                dir.path().display()
+            ),
+            Err(err) => eprintln!("Failed to write combined transcript: {}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Stores `transcript` of `video` in the databases, search indexes, and topics the arguments
+/// ask for, and embeds it in a local file's tags if asked to.
+async fn store_transcript(
+    args: &TranscribeArgs,
+    pool: &ProviderPool,
+    video: &Video,
+    transcript: &Transcript,
+) {
+    if let Some(store) = &args.vector_store {
+        print!("Embedding transcript... ");
+        std::io::stdout().flush().unwrap();
+        match store.ingest(pool, video, transcript).await {
+            Ok(count) => println!("stored {} chunks.", count),
+            Err(err) => {
+                println!("failed.");
+                eprintln!("Failed to store transcript embeddings: {}", err);
+            }
+        }
+    }
+    if let Some(elasticsearch) = &args.elasticsearch {
+        print!("Indexing transcript... ");
+        std::io::stdout().flush().unwrap();
+        match elasticsearch.index_transcript(video, transcript).await {
+            Ok(count) => println!("indexed {} segments.", count),
+            Err(err) => {
+                println!("failed.");
+                eprintln!("Failed to index transcript into Elasticsearch: {}", err);
+            }
+        }
+    }
+    if let Some(database) = &args.sql {
+        print!("Storing transcript in database... ");
+        std::io::stdout().flush().unwrap();
+        match database.store(video, transcript) {
+            Ok(()) => println!("done."),
+            Err(err) => {
+                println!("failed.");
+                eprintln!("Failed to store transcript in database: {}", err);
+            }
+        }
+    }
+    if let Some(path) = video
+        .url
+        .strip_prefix("file://")
+        .filter(|_| args.embed_lyrics)
+    {
+        print!("Embedding lyrics... ");
+        std::io::stdout().flush().unwrap();
+        match embed_lyrics(Path::new(path), transcript, args.synced_lyrics) {
+            Ok(()) => println!("done."),
+            Err(err) => {
+                println!("failed.");
+                eprintln!("Failed to embed lyrics: {}", err);
+            }
+        }
+    }
+    if let Some(url) = &args.mqtt {
+        let published: transcribe::Result<()> = async {
+            let mqtt = MqttPublisher::connect(url, Vec::new())?;
+            mqtt.publish_transcript(video, transcript).await?;
+            mqtt.close().await
+        }
+        .await;
+        if let Err(err) = published {
+            eprintln!("Failed to publish transcript to MQTT: {}", err);
+        }
+    }
+}
+
+/// Describes the confidence of a transcript against --min-confidence, e.g. for saying why it
+/// is refused.
+fn describe_confidence(confidence: Option<f64>, min_confidence: f64) -> String {
+    match confidence {
+        Some(confidence) => format!(
+            "The transcript is {:.0}% confident, below the {:.0}% required",
+            confidence * 100.0,
+            min_confidence * 100.0
+        ),
+        None => "The model reported no confidence for the transcript".to_string(),
+    }
+}
+
+/// What is shared by the jobs transcribing each part of a video.
+struct Job<'a> {
+    pool: &'a ProviderPool,
+    client: &'a Client,
+    source: &'a SourceOptions,
+    /// The model to transcribe with locally instead of with `pool`.
+    local: Option<&'a LocalModel>,
+    cache: Option<&'a Cache>,
+    limits: ResourceLimits,
+    options: TranscriptionOptions,
+    /// The preference to choose the model of each part by, overriding `options.model`.
+    plan: Option<Preference>,
+    needs_timestamps: bool,
+    /// The phrases to strip from segments the model thought were silent, unless kept.
+    blocklist: Option<Blocklist>,
+    /// How long fetching the video's metadata took, which every part shares.
+    metadata_time: Duration,
+    /// Whether each part's transcript is stored with `store_transcript`, rather than only the
+    /// combined transcript of a video transcribed by chapter.
+    store: bool,
+}
+
+/// Inserts the chapter's number and title before the extension of `path`, e.g.
+/// `book.03-the-long-road.txt`.
+fn chapter_path(path: &str, chapter: usize, title: &str) -> String {
+    let name = format!("{:02}-{}", chapter, slug(title));
+    match path.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.contains('/') => {
+            format!("{}.{}.{}", stem, name, ext)
+        }
+        _ => format!("{}.{}", path, name),
+    }
+}
+
+/// Inserts `.part<n>` before the extension of `path`, e.g. `talk.part2.srt`.
+fn part_path(path: &str, part: usize) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.contains('/') => {
+            format!("{}.part{}.{}", stem, part, ext)
+        }
+        _ => format!("{}.part{}", path, part),
+    }
+}
+
+/// Transcribes `clip` of `video`, writing the results to stdout, `output_path`, and
+/// `output_dir`, and returns the transcript.
+async fn run_job(
+    args: &TranscribeArgs,
+    job: &Job<'_>,
+    video: &Video,
+    clip: Clip,
+    output_path: Option<String>,
+    output_dir: Option<OutputDir>,
+) -> transcribe::Result<Transcript> {
+    let Job {
+        pool,
+        client,
+        source,
+        local,
+        cache,
+        limits,
+        ref options,
+        plan,
+        needs_timestamps,
+        ..
+    } = *job;
+    if args.pause_on_battery {
+        wait_for_mains(|| println!("On battery power, waiting to be plugged in...")).await;
+    }
+    let strategy = match args.background {
+        true => background_downloads(limits.download_strategy()),
+        false => limits.download_strategy(),
+    };
+    let mut timings = Timings::default();
+    timings.add(Stage::Metadata, job.metadata_time);
+    let mut options = options.clone();
+    let mut planned_audio = None;
+    let decision = match plan {
+        Some(preference) => {
+            let quality = match preference.needs_probe() {
+                true => {
+                    let audio =
+                        prepare_audio(client, video, cache, strategy, &clip, &mut timings).await;
+                    print!("Probing audio quality... ");
+                    std::io::stdout().flush().unwrap();
+                    let started = Instant::now();
+                    let quality = probe_audio(&audio).await;
+                    timings.add(Stage::Preprocess, started.elapsed());
+                    planned_audio = Some(audio);
+                    match quality {
+                        Ok(quality) => {
+                            println!("done.");
+                            Some(quality)
+                        }
+                        Err(err) => {
+                            println!("failed: {}", err);
+                            None
+                        }
+                    }
+                }
+                false => None,
+            };
+            let decision = preference.choose(needs_timestamps, quality.as_ref());
+            let minutes = video.duration.map(|duration| clip.length(duration) / 60.0);
+            match minutes {
+                Some(minutes) => println!(
+                    "Using {} because {}, at about ${:.2}.",
+                    decision.model,
+                    decision.reason,
+                    minutes * decision.model.cost_per_minute()
+                ),
+                None => println!("Using {} because {}.", decision.model, decision.reason),
+            }
+            options.model = decision.model;
+            Some(decision)
+        }
+        None => None,
+    };
+    let options = &options;
+    let output_file = output_path
+        .and_then(|path| expanduser(&path).ok())
+        .map(|path| File::create(path).expect("Failed to create output file"));
+
+    let read_cache = |key: &TranscriptKey| {
+        cache
+            .filter(|_| !args.refresh)
+            .and_then(|cache| cache.transcript(key))
+    };
+    let write_cache = |key: &TranscriptKey, transcript: &Transcript| {
+        if let Some(cache) = cache {
+            if let Err(err) = cache.put_transcript(key, transcript) {
+                eprintln!("Failed to cache transcript: {}", err);
+            }
+        }
+    };
+
+    let local_name = local.map(|local| match args.escalate_below {
+        Some(threshold) => format!(
+            "{}:escalate-below={}:{}",
+            local.cache_name(),
+            threshold,
+            options.model
+        ),
+        None => local.cache_name(),
+    });
+    let mut key = options.cache_key(video, &clip);
+    let mut translation_key = options.translation_cache_key(video, &clip);
+    if let Some(name) = &local_name {
+        key.model = name;
+        translation_key.model = name;
+    }
+    key.strip_silence = args.strip_silence.then_some(args.min_silence);
+    translation_key.strip_silence = key.strip_silence;
+    let cached = read_cache(&key);
+    let cached_translation = args
+        .bilingual
+        .then(|| read_cache(&translation_key))
+        .flatten();
+
+    let needs_audio = cached.is_none() || (args.bilingual && cached_translation.is_none());
+    let audio_bytes = match needs_audio {
+        true => match planned_audio {
+            Some(audio) => Some(audio),
+            None => Some(prepare_audio(client, video, cache, strategy, &clip, &mut timings).await),
+        },
+        false => None,
+    };
+    // Transcripts are moved back onto the source's timeline, through the clip and any silences
+    // left out, before anything else sees them.
+    let (audio_bytes, silence) = match audio_bytes {
+        Some(audio) if args.strip_silence => {
+            print!("Stripping silence... ");
+            std::io::stdout().flush().unwrap();
+            let cost_per_minute = match local {
+                Some(_) => 0.0,
+                None => options.model.cost_per_minute(),
+            };
+            let started = Instant::now();
+            let stripped = strip_silences(&audio, args.min_silence, cost_per_minute).await;
+            timings.add(Stage::Preprocess, started.elapsed());
+            match stripped {
+                Ok(Some((stripped, silence))) => {
+                    println!("done.");
+                    println!("Stripped {}.", silence.describe());
+                    (Some(stripped), Some(silence))
+                }
+                Ok(None) => {
+                    println!("found none.");
+                    (Some(audio), None)
+                }
+                Err(err) => {
+                    eprintln!("Failed to strip silence: {}", err);
+                    exit(1);
+                }
+            }
+        }
+        audio => (audio, None),
+    };
+    let timeline = match &silence {
+        Some(silence) => Timeline::trimmed(clip.offset()).then(&silence.timeline),
+        None => Timeline::trimmed(clip.offset()),
+    };
+    let input_sha256 = audio_bytes
+        .as_ref()
+        .map(|audio| format!("{:x}", Sha256::digest(audio)));
+
+    // Within --max-temp-disk, long clips are run through the local model a chunk at a time.
+    let local_chunks = video
+        .duration
+        .and_then(|duration| {
+            let length = clip.length(duration);
+            let chunk_length = limits.local_chunk_length(length)?;
+            Some(Clip::default().split(length, chunk_length))
+        })
+        .unwrap_or_else(|| vec![Clip::default()]);
+    let mut reruns = vec![];
+    let mut transcript = match cached {
+        Some(transcript) => {
+            println!("Using cached transcript.");
+            transcript
+        }
+        None => {
+            print!("Transcribing... ");
+            std::io::stdout().flush().unwrap();
+            let audio = audio_bytes.clone().unwrap();
+            let started = Instant::now();
+            let mut transcript = match (local, args.lyrics) {
+                (Some(local), _) => {
+                    local
+                        .transcribe_in_chunks(&audio, options, false, &local_chunks)
+                        .await
+                }
+                (None, true) => {
+                    transcribe_with_fallback(pool, audio, options, &LYRICS_TEMPERATURES).await
+                }
+                (None, false) => transcribe(pool, audio, options).await,
+            }
+            .map_err(|err| {
+                println!("failed.");
+                format!("Failed to transcribe: {}", err)
+            })?;
+            if let Some(duration) = video.duration {
+                let model = local.map_or(options.model.to_string(), LocalModel::cache_name);
+                let seconds = match &silence {
+                    Some(silence) => silence.kept_seconds,
+                    None => clip.length(duration),
+                };
+                record_throughput(|history| {
+                    history.record_transcription(&model, seconds, started.elapsed())
+                });
+            }
+            timings.add_requests(started, pool.take_upload_time());
+            timeline.apply(&mut transcript);
+            println!("done.");
+            let mut cacheable = true;
+            if let Some(threshold) = args.escalate_below.filter(|_| local.is_some()) {
+                print!("Escalating uncertain chunks... ");
+                std::io::stdout().flush().unwrap();
+                let started = Instant::now();
+                let result = escalate_chunks(
+                    &mut transcript,
+                    audio_bytes.as_deref().unwrap(),
+                    clip.offset(),
+                    pool,
+                    options,
+                    threshold,
+                    ESCALATION_CHUNK_LENGTH,
+                )
+                .await;
+                timings.add_requests(started, pool.take_upload_time());
+                match result {
+                    Ok((escalated, count)) => {
+                        println!("escalated {} of {} chunks.", escalated.len(), count);
+                        reruns.extend(escalated);
+                    }
+                    Err(err) => {
+                        println!("failed.");
+                        eprintln!("Failed to escalate chunks: {}", err);
+                        cacheable = false;
+                    }
+                }
+            }
+            // A transcript that failed to escalate is not cached, so that the next run retries.
+            if cacheable {
+                write_cache(&key, &transcript);
+            }
+            transcript
+        }
+    };
+
+    if args.review {
+        let audio = match &audio_bytes {
+            Some(audio) => audio.clone(),
+            None => prepare_audio(client, video, cache, strategy, &clip, &mut timings).await,
+        };
+        let chunk_length = args.review_chunk_length as f64;
+        reruns = review(
+            &mut transcript,
+            &audio,
+            clip.offset(),
+            pool,
+            options,
+            chunk_length,
+        )
+        .await;
+        // Reviewing waits on the user, so its re-runs are left out of the timings.
+        pool.take_upload_time();
+    }
+
+    // Transcripts are held to --min-confidence before their segments are filtered, which would
+    // otherwise hide the parts the model was least sure of.
+    let below = |confidence: Option<f64>| {
+        args.min_confidence
+            .is_some_and(|min| confidence.is_none_or(|confidence| confidence < min))
+    };
+    let min_confidence = args.min_confidence.unwrap_or_default();
+    let mut confidence = transcript.confidence();
+    let mut retried = None;
+    if below(confidence) && args.on_low_confidence == LowConfidence::Retry {
+        let retry = TranscriptionOptions {
+            model: args.retry_model.unwrap_or(options.model),
+            ..options.clone()
+        };
+        println!(
+            "{}, retrying with {}.",
+            describe_confidence(confidence, min_confidence),
+            retry.model
+        );
+        let mut retry_key = retry.cache_key(video, &clip);
+        retry_key.strip_silence = key.strip_silence;
+        transcript = match read_cache(&retry_key) {
+            Some(transcript) => {
+                println!("Using cached transcript.");
+                transcript
+            }
+            None => {
+                let audio = match &audio_bytes {
+                    Some(audio) => audio.clone(),
+                    None => {
+                        prepare_audio(client, video, cache, strategy, &clip, &mut timings).await
+                    }
+                };
+                print!("Transcribing... ");
+                std::io::stdout().flush().unwrap();
+                let started = Instant::now();
+                let mut transcript = transcribe(pool, audio, &retry).await.unwrap_or_else(|err| {
+                    println!("failed.");
+                    eprintln!("Failed to retry with {}: {}", retry.model, err);
+                    exit(1);
+                });
+                timings.add_requests(started, pool.take_upload_time());
+                timeline.apply(&mut transcript);
+                println!("done.");
+                write_cache(&retry_key, &transcript);
+                transcript
+            }
+        };
+        confidence = transcript.confidence();
+        retried = Some(retry);
+    }
+    // Models that only return text report no confidence, and are trusted as the better model
+    // that was asked for.
+    let trusted = retried
+        .as_ref()
+        .is_some_and(|retry| !retry.model.supports_timestamps());
+    let low_confidence = below(confidence) && !trusted;
+    if low_confidence {
+        match args.on_low_confidence {
+            LowConfidence::Flag => eprintln!(
+                "{}, writing it flagged as low confidence.",
+                describe_confidence(confidence, min_confidence)
+            ),
+            LowConfidence::Fail | LowConfidence::Retry => {
+                eprintln!(
+                    "{}, not writing it.",
+                    describe_confidence(confidence, min_confidence)
+                );
+                exit(1);
+            }
+        }
+    }
+
+    let mut translation = match (args.bilingual, cached_translation) {
+        (false, _) => None,
+        (true, Some(translation)) => {
+            println!("Using cached translation.");
+            Some(translation)
+        }
+        (true, None) => {
+            print!("Translating... ");
+            std::io::stdout().flush().unwrap();
+            let started = Instant::now();
+            let audio_bytes = audio_bytes.unwrap();
+            let mut translation = match local {
+                Some(local) => {
+                    local
+                        .transcribe_in_chunks(&audio_bytes, options, true, &local_chunks)
+                        .await
+                }
+                None => translate(pool, audio_bytes, options).await,
+            }
+            .map_err(|err| {
+                println!("failed.");
+                format!("Failed to translate: {}", err)
+            })?;
+            timings.add_requests(started, pool.take_upload_time());
+            timeline.apply(&mut translation);
+            println!("done.");
+            write_cache(&translation_key, &translation);
+            Some(translation)
+        }
+    };
+
+    let started = Instant::now();
+    let filter = SegmentFilter {
+        no_speech_threshold: args.no_speech_threshold,
+        compression_ratio_threshold: args.compression_ratio_threshold,
+    };
+    transcript.filter_segments(&filter);
+    if let Some(translation) = &mut translation {
+        translation.filter_segments(&filter);
+    }
+    let mut hallucinations = 0;
+    if let Some(blocklist) = &job.blocklist {
+        hallucinations = blocklist.strip(&mut transcript, args.hallucination_threshold);
+        if let Some(translation) = &mut translation {
+            blocklist.strip(translation, args.hallucination_threshold);
+        }
+        if hallucinations > 0 {
+            println!(
+                "Stripped {} sentences Whisper is known to make up in silence.",
+                hallucinations
+            );
+        }
+    }
+    let merge = args.merge_segments.then(|| SegmentMerge {
+        max_chars: args.max_cue_chars,
+        max_duration: args.max_cue_duration,
+        max_cps: args.max_cps,
+        ..SegmentMerge::default()
+    });
+    if let Some(merge) = &merge {
+        transcript.merge_segments(merge);
+        if let Some(translation) = &mut translation {
+            translation.merge_segments(merge);
+        }
+    }
+    let bilingual = translation.map(|translation| Bilingual {
+        transcript: transcript.clone(),
+        translation,
+    });
+
+    let style = subtitle_style(args);
+    let res = match &bilingual {
+        Some(bilingual) => bilingual.render_styled(args.format, &style),
+        None => transcript.render_styled(args.format, &style),
+    };
+    if args.token_count {
+        println!(
+            "Transcript: {} words, about {} tokens",
+            transcript.text.split_whitespace().count(),
+            estimate_tokens(&transcript.text)
+        );
+    }
+    let readability_report = args
+        .readability_report
+        .then(|| readability(&transcript.text))
+        .flatten();
+    if let Some(report) = &readability_report {
+        println!(
+            "Reading level: grade {:.1}, reading ease {:.0}, {:.1} words per sentence, {:.1}% complex words",
+            report.grade_level,
+            report.reading_ease,
+            report.average_sentence_length,
+            report.complex_word_ratio * 100.0
+        );
+    }
+    let started = timings.since(Stage::Postprocess, started);
+    if let Some(mut file) = output_file {
+        file.write_all(res.as_bytes())
+            .expect("Failed to write to output file");
+    }
+    let mut metadata = None;
+    if let Some(dir) = &output_dir {
+        dir.replace_transcript(&transcript, args.format, &style)
+            .expect("Failed to write transcript");
+        if let Some(bilingual) = &bilingual {
+            dir.write_export("translation", &bilingual.translation, args.format, &style)
+                .and_then(|_| dir.write("bilingual.srt", bilingual.render(Format::Srt)))
+                .expect("Failed to write translation");
+        }
+
+        let manifest = Manifest {
+            bilingual: bilingual.is_some(),
+            filter,
+            hallucinations,
+            merge,
+            min_confidence: args.min_confidence,
+            confidence,
+            low_confidence,
+            audio_sha256: cache
+                .and_then(|cache| cache.audio_entry(&video.id))
+                .map(|entry| entry.hash),
+            input_sha256: input_sha256.clone(),
+            silence,
+            timeline,
+            reruns,
+            plan: decision,
+            provider: local_name
+                .clone()
+                .filter(|_| retried.is_none())
+                .or_else(|| Some(pool.used().join(",")).filter(|used| !used.is_empty())),
+            ..Manifest::new(video, retried.as_ref().unwrap_or(options), &clip)
+        };
+        dir.write_manifest(&manifest)
+            .expect("Failed to write manifest");
+
+        let mut sidecar = Metadata::from(video);
+        sidecar.failovers = pool.failovers();
+        if video.info.is_some() {
+            match dir.write_info(video) {
+                Ok(file) => sidecar.info_file = Some(file),
+                Err(err) => eprintln!("Failed to save yt-dlp info JSON: {}", err),
+            }
+        }
+        if args.description {
+            match dir.write_description(video) {
+                Ok(file) => sidecar.description_file = Some(file),
+                Err(err) => eprintln!("Failed to save description: {}", err),
+            }
+        }
+        if args.thumbnail {
+            match dir.download_thumbnail(client, video).await {
+                Ok(file) => sidecar.thumbnail_file = Some(file),
+                Err(err) => eprintln!("Failed to save thumbnail: {}", err),
+            }
+        }
+        metadata = Some(sidecar);
+        if let Some(max_tokens) = args.chunk_for_llm {
+            let chunks = chunk_for_llm(&transcript, max_tokens, args.chunk_overlap);
+            match dir.write_llm_chunks(&chunks) {
+                Ok(path) => println!("Wrote {} LLM chunks to {}", chunks.len(), path.display()),
+                Err(err) => eprintln!("Failed to write LLM chunks: {}", err),
+            }
+        }
+        if let Some(report) = &readability_report {
+            let written = serde_json::to_string_pretty(report)
+                .map_err(Into::into)
+                .and_then(|json| dir.write("readability.json", json));
+            if let Err(err) = written {
+                eprintln!("Failed to write readability report: {}", err);
+            }
+        }
+        if args.audio_description {
+            let gaps = find_gaps(video, &transcript, args.min_gap);
+            let drafts = serde_json::to_string_pretty(&gaps)
+                .map_err(Into::into)
+                .and_then(|json| dir.write("audio-description.json", json))
+                .and_then(|_| dir.write("audio-description.txt", draft_script(video, &gaps)));
+            if let Err(err) = drafts {
+                eprintln!("Failed to write audio description draft: {}", err);
+            }
+        }
+        if args.slides || args.sections {
+            let max_size = limits.max_video_size();
+            write_video_analysis(args, dir, video, source, max_size, &clip, &transcript).await;
+        }
+        if args.confidence_report {
+            // The full audio track is saved so that the report's timestamps, which refer to
+            // the whole video, can be used to seek in it directly.
+            let report = download_audio(client, video, cache, strategy, |_, _| {})
+                .await
+                .and_then(|audio| dir.write("audio.m4a", audio))
+                .and_then(|_| {
+                    let html = confidence_report(&transcript, &video.title, "audio.m4a");
+                    dir.write("report.html", html)
+                });
+            if let Err(err) = report {
+                eprintln!("Failed to write confidence report: {}", err);
+            }
+        }
+    }
+    if job.store {
+        store_transcript(args, pool, video, &transcript).await;
+    }
+    // The metadata sidecar is written last, once every stage it records the timing of is
+    // done, and the checksums after it, as they cover it.
+    timings.since(Stage::Export, started);
+    if let (Some(dir), Some(mut metadata)) = (&output_dir, metadata) {
+        metadata.timings = Some(timings);
+        dir.write_metadata(&metadata)
+            .expect("Failed to write metadata sidecar");
+        if args.checksums || args.sign_key.is_some() {
+            let sums = dir.write_checksums().expect("Failed to write checksums");
+            if let Some(key) = args
+                .sign_key
+                .as_ref()
+                .and_then(|path| expanduser(path).ok())
+            {
+                if let Err(err) = sign(&sums, &key) {
+                    eprintln!("Failed to sign checksums: {}", err);
+                    exit(1);
+                }
+            }
+        }
+    }
+    if args.timings {
+        println!("Timings:\n{}", timings.describe());
+    }
+    println!("{}", res);
+    Ok(transcript)
+}
+
+fn subtitle_style(args: &TranscribeArgs) -> SubtitleStyle {
+    SubtitleStyle {
+        vtt_settings: args.vtt_settings.clone(),
+        font: args.font.clone(),
+        font_size: args.font_size,
+        speaker_colors: args.speaker_colors.clone(),
+        karaoke: args.karaoke,
+    }
+}
+
+/// Downloads the video to read its slides and propose sections from, as requested.
+async fn write_video_analysis(
+    args: &TranscribeArgs,
+    dir: &OutputDir,
+    video: &Video,
+    source: &SourceOptions,
+    max_size: Option<u64>,
+    clip: &Clip,
+    transcript: &Transcript,
+) {
+    let status = StatusLine::new("Downloading video... ");
+    let temp = std::env::temp_dir().join(format!("transcribe-video-{}", video.id));
+    let download = download_video(&video.url, source, max_size, &temp, |line| {
+        status.show(line)
+    });
+    let video_file = match download.await {
+        Ok(path) => {
+            status.clear();
+            path
+        }
+        Err(err) => {
+            status.clear();
+            println!("failed.");
+            eprintln!("Failed to download video: {}", err);
+            let _ = std::fs::remove_dir_all(&temp);
+            return;
+        }
+    };
+    println!("done.");
+
+    if args.slides {
+        print!("Reading slides... ");
+        std::io::stdout().flush().unwrap();
+        let slides = extract_slides(&video_file, clip, args.slide_interval, &temp).await;
+        let notes = slides.and_then(|slides| {
+            println!("found {}.", slides.len());
+            dir.write("slides.json", serde_json::to_string_pretty(&slides)?)?;
+            dir.write(
+                "lecture-notes.md",
+                lecture_notes(video, transcript, &slides),
+            )
+        });
+        if let Err(err) = notes {
+            println!("failed.");
+            eprintln!("Failed to write lecture notes: {}", err);
+        }
+    }
+    if args.sections {
+        print!("Detecting scene changes... ");
+        std::io::stdout().flush().unwrap();
+        let scene_changes = match detect_scene_changes(&video_file, clip).await {
+            Ok(changes) => {
+                println!("found {}.", changes.len());
+                changes
+            }
+            Err(err) => {
+                println!("failed.");
+                eprintln!("Failed to detect scene changes: {}", err);
+                vec![]
+            }
+        };
+        let end = clip
+            .end
+            .or(video.duration)
+            .or(transcript.segments.last().map(|segment| segment.end))
+            .unwrap_or_default();
+        let (boundaries, sections) = propose_sections(
+            video,
+            transcript,
+            &scene_changes,
+            clip.offset(),
+            end,
+            args.min_section,
+        );
+        let json = serde_json::json!({ "boundaries": boundaries, "sections": sections });
+        let written = serde_json::to_string_pretty(&json)
+            .map_err(Into::into)
+            .and_then(|json| dir.write("sections.json", json))
+            .and_then(|_| dir.write("sections.txt", render_sections(&sections)));
+        match written {
+            Ok(_) => println!("Proposed {} sections.", sections.len()),
+            Err(err) => eprintln!("Failed to write sections: {}", err),
+        }
+    }
+    let _ = std::fs::remove_dir_all(&temp);
+}
+
+async fn run_import(args: ImportArgs) {
+    let path = expanduser(&args.file).expect("Invalid transcript path");
+    let contents = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("Failed to read transcript: {}", err);
+        exit(1);
+    });
+    let mut transcript = parse_transcript(&contents).unwrap_or_else(|err| {
+        eprintln!("Failed to read transcript: {}", err);
+        exit(1);
+    });
+    let embeddings = parse_embeddings(&contents);
+    if args.format.is_subtitles() && transcript.segments.is_empty() {
         eprintln!(
-            "Audio file is too large to transcribe, max 25 MB, got {:.2} MB",
-            audio_file_size / 1000.0 / 1000.0
+            "The transcript has no timestamps to write {} from",
+            args.format.name()
         );
         exit(1);
     }
+    let speakers = transcript.speakers();
+    if (args.speakers.is_some() || args.name_speakers) && speakers.is_empty() {
+        eprintln!("The transcript is not diarized, so it has no speakers to name");
+        exit(1);
+    }
+    let given_names = match &args.speakers {
+        Some(spec) => parse_names(spec, &speakers).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        }),
+        None => BTreeMap::new(),
+    };
 
-    let title = video.title.expect("Missing video title");
-    let mut input = String::new();
-    print!("Transcribe '{}'? [y/N] ", &title);
-    std::io::stdout().flush().unwrap();
-    std::io::stdin().read_line(&mut input).unwrap();
-    if input.trim().to_lowercase() != "y" {
+    let input = Source::parse(&args.video, false);
+    if args.name_speakers && input.consumes_stdin() {
+        eprintln!("--name-speakers reads its answers from stdin, which the video is piped in on");
+        exit(1);
+    }
+    let status = StatusLine::new("Fetching video metadata... ");
+    let source = args.source.into_options();
+    let video = async {
+        let client = source.http_client()?;
+        input
+            .resolve(
+                &client,
+                &source,
+                |item| {
+                    status.clear();
+                    println!("done.");
+                    let file = pick_archive_file(item);
+                    if file.is_some() {
+                        print!("Fetching audio file... ");
+                        std::io::stdout().flush().unwrap();
+                    }
+                    file
+                },
+                |line| status.show(line),
+            )
+            .await
+    }
+    .await
+    .unwrap_or_else(|err| {
+        status.clear();
+        println!("failed.");
+        eprintln!("{}", err);
+        exit(1);
+    });
+    let Some(video) = video else {
         return;
+    };
+    status.clear();
+    println!("done.");
+
+    // Speakers whose voices were named in other videos of the archive are given the same
+    // names, unless they are named here.
+    let root = expanduser(&args.output_dir).expect("Invalid output directory");
+    let known = known_voices(&root, &root.join(&video.id)).unwrap_or_else(|err| {
+        eprintln!("Failed to read the voices in the archive: {}", err);
+        vec![]
+    });
+    let mut speaker_names = BTreeMap::new();
+    for (label, found) in match_voices(&embeddings, &known, args.voice_threshold) {
+        if !given_names.contains_key(&label) {
+            println!(
+                "Recognized {} as {} ({:.0}% similar)",
+                label,
+                found.name,
+                found.similarity * 100.0
+            );
+            speaker_names.insert(label, found.name);
+        }
+    }
+    speaker_names.extend(given_names);
+    if args.name_speakers {
+        speaker_names = name_speakers(&transcript, &video.audio_url, &speaker_names).await;
+    }
+    transcript.rename_speakers(&speaker_names);
+
+    let _lock = lock_output(&root.join(&video.id), args.wait_for_lock)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+    let dir = OutputDir::create(&root, &video).expect("Failed to create output directory");
+    dir.write_transcript(&transcript, args.format)
+        .expect("Failed to write transcript");
+    let mut metadata = Metadata::from(&video);
+    metadata.imported_from = path.file_name().map(|name| name.to_string_lossy().into());
+    metadata.speaker_names = speaker_names;
+    match dir.write_info(&video) {
+        Ok(file) => metadata.info_file = Some(file),
+        Err(err) => eprintln!("Failed to save yt-dlp info JSON: {}", err),
     }
+    if !embeddings.is_empty() {
+        let voices = embeddings
+            .into_iter()
+            .map(|(label, embedding)| {
+                let name = metadata.speaker_names.get(&label).cloned();
+                (label, Voice { name, embedding })
+            })
+            .collect();
+        write_voices(dir.path(), &voices).expect("Failed to write voices");
+    }
+    dir.write_metadata(&metadata)
+        .expect("Failed to write metadata sidecar");
+    println!(
+        "Imported {} segments into {}",
+        transcript.segments.len(),
+        dir.path().display()
+    );
+}
 
-    print!("Downloading audio track... ");
+async fn run_backfill(args: BackfillArgs) {
+    if args.background {
+        if let Err(err) = lower_priority().await {
+            eprintln!("Failed to lower priority: {}", err);
+        }
+    }
+    if args.format.is_subtitles() {
+        args.model
+            .require_timestamps(&format!("{} output", args.format.name()))
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+    }
+    let pool = resolve_providers(args.providers, args.api_key);
+    let root = expanduser(&args.output_dir).expect("Invalid output directory");
+    let queue_path = match &args.queue {
+        Some(path) => expanduser(path).expect("Invalid queue path"),
+        None => root.join("queue.json"),
+    };
+    let on_wait = |pid| {
+        println!(
+            "Waiting for the backfill using {} (process {}) to finish...",
+            queue_path.display(),
+            pid
+        )
+    };
+    let lock = FileLock::acquire(&queue_path, args.wait_for_lock, on_wait)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+    let mut queue = DiskQueue::open(&queue_path).unwrap_or_else(|err| {
+        eprintln!("Failed to open queue: {}", err);
+        exit(1);
+    });
+
+    print!("Listing videos... ");
     std::io::stdout().flush().unwrap();
-    let audio_bytes = download_file(&audio_url)
+    let source = args.source.into_options();
+    let urls = fetch_playlist(&args.url, &source)
         .await
-        .expect("Failed to download audio track");
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+    let added = queue.extend(urls).expect("Failed to write queue");
+    println!("{} new.", added);
+    if args.retry_failed {
+        queue.retry_failed().expect("Failed to write queue");
+    }
+
+    let client = source.http_client().expect("Failed to build HTTP client");
+    let cache = Cache::open_default().ok();
+    let options = TranscriptionOptions {
+        model: args.model,
+        language: args.language,
+        temperature: 0.0,
+        prompt: None,
+        word_timestamps: false,
+    };
+    let strategy = match args.background {
+        true => background_downloads(DownloadStrategy::default()),
+        false => DownloadStrategy::default(),
+    };
+    let started = Instant::now();
+    let mut items = vec![];
+    while let Some(url) = queue.start_next().expect("Failed to write queue") {
+        if args.pause_on_battery {
+            wait_for_mains(|| println!("On battery power, waiting to be plugged in...")).await;
+        }
+        let (pending, done, failed) = queue.counts();
+        print!(
+            "[{}/{}] {}... ",
+            done + failed + 1,
+            pending + done + failed,
+            url
+        );
+        std::io::stdout().flush().unwrap();
+        let result: transcribe::Result<BatchItem> = async {
+            let video = Source::parse(&url, false)
+                .resolve_unattended(&client, &source, |_| {})
+                .await?;
+            if let (Some(duration), Some(max_duration)) = (video.duration, args.max_duration) {
+                if duration > max_duration {
+                    return Err("Video is longer than the maximum duration".into());
+                }
+            }
+            // Held until the video's output is written, so that a run writing it meanwhile
+            // waits or fails.
+            let _lock = lock_output(&root.join(&video.id), args.wait_for_lock).await?;
+            let key = options.cache_key(&video, &Clip::default());
+            let cached = cache.as_ref().and_then(|cache| cache.transcript(&key));
+            let status = match cached {
+                Some(_) => ItemStatus::Cached,
+                None => ItemStatus::Done,
+            };
+            let transcript = match cached {
+                Some(transcript) => transcript,
+                None => {
+                    check_audio_size(video.audio_file_size)?;
+                    let audio =
+                        download_audio(&client, &video, cache.as_ref(), strategy, |_, _| {})
+                            .await?;
+                    let transcript = transcribe(&pool, audio, &options).await?;
+                    if let Some(cache) = &cache {
+                        cache.put_transcript(&key, &transcript)?;
+                    }
+                    transcript
+                }
+            };
+
+            let dir = OutputDir::create(&root, &video)?;
+            dir.write_transcript(&transcript, args.format)?;
+            let mut metadata = Metadata::from(&video);
+            metadata.info_file = dir.write_info(&video).ok();
+            metadata.failovers = pool.failovers();
+            dir.write_metadata(&metadata)?;
+            let cost = match status {
+                ItemStatus::Done => video.duration.unwrap_or_default() / 60.0,
+                ItemStatus::Cached | ItemStatus::Failed => 0.0,
+            } * options.model.cost_per_minute();
+            Ok(BatchItem {
+                url: url.clone(),
+                title: Some(video.title.clone()),
+                status,
+                duration: video.duration,
+                cost,
+                output: Some(dir.path().to_path_buf()),
+                error: None,
+            })
+        }
+        .await;
+        let state = match result {
+            Ok(item) => {
+                println!("done.");
+                items.push(item);
+                ItemState::Done
+            }
+            Err(err) => {
+                println!("failed: {}", err);
+                items.push(BatchItem::failed(&url, err.to_string()));
+                ItemState::Failed {
+                    error: err.to_string(),
+                }
+            }
+        };
+        let failed = matches!(state, ItemState::Failed { .. });
+        queue.finish(&url, state).expect("Failed to write queue");
+        if failed && args.fail_fast {
+            println!("Stopping at the first failure, as --fail-fast is set.");
+            break;
+        }
+    }
+
+    let report = BatchReport::new(items, started.elapsed());
+    if !report.items.is_empty() {
+        println!();
+        print!("{}", report.render_table());
+    }
+    if let Some(path) = &args.report {
+        let written = serde_json::to_string_pretty(&report)
+            .map_err(Into::into)
+            .and_then(|json| {
+                let path = expanduser(path)?;
+                std::fs::write(path, json).map_err(transcribe::Error::from)
+            });
+        if let Err(err) = written {
+            eprintln!("Failed to write report: {}", err);
+        }
+    }
+    let (_, done, failed) = queue.counts();
+    println!("Backfill finished: {} done, {} failed.", done, failed);
+    if failed > 0 {
+        println!("Failed videos can be retried with --retry-failed.");
+    }
+    // Like make, a run that carried on past failures still reports them in its exit status.
+    if report
+        .items
+        .iter()
+        .any(|item| item.status == ItemStatus::Failed)
+    {
+        drop(lock);
+        exit(1);
+    }
+}
+
+/// Opens the archive of output directories at `location`, mirroring it first if it is served
+/// over HTTP.
+async fn open_output_archive(location: &str) -> PathBuf {
+    open_archive(location).await.unwrap_or_else(|err| {
+        eprintln!("Failed to open {}: {}", location, err);
+        exit(1);
+    })
+}
+
+async fn run_digest(args: DigestArgs) {
+    let root = open_output_archive(&args.output_dir).await;
+    // Periods longer than the clock allows go back to the epoch, listing every video.
+    let since = args
+        .days
+        .checked_mul(24 * 60 * 60)
+        .and_then(|seconds| SystemTime::now().checked_sub(Duration::from_secs(seconds)))
+        .unwrap_or(UNIX_EPOCH);
+    let entries = collect_entries(&root, since).unwrap_or_else(|err| {
+        eprintln!("Failed to read output directory: {}", err);
+        exit(1);
+    });
+
+    if let Some(path) = &args.rss {
+        let path = expanduser(path).expect("Invalid feed path");
+        std::fs::write(&path, render_rss(&entries, &args.title)).expect("Failed to write feed");
+        println!("Wrote {} videos to {}", entries.len(), path.display());
+    }
+    match &args.email {
+        Some(to) => {
+            let subject = format!("{}: {} new videos", args.title, entries.len());
+            send_email(to, &subject, &render_text(&entries)).unwrap_or_else(|err| {
+                eprintln!("Failed to send digest: {}", err);
+                exit(1);
+            });
+            println!("Emailed {} videos to {}", entries.len(), to);
+        }
+        None if args.rss.is_none() => print!("{}", render_text(&entries)),
+        None => {}
+    }
+}
+
+async fn run_bot(args: BotArgs) {
+    let token = args
+        .telegram_token
+        .unwrap_or_else(|| var("TELEGRAM_BOT_TOKEN").expect("Missing Telegram bot token"));
+    let pool = resolve_providers(args.providers, args.api_key);
+    let options = TranscriptionOptions {
+        model: args.model,
+        language: args.language,
+        temperature: 0.0,
+        prompt: None,
+        word_timestamps: false,
+    };
+    if args.allow_any_chat {
+        eprintln!(
+            "Warning: answering every chat, so anyone who finds the bot can spend the API key. \
+             Pass --allow-chat instead to restrict who can use it."
+        );
+    }
+    let source = args.source.into_options();
+    let bot = TelegramBot::new(Client::new(), token, args.allowed_chats);
+    println!("Listening for messages...");
+    if let Err(err) = bot.run(&pool, &options, &source).await {
+        eprintln!("Bot error: {}", err);
+        exit(1);
+    }
+}
+
+async fn run_burn(args: BurnArgs) {
+    let source = args.source.into_options();
+    let input = Source::parse(&args.input, false);
+    let dir = ffmpeg::temp_path("burn");
+    std::fs::create_dir_all(&dir).expect("Failed to create temporary directory");
+
+    let status = StatusLine::new("Fetching video... ");
+    let video_path = async {
+        let client = source.http_client()?;
+        fetch_video_file(&input, &client, &source, &dir, |line| status.show(line)).await
+    }
+    .await
+    .unwrap_or_else(|err| {
+        status.clear();
+        println!("failed.");
+        eprintln!("Failed to fetch video: {}", err);
+        let _ = std::fs::remove_dir_all(&dir);
+        exit(1);
+    });
+    status.clear();
+    println!("done.");
+
+    let subtitles = match args.subtitles.as_ref() {
+        Some(path) => expanduser(path).expect("Invalid subtitles path"),
+        None => {
+            let pool = resolve_providers(args.providers, args.api_key);
+            let options = TranscriptionOptions {
+                model: Model::Whisper1,
+                language: args.language,
+                temperature: 0.0,
+                prompt: None,
+                word_timestamps: false,
+            };
+            let transcript: transcribe::Result<Transcript> = async {
+                print!("Extracting audio... ");
+                std::io::stdout().flush()?;
+                let audio_path = dir.join("audio.m4a");
+                ffmpeg::extract_audio(&video_path, &audio_path).await?;
+                println!("done.");
+                let audio = std::fs::read(audio_path)?;
+                print!("Transcribing audio... ");
+                std::io::stdout().flush()?;
+                let transcript = transcribe(&pool, audio, &options).await?;
+                println!("done.");
+                Ok(transcript)
+            }
+            .await;
+            let transcript = transcript.unwrap_or_else(|err| {
+                eprintln!("Failed to transcribe video: {}", err);
+                let _ = std::fs::remove_dir_all(&dir);
+                exit(1);
+            });
+            let style = SubtitleStyle {
+                font: args.font,
+                font_size: args.font_size,
+                ..SubtitleStyle::default()
+            };
+            let path = dir.join("subtitles.ass");
+            std::fs::write(&path, transcript.render_styled(Format::Ass, &style))
+                .expect("Failed to write subtitles");
+            path
+        }
+    };
+
+    print!("Burning in subtitles... ");
+    std::io::stdout().flush().unwrap();
+    let output_path = expanduser(&args.output_path).expect("Invalid output path");
+    let result = burn_subtitles(&video_path, &subtitles, &output_path, &dir).await;
+    let _ = std::fs::remove_dir_all(&dir);
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        exit(1);
+    }
     println!("done.");
+    println!("Captioned video: {}", output_path.display());
+}
+
+async fn run_re_export(args: ReExportArgs) {
+    if args.into.is_none() && is_archive_url(&args.output_dir) {
+        eprintln!("Archives served over HTTP are read-only, pass --into to re-export elsewhere");
+        exit(1);
+    }
+    let root = open_output_archive(&args.output_dir).await;
+    let into = args
+        .into
+        .as_ref()
+        .map(|path| expanduser(path).expect("Invalid output directory"));
+    let mut dirs = vec![];
+    for entry in std::fs::read_dir(&root).expect("Failed to read output directory") {
+        let path = entry.expect("Failed to read output directory").path();
+        if !path.is_dir() {
+            continue;
+        }
+        // Videos that were split have their transcripts in part-<n> subdirectories.
+        let parts = std::fs::read_dir(&path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|part| {
+                part.is_dir()
+                    && part
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with("part-"))
+            })
+            .collect::<Vec<_>>();
+        dirs.push(path);
+        dirs.extend(parts);
+    }
+    dirs.sort();
+
+    let mut count = 0;
+    // The lock on the video's directory being written to, which parts of a split video share.
+    let mut lock: Option<(PathBuf, FileLock)> = None;
+    for path in dirs {
+        let dir = OutputDir::open(&path);
+        let relative = path.strip_prefix(&root).unwrap_or(&path);
+        let name = relative.display();
+        let video_dir = relative
+            .components()
+            .next()
+            .map_or_else(PathBuf::new, |video| PathBuf::from(video.as_os_str()));
+        let locked = into.as_ref().unwrap_or(&root).join(&video_dir);
+        if lock.as_ref().is_none_or(|(path, _)| *path != locked) {
+            lock = None;
+            match lock_output(&locked, args.wait_for_lock).await {
+                Ok(acquired) => lock = Some((locked, acquired)),
+                Err(err) => {
+                    eprintln!("{}: {}", name, err);
+                    continue;
+                }
+            }
+        }
+        let target = into
+            .as_ref()
+            .map(|into| OutputDir::open(into.join(relative)));
+        let result = match dir.re_export(&args.formats, args.force, target.as_ref()) {
+            Ok(result) => result,
+            Err(err) => {
+                eprintln!("{}: {}", name, err);
+                continue;
+            }
+        };
+        if !result.skipped.is_empty() {
+            eprintln!(
+                "{}: skipped {}, since the stored transcript has no timestamps",
+                name,
+                result.skipped.join(", ")
+            );
+        }
+        if result.written.is_empty() {
+            continue;
+        }
+        println!("{}: {}", name, result.written.join(", "));
+        count += result.written.len();
+        if target.is_none() && dir.has_checksums() {
+            if let Err(err) = dir.write_checksums() {
+                eprintln!("{}: failed to update checksums: {}", name, err);
+            } else if path.join("SHA256SUMS.minisig").exists() {
+                eprintln!(
+                    "{}: SHA256SUMS was updated and needs to be signed again",
+                    name
+                );
+            }
+        }
+    }
+    println!("Re-exported {} files.", count);
+}
+
+async fn run_history(args: HistoryArgs) {
+    let root = open_output_archive(&args.output_dir).await;
+    let dir = OutputDir::open(root.join(&args.video_id));
+    let current = dir
+        .read_latest_transcript("transcript")
+        .unwrap_or_else(|_| {
+            eprintln!("No transcript of {} in {}", args.video_id, root.display());
+            exit(1);
+        });
+    let versions = versions(dir.path(), Some(current)).unwrap_or_else(|err| {
+        eprintln!("Failed to read the versions of the transcript: {}", err);
+        exit(1);
+    });
+    let latest = versions.len();
+    for version in &versions {
+        let current = match version.number == latest {
+            true => " (current)",
+            false => "",
+        };
+        println!(
+            "{:>3}  {}  {}{}",
+            version.number,
+            format_time(version.written_at),
+            version.origin(),
+            current
+        );
+    }
 
-    let openai = OpenAI::new(&OpenAI {
-        api_key,
-        org_id: None,
-    });
-    let req = CreateTranscriptionRequestBuilder::default()
-        .model(AudioModel::Whisper1)
-        .language(Language::English)
-        .response_format(ResponseFormat::Text)
-        .temperature(0.0)
-        .file(FileMeta {
-            buffer: audio_bytes.to_vec(),
-            filename: "audio.m4a".to_string(),
+    let to = args.to.unwrap_or(latest);
+    let from = args.from.unwrap_or(to.saturating_sub(1));
+    if args.from.is_none() && from == 0 {
+        println!("The transcript has not been replaced yet.");
+        return;
+    }
+    let find = |number: usize| {
+        versions.get(number.wrapping_sub(1)).unwrap_or_else(|| {
+            eprintln!("No version {}, there are {}", number, latest);
+            exit(1);
         })
-        .build()
-        .unwrap();
+    };
+    let new = find(to);
+    let old = find(from);
+    println!();
+    match diff(&old.transcript, &new.transcript) {
+        Some(diff) => println!("Changes from version {} to {}:\n{}", from, to, diff),
+        None => println!(
+            "Versions {} and {} read the same, apart from timestamps.",
+            from, to
+        ),
+    }
+}
 
-    print!("Transcribing... ");
+async fn run_browse(args: BrowseArgs) {
+    let root = open_output_archive(&args.output_dir).await;
+    let read_only = is_archive_url(&args.output_dir);
+    if let Err(err) = browse(&root, read_only, args.format) {
+        eprintln!("{}", err);
+        exit(1);
+    }
+}
+
+fn run_migrate(args: MigrateArgs) {
+    if args.print {
+        print!("{}", args.database.migrations_sql(&[]));
+        return;
+    }
+    print!("Migrating database... ");
     std::io::stdout().flush().unwrap();
-    let res = openai
-        .audio()
-        .create_transcription_with_text_response(&req)
-        .await
-        .unwrap();
+    if let Err(err) = args.database.migrate() {
+        println!("failed.");
+        eprintln!("{}", err);
+        exit(1);
+    }
     println!("done.");
+}
 
-    if let Some(mut file) = output_file {
-        file.write_all(res.as_bytes())
-            .expect("Failed to write to output file");
+async fn run_models(args: ModelsArgs) {
+    let store = ModelStore::open_default().unwrap_or_else(|err| {
+        eprintln!("Failed to open models directory: {}", err);
+        exit(1);
+    });
+    let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+    match args.command {
+        ModelsCommand::List => {
+            let installed = store.installed().unwrap_or_else(|err| {
+                eprintln!("Failed to list models: {}", err);
+                exit(1);
+            });
+            println!("Available for download:");
+            for (name, size) in MODELS {
+                let status = match installed.iter().find(|model| model.name == *name) {
+                    Some(model) if !model.partial => " (downloaded)",
+                    Some(_) => " (partially downloaded)",
+                    None => "",
+                };
+                println!("  {:<20} about {} MB{}", name, size, status);
+            }
+            println!("Downloaded to {}:", store.dir().display());
+            if installed.is_empty() {
+                println!("  none");
+            }
+            for model in &installed {
+                let partial = if model.partial { " (partial)" } else { "" };
+                println!(
+                    "  {:<20} {:.1} MB{}",
+                    model.name,
+                    megabytes(model.size),
+                    partial
+                );
+            }
+            let total = installed.iter().map(|model| model.size).sum::<u64>();
+            println!("Total disk usage: {:.1} MB", megabytes(total));
+        }
+        ModelsCommand::Download { name, sha256 } => {
+            if let Some(path) = store.find(&name) {
+                println!("{} is already downloaded to {}.", name, path.display());
+                return;
+            }
+            let status = StatusLine::new(format!("Downloading {}... ", name));
+            let client = Client::new();
+            let download = store.download(&client, &name, sha256.as_deref(), |received, total| {
+                status.show_download(received, total)
+            });
+            match download.await {
+                Ok(path) => {
+                    status.clear();
+                    println!("\rDownloaded {} to {}.", name, path.display());
+                }
+                Err(err) => {
+                    status.clear();
+                    println!("failed.");
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            }
+        }
+        ModelsCommand::Remove { name } => match store.remove(&name) {
+            Ok(freed) => println!("Removed {}, freeing {:.1} MB.", name, megabytes(freed)),
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        },
     }
-    println!("{}", res);
 }
 
-const CHUNK_SIZE: usize = 10 * 1024 * 1024; // 10 MB
+async fn run_bench(args: BenchArgs) {
+    let length = format_timestamp(args.synthetic);
+    print!("Encoding {} of synthetic audio... ", length);
+    std::io::stdout().flush().unwrap();
+    let audio = match synthesize_audio(args.synthetic).await {
+        Ok((audio, elapsed)) => {
+            println!(
+                "{:.1}s ({:.0}x realtime).",
+                elapsed.as_secs_f64(),
+                args.synthetic / elapsed.as_secs_f64()
+            );
+            audio
+        }
+        Err(err) => {
+            println!("failed.");
+            eprintln!("{}", err);
+            exit(1);
+        }
+    };
+
+    print!("Cutting a clip... ");
+    std::io::stdout().flush().unwrap();
+    match measure_cut(&audio, args.synthetic).await {
+        Ok(elapsed) => println!("{:.2}s.", elapsed.as_secs_f64()),
+        Err(err) => println!("failed: {}", err),
+    }
 
-async fn download_file(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let client = Client::new();
-    let response = client.get(url).send().await?;
-    let total_size = response.content_length().unwrap_or(0);
+    print!("Downloading a test file... ");
+    std::io::stdout().flush().unwrap();
+    match measure_download(&Client::new(), &args.download_url).await {
+        Ok((bytes, elapsed)) => println!(
+            "{:.1} MB in {:.1}s ({:.1} MB/s).",
+            bytes as f64 / 1_000_000.0,
+            elapsed.as_secs_f64(),
+            bytes as f64 / 1_000_000.0 / elapsed.as_secs_f64()
+        ),
+        Err(err) => println!("failed: {}", err),
+    }
 
-    let data = Arc::new(Mutex::new(Vec::with_capacity(total_size as usize)));
-    let mut handles = vec![];
+    if args.no_api {
+        return;
+    }
+    if let Err(err) = check_audio_size(audio.len() as f64) {
+        eprintln!("{}", err);
+        exit(1);
+    }
+    let pool = resolve_providers(args.providers, args.api_key);
+    let options = TranscriptionOptions {
+        model: args.model,
+        ..TranscriptionOptions::default()
+    };
+    let requests = pool.providers().len() * args.concurrency.max(1);
+    println!(
+        "Sending {} requests of {} each, at about ${:.2} in total.",
+        requests,
+        length,
+        requests as f64 * args.synthetic / 60.0 * args.model.cost_per_minute()
+    );
+    for provider in pool.providers() {
+        print!("Transcribing on {}... ", provider.name);
+        std::io::stdout().flush().unwrap();
+        match measure_provider(provider, &audio, &options, args.concurrency).await {
+            Ok(mut latencies) => {
+                latencies.sort();
+                let seconds = |i: usize| latencies[i].as_secs_f64();
+                let slowest = seconds(latencies.len() - 1);
+                println!(
+                    "{:.1}s median, {:.1}-{:.1}s ({:.0}x realtime with {} at once).",
+                    seconds(latencies.len() / 2),
+                    seconds(0),
+                    slowest,
+                    args.synthetic * latencies.len() as f64 / slowest,
+                    latencies.len()
+                );
+            }
+            Err(err) => println!("failed: {}", err),
+        }
+    }
+}
 
-    for i in (0..total_size).step_by(CHUNK_SIZE) {
-        let end = std::cmp::min(i + CHUNK_SIZE as u64 - 1, total_size - 1);
-        let range = format!("bytes={}-{}", i, end);
+struct LiveArgs {
+    output_path: Option<PathBuf>,
+    format: Format,
+    chunk_length: u32,
+    obs_caption_file: Option<PathBuf>,
+    mqtt: Option<String>,
+    mqtt_keywords: Vec<String>,
+    elasticsearch: Option<Elasticsearch>,
+    obs_websocket: Option<String>,
+    obs_password: Option<String>,
+}
 
-        let client = client.clone();
-        let url = url.to_string();
-        let data = Arc::clone(&data);
+/// Prints each live segment to stdout as it is transcribed.
+struct PrintSegments;
 
-        let handle = tokio::spawn(async move {
-            let chunk = client
-                .get(&url)
-                .header("Range", range)
-                .send()
-                .await?
-                .bytes()
-                .await?;
+impl Sink for PrintSegments {
+    fn write<'a>(&'a mut self, segments: &'a [Segment]) -> BoxFuture<'a, transcribe::Result<()>> {
+        for segment in segments {
+            println!("{}", segment.text.trim());
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
 
-            let mut data = data.lock().await;
-            data.extend_from_slice(&chunk);
-            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+async fn run_live(
+    url: &str,
+    pool: &ProviderPool,
+    options: TranscriptionOptions,
+    args: LiveArgs,
+    source: &SourceOptions,
+) {
+    print!("Fetching stream metadata... ");
+    std::io::stdout().flush().unwrap();
+    let input = Source::parse(url, true);
+    let stream = input.resolve_live(source).await.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    });
+    println!("done.");
+
+    let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(PrintSegments)];
+    if let Some(path) = args.output_path {
+        let appender =
+            SegmentAppender::create(&path, args.format).expect("Failed to create output file");
+        sinks.push(Box::new(appender));
+    }
+    if let Some(path) = args.obs_caption_file {
+        sinks.push(Box::new(CaptionFile::new(path)));
+    }
+    if let Some(url) = args.mqtt {
+        let mqtt = MqttPublisher::connect(&url, args.mqtt_keywords).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
         });
+        sinks.push(Box::new(mqtt));
+    }
+    if let Some(elasticsearch) = args.elasticsearch {
+        sinks.push(Box::new(elasticsearch.live_sink(&stream, url)));
+    }
+    if let Some(url) = args.obs_websocket {
+        print!("Connecting to OBS... ");
+        std::io::stdout().flush().unwrap();
+        let obs = ObsWebSocket::connect(&url, args.obs_password.as_deref())
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+        println!("done.");
+        sinks.push(Box::new(obs));
+    }
+    println!(
+        "Transcribing '{}' in {} second chunks, press Ctrl-C to stop.",
+        stream.title, args.chunk_length
+    );
+    let result = transcribe_live(pool, &stream, &options, args.chunk_length, &mut sinks).await;
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        exit(1);
+    }
+}
+
+/// Downloads the audio track of `video` and cuts `clip` out of it.
+async fn prepare_audio(
+    client: &Client,
+    video: &Video,
+    cache: Option<&Cache>,
+    strategy: DownloadStrategy,
+    clip: &Clip,
+    timings: &mut Timings,
+) -> Vec<u8> {
+    let status = StatusLine::new("Downloading audio track... ");
+    let started = Instant::now();
+    let audio_bytes = download_audio(client, video, cache, strategy, |received, total| {
+        status.show_download(received, total)
+    })
+    .await
+    .expect("Failed to download audio track");
+    let bytes = audio_bytes.len() as f64;
+    record_throughput(|history| history.record_download(bytes, started.elapsed()));
+    timings.add(Stage::Download, started.elapsed());
+    status.clear();
+    println!("done.");
 
-        handles.push(handle);
+    if clip.is_empty() {
+        return audio_bytes;
+    }
+    print!("Cutting clip... ");
+    std::io::stdout().flush().unwrap();
+    let started = Instant::now();
+    let clipped = clip.cut(&audio_bytes).await.expect("Failed to cut clip");
+    if let Some(duration) = video.duration {
+        let seconds = clip.length(duration);
+        record_throughput(|history| history.record_preprocess(seconds, started.elapsed()));
     }
+    timings.add(Stage::Preprocess, started.elapsed());
+    println!("done.");
+    clipped
+}
 
-    futures::future::try_join_all(handles).await?;
+/// The widest a status shown by [`StatusLine`] gets, so that it fits on one line.
+const MAX_STATUS_WIDTH: usize = 60;
 
-    let result = Arc::try_unwrap(data)
-        .map_err(|_| "Failed to unwrap Arc")?
-        .into_inner();
-    Ok(result)
+/// A line of the terminal announcing a step, e.g. `Fetching video metadata... `, after which
+/// the latest progress of the step, such as how much of a download has arrived, is shown in
+/// place until it finishes. Progress is only shown on terminals, so that logs get the
+/// announcement and the outcome alone.
+struct StatusLine {
+    prefix: String,
+    /// The status shown, which is not redrawn while it stays the same.
+    shown: RefCell<String>,
+    terminal: bool,
+}
+
+impl StatusLine {
+    fn new(prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        print!("{}", prefix);
+        std::io::stdout().flush().unwrap();
+        StatusLine {
+            prefix,
+            shown: RefCell::default(),
+            terminal: std::io::stdout().is_terminal(),
+        }
+    }
+
+    /// Shows `status` in place of the status shown before it.
+    fn show(&self, status: &str) {
+        if !self.terminal {
+            return;
+        }
+        let status = match status.chars().count() > MAX_STATUS_WIDTH {
+            true => {
+                let status = status
+                    .chars()
+                    .take(MAX_STATUS_WIDTH - 1)
+                    .collect::<String>();
+                format!("{}…", status)
+            }
+            false => status.to_string(),
+        };
+        let mut shown = self.shown.borrow_mut();
+        if *shown == status {
+            return;
+        }
+        let width = status.chars().count();
+        let padding = " ".repeat(shown.chars().count().saturating_sub(width));
+        print!("\r{}{}{}", self.prefix, status, padding);
+        std::io::stdout().flush().unwrap();
+        *shown = status;
+    }
+
+    /// Shows how far a download has got, given the bytes received so far and the total.
+    fn show_download(&self, received: u64, total: u64) {
+        self.show(&format_progress(received, Some(total)));
+    }
+
+    /// Clears the status, leaving the announcement for the outcome to follow.
+    fn clear(&self) {
+        let shown = self.shown.take();
+        if shown.is_empty() {
+            return;
+        }
+        let padding = " ".repeat(shown.chars().count());
+        print!("\r{}{}\r{}", self.prefix, padding, self.prefix);
+        std::io::stdout().flush().unwrap();
+    }
+}
+
+/// Adds a measurement to the throughput history that job estimates are made from. Failing to
+/// save it only makes later estimates less accurate, so errors are ignored.
+fn record_throughput(record: impl FnOnce(&mut ThroughputHistory)) {
+    if let Ok(mut history) = ThroughputHistory::open_default() {
+        record(&mut history);
+        let _ = history.save();
+    }
 }
@@ -0,0 +1,172 @@
+use crate::Result;
+use expanduser::expanduser;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A file of an archive of output directories, as `transcribe serve --archive` lists them at
+/// `/archive/index.json`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// The path of the file relative to the archive, with `/` between its components, e.g.
+    /// `dQw4w9WgXcQ/transcript.srt`.
+    pub path: String,
+    /// When the file was last modified, in seconds since the Unix epoch.
+    pub modified: u64,
+    pub size: u64,
+}
+
+/// Lists every file of the archive of output directories at `root`, leaving out the locks of
+/// runs writing to it.
+pub fn index(root: &Path) -> Result<Vec<IndexEntry>> {
+    let mut entries = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if !metadata.is_file() || path.extension().is_some_and(|ext| ext == "lock") {
+                continue;
+            }
+            let relative = path.strip_prefix(root)?;
+            let components = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>();
+            entries.push(IndexEntry {
+                path: components.join("/"),
+                modified: unix_seconds(metadata.modified()?),
+                size: metadata.len(),
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Resolves `path`, relative to the archive at `root`, to a file in it, refusing paths that
+/// would leave it.
+pub fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path);
+    let inside = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (inside && !path.is_empty()).then(|| root.join(relative))
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether `location` is the URL of a served archive rather than a directory.
+pub fn is_archive_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Opens the archive of output directories at `location` for reading, which is either a
+/// directory, such as one on a network share, or the URL of a server running
+/// `transcribe serve --archive`. Archives on servers are mirrored into the cache directory,
+/// fetching only the files that changed since they were last mirrored, and the directory of
+/// the mirror is returned. Nothing is ever written to the archive itself.
+pub async fn open_archive(location: &str) -> Result<PathBuf> {
+    if !is_archive_url(location) {
+        return Ok(expanduser(location)?);
+    }
+    let base_url = location.trim_end_matches('/');
+    let hash = format!("{:x}", Sha256::digest(base_url.as_bytes()));
+    let root = dirs::cache_dir()
+        .ok_or("Missing cache directory")?
+        .join("transcribe")
+        .join("archives")
+        .join(&hash[..16]);
+    mirror(&Client::new(), base_url, &root).await?;
+    Ok(root)
+}
+
+/// Brings the mirror at `root` of the archive served at `base_url` up to date, keeping each
+/// file's modification time, which digests and histories go by.
+async fn mirror(client: &Client, base_url: &str, root: &Path) -> Result<()> {
+    let index = client
+        .get(format!("{}/archive/index.json", base_url))
+        .send()
+        .await?
+        .error_for_status()
+        .map_err(|err| format!("Failed to list the archive at {}: {}", base_url, err))?
+        .json::<Vec<IndexEntry>>()
+        .await?;
+
+    let mut kept = HashSet::new();
+    for entry in &index {
+        let path = resolve(root, &entry.path).ok_or_else(|| {
+            format!(
+                "The archive at {} lists an invalid path: {}",
+                base_url, entry.path
+            )
+        })?;
+        kept.insert(path.clone());
+        let modified = UNIX_EPOCH + Duration::from_secs(entry.modified);
+        let current = fs::metadata(&path).ok();
+        let up_to_date = current.is_some_and(|current| {
+            current.len() == entry.size && current.modified().ok() == Some(modified)
+        });
+        if up_to_date {
+            continue;
+        }
+        let contents = client
+            .get(file_url(base_url, &entry.path)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &contents)?;
+        fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified)?;
+    }
+
+    // Files removed from the archive are removed from the mirror too, e.g. the chunks of a
+    // transcript that was chunked again.
+    if root.exists() {
+        for entry in index_paths(root)? {
+            if !kept.contains(&entry) {
+                fs::remove_file(&entry)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The URL of the file at `path` in the archive served at `base_url`, with each segment of the
+/// path percent-encoded.
+fn file_url(base_url: &str, path: &str) -> Result<Url> {
+    let mut url = Url::parse(base_url)?;
+    url.path_segments_mut()
+        .map_err(|_| format!("Invalid archive URL: {}", base_url))?
+        .pop_if_empty()
+        .push("archive")
+        .extend(path.split('/'));
+    Ok(url)
+}
+
+fn index_paths(root: &Path) -> Result<Vec<PathBuf>> {
+    Ok(index(root)?
+        .into_iter()
+        .filter_map(|entry| resolve(root, &entry.path))
+        .collect())
+}
@@ -0,0 +1,228 @@
+use crate::Result;
+use futures::StreamExt;
+use reqwest::{header, Client, StatusCode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// The Hugging Face repository whisper.cpp's ggml models are published in.
+const MODEL_REPO: &str = "ggerganov/whisper.cpp";
+
+/// The Hugging Face Hub used when `HF_ENDPOINT` is unset, which points at a mirror instead.
+const DEFAULT_HF_ENDPOINT: &str = "https://huggingface.co";
+
+/// The quantized models offered for download, with their approximate size in MB. Any other
+/// model in the repository can be downloaded by name too, e.g. `large-v3`.
+pub const MODELS: &[(&str, u64)] = &[
+    ("tiny-q5_1", 31),
+    ("tiny.en-q5_1", 31),
+    ("base-q5_1", 57),
+    ("base.en-q5_1", 57),
+    ("small-q5_1", 181),
+    ("small.en-q5_1", 181),
+    ("medium-q5_0", 514),
+    ("medium.en-q5_0", 514),
+    ("large-v3-turbo-q5_0", 547),
+    ("large-v3-q5_0", 1031),
+];
+
+/// A model file in the models directory, with its size in bytes. Partial downloads are listed
+/// too, so that they count towards disk usage.
+#[derive(Clone, Debug)]
+pub struct InstalledModel {
+    pub name: String,
+    pub size: u64,
+    pub partial: bool,
+}
+
+/// A file in a Hugging Face repository as listed by its API, with the SHA-256 of its contents
+/// if it is stored with Git LFS, as models are.
+#[derive(Deserialize)]
+struct RepoFile {
+    path: String,
+    lfs: Option<LfsFile>,
+}
+
+#[derive(Deserialize)]
+struct LfsFile {
+    oid: String,
+    size: u64,
+}
+
+/// The directory that whisper.cpp models are downloaded into, under the user's data directory,
+/// e.g. `~/.local/share/transcribe/models`.
+pub struct ModelStore {
+    dir: PathBuf,
+}
+
+impl ModelStore {
+    pub fn open_default() -> Result<Self> {
+        let root = dirs::data_dir().ok_or("Missing data directory")?;
+        let dir = root.join("transcribe").join("models");
+        std::fs::create_dir_all(&dir)?;
+        Ok(ModelStore { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("ggml-{}.bin", name))
+    }
+
+    fn partial_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("ggml-{}.bin.part", name))
+    }
+
+    /// The path of the downloaded model `name`, if it has been downloaded.
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        Some(self.path(name)).filter(|path| path.is_file())
+    }
+
+    /// The downloaded and partially downloaded models, by name.
+    pub fn installed(&self) -> Result<Vec<InstalledModel>> {
+        let mut models = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(stem) = file_name.strip_prefix("ggml-") else {
+                continue;
+            };
+            let (name, partial) = match stem.strip_suffix(".bin.part") {
+                Some(name) => (name, true),
+                None => match stem.strip_suffix(".bin") {
+                    Some(name) => (name, false),
+                    None => continue,
+                },
+            };
+            models.push(InstalledModel {
+                name: name.to_string(),
+                size: entry.metadata()?.len(),
+                partial,
+            });
+        }
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(models)
+    }
+
+    /// Downloads the model `name`, resuming a download that was interrupted, and calling
+    /// `on_progress` with the bytes downloaded so far and the total. The file is checked against
+    /// `sha256` if given, and otherwise against the checksum Hugging Face lists for it, and only
+    /// moved into place if it matches.
+    pub async fn download(
+        &self,
+        client: &Client,
+        name: &str,
+        sha256: Option<&str>,
+        on_progress: impl Fn(u64, u64),
+    ) -> Result<PathBuf> {
+        let path = self.path(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        let file_name = format!("ggml-{}.bin", name);
+        let (expected, total) = remote_checksum(client, &file_name).await?;
+        let expected = sha256.map_or(expected, |sha256| sha256.to_lowercase());
+
+        let partial = self.partial_path(name);
+        let mut offset = std::fs::metadata(&partial).map_or(0, |metadata| metadata.len());
+        if offset < total {
+            let url = format!(
+                "{}/{}/resolve/main/{}",
+                hf_endpoint(),
+                MODEL_REPO,
+                file_name
+            );
+            let response = client
+                .get(url)
+                .header(header::RANGE, format!("bytes={}-", offset))
+                .send()
+                .await?
+                .error_for_status()?;
+            // Servers that ignore the range send the whole file, which then starts over.
+            let mut file = match response.status() {
+                StatusCode::PARTIAL_CONTENT => {
+                    tokio::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&partial)
+                        .await?
+                }
+                _ => {
+                    offset = 0;
+                    tokio::fs::File::create(&partial).await?
+                }
+            };
+            let mut body = response.bytes_stream();
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk?;
+                file.write_all(&chunk).await?;
+                offset += chunk.len() as u64;
+                on_progress(offset, total);
+            }
+            file.flush().await?;
+        }
+
+        let actual = file_sha256(&partial)?;
+        if actual != expected {
+            let _ = std::fs::remove_file(&partial);
+            return Err(format!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                file_name, expected, actual
+            )
+            .into());
+        }
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Deletes the model `name`, along with any partial download of it, returning the bytes
+    /// freed.
+    pub fn remove(&self, name: &str) -> Result<u64> {
+        let mut freed = 0;
+        for path in [self.path(name), self.partial_path(name)] {
+            if let Ok(metadata) = std::fs::metadata(&path) {
+                std::fs::remove_file(&path)?;
+                freed += metadata.len();
+            }
+        }
+        if freed == 0 {
+            return Err(format!("Model '{}' is not downloaded", name).into());
+        }
+        Ok(freed)
+    }
+}
+
+/// Looks up the SHA-256 and size of `file_name` in the model repository.
+async fn remote_checksum(client: &Client, file_name: &str) -> Result<(String, u64)> {
+    let url = format!("{}/api/models/{}/tree/main", hf_endpoint(), MODEL_REPO);
+    let files = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<RepoFile>>()
+        .await?;
+    let file = files
+        .into_iter()
+        .find(|file| file.path == file_name)
+        .ok_or_else(|| format!("No model named {} in {}", file_name, MODEL_REPO))?;
+    match file.lfs {
+        Some(lfs) => Ok((lfs.oid, lfs.size)),
+        None => Err(format!("{} has no checksum listed", file.path).into()),
+    }
+}
+
+fn hf_endpoint() -> String {
+    std::env::var("HF_ENDPOINT")
+        .map(|endpoint| endpoint.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| DEFAULT_HF_ENDPOINT.to_string())
+}
+
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
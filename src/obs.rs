@@ -0,0 +1,130 @@
+use crate::live::Sink;
+use crate::transcript::Segment;
+use crate::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::future::BoxFuture;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+/// Keeps a text file containing only the latest caption, for use as the source file of an OBS
+/// text source. The file is replaced atomically so that OBS never reads a partial caption.
+pub struct CaptionFile {
+    path: PathBuf,
+}
+
+impl CaptionFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CaptionFile { path: path.into() }
+    }
+
+    fn update(&self, segments: &[Segment]) -> Result<()> {
+        let Some(segment) = segments.last() else {
+            return Ok(());
+        };
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, segment.text.trim())?;
+        std::fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Sink for CaptionFile {
+    fn write<'a>(&'a mut self, segments: &'a [Segment]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.update(segments) })
+    }
+}
+
+/// Sends each segment to OBS as a stream caption (CEA-608) through obs-websocket v5.
+pub struct ObsWebSocket {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_request_id: u64,
+}
+
+impl ObsWebSocket {
+    /// Connects and identifies to obs-websocket at `url`, e.g. `ws://localhost:4455`, using
+    /// `password` if the server requires authentication.
+    pub async fn connect(url: &str, password: Option<&str>) -> Result<Self> {
+        let (socket, _) = connect_async(url).await?;
+        let mut obs = ObsWebSocket {
+            socket,
+            next_request_id: 1,
+        };
+
+        let hello = obs.receive(0).await?;
+        let mut identify = json!({ "rpcVersion": 1 });
+        if let Some(auth) = hello.get("authentication") {
+            let password = password.ok_or("OBS requires a password")?;
+            let challenge = auth["challenge"].as_str().ok_or("Missing auth challenge")?;
+            let salt = auth["salt"].as_str().ok_or("Missing auth salt")?;
+            let secret = BASE64.encode(Sha256::digest(format!("{}{}", password, salt)));
+            identify["authentication"] = BASE64
+                .encode(Sha256::digest(format!("{}{}", secret, challenge)))
+                .into();
+        }
+        obs.send(1, identify).await?;
+        obs.receive(2).await?;
+        Ok(obs)
+    }
+
+    pub async fn send_caption(&mut self, text: &str) -> Result<()> {
+        let request_id = self.next_request_id.to_string();
+        self.next_request_id += 1;
+        self.send(
+            6,
+            json!({
+                "requestType": "SendStreamCaption",
+                "requestId": request_id,
+                "requestData": { "captionText": text },
+            }),
+        )
+        .await?;
+
+        loop {
+            let response = self.receive(7).await?;
+            if response["requestId"] != request_id.as_str() {
+                continue;
+            }
+            let status = &response["requestStatus"];
+            if status["result"] != true {
+                let comment = status["comment"].as_str().unwrap_or("unknown error");
+                return Err(format!("OBS rejected the caption: {}", comment).into());
+            }
+            return Ok(());
+        }
+    }
+
+    async fn send(&mut self, op: u8, d: Value) -> Result<()> {
+        let message = json!({ "op": op, "d": d }).to_string();
+        self.socket.send(Message::text(message)).await?;
+        Ok(())
+    }
+
+    /// Waits for the next message with opcode `op`, skipping events and other messages.
+    async fn receive(&mut self, op: u8) -> Result<Value> {
+        while let Some(message) = self.socket.next().await {
+            let Message::Text(text) = message? else {
+                continue;
+            };
+            let mut message: Value = serde_json::from_str(text.as_ref())?;
+            if message["op"] == op {
+                return Ok(message["d"].take());
+            }
+        }
+        Err("OBS closed the connection".into())
+    }
+}
+
+impl Sink for ObsWebSocket {
+    fn write<'a>(&'a mut self, segments: &'a [Segment]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            for segment in segments {
+                self.send_caption(segment.text.trim()).await?;
+            }
+            Ok(())
+        })
+    }
+}
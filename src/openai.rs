@@ -0,0 +1,295 @@
+use crate::Result;
+use futures::StreamExt;
+use reqwest::{header::HeaderMap, multipart, Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_API_BASE: &str = "https://api.openai.com/v1";
+
+/// The size of the pieces audio is uploaded in, which is timed by when the last one is sent.
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// An OpenAI-compatible API and the key to authenticate with.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    /// The URL the `/audio/...` routes are relative to, e.g. [`DEFAULT_API_BASE`].
+    pub base_url: String,
+    pub api_key: String,
+}
+
+impl Endpoint {
+    pub fn openai(api_key: impl Into<String>) -> Self {
+        Endpoint {
+            base_url: DEFAULT_API_BASE.to_string(),
+            api_key: api_key.into(),
+        }
+    }
+}
+
+/// The request rate limit state reported in the `x-ratelimit-*` headers of a response.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimits {
+    pub remaining_requests: Option<u64>,
+    /// How long until the request limit is fully replenished.
+    pub reset_requests: Option<Duration>,
+}
+
+impl RateLimits {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        RateLimits {
+            remaining_requests: header("x-ratelimit-remaining-requests")
+                .and_then(|value| value.parse().ok()),
+            reset_requests: header("x-ratelimit-reset-requests").and_then(parse_duration),
+        }
+    }
+}
+
+/// Parses durations in the format of the `x-ratelimit-reset-*` headers, e.g. `1s`, `6m0s`, or
+/// `20ms`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(split);
+        let number = number.parse::<f64>().ok()?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        total += number
+            * match unit {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                "ms" => 0.001,
+                _ => return None,
+            };
+        rest = tail;
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+/// An unsuccessful response from the API.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    /// How long the API asked to wait before retrying, from the `retry-after` header.
+    pub retry_after: Option<Duration>,
+    pub rate_limits: RateLimits,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OpenAI API error ({}): {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// A request to the `/audio/transcriptions` endpoint.
+#[derive(Clone)]
+pub struct TranscriptionRequest {
+    pub file: Vec<u8>,
+    pub filename: String,
+    pub model: String,
+    /// The ISO-639-1 code of the spoken language, or `None` to have the model detect it.
+    pub language: Option<String>,
+    pub temperature: f32,
+    /// Text to steer the model with, e.g. the spelling of names or a previous segment.
+    pub prompt: Option<String>,
+    /// Either `verbose_json`, for models that report segments, or `json`.
+    pub response_format: &'static str,
+    /// Whether to also request word timings, which requires `verbose_json`.
+    pub word_timestamps: bool,
+}
+
+/// The `verbose_json` response, which the fields of the plain `json` response are a subset of.
+#[derive(Debug, Deserialize)]
+pub struct VerboseTranscription {
+    pub text: String,
+    pub language: Option<String>,
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub segments: Vec<VerboseSegment>,
+    /// The timings of every word, if requested, across all segments.
+    #[serde(default)]
+    pub words: Vec<VerboseWord>,
+    /// How long the audio took to upload, after which the rest of the request was spent
+    /// waiting for the model.
+    #[serde(skip)]
+    pub upload_time: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerboseWord {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerboseSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    pub avg_logprob: Option<f64>,
+    pub compression_ratio: Option<f64>,
+    pub no_speech_prob: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetail {
+    message: String,
+}
+
+/// Transcribes audio, returning per-segment timestamps if requested with `verbose_json`, along
+/// with the rate limits reported in the response.
+pub async fn create_transcription(
+    endpoint: &Endpoint,
+    req: TranscriptionRequest,
+) -> Result<(VerboseTranscription, RateLimits)> {
+    post_audio(endpoint, "/audio/transcriptions", req).await
+}
+
+/// Translates audio into English, returning the `verbose_json` response with per-segment
+/// timestamps. The endpoint does not take a language, so `req.language` is ignored.
+pub async fn create_translation(
+    endpoint: &Endpoint,
+    req: TranscriptionRequest,
+) -> Result<(VerboseTranscription, RateLimits)> {
+    post_audio(
+        endpoint,
+        "/audio/translations",
+        TranscriptionRequest {
+            language: None,
+            ..req
+        },
+    )
+    .await
+}
+
+async fn post_audio(
+    endpoint: &Endpoint,
+    route: &str,
+    req: TranscriptionRequest,
+) -> Result<(VerboseTranscription, RateLimits)> {
+    // The audio is streamed, so that the upload can be timed by when the stream runs out.
+    let started = Instant::now();
+    let uploaded = Arc::new(OnceLock::new());
+    let length = req.file.len() as u64;
+    let chunks = req
+        .file
+        .chunks(UPLOAD_CHUNK_SIZE)
+        .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+        .collect::<Vec<_>>();
+    let finished = uploaded.clone();
+    let stream = futures::stream::iter(chunks).chain(futures::stream::poll_fn(move |_| {
+        let _ = finished.set(Instant::now());
+        Poll::Ready(None)
+    }));
+    let file = multipart::Part::stream_with_length(reqwest::Body::wrap_stream(stream), length)
+        .file_name(req.filename)
+        .mime_str("application/octet-stream")?;
+    let mut form = multipart::Form::new()
+        .part("file", file)
+        .text("model", req.model)
+        .text("response_format", req.response_format)
+        .text("temperature", req.temperature.to_string());
+    if let Some(language) = req.language {
+        form = form.text("language", language);
+    }
+    if let Some(prompt) = req.prompt {
+        form = form.text("prompt", prompt);
+    }
+    if req.word_timestamps {
+        form = form
+            .text("timestamp_granularities[]", "segment")
+            .text("timestamp_granularities[]", "word");
+    }
+
+    let response = Client::new()
+        .post(format!("{}{}", endpoint.base_url, route))
+        .bearer_auth(&endpoint.api_key)
+        .multipart(form)
+        .send()
+        .await?;
+    let (mut transcription, limits) = parse_response::<VerboseTranscription>(response).await?;
+    transcription.upload_time = uploaded
+        .get()
+        .map_or_else(|| started.elapsed(), |uploaded| *uploaded - started);
+    Ok((transcription, limits))
+}
+
+/// The embedding model used for [`create_embeddings`].
+pub const EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Embeds each of `inputs` with [`EMBEDDING_MODEL`], returning the vectors in the same order.
+pub async fn create_embeddings(
+    endpoint: &Endpoint,
+    inputs: &[String],
+) -> Result<(Vec<Vec<f32>>, RateLimits)> {
+    let response = Client::new()
+        .post(format!("{}/embeddings", endpoint.base_url))
+        .bearer_auth(&endpoint.api_key)
+        .json(&serde_json::json!({ "model": EMBEDDING_MODEL, "input": inputs }))
+        .send()
+        .await?;
+    let (mut res, rate_limits) = parse_response::<EmbeddingResponse>(response).await?;
+    res.data.sort_by_key(|embedding| embedding.index);
+    let embeddings = res.data.into_iter().map(|e| e.embedding).collect();
+    Ok((embeddings, rate_limits))
+}
+
+/// Parses a successful response as JSON, or turns an unsuccessful one into an [`ApiError`].
+async fn parse_response<T: DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<(T, RateLimits)> {
+    let rate_limits = RateLimits::from_headers(response.headers());
+    if !response.status().is_success() {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<f64>().ok())
+            .map(Duration::from_secs_f64);
+        let body = response.text().await?;
+        let message = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(err) => err.error.message,
+            Err(_) => body,
+        };
+        return Err(ApiError {
+            status,
+            message,
+            retry_after,
+            rate_limits,
+        }
+        .into());
+    }
+    Ok((response.json().await?, rate_limits))
+}
@@ -0,0 +1,635 @@
+use crate::clip::Clip;
+use crate::history::keep_version;
+use crate::import::parse_transcript;
+use crate::live::Sink;
+use crate::llm::LlmChunk;
+use crate::pipeline::{slug, Model, TranscriptionOptions, Video};
+use crate::plan::Decision;
+use crate::provider::Failover;
+use crate::silence::StrippedSilence;
+use crate::timeline::Timeline;
+use crate::timings::Timings;
+use crate::transcript::{
+    ass_header, html_header, Format, Segment, SegmentFilter, SegmentMerge, SubtitleStyle,
+    Transcript,
+};
+use crate::Result;
+use clap::ValueEnum;
+use flate2::{write::GzEncoder, Compression};
+use futures::future::BoxFuture;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CHECKSUMS_FILE: &str = "SHA256SUMS";
+const EXPORTS_FILE: &str = "exports.json";
+/// The tags the video was given when browsing the archive, kept apart from `metadata.json`
+/// so that transcribing the video again keeps them.
+const TAGS_FILE: &str = "tags.json";
+
+/// How a rendered transcript in an output directory was generated, recorded by file name in
+/// `exports.json` so that outputs from older exporters can be regenerated.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Export {
+    pub format: Format,
+    pub exporter_version: u32,
+    pub tool_version: String,
+    pub style: SubtitleStyle,
+}
+
+impl Export {
+    pub fn new(format: Format, style: &SubtitleStyle) -> Self {
+        Export {
+            format,
+            exporter_version: format.exporter_version(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            style: style.clone(),
+        }
+    }
+
+    /// Whether the exporter has changed since the output was generated.
+    pub fn is_stale(&self) -> bool {
+        self.exporter_version < self.format.exporter_version()
+    }
+}
+
+/// The metadata sidecar written next to each transcript as `metadata.json`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Metadata {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub channel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    pub upload_date: Option<String>,
+    pub duration: Option<f64>,
+    /// The file name of the saved description, relative to the output directory.
+    pub description_file: Option<String>,
+    /// The file name of the saved thumbnail, relative to the output directory.
+    pub thumbnail_file: Option<String>,
+    /// The file name of the saved yt-dlp info JSON, relative to the output directory.
+    pub info_file: Option<String>,
+    /// The file the transcript was imported from, if it was produced by another tool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imported_from: Option<String>,
+    /// The providers that were failed over from while transcribing.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failovers: Vec<Failover>,
+    /// The names given to the speakers of a diarized transcript, by the label they had, e.g.
+    /// `SPEAKER_00`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub speaker_names: BTreeMap<String, String>,
+    /// How long each stage of the job took.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+}
+
+/// Everything needed to regenerate or audit a transcript, written as `manifest.json`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Manifest {
+    pub tool: &'static str,
+    pub version: &'static str,
+    /// The names of the providers that served the transcription, in order of first use, or
+    /// `None` if the transcript came from the cache.
+    pub provider: Option<String>,
+    pub model: Model,
+    pub language: Option<String>,
+    pub temperature: f32,
+    pub prompt: Option<String>,
+    /// Why the model was chosen, if it was chosen automatically.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<Decision>,
+    pub clip: Option<String>,
+    pub bilingual: bool,
+    pub filter: SegmentFilter,
+    /// How many sentences Whisper is known to make up in silence were stripped.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub hallucinations: usize,
+    /// The limits short segments were merged into cues within, if they were.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merge: Option<SegmentMerge>,
+    /// The confidence the transcript was required to have, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_confidence: Option<f64>,
+    /// The confidence of the transcript, before its segments were filtered, if the model
+    /// reported it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    /// Whether the transcript was written despite being less confident than required.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub low_confidence: bool,
+    pub source_url: String,
+    pub video_id: String,
+    /// The chunks that were re-transcribed after review and spliced into the transcript.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reruns: Vec<Rerun>,
+    /// The SHA-256 of the full downloaded audio track, if it is in the cache.
+    pub audio_sha256: Option<String>,
+    /// The SHA-256 of the audio sent to the model, after clipping, or `None` if no audio was
+    /// sent because the transcript came from the cache.
+    pub input_sha256: Option<String>,
+    /// The silences left out of the audio sent to the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub silence: Option<StrippedSilence>,
+    /// Where each stretch of the audio sent to the model lies in the source, which the
+    /// transcript's timestamps have already been mapped through.
+    pub timeline: Timeline,
+}
+
+/// A chunk of a transcript that was re-transcribed with different options.
+#[derive(Clone, Debug, Serialize)]
+pub struct Rerun {
+    pub start: f64,
+    pub end: f64,
+    pub model: Model,
+    pub prompt: Option<String>,
+}
+
+impl Manifest {
+    pub fn new(video: &Video, options: &TranscriptionOptions, clip: &Clip) -> Self {
+        Manifest {
+            tool: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            provider: None,
+            model: options.model,
+            language: options.language.clone(),
+            temperature: options.temperature,
+            prompt: options.prompt.clone(),
+            plan: None,
+            clip: Some(clip.to_string()).filter(|_| !clip.is_empty()),
+            bilingual: false,
+            filter: SegmentFilter::default(),
+            hallucinations: 0,
+            merge: None,
+            min_confidence: None,
+            confidence: None,
+            low_confidence: false,
+            source_url: video.url.clone(),
+            video_id: video.id.clone(),
+            reruns: Vec::new(),
+            audio_sha256: None,
+            input_sha256: None,
+            silence: None,
+            timeline: Timeline::trimmed(clip.offset()),
+        }
+    }
+}
+
+impl From<&Video> for Metadata {
+    fn from(video: &Video) -> Self {
+        Metadata {
+            id: video.id.clone(),
+            title: video.title.clone(),
+            url: video.url.clone(),
+            channel: video.channel.clone(),
+            album: video.album.clone(),
+            upload_date: video.upload_date.clone(),
+            duration: video.duration,
+            description_file: None,
+            thumbnail_file: None,
+            info_file: None,
+            imported_from: None,
+            failovers: Vec::new(),
+            speaker_names: BTreeMap::new(),
+            timings: None,
+        }
+    }
+}
+
+/// The per-video output directory, `<root>/<video id>/`.
+pub struct OutputDir {
+    path: PathBuf,
+}
+
+impl OutputDir {
+    pub fn create(root: &Path, video: &Video) -> Result<Self> {
+        let path = root.join(&video.id);
+        fs::create_dir_all(&path)?;
+        Ok(OutputDir { path })
+    }
+
+    /// Creates `<root>/<video id>/part-<part>/` for one part of a video that was split.
+    pub fn create_part(root: &Path, video: &Video, part: usize) -> Result<Self> {
+        let path = root.join(&video.id).join(format!("part-{}", part));
+        fs::create_dir_all(&path)?;
+        Ok(OutputDir { path })
+    }
+
+    /// Creates `<root>/<video id>/chapter-<nn>-<title>/` for one chapter of a video that was
+    /// transcribed by chapter, e.g. `chapter-03-the-long-road/`.
+    pub fn create_chapter(root: &Path, video: &Video, chapter: usize, title: &str) -> Result<Self> {
+        let name = format!("chapter-{:02}-{}", chapter, slug(title));
+        let path = root.join(&video.id).join(name);
+        fs::create_dir_all(&path)?;
+        Ok(OutputDir { path })
+    }
+
+    /// Opens an output directory that was created before.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        OutputDir { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes `contents` to `file_name` in the output directory.
+    pub fn write(&self, file_name: &str, contents: impl AsRef<[u8]>) -> Result<PathBuf> {
+        let path = self.path.join(file_name);
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    pub fn write_transcript(&self, transcript: &Transcript, format: Format) -> Result<PathBuf> {
+        self.replace_transcript(transcript, format, &SubtitleStyle::default())
+    }
+
+    /// Renders a newly made transcript to `transcript.<ext>`, first keeping the transcript it
+    /// replaces, unless it reads the same, as the next version in `versions/`.
+    pub fn replace_transcript(
+        &self,
+        transcript: &Transcript,
+        format: Format,
+        style: &SubtitleStyle,
+    ) -> Result<PathBuf> {
+        if let Ok(previous) = self.read_latest_transcript("transcript") {
+            keep_version(&self.path, previous, transcript)?;
+        }
+        self.write_export("transcript", transcript, format, style)
+    }
+
+    /// Renders `transcript` to `<stem>.<ext>`, recording how in `exports.json`.
+    pub fn write_export(
+        &self,
+        stem: &str,
+        transcript: &Transcript,
+        format: Format,
+        style: &SubtitleStyle,
+    ) -> Result<PathBuf> {
+        let file_name = format!("{}.{}", stem, format.extension());
+        let path = self.write(&file_name, transcript.render_styled(format, style))?;
+        let mut exports = self.exports()?;
+        exports.insert(file_name, Export::new(format, style));
+        self.write(EXPORTS_FILE, serde_json::to_string_pretty(&exports)?)?;
+        Ok(path)
+    }
+
+    /// The rendered transcripts recorded in `exports.json`, by file name.
+    pub fn exports(&self) -> Result<BTreeMap<String, Export>> {
+        match fs::read_to_string(self.path.join(EXPORTS_FILE)) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Reads back the transcript rendered to `<stem>.<ext>`, from the most complete format
+    /// available: JSON, then SRT, then plain text, which has no timestamps.
+    pub fn read_transcript(&self, stem: &str) -> Result<Transcript> {
+        for format in [Format::Json, Format::Srt] {
+            let path = self.path.join(format!("{}.{}", stem, format.extension()));
+            if let Ok(contents) = fs::read_to_string(path) {
+                return parse_transcript(&contents);
+            }
+        }
+        self.read_text(stem)
+    }
+
+    /// Reads back the transcript rendered to `<stem>.<ext>` most recently, in JSON, SRT, or
+    /// plain text, since runs in other formats leave the renderings of earlier transcripts
+    /// behind.
+    pub fn read_latest_transcript(&self, stem: &str) -> Result<Transcript> {
+        let latest = [Format::Json, Format::Srt, Format::Text]
+            .into_iter()
+            .filter_map(|format| {
+                let path = self.path.join(format!("{}.{}", stem, format.extension()));
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((modified, format, path))
+            })
+            .max_by_key(|(modified, _, _)| *modified);
+        match latest {
+            Some((_, Format::Text, _)) | None => self.read_text(stem),
+            Some((_, _, path)) => parse_transcript(&fs::read_to_string(path)?),
+        }
+    }
+
+    fn read_text(&self, stem: &str) -> Result<Transcript> {
+        let text = fs::read_to_string(self.path.join(format!("{}.txt", stem)))
+            .map_err(|_| format!("No stored {} in {}", stem, self.path.display()))?;
+        Ok(Transcript {
+            text,
+            language: None,
+            duration: None,
+            segments: vec![],
+        })
+    }
+
+    /// Regenerates the rendered transcripts whose exporter has changed since, or all of them if
+    /// `force` is set, from the stored transcript, and renders it in each of `formats` too.
+    /// Outputs written before exports were recorded are treated as stale. If `into` is given,
+    /// every rendered transcript is written there instead, leaving this directory untouched,
+    /// e.g. for archives that are read-only.
+    pub fn re_export(
+        &self,
+        formats: &[Format],
+        force: bool,
+        into: Option<&OutputDir>,
+    ) -> Result<ReExported> {
+        let force = force || into.is_some();
+        let target = match into {
+            Some(into) => {
+                fs::create_dir_all(&into.path)?;
+                into
+            }
+            None => self,
+        };
+        let mut targets = self.exports()?;
+        for stem in ["transcript", "translation"] {
+            for &format in Format::value_variants() {
+                let file_name = format!("{}.{}", stem, format.extension());
+                if self.path.join(&file_name).exists() && !targets.contains_key(&file_name) {
+                    let untracked = Export {
+                        exporter_version: 0,
+                        ..Export::new(format, &SubtitleStyle::default())
+                    };
+                    targets.insert(file_name, untracked);
+                }
+            }
+        }
+
+        let mut jobs = vec![];
+        for (file_name, export) in &targets {
+            if force || export.is_stale() || formats.contains(&export.format) {
+                let stem = file_name
+                    .rsplit_once('.')
+                    .map_or(file_name.as_str(), |(stem, _)| stem);
+                jobs.push((stem.to_string(), export.format, export.style.clone()));
+            }
+        }
+        for stem in ["transcript", "translation"] {
+            let has_stem = targets
+                .keys()
+                .any(|name| name.starts_with(&format!("{}.", stem)));
+            for &format in formats {
+                let file_name = format!("{}.{}", stem, format.extension());
+                if has_stem && !targets.contains_key(&file_name) {
+                    jobs.push((stem.to_string(), format, SubtitleStyle::default()));
+                }
+            }
+        }
+
+        // Each transcript is read before any of its outputs are overwritten.
+        let mut transcripts = BTreeMap::new();
+        let mut result = ReExported::default();
+        for (stem, format, style) in jobs {
+            if !transcripts.contains_key(&stem) {
+                transcripts.insert(stem.clone(), self.read_transcript(&stem)?);
+            }
+            let transcript = &transcripts[&stem];
+            let file_name = format!("{}.{}", stem, format.extension());
+            if format.is_subtitles() && transcript.segments.is_empty() {
+                result.skipped.push(file_name);
+                continue;
+            }
+            target.write_export(&stem, transcript, format, &style)?;
+            result.written.push(file_name);
+        }
+        Ok(result)
+    }
+
+    /// The tags the video was given, in the order they were added.
+    pub fn tags(&self) -> Result<Vec<String>> {
+        match fs::read_to_string(self.path.join(TAGS_FILE)) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Replaces the tags the video was given, removing `tags.json` if there are none left.
+    pub fn write_tags(&self, tags: &[String]) -> Result<()> {
+        if tags.is_empty() {
+            return match fs::remove_file(self.path.join(TAGS_FILE)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            };
+        }
+        self.write(TAGS_FILE, serde_json::to_string_pretty(tags)?)?;
+        Ok(())
+    }
+
+    /// Whether `SHA256SUMS` has been written, and should be rewritten if files change.
+    pub fn has_checksums(&self) -> bool {
+        self.path.join(CHECKSUMS_FILE).exists()
+    }
+
+    pub fn write_metadata(&self, metadata: &Metadata) -> Result<PathBuf> {
+        let path = self.path.join("metadata.json");
+        fs::write(&path, serde_json::to_string_pretty(metadata)?)?;
+        Ok(path)
+    }
+
+    pub fn write_manifest(&self, manifest: &Manifest) -> Result<PathBuf> {
+        self.write("manifest.json", serde_json::to_string_pretty(manifest)?)
+    }
+
+    /// Saves the full video description as `description.txt`, returning its file name.
+    pub fn write_description(&self, video: &Video) -> Result<String> {
+        let description = video
+            .description
+            .as_deref()
+            .ok_or("Missing video description")?;
+        fs::write(self.path.join("description.txt"), description)?;
+        Ok("description.txt".to_string())
+    }
+
+    /// Saves the yt-dlp info JSON gzipped as `info.json.gz`, returning its file name. Cookies
+    /// that yt-dlp attaches to formats are left out, since they may be session cookies.
+    pub fn write_info(&self, video: &Video) -> Result<String> {
+        let mut info = video.info.clone().ok_or("Missing yt-dlp info JSON")?;
+        strip_cookies(&mut info);
+        let file = File::create(self.path.join("info.json.gz"))?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        serde_json::to_writer(&mut encoder, &info)?;
+        encoder.finish()?;
+        Ok("info.json.gz".to_string())
+    }
+
+    /// Writes the SHA-256 sums of every other file in the output directory to `SHA256SUMS`, in
+    /// the format `sha256sum -c` checks.
+    pub fn write_checksums(&self) -> Result<PathBuf> {
+        let mut names = vec![];
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_file() && !name.starts_with(CHECKSUMS_FILE) {
+                names.push(name);
+            }
+        }
+        names.sort();
+
+        let mut sums = String::new();
+        for name in names {
+            let hash = Sha256::digest(fs::read(self.path.join(&name))?);
+            sums.push_str(&format!("{:x}  {}\n", hash, name));
+        }
+        self.write(CHECKSUMS_FILE, sums)
+    }
+
+    /// Writes each chunk to `llm-chunks/chunk-<n>.txt`, with an index of their time ranges and
+    /// token counts in `llm-chunks/index.json`, returning the directory.
+    pub fn write_llm_chunks(&self, chunks: &[LlmChunk]) -> Result<PathBuf> {
+        let dir = self.path.join("llm-chunks");
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        let mut index = vec![];
+        for chunk in chunks {
+            let file = format!("chunk-{:04}.txt", chunk.index);
+            fs::write(dir.join(&file), &chunk.text)?;
+            index.push(serde_json::json!({ "file": file, "chunk": chunk }));
+        }
+        fs::write(
+            dir.join("index.json"),
+            serde_json::to_string_pretty(&index)?,
+        )?;
+        Ok(dir)
+    }
+
+    /// Downloads the video thumbnail as `thumbnail.<ext>`, returning its file name.
+    pub async fn download_thumbnail(&self, client: &Client, video: &Video) -> Result<String> {
+        let url = video
+            .thumbnail_url
+            .as_deref()
+            .ok_or("Missing video thumbnail")?;
+        let bytes = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let extension = Path::new(url.split(['?', '#']).next().unwrap_or(url))
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("jpg");
+        let file_name = format!("thumbnail.{}", extension);
+        fs::write(self.path.join(&file_name), bytes)?;
+        Ok(file_name)
+    }
+}
+
+/// Signs the file at `path` with `minisign` using the secret key at `key`, writing the signature
+/// to `<path>.minisig`. minisign prompts for the key's password, if it has one.
+pub fn sign(path: &Path, key: &Path) -> Result<PathBuf> {
+    let status = Command::new("minisign")
+        .arg("-S")
+        .arg("-s")
+        .arg(key)
+        .arg("-m")
+        .arg(path)
+        .status()
+        .map_err(|err| format!("Failed to run minisign: {}", err))?;
+    if !status.success() {
+        return Err(format!("minisign failed with {}", status).into());
+    }
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(".minisig");
+    Ok(signature.into())
+}
+
+fn strip_cookies(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.remove("cookies");
+            map.values_mut().for_each(strip_cookies);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_cookies),
+        _ => {}
+    }
+}
+
+/// The files regenerated by [`OutputDir::re_export`], and those skipped because the stored
+/// transcript has no timestamps to render them from.
+#[derive(Clone, Debug, Default)]
+pub struct ReExported {
+    pub written: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Appends segments to a transcript file as they are finalized, so that other tools can follow
+/// it with e.g. `tail -f` while it grows.
+///
+/// Each append holds an exclusive lock on the file, so that cooperating readers that also lock
+/// it never see a partially written cue. JSON is written as one segment object per line.
+pub struct SegmentAppender {
+    file: File,
+    format: Format,
+    count: usize,
+}
+
+impl SegmentAppender {
+    pub fn create(path: &Path, format: Format) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(SegmentAppender {
+            file,
+            format,
+            count: 0,
+        })
+    }
+
+    fn append(&mut self, segments: &[Segment]) -> Result<()> {
+        let mut out = String::new();
+        for segment in segments {
+            self.count += 1;
+            out += &match self.format {
+                Format::Text => format!("{}\n", segment.text.trim()),
+                Format::Srt => format!("{}\n", segment.to_srt_cue(self.count)),
+                Format::Vtt if self.count == 1 => format!("WEBVTT\n\n{}\n", segment.to_vtt_cue()),
+                Format::Vtt => format!("{}\n", segment.to_vtt_cue()),
+                Format::Ass if self.count == 1 => {
+                    let header = ass_header(&[], &SubtitleStyle::default());
+                    format!("{}{}", header, segment.to_ass_dialogue())
+                }
+                Format::Ass => segment.to_ass_dialogue(),
+                Format::Json => format!("{}\n", serde_json::to_string(segment)?),
+                Format::Html if self.count == 1 => {
+                    format!("{}{}", html_header(), segment.to_html_paragraph())
+                }
+                Format::Html => segment.to_html_paragraph(),
+                Format::Lrc => segment.to_lrc_line(),
+            };
+        }
+
+        self.file.lock()?;
+        let result = self
+            .file
+            .write_all(out.as_bytes())
+            .and_then(|_| self.file.flush());
+        self.file.unlock()?;
+        Ok(result?)
+    }
+}
+
+impl Sink for SegmentAppender {
+    fn write<'a>(&'a mut self, segments: &'a [Segment]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.append(segments) })
+    }
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
@@ -0,0 +1,202 @@
+use crate::ffmpeg::extract_audio;
+use crate::pipeline::Video;
+use crate::Result;
+use reqwest::{Client, Url};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// The IPFS gateway used when `IPFS_GATEWAY` is unset. Point it at a local node, e.g.
+/// `http://127.0.0.1:8080`, to fetch through it instead.
+const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io";
+
+pub const MEDIA_EXTENSIONS: &[&str] = &[
+    "aac", "flac", "m4a", "mkv", "mov", "mp3", "mp4", "ogg", "opus", "wav", "webm",
+];
+
+/// A video or audio file on a peer-to-peer network rather than a site yt-dlp supports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum P2pSource {
+    /// An IPFS CID, optionally followed by a path within it.
+    Ipfs(String),
+    /// A BitTorrent magnet link and its info hash.
+    Magnet { uri: String, info_hash: String },
+}
+
+impl P2pSource {
+    /// Recognizes `magnet:` links, `ipfs://` URLs, `/ipfs/` paths, and bare CIDs.
+    pub fn parse(url: &str) -> Option<Self> {
+        if url.starts_with("magnet:") {
+            let info_hash = url
+                .split(['?', '&'])
+                .find_map(|param| param.strip_prefix("xt=urn:btih:"))?;
+            return Some(P2pSource::Magnet {
+                uri: url.to_string(),
+                info_hash: info_hash.to_lowercase(),
+            });
+        }
+        let path = url
+            .strip_prefix("ipfs://")
+            .or_else(|| url.strip_prefix("/ipfs/"))
+            .or_else(|| is_cid(url.split('/').next()?).then_some(url))?;
+        Some(P2pSource::Ipfs(path.trim_end_matches('/').to_string()))
+    }
+
+    /// An identifier for the file that is stable across runs, used as its video ID.
+    pub fn id(&self) -> String {
+        match self {
+            P2pSource::Ipfs(path) => path.replace('/', "_"),
+            P2pSource::Magnet { info_hash, .. } => info_hash.clone(),
+        }
+    }
+
+    fn url(&self) -> String {
+        match self {
+            P2pSource::Ipfs(path) => format!("ipfs://{}", path),
+            P2pSource::Magnet { uri, .. } => uri.clone(),
+        }
+    }
+}
+
+fn is_cid(s: &str) -> bool {
+    let base58 = s.starts_with("Qm") && s.len() == 46;
+    let base32 = s.starts_with("baf") && s.len() > 50;
+    (base58 || base32) && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Fetches the file behind `source` into `dir` and extracts its audio track as m4a, returning a
+/// video whose audio URL points at the extracted file. Files fetched by an earlier run are
+/// reused.
+///
+/// IPFS files are fetched through the gateway in `IPFS_GATEWAY`, and torrents with `aria2c`,
+/// which must be installed. Of a torrent's files, the largest audio or video file is used.
+pub async fn fetch_p2p_video(client: &Client, source: &P2pSource, dir: &Path) -> Result<Video> {
+    tokio::fs::create_dir_all(dir).await?;
+    let id = source.id();
+    let audio_path = dir.join(format!("{}.m4a", id));
+
+    let title = match source {
+        P2pSource::Ipfs(path) => path.rsplit('/').next().unwrap_or(path).to_string(),
+        P2pSource::Magnet { uri, .. } => uri
+            .split(['?', '&'])
+            .find_map(|param| param.strip_prefix("dn="))
+            .map(|name| name.replace('+', " "))
+            .unwrap_or_else(|| id.clone()),
+    };
+
+    if !audio_path.exists() {
+        let media_path = fetch_p2p_file(client, source, dir).await?;
+        extract_audio(&media_path, &audio_path).await?;
+        let _ = tokio::fs::remove_dir_all(dir.join(&id)).await;
+        let _ = tokio::fs::remove_file(&media_path).await;
+    }
+
+    Ok(Video {
+        id,
+        title,
+        url: source.url(),
+        channel: None,
+        album: None,
+        upload_date: None,
+        duration: None,
+        description: None,
+        thumbnail_url: None,
+        audio_url: format!("file://{}", audio_path.display()),
+        audio_file_size: tokio::fs::metadata(&audio_path).await?.len() as f64,
+        info: None,
+    })
+}
+
+/// Fetches the file behind `source` into `dir` as is, returning where it was written. Of a
+/// torrent's files, only the largest audio or video file is returned.
+pub async fn fetch_p2p_file(client: &Client, source: &P2pSource, dir: &Path) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let dest = dir.join(source.id());
+    match source {
+        P2pSource::Ipfs(path) => fetch_ipfs(client, path, &dest).await,
+        P2pSource::Magnet { uri, .. } => fetch_torrent(uri, &dest).await,
+    }
+}
+
+/// Streams the file at `path`, a CID optionally followed by a path within it, from the IPFS
+/// gateway into `dest`, refusing CIDs that are not valid rather than sending them to it.
+async fn fetch_ipfs(client: &Client, path: &str, dest: &Path) -> Result<PathBuf> {
+    let mut segments = path.split('/');
+    let cid = segments.next().unwrap_or_default();
+    if !is_cid(cid) {
+        return Err(format!("Invalid IPFS CID: {}", cid).into());
+    }
+    let rest = segments.collect::<Vec<_>>();
+    if rest
+        .iter()
+        .any(|segment| matches!(*segment, "" | "." | ".."))
+    {
+        return Err(format!("Invalid IPFS path: {}", path).into());
+    }
+    let gateway = std::env::var("IPFS_GATEWAY").unwrap_or_else(|_| DEFAULT_IPFS_GATEWAY.into());
+    let mut url =
+        Url::parse(&gateway).map_err(|err| format!("Invalid IPFS_GATEWAY {}: {}", gateway, err))?;
+    url.path_segments_mut()
+        .map_err(|_| format!("Invalid IPFS_GATEWAY {}", gateway))?
+        .pop_if_empty()
+        .push("ipfs")
+        .push(cid)
+        .extend(rest);
+
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    // The file is written under another name until it is complete, so that an interrupted
+    // fetch is not mistaken for the whole file.
+    let partial = dest.with_extension("partial");
+    let mut file = tokio::fs::File::create(&partial).await?;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    tokio::fs::rename(&partial, dest).await?;
+    Ok(dest.to_path_buf())
+}
+
+async fn fetch_torrent(uri: &str, dest: &Path) -> Result<PathBuf> {
+    let output = Command::new("aria2c")
+        .args([
+            "--seed-time=0",
+            "--follow-torrent=mem",
+            "--summary-interval=0",
+        ])
+        .arg("--dir")
+        .arg(dest)
+        .arg(uri)
+        .output()
+        .await
+        .map_err(|err| format!("Failed to run aria2c: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "aria2c failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let mut files = vec![];
+    let mut dirs = vec![dest.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| MEDIA_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            {
+                files.push((entry.metadata()?.len(), path));
+            }
+        }
+    }
+    files
+        .into_iter()
+        .max()
+        .map(|(_, path)| path)
+        .ok_or_else(|| "Torrent contains no audio or video files".into())
+}
@@ -0,0 +1,56 @@
+use std::sync::OnceLock;
+use tokio::sync::watch;
+
+/// Whether work is paused, shared by the whole process so that it can be checked wherever
+/// downloads and requests are started. Pausing lets the chunks and requests already under way
+/// finish, so that no completed work is lost, but starts no new ones until resumed.
+fn paused() -> &'static watch::Sender<bool> {
+    static PAUSED: OnceLock<watch::Sender<bool>> = OnceLock::new();
+    PAUSED.get_or_init(|| watch::Sender::new(false))
+}
+
+pub fn pause() {
+    paused().send_replace(true);
+}
+
+pub fn resume() {
+    paused().send_replace(false);
+}
+
+/// Returns once work is not paused, straight away unless it is.
+pub async fn wait_while_paused() {
+    let mut receiver = paused().subscribe();
+    // The sender lives in a static, so it is never dropped and waiting cannot fail.
+    let _ = receiver.wait_for(|paused| !paused).await;
+}
+
+/// Pauses on `SIGUSR1` and resumes on `SIGUSR2`, e.g. `pkill -USR1 transcribe`, printing each
+/// change. Other platforms have no such signals, so work there can only be paused through the
+/// server.
+pub fn listen_for_signals() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let (Ok(mut pauses), Ok(mut resumes)) = (
+            signal(SignalKind::user_defined1()),
+            signal(SignalKind::user_defined2()),
+        ) else {
+            return;
+        };
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(()) = pauses.recv() => {
+                        pause();
+                        eprintln!("Paused, finishing what is under way. Send SIGUSR2 to resume.");
+                    }
+                    Some(()) = resumes.recv() => {
+                        resume();
+                        eprintln!("Resumed.");
+                    }
+                    else => break,
+                }
+            }
+        });
+    }
+}
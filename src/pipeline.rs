@@ -0,0 +1,601 @@
+use crate::cache::{AudioEntry, Cache, TranscriptKey};
+use crate::clip::Clip;
+use crate::download::{download_file_if_modified, Download, DownloadStrategy};
+use crate::ffmpeg::extract_audio;
+use crate::openai::{self, TranscriptionRequest, VerboseTranscription};
+use crate::provider::{Provider, ProviderPool};
+use crate::tags::{media_hash, read_chapters, read_file_tags};
+use crate::transcript::{Segment, Transcript, Word};
+use crate::ytdlp::{dump_json, Retries};
+use crate::Result;
+use clap::ValueEnum;
+use itertools::Itertools;
+use reqwest::{cookie::Jar, Client, Proxy, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use youtube_dl::SingleVideo;
+
+/// The largest audio file the transcription API accepts.
+pub const MAX_AUDIO_FILE_SIZE: f64 = 25.0 * 1000.0 * 1000.0; // 25 MB
+
+/// The longest the name part of a local file's video ID is, in characters.
+const MAX_SLUG_LENGTH: usize = 60;
+
+/// The metadata of a video needed to download and transcribe its audio track.
+#[derive(Clone, Debug)]
+pub struct Video {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub channel: Option<String>,
+    /// The album or podcast a local audio file belongs to, from its tags.
+    pub album: Option<String>,
+    pub upload_date: Option<String>,
+    /// The length of the video in seconds.
+    pub duration: Option<f64>,
+    pub description: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub audio_url: String,
+    pub audio_file_size: f64,
+    /// The full info JSON yt-dlp reported, kept for provenance.
+    pub info: Option<serde_json::Value>,
+}
+
+/// Options for reaching videos that need authentication, a proxy, or extra yt-dlp flags.
+#[derive(Clone, Debug, Default)]
+pub struct SourceOptions {
+    /// A Netscape-format cookies file.
+    pub cookies: Option<PathBuf>,
+    /// The browser to load cookies from, as accepted by `yt-dlp --cookies-from-browser`.
+    pub cookies_from_browser: Option<String>,
+    pub proxy: Option<String>,
+    /// Extra arguments passed through to yt-dlp as is.
+    pub ytdlp_args: Vec<String>,
+    /// yt-dlp config files to load on top of the user's own, which yt-dlp reads by itself.
+    pub ytdlp_configs: Vec<PathBuf>,
+    /// Directories to load yt-dlp extractor plugins from, besides yt-dlp's default ones.
+    pub ytdlp_plugin_dirs: Vec<PathBuf>,
+    /// Whether to ignore the user's yt-dlp config files, so that runs do not depend on them.
+    pub ignore_ytdlp_config: bool,
+    /// How to retry yt-dlp when fetching metadata fails, e.g. on a network hiccup.
+    pub ytdlp_retries: Retries,
+}
+
+impl SourceOptions {
+    /// The arguments every yt-dlp run is given, before any particular to the run.
+    pub(crate) fn ytdlp_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(cookies) = &self.cookies {
+            args.extend(["--cookies".to_string(), cookies.display().to_string()]);
+        }
+        if let Some(browser) = &self.cookies_from_browser {
+            args.extend(["--cookies-from-browser".to_string(), browser.clone()]);
+        }
+        if let Some(proxy) = &self.proxy {
+            args.extend(["--proxy".to_string(), proxy.clone()]);
+        }
+        if self.ignore_ytdlp_config {
+            args.push("--ignore-config".to_string());
+        }
+        for config in &self.ytdlp_configs {
+            args.extend([
+                "--config-locations".to_string(),
+                config.display().to_string(),
+            ]);
+        }
+        for dir in &self.ytdlp_plugin_dirs {
+            args.extend(["--plugin-dirs".to_string(), dir.display().to_string()]);
+        }
+        args.extend(self.ytdlp_args.iter().cloned());
+        args
+    }
+
+    /// Fetches the info JSON of `url` with yt-dlp, and with `extra_args` after the rest,
+    /// retrying as configured.
+    pub(crate) async fn ytdlp_info(
+        &self,
+        url: &str,
+        extra_args: &[&str],
+        on_progress: impl FnMut(&str),
+    ) -> Result<Value> {
+        let mut args = self.ytdlp_args();
+        args.extend(extra_args.iter().map(|arg| arg.to_string()));
+        args.push(url.to_string());
+        dump_json(&args, self.ytdlp_retries, on_progress).await
+    }
+
+    /// Builds the HTTP client used for downloads, going through the same proxy as yt-dlp and
+    /// sending the cookies from the cookies file, if any.
+    pub fn http_client(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(cookies) = &self.cookies {
+            builder = builder.cookie_provider(Arc::new(read_cookies_file(cookies)?));
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Reads a Netscape-format cookies file, as exported by browser extensions and yt-dlp.
+fn read_cookies_file(path: &Path) -> Result<Jar> {
+    let jar = Jar::default();
+    for line in std::fs::read_to_string(path)?.lines() {
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let [domain, _, path, _, _, name, value] = fields[..] else {
+            continue;
+        };
+        let url = format!("https://{}{}", domain.trim_start_matches('.'), path).parse::<Url>()?;
+        jar.add_cookie_str(
+            &format!("{}={}; Domain={}; Path={}", name, value, domain, path),
+            &url,
+        );
+    }
+    Ok(jar)
+}
+
+/// Fetches the metadata of the video at `url` and picks its smallest m4a audio track, calling
+/// `on_progress` with each step yt-dlp logs along the way.
+pub async fn fetch_video(
+    url: &str,
+    options: &SourceOptions,
+    on_progress: impl FnMut(&str),
+) -> Result<Video> {
+    let info = options.ytdlp_info(url, &[], on_progress).await?;
+    if info.get("_type").is_some_and(|t| t == "playlist") {
+        return Err("URL does not point to a single video".into());
+    }
+    let video: SingleVideo = serde_json::from_value(info.clone())?;
+
+    let (audio_file_size, audio_url) = video
+        .formats
+        .ok_or("Missing video formats")?
+        .into_iter()
+        .filter(|f| f.ext.as_ref().is_some_and(|ext| ext == "m4a"))
+        .map(|f| (f.filesize.or(f.filesize_approx), f.url))
+        .filter(|(size, url)| size.is_some() && url.is_some())
+        .map(|(size, url)| (size.unwrap(), url.unwrap()))
+        .sorted_by(|a, b| f64::total_cmp(&a.0, &b.0))
+        .next()
+        .ok_or("No suitable audio tracks found")?;
+
+    Ok(Video {
+        id: video.id,
+        title: video.title.ok_or("Missing video title")?,
+        url: video.webpage_url.unwrap_or_else(|| url.to_string()),
+        channel: video.channel.or(video.uploader),
+        album: None,
+        upload_date: video.upload_date,
+        duration: video.duration.and_then(|duration| duration.as_f64()),
+        description: video.description,
+        thumbnail_url: video.thumbnail,
+        audio_url,
+        audio_file_size,
+        info: Some(info),
+    })
+}
+
+/// Reads the metadata of the local audio or video file at `path` from its ID3 tags or MP4
+/// metadata, falling back to its file name for the title, and extracts its audio track as m4a
+/// into `dir` unless it is m4a already.
+///
+/// The video ID, which names the output directory, is made from the artist and title, and
+/// ends in a hash of the file so that different files with the same tags are told apart.
+pub async fn local_video(path: &Path, dir: &Path) -> Result<Video> {
+    let tags = read_file_tags(path).unwrap_or_default();
+    // Chapters are kept in the form yt-dlp reports them in, where the rest of the pipeline
+    // looks for them. Without ffprobe, the file is taken to have none.
+    let chapters = read_chapters(path).await.unwrap_or_default();
+    // The hash leaves out the tags, so that embedding the transcript in them keeps the ID.
+    let hash = media_hash(&tokio::fs::read(path).await?);
+
+    let stem = path.file_stem().map_or_else(
+        || "audio".into(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+    let name = match (&tags.artist, &tags.title) {
+        (Some(artist), Some(title)) => format!("{} {}", artist, title),
+        (None, Some(title)) => title.clone(),
+        (_, None) => stem.clone(),
+    };
+    let id = format!("{}_{}", slug(&name), &hash[..8]);
+    let path = tokio::fs::canonicalize(path).await?;
+
+    let audio_path = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("m4a") => path.clone(),
+        _ => {
+            tokio::fs::create_dir_all(dir).await?;
+            let audio_path = dir.join(format!("{}.m4a", id));
+            if !audio_path.exists() {
+                extract_audio(&path, &audio_path).await?;
+            }
+            audio_path
+        }
+    };
+    Ok(Video {
+        id,
+        title: tags.title.clone().unwrap_or(stem),
+        url: format!("file://{}", path.display()),
+        channel: tags.artist.clone(),
+        album: tags.album.clone(),
+        upload_date: tags.upload_date(),
+        duration: tags.duration,
+        description: None,
+        thumbnail_url: None,
+        audio_url: format!("file://{}", audio_path.display()),
+        audio_file_size: tokio::fs::metadata(&audio_path).await?.len() as f64,
+        info: (!chapters.is_empty()).then(|| serde_json::json!({ "chapters": chapters })),
+    })
+}
+
+/// Lowercases `name` and joins its words with hyphens, for use in file names.
+pub fn slug(name: &str) -> String {
+    let slug = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .join("-");
+    match slug.is_empty() {
+        true => "audio".to_string(),
+        false => {
+            let slug = slug.chars().take(MAX_SLUG_LENGTH).collect::<String>();
+            slug.trim_end_matches('-').to_string()
+        }
+    }
+}
+
+/// Lists the URLs of the videos in the playlist or channel at `url`, without fetching the
+/// metadata of each video.
+pub async fn fetch_playlist(url: &str, options: &SourceOptions) -> Result<Vec<String>> {
+    let info = options
+        .ytdlp_info(url, &["--flat-playlist"], |_| {})
+        .await?;
+    let entries = info
+        .get("entries")
+        .and_then(|entries| entries.as_array())
+        .ok_or("URL does not point to a playlist or channel")?;
+    let urls = entries
+        .iter()
+        .filter_map(|entry| {
+            let url = entry.get("url").or_else(|| entry.get("webpage_url"))?;
+            url.as_str().map(str::to_string)
+        })
+        .collect();
+    Ok(urls)
+}
+
+/// Fails if an audio file of `size` bytes is too large to be transcribed.
+pub fn check_audio_size(size: f64) -> Result<()> {
+    if size >= MAX_AUDIO_FILE_SIZE {
+        return Err(format!(
+            "Audio file is too large to transcribe, max 25 MB, got {:.2} MB",
+            size / 1000.0 / 1000.0
+        )
+        .into());
+    }
+    Ok(())
+}
+
+impl Video {
+    /// Estimates the size of the part of the audio track covered by `clip`, assuming a constant
+    /// bitrate.
+    pub fn estimated_audio_size(&self, clip: &Clip) -> f64 {
+        match self.duration {
+            Some(duration) if duration > 0.0 && !clip.is_empty() => {
+                self.audio_file_size * clip.length(duration).max(0.0) / duration
+            }
+            _ => self.audio_file_size,
+        }
+    }
+}
+
+/// Downloads the audio track of `video`, reusing the copy in `cache` if the server reports that
+/// it is unchanged since it was cached, and caching it otherwise. `file://` URLs are read from
+/// disk without caching.
+pub async fn download_audio(
+    client: &Client,
+    video: &Video,
+    cache: Option<&Cache>,
+    strategy: DownloadStrategy,
+    on_progress: impl Fn(u64, u64),
+) -> Result<Vec<u8>> {
+    if let Some(path) = video.audio_url.strip_prefix("file://") {
+        return Ok(tokio::fs::read(path).await?);
+    }
+
+    let cached = cache.and_then(|cache| {
+        let entry = cache.audio_entry(&video.id)?;
+        let data = cache.get_blob(&entry.hash)?;
+        Some((entry, data))
+    });
+
+    let validators = cached.as_ref().map(|(entry, _)| &entry.validators);
+    let download =
+        download_file_if_modified(client, &video.audio_url, validators, strategy, on_progress);
+    match download.await? {
+        Download::NotModified => Ok(cached
+            .map(|(_, data)| data)
+            .ok_or("Unexpected 304 response")?),
+        Download::Modified { data, validators } => {
+            if let Some(cache) = cache {
+                let hash = cache.put_blob(&data)?;
+                cache.put_audio_entry(&video.id, &AudioEntry { hash, validators })?;
+            }
+            Ok(data)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Model {
+    #[default]
+    #[value(name = "whisper-1")]
+    #[serde(rename = "whisper-1")]
+    Whisper1,
+    #[value(name = "gpt-4o-transcribe")]
+    #[serde(rename = "gpt-4o-transcribe")]
+    Gpt4oTranscribe,
+    #[value(name = "gpt-4o-mini-transcribe")]
+    #[serde(rename = "gpt-4o-mini-transcribe")]
+    Gpt4oMiniTranscribe,
+}
+
+impl Model {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Model::Whisper1 => "whisper-1",
+            Model::Gpt4oTranscribe => "gpt-4o-transcribe",
+            Model::Gpt4oMiniTranscribe => "gpt-4o-mini-transcribe",
+        }
+    }
+
+    /// Whether the model reports timed segments, which SRT output, translation, segment
+    /// filtering, and confidence reports all depend on. The GPT-4o models only return text.
+    pub fn supports_timestamps(&self) -> bool {
+        *self == Model::Whisper1
+    }
+
+    /// Fails if the model cannot produce output that needs timestamps, naming the feature
+    /// (e.g. "SRT output") that needs them.
+    pub fn require_timestamps(&self, feature: &str) -> Result<()> {
+        if !self.supports_timestamps() {
+            return Err(format!(
+                "{} requires timestamps, which {} does not support, use whisper-1 instead",
+                feature,
+                self.as_str()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The list price in US dollars per minute of audio.
+    pub fn cost_per_minute(&self) -> f64 {
+        match self {
+            Model::Whisper1 | Model::Gpt4oTranscribe => 0.006,
+            Model::Gpt4oMiniTranscribe => 0.003,
+        }
+    }
+
+    fn response_format(&self) -> &'static str {
+        match self.supports_timestamps() {
+            true => "verbose_json",
+            false => "json",
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Checks that `temperature` is a sampling temperature the API accepts, between 0 and 1.
+pub fn check_temperature(temperature: f32) -> std::result::Result<f32, String> {
+    match (0.0..=1.0).contains(&temperature) {
+        true => Ok(temperature),
+        false => Err(format!(
+            "Expected a temperature between 0 and 1, got {}",
+            temperature
+        )),
+    }
+}
+
+/// Options controlling how the transcription model is run.
+#[derive(Clone, Debug, Default)]
+pub struct TranscriptionOptions {
+    pub model: Model,
+    /// The ISO-639-1 code of the spoken language, or `None` to have the model detect it.
+    pub language: Option<String>,
+    /// The sampling temperature, between 0 and 1, where 0 lets the model raise it automatically
+    /// when decoding fails.
+    pub temperature: f32,
+    /// Text to steer the model with, e.g. the spelling of names and jargon.
+    pub prompt: Option<String>,
+    /// Whether to also request word timings, which only whisper-1 reports.
+    pub word_timestamps: bool,
+}
+
+impl TranscriptionOptions {
+    /// The key the transcript of `clip` of `video` is cached under when transcribed with these
+    /// options.
+    pub fn cache_key<'a>(&'a self, video: &'a Video, clip: &Clip) -> TranscriptKey<'a> {
+        TranscriptKey {
+            video_id: &video.id,
+            model: self.model.as_str(),
+            language: self.language.as_deref(),
+            temperature: self.temperature,
+            clip: Some(clip.to_string()).filter(|_| !clip.is_empty()),
+            translate: false,
+            prompt: self.prompt.as_deref(),
+            word_timestamps: self.word_timestamps,
+            strip_silence: None,
+        }
+    }
+
+    /// The key the English translation of `clip` of `video` is cached under.
+    pub fn translation_cache_key<'a>(&'a self, video: &'a Video, clip: &Clip) -> TranscriptKey<'a> {
+        TranscriptKey {
+            language: None,
+            translate: true,
+            ..self.cache_key(video, clip)
+        }
+    }
+}
+
+/// Transcribes an m4a audio track on whichever provider in `pool` has headroom.
+pub async fn transcribe(
+    pool: &ProviderPool,
+    audio: Vec<u8>,
+    options: &TranscriptionOptions,
+) -> Result<Transcript> {
+    check_audio_size(audio.len() as f64)?;
+    let request = options.request(audio);
+    let (res, _) = pool
+        .run(|provider| {
+            let request = request.for_provider(&provider);
+            async move { openai::create_transcription(&provider.endpoint, request).await }
+        })
+        .await?;
+    pool.record_upload(res.upload_time);
+    Ok(res.into())
+}
+
+/// The prompt songs are transcribed with unless another is given, since prompts steer the style
+/// of the transcript as well as its spelling.
+pub const LYRICS_PROMPT: &str = "Song lyrics, one sung line per line:";
+
+/// The temperatures songs are retried at, in order, while their transcript falls into repetition
+/// loops, which sung and held notes often send the model into at low temperatures.
+pub const LYRICS_TEMPERATURES: [f32; 2] = [0.4, 0.8];
+
+/// The compression ratio above which a segment is taken to be stuck repeating itself.
+const LOOPING_COMPRESSION_RATIO: f64 = 2.4;
+
+/// Transcribes like [`transcribe`], retrying at each of `temperatures` above the configured one
+/// while segments repeat themselves, and keeping the transcript with the fewest such segments.
+pub async fn transcribe_with_fallback(
+    pool: &ProviderPool,
+    audio: Vec<u8>,
+    options: &TranscriptionOptions,
+    temperatures: &[f32],
+) -> Result<Transcript> {
+    let looping = |transcript: &Transcript| {
+        transcript
+            .segments
+            .iter()
+            .filter(|segment| {
+                segment
+                    .compression_ratio
+                    .is_some_and(|ratio| ratio > LOOPING_COMPRESSION_RATIO)
+            })
+            .count()
+    };
+    let mut best = transcribe(pool, audio.clone(), options).await?;
+    let mut best_looping = looping(&best);
+    for &temperature in temperatures.iter().filter(|&&t| t > options.temperature) {
+        if best_looping == 0 {
+            break;
+        }
+        let options = TranscriptionOptions {
+            temperature,
+            ..options.clone()
+        };
+        let transcript = transcribe(pool, audio.clone(), &options).await?;
+        let transcript_looping = looping(&transcript);
+        if transcript_looping < best_looping {
+            best = transcript;
+            best_looping = transcript_looping;
+        }
+    }
+    Ok(best)
+}
+
+/// Translates an m4a audio track into English, which only whisper-1 supports.
+pub async fn translate(
+    pool: &ProviderPool,
+    audio: Vec<u8>,
+    options: &TranscriptionOptions,
+) -> Result<Transcript> {
+    check_audio_size(audio.len() as f64)?;
+    options.model.require_timestamps("Translation")?;
+    let request = options.request(audio);
+    let (res, _) = pool
+        .run(|provider| {
+            let request = request.for_provider(&provider);
+            async move { openai::create_translation(&provider.endpoint, request).await }
+        })
+        .await?;
+    pool.record_upload(res.upload_time);
+    Ok(res.into())
+}
+
+impl TranscriptionOptions {
+    pub(crate) fn request(&self, audio: Vec<u8>) -> TranscriptionRequest {
+        TranscriptionRequest {
+            file: audio,
+            filename: "audio.m4a".to_string(),
+            model: self.model.as_str().to_string(),
+            language: self.language.clone(),
+            temperature: self.temperature,
+            prompt: self.prompt.clone(),
+            response_format: self.model.response_format(),
+            word_timestamps: self.word_timestamps,
+        }
+    }
+}
+
+impl TranscriptionRequest {
+    /// A copy of the request with the model renamed as `provider` names it.
+    pub(crate) fn for_provider(&self, provider: &Provider) -> TranscriptionRequest {
+        TranscriptionRequest {
+            model: provider.model.clone().unwrap_or_else(|| self.model.clone()),
+            ..self.clone()
+        }
+    }
+}
+
+impl From<VerboseTranscription> for Transcript {
+    /// Attaches each word to the segment it starts in, since the API reports words separately.
+    fn from(res: VerboseTranscription) -> Self {
+        let mut words = res.words.into_iter().peekable();
+        let count = res.segments.len();
+        let segments = res
+            .segments
+            .into_iter()
+            .enumerate()
+            .map(|(i, segment)| Segment {
+                words: words
+                    .peeking_take_while(|word| i + 1 == count || word.start < segment.end)
+                    .map(|word| Word {
+                        word: word.word,
+                        start: word.start,
+                        end: word.end,
+                    })
+                    .collect(),
+                start: segment.start,
+                end: segment.end,
+                text: segment.text,
+                avg_logprob: segment.avg_logprob,
+                compression_ratio: segment.compression_ratio,
+                no_speech_prob: segment.no_speech_prob,
+                speaker: None,
+            })
+            .collect();
+        Transcript {
+            text: res.text,
+            language: res.language,
+            duration: res.duration,
+            segments,
+        }
+    }
+}
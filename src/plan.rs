@@ -0,0 +1,77 @@
+use crate::pipeline::Model;
+use crate::probe::AudioQuality;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+/// Below this signal-to-noise ratio, in dB, audio is considered noisy enough to be worth the
+/// larger model when balancing cost against accuracy.
+const NOISY_SNR: f64 = 35.0;
+
+/// What to optimize for when choosing a model automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preference {
+    /// The cheapest model.
+    Cost,
+    /// The cheapest model for clean audio, and the most accurate one for noisy audio.
+    Balanced,
+    /// The most accurate model.
+    Accuracy,
+}
+
+impl fmt::Display for Preference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Preference::Cost => "cost",
+            Preference::Balanced => "balanced",
+            Preference::Accuracy => "accuracy",
+        })
+    }
+}
+
+/// A model chosen for a job, and why.
+#[derive(Clone, Debug, Serialize)]
+pub struct Decision {
+    pub model: Model,
+    pub reason: String,
+}
+
+impl Preference {
+    /// Whether choosing a model needs the audio to be probed first.
+    pub fn needs_probe(&self) -> bool {
+        *self == Preference::Balanced
+    }
+
+    /// Chooses a model for audio of `quality`, which is only looked at when balancing, limited
+    /// to whisper-1 if `needs_timestamps`.
+    pub fn choose(&self, needs_timestamps: bool, quality: Option<&AudioQuality>) -> Decision {
+        let decision = |model, reason: String| Decision { model, reason };
+        if needs_timestamps {
+            let reason = "only whisper-1 reports the timestamps the requested output needs";
+            return decision(Model::Whisper1, reason.to_string());
+        }
+        match (self, quality) {
+            (Preference::Cost, _) => decision(
+                Model::Gpt4oMiniTranscribe,
+                "it is the cheapest model".to_string(),
+            ),
+            (Preference::Accuracy, _) => decision(
+                Model::Gpt4oTranscribe,
+                "it is the most accurate model".to_string(),
+            ),
+            (Preference::Balanced, Some(quality)) if quality.snr() < NOISY_SNR => decision(
+                Model::Gpt4oTranscribe,
+                format!("the audio is noisy ({:.0} dB SNR)", quality.snr()),
+            ),
+            (Preference::Balanced, Some(quality)) => decision(
+                Model::Gpt4oMiniTranscribe,
+                format!("the audio is clean ({:.0} dB SNR)", quality.snr()),
+            ),
+            (Preference::Balanced, None) => decision(
+                Model::Gpt4oTranscribe,
+                "the audio quality could not be measured".to_string(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use clap::ValueEnum;
+use std::io::Write;
+use transcribe::{
+    archive::{ArchiveFile, ArchiveItem},
+    clip::{format_timestamp, Clip},
+    describe::Chapter,
+    pipeline::{check_audio_size, Model, Video},
+    plan::Preference,
+    throughput::{Estimate, ThroughputHistory},
+    Result,
+};
+
+/// Lets the user pick which of the files of an archive.org item to transcribe, defaulting to
+/// [`ArchiveItem::best_file`]. Returns `None` if the user aborts.
+pub fn pick_archive_file(item: &ArchiveItem) -> Option<&ArchiveFile> {
+    let best = item.best_file();
+    if item.files.len() == 1 {
+        return Some(best);
+    }
+    println!("'{}' has {} media files:", item.title, item.files.len());
+    for (i, file) in item.files.iter().enumerate() {
+        let size = file
+            .size
+            .map(|size| format!("{:.2} MB", size as f64 / 1000.0 / 1000.0))
+            .unwrap_or_else(|| "unknown size".to_string());
+        let marker = if std::ptr::eq(file, best) { "*" } else { " " };
+        println!(
+            "{} [{}] {} ({}, {})",
+            marker,
+            i + 1,
+            file.name,
+            file.format,
+            size
+        );
+    }
+    loop {
+        let choice = prompt("File to transcribe [* by default, q to quit]: ");
+        match choice.as_str() {
+            "" => return Some(best),
+            "q" => return None,
+            _ => match choice.parse::<usize>() {
+                Ok(i) if (1..=item.files.len()).contains(&i) => return Some(&item.files[i - 1]),
+                _ => eprintln!("Expected a number between 1 and {}", item.files.len()),
+            },
+        }
+    }
+}
+
+/// The options that can still be changed after the video metadata has been fetched.
+pub struct Preflight {
+    pub model: Model,
+    pub language: Option<String>,
+    pub output_path: Option<String>,
+    pub clip: Clip,
+    /// The requested features that only work with a model that supports timestamps.
+    pub timestamp_features: Vec<&'static str>,
+    /// The longest clip, in seconds, that may be transcribed as a single job.
+    pub max_duration: Option<f64>,
+    /// The preference to choose the model by once the audio is available, instead of `model`.
+    pub plan: Option<Preference>,
+    /// Whether to split clips longer than `max_duration` into parts rather than refuse them.
+    pub split: bool,
+    /// How fast past jobs ran, to estimate how long this one will take.
+    pub history: Option<ThroughputHistory>,
+    /// The cache name of the local model to transcribe with instead of `model`.
+    pub local_model: Option<String>,
+    /// The chapters to transcribe as separate jobs, if transcribing by chapter.
+    pub chapters: Vec<Chapter>,
+    /// Whether to go ahead without asking, as when the audio was piped in on stdin, where the
+    /// answer would otherwise have been read from.
+    pub unattended: bool,
+}
+
+impl Preflight {
+    /// Summarizes the job and lets the user edit its options until they either confirm, which
+    /// returns `true`, or abort. Unattended jobs go ahead unless they are invalid.
+    pub fn confirm(&mut self, video: &Video) -> bool {
+        loop {
+            let parts = self.parts(video);
+            let size = parts
+                .iter()
+                .map(|part| video.estimated_audio_size(part))
+                .fold(0.0, f64::max);
+            println!("Transcribe '{}'?", video.title);
+            if let Some(duration) = video.duration {
+                println!("      Duration: {}", format_timestamp(duration));
+            }
+            if !self.chapters.is_empty() {
+                println!("      Chapters: {}", parts.len());
+            } else if parts.len() > 1 {
+                let max_duration = self.max_duration.unwrap_or_default();
+                println!(
+                    "      Parts:    {} of up to {}",
+                    parts.len(),
+                    format_timestamp(max_duration)
+                );
+            }
+            println!("      Audio:    {:.2} MB", size / 1000.0 / 1000.0);
+            if let Some(time) = self.estimate(video, &parts).describe() {
+                println!("      Time:     {}", time);
+            }
+            match self.plan {
+                Some(plan) => println!("  [m] Model:    auto, optimizing for {}", plan),
+                None => println!("  [m] Model:    {}", self.model),
+            }
+            println!(
+                "  [l] Language: {}",
+                self.language.as_deref().unwrap_or("auto-detect")
+            );
+            println!(
+                "  [o] Output:   {}",
+                self.output_path.as_deref().unwrap_or("stdout only")
+            );
+            match self.clip.is_empty() {
+                true => println!("  [c] Clip:     whole video"),
+                false => println!("  [c] Clip:     {}", self.clip),
+            }
+
+            if self.unattended {
+                return match self.validate(video, size) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        false
+                    }
+                };
+            }
+            match prompt("Proceed? [y/N/m/l/o/c] ").to_lowercase().as_str() {
+                "y" => match self.validate(video, size) {
+                    Ok(()) => return true,
+                    Err(err) => eprintln!("{}", err),
+                },
+                "m" => match Model::from_str(&prompt("Model: "), true) {
+                    Ok(model) => {
+                        self.model = model;
+                        self.plan = None;
+                    }
+                    Err(err) => eprintln!("{}", err),
+                },
+                "l" => {
+                    let language = prompt("Language (ISO-639-1 code, blank to auto-detect): ");
+                    self.language = Some(language.to_lowercase()).filter(|l| !l.is_empty());
+                }
+                "o" => {
+                    let path = prompt("Output path (blank for none): ");
+                    self.output_path = Some(path).filter(|p| !p.is_empty());
+                }
+                "c" => match prompt("Clip (START-END, blank for whole video): ").as_str() {
+                    "" => self.clip = Clip::default(),
+                    clip => match clip.parse() {
+                        Ok(clip) => self.clip = clip,
+                        Err(err) => eprintln!("{}", err),
+                    },
+                },
+                _ => return false,
+            }
+            println!();
+        }
+    }
+
+    /// The clips to transcribe as separate jobs, which is only the clip itself unless it is
+    /// longer than `max_duration` and splitting is enabled.
+    pub fn parts(&self, video: &Video) -> Vec<Clip> {
+        if !self.chapters.is_empty() {
+            return self
+                .chapter_parts()
+                .into_iter()
+                .map(|(clip, _)| clip)
+                .collect();
+        }
+        match (self.too_long(video), self.max_duration, video.duration) {
+            (true, Some(max_duration), Some(duration)) if self.split => {
+                self.clip.split(duration, max_duration)
+            }
+            _ => vec![self.clip],
+        }
+    }
+
+    /// Estimates how long transcribing `parts` of `video` takes from the throughput history.
+    /// Transcription is not estimated when the model is yet to be planned.
+    fn estimate(&self, video: &Video, parts: &[Clip]) -> Estimate {
+        let Some(history) = &self.history else {
+            return Estimate::default();
+        };
+        let seconds = video.duration.map_or(0.0, |duration| {
+            parts.iter().map(|part| part.length(duration)).sum()
+        });
+        let model = match (&self.local_model, self.plan) {
+            (Some(local_model), _) => local_model.as_str(),
+            (None, Some(_)) => "",
+            (None, None) => self.model.as_str(),
+        };
+        let cut = parts.len() > 1 || !self.clip.is_empty();
+        history.estimate(video.audio_file_size, seconds, model, cut)
+    }
+
+    /// The part of `clip` in each chapter it overlaps, with that chapter.
+    pub fn chapter_parts(&self) -> Vec<(Clip, &Chapter)> {
+        let start = self.clip.offset();
+        let end = self.clip.end.unwrap_or(f64::INFINITY);
+        self.chapters
+            .iter()
+            .filter(|chapter| chapter.start_time < end && chapter.end_time > start)
+            .map(|chapter| {
+                let clip = Clip {
+                    start: Some(chapter.start_time.max(start)).filter(|start| *start > 0.0),
+                    end: Some(chapter.end_time.min(end)),
+                };
+                (clip, chapter)
+            })
+            .collect()
+    }
+
+    fn too_long(&self, video: &Video) -> bool {
+        if !self.chapters.is_empty() {
+            let max_duration = self.max_duration.unwrap_or(f64::INFINITY);
+            return self
+                .chapter_parts()
+                .iter()
+                .any(|(clip, _)| clip.length(f64::INFINITY) > max_duration);
+        }
+        match (self.max_duration, video.duration) {
+            (Some(max_duration), Some(duration)) => self.clip.length(duration) > max_duration,
+            _ => false,
+        }
+    }
+
+    fn validate(&self, video: &Video, size: f64) -> Result<()> {
+        let max_duration = format_timestamp(self.max_duration.unwrap_or_default());
+        if self.too_long(video) && !self.chapters.is_empty() {
+            return Err(format!(
+                "A chapter is longer than the maximum duration of {}",
+                max_duration
+            )
+            .into());
+        }
+        if self.too_long(video) && !self.split {
+            return Err(format!(
+                "The clip is longer than the maximum duration of {}, pass --split to transcribe it in parts",
+                max_duration
+            )
+            .into());
+        }
+        check_audio_size(size)?;
+        if self.plan.is_some() {
+            return Ok(());
+        }
+        for feature in &self.timestamp_features {
+            self.model.require_timestamps(feature)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn prompt(message: &str) -> String {
+    let mut input = String::new();
+    print!("{}", message);
+    std::io::stdout().flush().unwrap();
+    std::io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
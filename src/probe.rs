@@ -0,0 +1,59 @@
+use crate::ffmpeg::temp_path;
+use crate::Result;
+use tokio::process::Command;
+
+/// Loudness statistics of an audio track, in dBFS, as measured by ffmpeg's `astats` filter.
+#[derive(Clone, Copy, Debug)]
+pub struct AudioQuality {
+    pub rms_level: f64,
+    /// The level of the quietest stretches of the track, which is the background noise in
+    /// recordings without true silence.
+    pub noise_floor: f64,
+}
+
+impl AudioQuality {
+    /// A rough signal-to-noise ratio in dB: around 50 for studio recordings, and under 30 for
+    /// noisy rooms or phone audio.
+    pub fn snr(&self) -> f64 {
+        self.rms_level - self.noise_floor
+    }
+}
+
+/// Measures the loudness of an m4a audio track.
+pub async fn probe_audio(audio: &[u8]) -> Result<AudioQuality> {
+    let path = temp_path("m4a");
+    tokio::fs::write(&path, audio).await?;
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(&path)
+        .args([
+            "-af",
+            "astats=measure_perchannel=none:measure_overall=RMS_level+Noise_floor",
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .await;
+    let _ = tokio::fs::remove_file(&path).await;
+    let output = output.map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stat = |name: &str| {
+        stderr
+            .lines()
+            .find_map(|line| line.split_once(name)?.1.trim().parse::<f64>().ok())
+            .ok_or_else(|| format!("ffmpeg did not report the {}", name.trim_end_matches(':')))
+    };
+    Ok(AudioQuality {
+        rms_level: stat("RMS level dB:")?,
+        noise_floor: stat("Noise floor dB:")?,
+    })
+}
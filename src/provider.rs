@@ -0,0 +1,300 @@
+use crate::openai::{ApiError, Endpoint, RateLimits, DEFAULT_API_BASE};
+use crate::pause::wait_while_paused;
+use crate::Result;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a provider is avoided after a server or network error, or a rate limit error that
+/// did not say when to retry, unless its configuration says otherwise.
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How many requests in a row can fail with a server or network error before a provider is
+/// given up on for the rest of the pool's lifetime.
+pub const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+/// A provider as configured in a providers file, which is a JSON array of these.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProviderConfig {
+    pub name: String,
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    /// The environment variable holding the API key.
+    pub api_key_env: String,
+    /// The model name to send instead of the requested one, for providers that name their
+    /// models differently, e.g. `whisper-large-v3`.
+    pub model: Option<String>,
+    /// How long to avoid the provider after a failed request, in milliseconds, instead of 5s.
+    pub retry_delay_ms: Option<u64>,
+}
+
+fn default_base_url() -> String {
+    DEFAULT_API_BASE.to_string()
+}
+
+/// An OpenAI-compatible transcription API.
+#[derive(Clone, Debug)]
+pub struct Provider {
+    pub name: String,
+    pub endpoint: Endpoint,
+    pub model: Option<String>,
+    /// How long the provider is avoided after a failed request that did not say when to retry.
+    pub retry_delay: Duration,
+}
+
+#[derive(Debug, Default)]
+struct ProviderState {
+    /// The requests left before the provider's rate limit, as of its last response.
+    remaining_requests: Option<u64>,
+    /// Until when the provider is skipped, after running out of requests or being rate limited.
+    blocked_until: Option<Instant>,
+    in_flight: u64,
+    /// The number of requests in a row that failed with a server or network error.
+    failures: u32,
+    /// Whether the provider was given up on after too many failures.
+    failed: bool,
+}
+
+impl ProviderState {
+    fn headroom(&self) -> u64 {
+        self.remaining_requests
+            .unwrap_or(u64::MAX)
+            .saturating_sub(self.in_flight)
+    }
+
+    fn record(&mut self, limits: &RateLimits, retry_delay: Duration) {
+        self.remaining_requests = limits.remaining_requests;
+        if limits.remaining_requests == Some(0) {
+            let reset = limits.reset_requests.unwrap_or(retry_delay);
+            self.blocked_until = Some(Instant::now() + reset);
+        }
+    }
+}
+
+/// A provider that was given up on after failing persistently.
+#[derive(Clone, Debug, Serialize)]
+pub struct Failover {
+    pub provider: String,
+    /// The last error the provider responded with.
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    providers: Vec<ProviderState>,
+    /// The indices of the providers that served requests, in order of first use.
+    used: Vec<usize>,
+    failovers: Vec<Failover>,
+    /// The time spent uploading audio since it was last taken.
+    upload_time: Duration,
+}
+
+/// Routes requests to whichever of several providers has the most rate limit headroom, going
+/// by the `x-ratelimit-*` headers of their responses, and retries requests that hit a rate
+/// limit on another provider.
+///
+/// Requests that fail with a server or network error are also retried, and a provider that
+/// fails [`MAX_CONSECUTIVE_FAILURES`] times in a row is failed over from, so that the remaining
+/// requests go to the other providers.
+pub struct ProviderPool {
+    providers: Vec<Provider>,
+    state: Mutex<PoolState>,
+}
+
+impl ProviderPool {
+    pub fn new(providers: Vec<Provider>) -> Result<Self> {
+        if providers.is_empty() {
+            return Err("No providers configured".into());
+        }
+        let state = PoolState {
+            providers: providers.iter().map(|_| ProviderState::default()).collect(),
+            ..PoolState::default()
+        };
+        Ok(ProviderPool {
+            providers,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// A pool with only OpenAI itself.
+    pub fn openai(api_key: impl Into<String>) -> Self {
+        ProviderPool::new(vec![Provider {
+            name: "openai".to_string(),
+            endpoint: Endpoint::openai(api_key),
+            model: None,
+            retry_delay: DEFAULT_BACKOFF,
+        }])
+        .expect("pool has a provider")
+    }
+
+    /// Loads the providers in the JSON file at `path`, reading their API keys from the
+    /// environment.
+    pub fn load(path: &Path) -> Result<Self> {
+        let configs: Vec<ProviderConfig> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let providers = configs
+            .into_iter()
+            .map(|config| {
+                let api_key = std::env::var(&config.api_key_env).map_err(|_| {
+                    format!(
+                        "Missing {} for provider '{}'",
+                        config.api_key_env, config.name
+                    )
+                })?;
+                Ok(Provider {
+                    name: config.name,
+                    endpoint: Endpoint {
+                        base_url: config.base_url.trim_end_matches('/').to_string(),
+                        api_key,
+                    },
+                    model: config.model,
+                    retry_delay: config
+                        .retry_delay_ms
+                        .map_or(DEFAULT_BACKOFF, Duration::from_millis),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ProviderPool::new(providers)
+    }
+
+    pub fn providers(&self) -> &[Provider] {
+        &self.providers
+    }
+
+    /// The names of the providers that have served requests so far, in order of first use.
+    pub fn used(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let used = state.used.iter();
+        used.map(|&i| self.providers[i].name.clone()).collect()
+    }
+
+    /// The providers that have been failed over from so far.
+    pub fn failovers(&self) -> Vec<Failover> {
+        self.state.lock().unwrap().failovers.clone()
+    }
+
+    /// Adds to the time spent uploading audio to the providers.
+    pub fn record_upload(&self, duration: Duration) {
+        self.state.lock().unwrap().upload_time += duration;
+    }
+
+    /// The time spent uploading audio to the providers since it was last taken, which starts
+    /// over from zero.
+    pub fn take_upload_time(&self) -> Duration {
+        std::mem::take(&mut self.state.lock().unwrap().upload_time)
+    }
+
+    /// Runs `request` against the provider with the most headroom, waiting for one to become
+    /// available if all are rate limited, and retrying on another provider if it is rate
+    /// limited or fails with a server or network error. Returns the response and the name of
+    /// the provider that served it.
+    pub async fn run<T, F, Fut>(&self, request: F) -> Result<(T, &str)>
+    where
+        F: Fn(Provider) -> Fut,
+        Fut: Future<Output = Result<(T, RateLimits)>>,
+    {
+        loop {
+            wait_while_paused().await;
+            let index = self.acquire().await?;
+            let result = request(self.providers[index].clone()).await;
+
+            let retry_delay = self.providers[index].retry_delay;
+            let mut pool = self.state.lock().unwrap();
+            let state = &mut pool.providers[index];
+            state.in_flight -= 1;
+            let err = match result {
+                Ok((response, limits)) => {
+                    state.record(&limits, retry_delay);
+                    state.failures = 0;
+                    if !pool.used.contains(&index) {
+                        pool.used.push(index);
+                    }
+                    return Ok((response, &self.providers[index].name));
+                }
+                Err(err) => err,
+            };
+
+            match err.downcast_ref::<ApiError>() {
+                Some(api) if api.status == StatusCode::TOO_MANY_REQUESTS => {
+                    state.record(&api.rate_limits, retry_delay);
+                    let backoff = api
+                        .retry_after
+                        .or(api.rate_limits.reset_requests)
+                        .unwrap_or(retry_delay);
+                    state.blocked_until = Some(Instant::now() + backoff);
+                }
+                _ if is_transient(&*err) => {
+                    state.failures += 1;
+                    state.blocked_until = Some(Instant::now() + retry_delay);
+                    if state.failures >= MAX_CONSECUTIVE_FAILURES {
+                        state.failed = true;
+                        pool.failovers.push(Failover {
+                            provider: self.providers[index].name.clone(),
+                            error: err.to_string(),
+                        });
+                        if pool.providers.iter().all(|state| state.failed) {
+                            return Err(err);
+                        }
+                    }
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
+    /// Picks the available provider with the most headroom, preferring those listed first on
+    /// ties, and counts a request as in flight on it. Fails if every provider has failed.
+    async fn acquire(&self) -> Result<usize> {
+        loop {
+            let wait = {
+                let mut pool = self.state.lock().unwrap();
+                let states = &mut pool.providers;
+                if states.iter().all(|state| state.failed) {
+                    return Err("Every provider has failed".into());
+                }
+                let now = Instant::now();
+                let available = states
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, state)| !state.failed)
+                    .filter(|(_, state)| state.blocked_until.is_none_or(|until| until <= now))
+                    .max_by_key(|(i, state)| (state.headroom(), std::cmp::Reverse(*i)))
+                    .map(|(i, _)| i);
+                match available {
+                    Some(index) => {
+                        let state = &mut states[index];
+                        state.blocked_until = None;
+                        state.in_flight += 1;
+                        return Ok(index);
+                    }
+                    None => states
+                        .iter()
+                        .filter(|state| !state.failed)
+                        .filter_map(|state| state.blocked_until)
+                        .min()
+                        .map(|until| until.saturating_duration_since(now))
+                        .unwrap_or(DEFAULT_BACKOFF),
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Whether `err` is worth retrying: a server error, a rate limit, a timeout, or a failure to
+/// connect. Other request errors, such as invalid URLs or TLS failures, would fail again.
+fn is_transient(err: &(dyn std::error::Error + 'static)) -> bool {
+    let is_transient_status =
+        |status: StatusCode| status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+    if let Some(err) = err.downcast_ref::<ApiError>() {
+        return is_transient_status(err.status);
+    }
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return err.is_timeout()
+            || err.is_connect()
+            || err.status().is_some_and(is_transient_status);
+    }
+    false
+}
@@ -0,0 +1,194 @@
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The state of an item in a [`DiskQueue`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum ItemState {
+    Pending,
+    Running,
+    Done,
+    Failed { error: String },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueueItem {
+    pub url: String,
+    #[serde(flatten)]
+    pub state: ItemState,
+    /// How many times the item has been started.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// A work queue persisted to a JSON file after every state transition, so that long-running
+/// jobs survive crashes and reboots. The file is replaced atomically, so it is never left
+/// half-written.
+pub struct DiskQueue {
+    path: PathBuf,
+    items: Vec<QueueItem>,
+}
+
+impl DiskQueue {
+    /// Opens the queue at `path`, or starts an empty one if it does not exist yet. Items left
+    /// running by a process that did not finish them are put back to pending.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut items: Vec<QueueItem> = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        for item in &mut items {
+            if item.state == ItemState::Running {
+                item.state = ItemState::Pending;
+            }
+        }
+        Ok(DiskQueue { path, items })
+    }
+
+    pub fn items(&self) -> &[QueueItem] {
+        &self.items
+    }
+
+    /// Adds the URLs that are not already in the queue as pending, returning how many were
+    /// added.
+    pub fn extend(&mut self, urls: impl IntoIterator<Item = String>) -> Result<usize> {
+        let count = self.items.len();
+        for url in urls {
+            if !self.items.iter().any(|item| item.url == url) {
+                self.items.push(QueueItem {
+                    url,
+                    state: ItemState::Pending,
+                    attempts: 0,
+                });
+            }
+        }
+        self.save()?;
+        Ok(self.items.len() - count)
+    }
+
+    /// Puts failed items back to pending so that they are retried.
+    pub fn retry_failed(&mut self) -> Result<()> {
+        for item in &mut self.items {
+            if matches!(item.state, ItemState::Failed { .. }) {
+                item.state = ItemState::Pending;
+            }
+        }
+        self.save()
+    }
+
+    /// Marks the first pending item as running and returns its URL.
+    pub fn start_next(&mut self) -> Result<Option<String>> {
+        let Some(item) = self
+            .items
+            .iter_mut()
+            .find(|item| item.state == ItemState::Pending)
+        else {
+            return Ok(None);
+        };
+        item.state = ItemState::Running;
+        item.attempts += 1;
+        let url = item.url.clone();
+        self.save()?;
+        Ok(Some(url))
+    }
+
+    pub fn finish(&mut self, url: &str, state: ItemState) -> Result<()> {
+        if let Some(item) = self.items.iter_mut().find(|item| item.url == url) {
+            item.state = state;
+        }
+        self.save()
+    }
+
+    /// Counts the items that are pending, done, and failed.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let count = |f: fn(&ItemState) -> bool| self.items.iter().filter(|i| f(&i.state)).count();
+        (
+            count(|state| matches!(state, ItemState::Pending | ItemState::Running)),
+            count(|state| *state == ItemState::Done),
+            count(|state| matches!(state, ItemState::Failed { .. })),
+        )
+    }
+
+    fn save(&self) -> Result<()> {
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&self.items)?)
+    }
+}
+
+/// Replaces the file at `path` with `data`, so that after a crash or power loss it holds either
+/// the old contents or the new ones in full.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    // Without syncing, the rename can reach the disk before the data, leaving an empty file.
+    file.sync_all()?;
+    fs::rename(tmp, path)?;
+    // The rename itself is only durable once the directory is synced, which Windows has no
+    // way to do through a `File`.
+    #[cfg(unix)]
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[test]
+    fn puts_items_left_running_back_to_pending() {
+        let dir = temp_dir("queue").unwrap();
+        let path = dir.join("queue.json");
+        let mut queue = DiskQueue::open(&path).unwrap();
+        let urls = ["https://example.com/1", "https://example.com/2"];
+        assert_eq!(queue.extend(urls.map(String::from)).unwrap(), 2);
+        assert_eq!(queue.start_next().unwrap().as_deref(), Some(urls[0]));
+        // The process crashes here, without finishing the item.
+        drop(queue);
+
+        let mut queue = DiskQueue::open(&path).unwrap();
+        assert_eq!(queue.items()[0].state, ItemState::Pending);
+        assert_eq!(queue.items()[0].attempts, 1);
+        assert_eq!(queue.counts(), (2, 0, 0));
+        assert_eq!(queue.start_next().unwrap().as_deref(), Some(urls[0]));
+        assert_eq!(queue.items()[0].attempts, 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_finished_items_and_retries_failed_ones() {
+        let dir = temp_dir("queue").unwrap();
+        let path = dir.join("queue.json");
+        let mut queue = DiskQueue::open(&path).unwrap();
+        let urls = ["https://example.com/1", "https://example.com/2"];
+        queue.extend(urls.map(String::from)).unwrap();
+        let first = queue.start_next().unwrap().unwrap();
+        queue.finish(&first, ItemState::Done).unwrap();
+        let second = queue.start_next().unwrap().unwrap();
+        let failed = ItemState::Failed {
+            error: "Video unavailable".to_string(),
+        };
+        queue.finish(&second, failed.clone()).unwrap();
+        assert_eq!(queue.start_next().unwrap(), None);
+
+        let mut queue = DiskQueue::open(&path).unwrap();
+        assert_eq!(queue.items()[1].state, failed);
+        assert_eq!(queue.counts(), (0, 1, 1));
+        // URLs already queued are not added again, whatever their state.
+        assert_eq!(queue.extend(urls.map(String::from)).unwrap(), 0);
+        queue.retry_failed().unwrap();
+        assert_eq!(queue.start_next().unwrap().as_deref(), Some(urls[1]));
+        assert!(!path.with_extension("tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+
+/// Readability statistics of a transcript, using formulas calibrated for English.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReadabilityReport {
+    pub words: usize,
+    pub sentences: usize,
+    pub average_sentence_length: f64,
+    pub average_syllables_per_word: f64,
+    /// The Flesch-Kincaid US school grade needed to follow the text.
+    pub grade_level: f64,
+    /// The Flesch reading ease, from 0 (very difficult) to 100 (very easy).
+    pub reading_ease: f64,
+    /// The Gunning fog index, another estimate of the grade level.
+    pub fog_index: f64,
+    /// The share of words with three or more syllables, which stands in for jargon density.
+    pub complex_word_ratio: f64,
+    /// The most frequent complex words, as candidates for a glossary.
+    pub complex_words: Vec<String>,
+}
+
+/// How many of the most frequent complex words are listed.
+const MAX_COMPLEX_WORDS: usize = 20;
+
+/// Computes readability statistics of `text`, or `None` if it has no words.
+pub fn readability(text: &str) -> Option<ReadabilityReport> {
+    let words = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| word.chars().any(|c| c.is_alphabetic()))
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return None;
+    }
+    let sentences = text
+        .split(['.', '!', '?'])
+        .filter(|sentence| sentence.chars().any(|c| c.is_alphabetic()))
+        .count()
+        .max(1);
+
+    let syllables = words
+        .iter()
+        .map(|word| count_syllables(word))
+        .collect::<Vec<_>>();
+    let mut complex = std::collections::HashMap::<String, usize>::new();
+    for (word, &count) in words.iter().zip(&syllables) {
+        if count >= 3 && !word.chars().next().is_some_and(char::is_uppercase) {
+            *complex.entry(word.to_lowercase()).or_default() += 1;
+        }
+    }
+    let complex_count = complex.values().sum::<usize>();
+    let mut complex_words = complex.into_iter().collect::<Vec<_>>();
+    complex_words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let word_count = words.len() as f64;
+    let sentence_length = word_count / sentences as f64;
+    let syllables_per_word = syllables.iter().sum::<usize>() as f64 / word_count;
+    let complex_word_ratio = complex_count as f64 / word_count;
+    Some(ReadabilityReport {
+        words: words.len(),
+        sentences,
+        average_sentence_length: sentence_length,
+        average_syllables_per_word: syllables_per_word,
+        grade_level: 0.39 * sentence_length + 11.8 * syllables_per_word - 15.59,
+        reading_ease: 206.835 - 1.015 * sentence_length - 84.6 * syllables_per_word,
+        fog_index: 0.4 * (sentence_length + 100.0 * complex_word_ratio),
+        complex_word_ratio,
+        complex_words: complex_words
+            .into_iter()
+            .take(MAX_COMPLEX_WORDS)
+            .map(|(word, _)| word)
+            .collect(),
+    })
+}
+
+/// Estimates the syllables of an English word by counting its groups of vowels, not counting a
+/// silent final `e`.
+fn count_syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    if word.ends_with('e') && !word.ends_with("le") && count > 1 {
+        count -= 1;
+    }
+    count.max(1)
+}
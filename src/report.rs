@@ -0,0 +1,100 @@
+use crate::clip::format_timestamp;
+use crate::transcript::{Segment, Transcript};
+
+/// Renders a standalone HTML page showing the transcript with each word shaded from red to green
+/// by the model's confidence, playing the corresponding snippet of `audio_src` on hover.
+///
+/// The API reports confidence per segment rather than per word, so every word in a segment
+/// shares its segment's confidence, and word timings are interpolated by character position.
+pub fn confidence_report(transcript: &Transcript, title: &str, audio_src: &str) -> String {
+    let body = transcript
+        .segments
+        .iter()
+        .map(render_segment)
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 48em; margin: 2em auto; line-height: 1.8; }}
+.segment {{ margin: 0 0 0.5em; }}
+.time {{ color: #888; font-size: 0.8em; margin-right: 0.5em; }}
+.word {{ border-radius: 3px; padding: 0 1px; cursor: pointer; }}
+.word:hover {{ outline: 1px solid #333; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>Words are shaded by confidence, from red (low) to green (high). Hover over a word to hear it.</p>
+<audio id="audio" src="{audio_src}" preload="auto"></audio>
+{body}
+<script>
+const audio = document.getElementById("audio");
+let stopAt = null;
+audio.addEventListener("timeupdate", () => {{
+  if (stopAt !== null && audio.currentTime >= stopAt) {{ audio.pause(); stopAt = null; }}
+}});
+document.querySelectorAll(".word").forEach((word) => {{
+  word.addEventListener("mouseenter", () => {{
+    audio.currentTime = parseFloat(word.dataset.start);
+    stopAt = parseFloat(word.dataset.end);
+    audio.play();
+  }});
+}});
+</script>
+</body>
+</html>
+"#,
+        title = escape(title),
+        audio_src = escape(audio_src),
+    )
+}
+
+fn render_segment(segment: &Segment) -> String {
+    let confidence = segment.confidence();
+    let color = confidence.map_or("transparent".to_string(), |confidence| {
+        format!("hsl({:.0}, 70%, 85%)", confidence * 120.0)
+    });
+    let label = confidence.map_or("unknown".to_string(), |confidence| {
+        format!("{:.0}%", confidence * 100.0)
+    });
+
+    let text = segment.text.trim();
+    let length = text.chars().count().max(1) as f64;
+    let duration = segment.end - segment.start;
+    let mut offset = 0;
+    let words = text
+        .split_whitespace()
+        .map(|word| {
+            let position = text[offset..].find(word).unwrap() + offset;
+            offset = position + word.len();
+            let start = text[..position].chars().count() as f64;
+            let end = start + word.chars().count() as f64;
+            format!(
+                r#"<span class="word" style="background: {}" title="{}" data-start="{:.2}" data-end="{:.2}">{}</span>"#,
+                color,
+                label,
+                segment.start + duration * start / length,
+                segment.start + duration * end / length,
+                escape(word)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        r#"<p class="segment"><span class="time">{}</span>{}</p>"#,
+        format_timestamp(segment.start),
+        words
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
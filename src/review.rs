@@ -0,0 +1,128 @@
+use crate::preflight::prompt;
+use clap::ValueEnum;
+use std::io::Write;
+use transcribe::{
+    clip::{format_timestamp, Clip},
+    output::Rerun,
+    pipeline::{transcribe, Model, TranscriptionOptions},
+    provider::ProviderPool,
+    transcript::Transcript,
+};
+
+/// Shows the confidence of each chunk of about `chunk_length` seconds of `transcript`, and lets
+/// the user pick chunks to re-transcribe with a different model or prompt until they are done,
+/// splicing the results in. `audio` is the audio the transcript was made from, starting at
+/// `offset` seconds into the video. Returns the chunks that were re-transcribed.
+pub async fn review(
+    transcript: &mut Transcript,
+    audio: &[u8],
+    offset: f64,
+    pool: &ProviderPool,
+    options: &TranscriptionOptions,
+    chunk_length: f64,
+) -> Vec<Rerun> {
+    let mut options = options.clone();
+    let mut reruns = vec![];
+    loop {
+        let chunks = transcript.chunks(chunk_length);
+        println!();
+        println!("  #  Time                 Confidence");
+        for (i, chunk) in chunks.iter().enumerate() {
+            let confidence = chunk
+                .confidence()
+                .map(|confidence| format!("{:.0}%", confidence * 100.0))
+                .unwrap_or_else(|| "unknown".to_string());
+            let time = format!(
+                "{}-{}",
+                format_timestamp(chunk.start),
+                format_timestamp(chunk.end)
+            );
+            println!("{:>3}  {:<20} {}", i + 1, time, confidence);
+        }
+
+        let selection = prompt("Chunks to re-run (e.g. 2,5-7, blank to finish): ");
+        if selection.is_empty() {
+            return reruns;
+        }
+        let selected = match parse_selection(&selection, chunks.len()) {
+            Ok(selected) => selected,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+
+        let model = prompt(&format!("Model [{}]: ", options.model));
+        if !model.is_empty() {
+            match Model::from_str(&model, true) {
+                Ok(model) => options.model = model,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    continue;
+                }
+            }
+        }
+        if let Err(err) = options.model.require_timestamps("Re-running chunks") {
+            eprintln!("{}", err);
+            continue;
+        }
+        let current = options.prompt.as_deref().unwrap_or("none");
+        let text = prompt(&format!("Prompt [{}, - for none]: ", current));
+        match text.as_str() {
+            "" => {}
+            "-" => options.prompt = None,
+            _ => options.prompt = Some(text),
+        }
+
+        for i in selected {
+            let chunk = &chunks[i];
+            print!("Re-transcribing chunk {}... ", i + 1);
+            std::io::stdout().flush().unwrap();
+            let result: transcribe::Result<Transcript> = async {
+                let clip = Clip::new(Some(chunk.start - offset), Some(chunk.end - offset))?;
+                let audio = clip.cut(audio).await?;
+                let mut replacement = transcribe(pool, audio, &options).await?;
+                replacement.offset(chunk.start);
+                Ok(replacement)
+            }
+            .await;
+            match result {
+                Ok(replacement) => {
+                    transcript.splice(chunk.start, chunk.end, replacement);
+                    reruns.push(Rerun {
+                        start: chunk.start,
+                        end: chunk.end,
+                        model: options.model,
+                        prompt: options.prompt.clone(),
+                    });
+                    println!("done.");
+                }
+                Err(err) => println!("failed: {}", err),
+            }
+        }
+    }
+}
+
+/// Parses a comma-separated list of 1-based chunk numbers and inclusive ranges into 0-based
+/// indices.
+fn parse_selection(s: &str, count: usize) -> Result<Vec<usize>, String> {
+    let invalid = || {
+        format!(
+            "Expected chunk numbers between 1 and {}, got '{}'",
+            count, s
+        )
+    };
+    let mut selected = vec![];
+    for part in s.split(',').map(str::trim) {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let first = first.trim().parse::<usize>().map_err(|_| invalid())?;
+        let last = last.trim().parse::<usize>().map_err(|_| invalid())?;
+        if first == 0 || last > count || first > last {
+            return Err(invalid());
+        }
+        selected.extend(first - 1..last);
+    }
+    selected.sort();
+    selected.dedup();
+    Ok(selected)
+}
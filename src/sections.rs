@@ -0,0 +1,214 @@
+use crate::clip::{format_timestamp, Clip};
+use crate::describe::chapters;
+use crate::pipeline::Video;
+use crate::transcript::Transcript;
+use crate::Result;
+use serde::Serialize;
+use std::path::Path;
+use tokio::process::Command;
+
+/// The shortest section, in seconds, proposed by default.
+pub const DEFAULT_MIN_SECTION: f64 = 120.0;
+
+/// How different consecutive frames must be, from 0 to 1, to count as a scene change. Cuts
+/// between the speaker and the slides, or to a new slide, score well above this.
+const SCENE_THRESHOLD: f64 = 0.3;
+
+/// The shortest pause in speech, in seconds, that is considered a boundary when the video has
+/// no scene changes, e.g. a single static camera.
+const MIN_PAUSE: f64 = 1.5;
+
+/// How many words of a section's opening sentence make up its proposed title.
+const TITLE_WORDS: usize = 8;
+
+/// What the section boundaries were taken from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Boundaries {
+    Chapters,
+    SceneChanges,
+    Pauses,
+}
+
+/// A proposed section of the video, with `start` and `end` in seconds.
+#[derive(Clone, Debug, Serialize)]
+pub struct Section {
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+}
+
+/// Finds the scene changes in `clip` of the video file at `video` with ffmpeg's scene detection,
+/// returning the time of each in seconds with how different the frames before and after were.
+pub async fn detect_scene_changes(video: &Path, clip: &Clip) -> Result<Vec<(f64, f64)>> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-hide_banner", "-nostats", "-loglevel", "error"]);
+    if let Some(start) = clip.start {
+        command.args(["-ss", &start.to_string()]);
+    }
+    command.arg("-i").arg(video);
+    if let Some(end) = clip.end {
+        command.args(["-t", &(end - clip.offset()).to_string()]);
+    }
+    // Frames are scaled down first, which is plenty to tell scenes apart and much faster.
+    let filter = format!(
+        "scale=160:-2,select='gt(scene,{})',metadata=print:file=-",
+        SCENE_THRESHOLD
+    );
+    let output = command
+        .args(["-an", "-vf", &filter, "-f", "null", "-"])
+        .output()
+        .await
+        .map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    // Each selected frame is printed as its timing, followed by its metadata.
+    let mut changes = vec![];
+    let mut time = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(pts_time) = line
+            .split_whitespace()
+            .find_map(|f| f.strip_prefix("pts_time:"))
+        {
+            time = pts_time.parse::<f64>().ok();
+        } else if let Some(score) = line.strip_prefix("lavfi.scene_score=") {
+            if let (Some(time), Ok(score)) = (time.take(), score.parse::<f64>()) {
+                changes.push((clip.offset() + time, score));
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Proposes sections of at least `min_length` seconds between `start` and `end`, from the
+/// video's chapters if it has any, or else at the strongest of `scene_changes`, or at the
+/// longest pauses in speech if there are none. Boundaries are moved to the nearest start of a
+/// segment so that sections do not split sentences, and each section is titled with the start
+/// of what is said in it.
+pub fn propose_sections(
+    video: &Video,
+    transcript: &Transcript,
+    scene_changes: &[(f64, f64)],
+    start: f64,
+    end: f64,
+    min_length: f64,
+) -> (Boundaries, Vec<Section>) {
+    let chapters = chapters(video);
+    if !chapters.is_empty() {
+        let sections = chapters
+            .into_iter()
+            .filter(|chapter| chapter.end_time > start && chapter.start_time < end)
+            .map(|chapter| Section {
+                start: chapter.start_time.max(start),
+                end: chapter.end_time.min(end),
+                title: chapter.title,
+            })
+            .collect();
+        return (Boundaries::Chapters, sections);
+    }
+
+    let segments = &transcript.segments;
+    let (boundaries, mut candidates) = match scene_changes.is_empty() {
+        false => (Boundaries::SceneChanges, scene_changes.to_vec()),
+        true => (
+            Boundaries::Pauses,
+            segments
+                .windows(2)
+                .map(|pair| (pair[1].start, pair[1].start - pair[0].end))
+                .filter(|(_, pause)| *pause >= MIN_PAUSE)
+                .collect(),
+        ),
+    };
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut cuts: Vec<f64> = vec![];
+    for (time, _) in candidates {
+        let time = segments
+            .iter()
+            .map(|segment| segment.start)
+            .min_by(|a, b| (a - time).abs().total_cmp(&(b - time).abs()))
+            .unwrap_or(time);
+        let far_enough = |other: f64| (time - other).abs() >= min_length;
+        if far_enough(start) && far_enough(end) && cuts.iter().all(|&cut| far_enough(cut)) {
+            cuts.push(time);
+        }
+    }
+    cuts.sort_by(f64::total_cmp);
+
+    let bounds = std::iter::once(start)
+        .chain(cuts)
+        .chain(std::iter::once(end))
+        .collect::<Vec<_>>();
+    let sections = bounds
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let mut words = segments
+                .iter()
+                .filter(|segment| segment.start >= pair[0] && segment.start < pair[1])
+                .flat_map(|segment| segment.text.split_whitespace())
+                .take(TITLE_WORDS + 1)
+                .collect::<Vec<_>>();
+            if let Some(end) = words
+                .iter()
+                .position(|word| word.ends_with(['.', '!', '?']))
+            {
+                words.truncate(end + 1);
+            }
+            let title = match words.len() {
+                0 => format!("Section {}", i + 1),
+                n if n > TITLE_WORDS => format!("{}…", words[..TITLE_WORDS].join(" ")),
+                _ => words.join(" "),
+            };
+            Section {
+                start: pair[0],
+                end: pair[1],
+                title,
+            }
+        })
+        .collect();
+    (boundaries, sections)
+}
+
+/// Lists the sections one per line as `<timestamp> <title>`, the format YouTube recognizes as
+/// chapters in a video's description.
+pub fn render_sections(sections: &[Section]) -> String {
+    sections
+        .iter()
+        .map(|section| {
+            format!(
+                "{} {}\n",
+                format_timestamp(section.start.floor()),
+                section.title
+            )
+        })
+        .collect()
+}
+
+/// Combines the transcripts of consecutive chapters into one, with each chapter's text headed
+/// by its title in plain text output. Timestamps are kept as they are, since each chapter's
+/// transcript already refers to the whole video.
+pub fn combine_chapters(chapters: &[(Section, Transcript)]) -> Transcript {
+    let text = chapters
+        .iter()
+        .map(|(section, transcript)| format!("{}\n\n{}", section.title, transcript.text.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Transcript {
+        text,
+        language: chapters
+            .iter()
+            .find_map(|(_, transcript)| transcript.language.clone()),
+        duration: chapters.last().map(|(section, _)| section.end),
+        segments: chapters
+            .iter()
+            .flat_map(|(_, transcript)| transcript.segments.iter().cloned())
+            .collect(),
+    }
+}
@@ -0,0 +1,580 @@
+use crate::cache::Cache;
+use crate::clip::{format_timestamp, Clip};
+use crate::download::DownloadStrategy;
+use crate::events::{Event, EventKind, EventPublisher};
+use crate::local::WarmModel;
+use crate::mirror;
+use crate::pause;
+use crate::pipeline::{
+    check_audio_size, check_temperature, download_audio, transcribe, Model, SourceOptions,
+    TranscriptionOptions,
+};
+use crate::provider::ProviderPool;
+use crate::source::Source;
+use crate::transcript::{Format, Transcript};
+use crate::Result;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Queued,
+    FetchingMetadata,
+    Downloading,
+    Transcribing,
+    Done,
+    Failed,
+}
+
+/// The state of a single transcription job, as reported by `GET /jobs/:id`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub url: String,
+    pub language: Option<String>,
+    pub model: Model,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    pub title: Option<String>,
+    pub stage: Stage,
+    /// Fraction of the current stage completed, between 0 and 1, where known.
+    pub progress: Option<f64>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub transcript: Option<Transcript>,
+    /// When the job was done or failed, after which it is only kept for a while.
+    #[serde(skip)]
+    pub finished_at: Option<Instant>,
+}
+
+/// What server jobs are transcribed with.
+pub enum Backend {
+    /// Whichever of the providers in the pool has headroom.
+    Api(ProviderPool),
+    /// A local model, which is loaded when the server starts and kept loaded between jobs.
+    Local(Box<WarmModel>),
+}
+
+struct AppState {
+    backend: Backend,
+    /// The longest video, in seconds, that jobs may transcribe.
+    max_duration: Option<f64>,
+    options: SourceOptions,
+    client: Client,
+    jobs: Mutex<HashMap<u64, Job>>,
+    next_id: AtomicU64,
+    /// How long, and how many of, the finished jobs are kept for.
+    retention: Retention,
+    queue: mpsc::Sender<u64>,
+    events: Option<EventPublisher>,
+    /// The archive of output directories served read-only under `/archive`, if any.
+    archive: Option<PathBuf>,
+}
+
+impl AppState {
+    fn update(&self, id: u64, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            f(job);
+        }
+    }
+
+    /// The jobs, without the finished ones that are past being kept.
+    fn jobs(&self) -> MutexGuard<'_, HashMap<u64, Job>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.retention.prune(&mut jobs);
+        jobs
+    }
+
+    /// The response for a job that is not among the ones kept: gone if it was created, and
+    /// otherwise not found.
+    fn missing(&self, id: u64) -> Response {
+        match id > 0 && id < self.next_id.load(Ordering::Relaxed) {
+            true => (StatusCode::GONE, "Job has expired").into_response(),
+            false => (StatusCode::NOT_FOUND, "No such job").into_response(),
+        }
+    }
+}
+
+/// How long finished jobs, along with their transcripts, are kept in memory for `GET /jobs/:id`
+/// to report, so that a long-running server does not keep every job it has run.
+#[derive(Clone, Copy, Debug)]
+pub struct Retention {
+    /// How long after finishing a job is removed.
+    pub ttl: Duration,
+    /// The most finished jobs kept, beyond which the ones that finished first are removed.
+    pub max_jobs: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            ttl: Duration::from_secs(24 * 60 * 60),
+            max_jobs: 1000,
+        }
+    }
+}
+
+impl Retention {
+    fn prune(&self, jobs: &mut HashMap<u64, Job>) {
+        jobs.retain(|_, job| job.finished_at.is_none_or(|at| at.elapsed() <= self.ttl));
+        let mut finished = jobs
+            .values()
+            .filter_map(|job| Some((job.finished_at?, job.id)))
+            .collect::<Vec<_>>();
+        if finished.len() > self.max_jobs {
+            finished.sort();
+            for (_, id) in &finished[..finished.len() - self.max_jobs] {
+                jobs.remove(id);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateJob {
+    url: String,
+    /// The ISO-639-1 code of the spoken language, detected automatically if omitted.
+    language: Option<String>,
+    #[serde(default)]
+    model: Model,
+    #[serde(default)]
+    temperature: f32,
+    prompt: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TranscriptQuery {
+    #[serde(default)]
+    format: Format,
+}
+
+/// How the server takes on jobs, and what else it serves.
+#[derive(Clone, Debug, Default)]
+pub struct ServeOptions {
+    /// The most pending jobs that may be queued.
+    pub queue_size: usize,
+    /// How many jobs run at a time, in order of submission.
+    pub workers: usize,
+    /// The longest video, in seconds, that jobs may transcribe; longer ones fail rather than run.
+    pub max_duration: Option<f64>,
+    /// An archive of output directories to serve read-only under `/archive`, for
+    /// `transcribe digest`, `history` and `re-export` to read elsewhere.
+    pub archive: Option<PathBuf>,
+    /// How long, and how many of, the finished jobs are kept for.
+    pub retention: Retention,
+}
+
+/// Serves the job queue API on `port` until the process is terminated, queueing and running jobs
+/// with `backend` as `serve_options` allow, and failing jobs for videos longer than its
+/// `max_duration` rather than running them. If `events` is given, the segments of each finished
+/// transcript and the outcome of each job are published to it.
+pub async fn serve(
+    port: u16,
+    backend: Backend,
+    serve_options: ServeOptions,
+    options: SourceOptions,
+    events: Option<EventPublisher>,
+) -> Result<()> {
+    let ServeOptions {
+        queue_size,
+        workers,
+        max_duration,
+        archive,
+        retention,
+    } = serve_options;
+    let (queue, jobs) = mpsc::channel(queue_size);
+    let jobs = Arc::new(tokio::sync::Mutex::new(jobs));
+    let state = Arc::new(AppState {
+        backend,
+        max_duration,
+        client: options.http_client()?,
+        options,
+        jobs: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+        retention,
+        queue,
+        events,
+        archive,
+    });
+
+    for _ in 0..workers.max(1) {
+        let worker = Arc::clone(&state);
+        let jobs = Arc::clone(&jobs);
+        tokio::spawn(async move {
+            loop {
+                let Some(id) = jobs.lock().await.recv().await else {
+                    break;
+                };
+                run_job(&worker, id).await;
+            }
+        });
+    }
+
+    if let Backend::Local(model) = &state.backend {
+        print!("Loading local model... ");
+        std::io::stdout().flush()?;
+        model.warm_up().await?;
+        println!("done.");
+    }
+
+    let app = Router::new()
+        .route("/warmup", post(warm_up))
+        .route("/pause", post(pause_work))
+        .route("/resume", post(resume_work))
+        .route("/jobs", post(create_job))
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/transcript", get(get_transcript))
+        .route("/archive/index.json", get(get_archive_index))
+        .route("/archive/*path", get(get_archive_file))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    println!("Listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn run_job(state: &AppState, id: u64) {
+    let result: Result<Transcript> = async {
+        let (url, options) = {
+            let job = &state.jobs.lock().unwrap()[&id];
+            let options = TranscriptionOptions {
+                model: job.model,
+                language: job.language.clone(),
+                temperature: job.temperature,
+                prompt: job.prompt.clone(),
+                word_timestamps: false,
+            };
+            (job.url.clone(), options)
+        };
+        state.update(id, |job| job.stage = Stage::FetchingMetadata);
+        // Whatever is submitted is taken as a URL, never as a path on this machine.
+        let video = Source::RemoteVideo(url)
+            .resolve_unattended(&state.client, &state.options, |_| {})
+            .await?;
+        state.update(id, |job| job.title = Some(video.title.clone()));
+        if let (Some(max_duration), Some(duration)) = (state.max_duration, video.duration) {
+            if duration > max_duration {
+                return Err(format!(
+                    "Video is longer than the maximum duration of {}",
+                    format_timestamp(max_duration)
+                )
+                .into());
+            }
+        }
+
+        let local_name = match &state.backend {
+            Backend::Local(model) => Some(model.model().cache_name()),
+            Backend::Api(_) => None,
+        };
+        let mut key = options.cache_key(&video, &Clip::default());
+        if let Some(name) = &local_name {
+            key.model = name;
+        }
+        let cache = Cache::open_default().ok();
+        if let Some(transcript) = cache.as_ref().and_then(|cache| cache.transcript(&key)) {
+            return Ok(transcript);
+        }
+
+        check_audio_size(video.audio_file_size)?;
+        state.update(id, |job| {
+            job.stage = Stage::Downloading;
+            job.progress = Some(0.0);
+        });
+        let audio = download_audio(
+            &state.client,
+            &video,
+            cache.as_ref(),
+            DownloadStrategy::default(),
+            |downloaded, total| {
+                state.update(id, |job| {
+                    job.progress = Some(downloaded as f64 / total as f64)
+                });
+            },
+        )
+        .await?;
+
+        state.update(id, |job| {
+            job.stage = Stage::Transcribing;
+            job.progress = None;
+        });
+        let transcript = match &state.backend {
+            Backend::Api(pool) => transcribe(pool, audio, &options).await?,
+            Backend::Local(model) => model.transcribe(&audio, &options, false).await?,
+        };
+        if let Some(cache) = &cache {
+            cache.put_transcript(&key, &transcript)?;
+        }
+        Ok(transcript)
+    }
+    .await;
+
+    state.update(id, |job| match result {
+        Ok(transcript) => {
+            job.stage = Stage::Done;
+            job.transcript = Some(transcript);
+            job.finished_at = Some(Instant::now());
+        }
+        Err(err) => {
+            job.stage = Stage::Failed;
+            job.progress = None;
+            job.error = Some(err.to_string());
+            job.finished_at = Some(Instant::now());
+        }
+    });
+
+    if let Some(events) = &state.events {
+        let job = state.jobs.lock().unwrap().get(&id).cloned();
+        if let Some(job) = job {
+            if let Err(err) = events.publish(&job_events(&job)).await {
+                eprintln!("Failed to publish events of job {}: {}", id, err);
+            }
+        }
+    }
+    state.retention.prune(&mut state.jobs.lock().unwrap());
+}
+
+/// The events of a finished job: one per segment of its transcript, followed by its outcome.
+fn job_events(job: &Job) -> Vec<Event> {
+    let mut events = vec![];
+    if let Some(transcript) = &job.transcript {
+        for (i, segment) in transcript.segments.iter().enumerate() {
+            events.push(Event {
+                kind: EventKind::Segment,
+                payload: json!({
+                    "job_id": job.id,
+                    "url": job.url,
+                    "title": job.title,
+                    "segment": i + 1,
+                    "start": segment.start,
+                    "end": segment.end,
+                    "speaker": segment.speaker,
+                    "text": segment.text.trim(),
+                }),
+            });
+        }
+    }
+    let transcript = job.transcript.as_ref();
+    events.push(Event {
+        kind: EventKind::Job,
+        payload: json!({
+            "job_id": job.id,
+            "url": job.url,
+            "title": job.title,
+            "stage": job.stage,
+            "error": job.error,
+            "language": transcript.and_then(|t| t.language.clone()),
+            "duration": transcript.and_then(|t| t.duration),
+            "segments": transcript.map(|t| t.segments.len()),
+            "text": transcript.map(|t| t.text.clone()),
+        }),
+    });
+    events
+}
+
+/// Loads the local model unless it is already loaded, e.g. so that a load balancer can wait for
+/// a new instance to be ready, and reports how long loading took.
+async fn warm_up(State(state): State<Arc<AppState>>) -> Response {
+    let Backend::Local(model) = &state.backend else {
+        return (
+            StatusCode::NOT_FOUND,
+            "The server does not use a local model",
+        )
+            .into_response();
+    };
+    match model.warm_up().await {
+        Ok(loaded) => Json(json!({
+            "ready": true,
+            "load_seconds": loaded.map(|duration| duration.as_secs_f64()),
+        }))
+        .into_response(),
+        Err(err) => (StatusCode::SERVICE_UNAVAILABLE, err.to_string()).into_response(),
+    }
+}
+
+/// Pauses downloads and API requests across all jobs, letting those under way finish.
+async fn pause_work() -> Json<serde_json::Value> {
+    pause::pause();
+    Json(json!({ "paused": true }))
+}
+
+async fn resume_work() -> Json<serde_json::Value> {
+    pause::resume();
+    Json(json!({ "paused": false }))
+}
+
+async fn create_job(State(state): State<Arc<AppState>>, Json(req): Json<CreateJob>) -> Response {
+    // The same check as the command line's, so that the API is not sent what it would refuse.
+    if let Err(err) = check_temperature(req.temperature) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, err).into_response();
+    }
+    let id = state.next_id.fetch_add(1, Ordering::Relaxed);
+    let job = Job {
+        id,
+        url: req.url,
+        language: req.language,
+        model: req.model,
+        temperature: req.temperature,
+        prompt: req.prompt,
+        title: None,
+        stage: Stage::Queued,
+        progress: None,
+        error: None,
+        transcript: None,
+        finished_at: None,
+    };
+    state.jobs().insert(id, job.clone());
+
+    if state.queue.try_send(id).is_err() {
+        state.jobs.lock().unwrap().remove(&id);
+        return (StatusCode::SERVICE_UNAVAILABLE, "Job queue is full").into_response();
+    }
+    (StatusCode::ACCEPTED, Json(job)).into_response()
+}
+
+async fn get_job(State(state): State<Arc<AppState>>, Path(id): Path<u64>) -> Response {
+    match state.jobs().get(&id) {
+        Some(job) => Json(job.clone()).into_response(),
+        None => state.missing(id),
+    }
+}
+
+async fn get_transcript(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+    Query(query): Query<TranscriptQuery>,
+) -> Response {
+    let jobs = state.jobs();
+    let Some(job) = jobs.get(&id) else {
+        return state.missing(id);
+    };
+    // Local models always produce timestamps, whichever API model the job asked for.
+    let local = matches!(state.backend, Backend::Local(_));
+    if query.format.is_subtitles()
+        && job.transcript.is_some()
+        && !job.model.supports_timestamps()
+        && !local
+    {
+        let message = format!(
+            "{} does not produce timestamps, so there is no {}",
+            job.model,
+            query.format.name()
+        );
+        return (StatusCode::UNPROCESSABLE_ENTITY, message).into_response();
+    }
+    let Some(transcript) = &job.transcript else {
+        let message = match job.stage {
+            Stage::Failed => "Job failed",
+            _ => "Job has not finished",
+        };
+        return (StatusCode::CONFLICT, message).into_response();
+    };
+    (
+        [(header::CONTENT_TYPE, query.format.content_type())],
+        transcript.render(query.format),
+    )
+        .into_response()
+}
+
+async fn get_archive_index(State(state): State<Arc<AppState>>) -> Response {
+    let Some(archive) = state.archive.clone() else {
+        return (StatusCode::NOT_FOUND, "No archive is served").into_response();
+    };
+    match tokio::task::spawn_blocking(move || mirror::index(&archive)).await {
+        Ok(Ok(index)) => Json(index).into_response(),
+        Ok(Err(err)) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn get_archive_file(
+    State(state): State<Arc<AppState>>,
+    Path(path): Path<String>,
+) -> Response {
+    let Some(archive) = &state.archive else {
+        return (StatusCode::NOT_FOUND, "No archive is served").into_response();
+    };
+    // Locks are left out of the index, and paths that would leave the archive are refused.
+    let file = mirror::resolve(archive, &path)
+        .filter(|file| file.extension().is_none_or(|ext| ext != "lock"));
+    let Some(file) = file else {
+        return (StatusCode::NOT_FOUND, "No such file").into_response();
+    };
+    match tokio::fs::read(&file).await {
+        Ok(contents) => contents.into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "No such file").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Jobs with the given IDs, finished as long ago as given, or still running for `None`.
+    fn jobs(finished: &[(u64, Option<u64>)]) -> HashMap<u64, Job> {
+        let now = Instant::now();
+        let job = |&(id, ago): &(u64, Option<u64>)| Job {
+            id,
+            url: format!("https://example.com/{}", id),
+            language: None,
+            model: Model::Whisper1,
+            temperature: 0.0,
+            prompt: None,
+            title: None,
+            stage: Stage::Queued,
+            progress: None,
+            error: None,
+            transcript: None,
+            finished_at: ago.map(|ago| now - Duration::from_secs(ago)),
+        };
+        finished.iter().map(|entry| (entry.0, job(entry))).collect()
+    }
+
+    fn ids(jobs: &HashMap<u64, Job>) -> Vec<u64> {
+        let mut ids = jobs.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn removes_finished_jobs_past_their_ttl() {
+        let retention = Retention {
+            ttl: Duration::from_secs(60),
+            max_jobs: 10,
+        };
+        let mut jobs = jobs(&[(1, Some(120)), (2, Some(30)), (3, None)]);
+        retention.prune(&mut jobs);
+        assert_eq!(ids(&jobs), [2, 3]);
+    }
+
+    #[test]
+    fn keeps_the_most_recently_finished_jobs_up_to_the_limit() {
+        let retention = Retention {
+            ttl: Duration::from_secs(3600),
+            max_jobs: 2,
+        };
+        let mut jobs = jobs(&[(1, Some(30)), (2, Some(20)), (3, Some(10)), (4, None)]);
+        retention.prune(&mut jobs);
+        // Jobs still running do not count towards the limit.
+        assert_eq!(ids(&jobs), [2, 3, 4]);
+    }
+}
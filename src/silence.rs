@@ -0,0 +1,169 @@
+use crate::clip::parse_timestamp;
+use crate::ffmpeg::{temp_path, transform};
+use crate::throughput::format_eta;
+use crate::timeline::Timeline;
+use crate::Result;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// The shortest silence, in seconds, left out of the audio by default.
+pub const DEFAULT_MIN_SILENCE: f64 = 2.0;
+
+/// How quiet audio must be to count as silence. Room tone in most recordings sits well below
+/// this, while even soft speech sits well above it.
+const SILENCE_THRESHOLD: &str = "-40dB";
+
+/// How much of each silence is kept at either end, so that the model hears where one
+/// utterance stops and the next starts, and quiet word endings are not cut off.
+const SILENCE_PADDING: f64 = 0.25;
+
+/// What stripping silence left out of the audio sent to the model.
+#[derive(Clone, Debug, Serialize)]
+pub struct StrippedSilence {
+    /// The number of silences left out.
+    pub silences: usize,
+    /// The seconds of audio left out.
+    pub removed_seconds: f64,
+    /// The seconds of audio sent to the model.
+    pub kept_seconds: f64,
+    /// What transcribing the left-out audio would have cost, in US dollars at list price.
+    pub saved_usd: f64,
+    /// Where each stretch of the audio sent to the model lies in the audio it was made from,
+    /// which the manifest records on the source's timeline instead.
+    #[serde(skip)]
+    pub timeline: Timeline,
+}
+
+impl StrippedSilence {
+    /// Describes what was left out, e.g. `12m 5s of silence in 40 places (9% of the audio),
+    /// saving $0.07`.
+    pub fn describe(&self) -> String {
+        let total = self.removed_seconds + self.kept_seconds;
+        let mut description = format!(
+            "{} of silence in {} places ({:.0}% of the audio)",
+            format_eta(Duration::from_secs_f64(self.removed_seconds)),
+            self.silences,
+            self.removed_seconds / total.max(f64::EPSILON) * 100.0
+        );
+        if self.saved_usd > 0.0 {
+            description.push_str(&format!(", saving ${:.2}", self.saved_usd));
+        }
+        description
+    }
+}
+
+/// Finds the silences of an m4a audio track at least `min_length` seconds long, returning the
+/// length of the track and each silence as `(start, end)` in seconds.
+pub async fn detect_silences(audio: &[u8], min_length: f64) -> Result<(f64, Vec<(f64, f64)>)> {
+    let path = temp_path("m4a");
+    tokio::fs::write(&path, audio).await?;
+    let filter = format!("silencedetect=noise={}:d={}", SILENCE_THRESHOLD, min_length);
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(&path)
+        .args(["-vn", "-af", &filter, "-f", "null", "-"])
+        .output()
+        .await;
+    let _ = tokio::fs::remove_file(&path).await;
+    let output = output.map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    // The filter logs `silence_start: 12.5` when a silence starts, and
+    // `silence_end: 15.1 | silence_duration: 2.6` when it ends, which it never does for a
+    // silence running to the end of the track.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let value = |line: &str, name: &str| -> Option<f64> {
+        let (_, rest) = line.split_once(name)?;
+        rest.split_whitespace().next()?.parse().ok()
+    };
+    let duration = stderr
+        .lines()
+        .find_map(|line| {
+            let (_, rest) = line.split_once("Duration:")?;
+            parse_timestamp(rest.split(',').next()?.trim()).ok()
+        })
+        .ok_or("ffmpeg did not report the length of the audio")?;
+    let mut silences = vec![];
+    let mut start = None;
+    for line in stderr.lines() {
+        if let Some(time) = value(line, "silence_start:") {
+            start = Some(time.max(0.0));
+        } else if let Some(end) = value(line, "silence_end:") {
+            if let Some(start) = start.take() {
+                silences.push((start, end.min(duration)));
+            }
+        }
+    }
+    if let Some(start) = start {
+        silences.push((start, duration));
+    }
+    Ok((duration, silences))
+}
+
+/// Leaves the silences of an m4a audio track at least `min_length` seconds long out of it,
+/// re-encoding what is left, and returns it with the timeline mapping it back onto the track.
+/// Returns `None` if the track has no such silences.
+pub async fn strip_silences(
+    audio: &[u8],
+    min_length: f64,
+    cost_per_minute: f64,
+) -> Result<Option<(Vec<u8>, StrippedSilence)>> {
+    let (duration, silences) = detect_silences(audio, min_length).await?;
+    let mut kept = vec![];
+    let mut cuts = 0;
+    let mut cursor = 0.0;
+    for (start, end) in &silences {
+        // Silences at the very start and end of the track need no padding on that side.
+        let cut_start = match *start <= 0.0 {
+            true => 0.0,
+            false => start + SILENCE_PADDING,
+        };
+        let cut_end = match *end >= duration {
+            true => duration,
+            false => end - SILENCE_PADDING,
+        };
+        if cut_end <= cut_start {
+            continue;
+        }
+        kept.push((cursor, cut_start));
+        cursor = cut_end;
+        cuts += 1;
+    }
+    kept.push((cursor, duration));
+    let timeline = Timeline::from_kept(&kept);
+    let kept_seconds = kept.iter().map(|(start, end)| end - start).sum::<f64>();
+    let removed_seconds = duration - kept_seconds;
+    if cuts == 0 || timeline.spans().is_empty() {
+        return Ok(None);
+    }
+
+    let selection = kept
+        .iter()
+        .filter(|(start, end)| end > start)
+        .map(|(start, end)| format!("between(t,{},{})", start, end))
+        .collect::<Vec<_>>()
+        .join("+");
+    let filter = format!("aselect='{}',asetpts=N/SR/TB", selection);
+    let stripped = transform(
+        audio,
+        &[],
+        &["-vn", "-af", &filter, "-c:a", "aac", "-b:a", "64k"],
+        "m4a",
+    )
+    .await?;
+    let report = StrippedSilence {
+        silences: cuts,
+        removed_seconds,
+        kept_seconds,
+        saved_usd: removed_seconds / 60.0 * cost_per_minute,
+        timeline,
+    };
+    Ok(Some((stripped, report)))
+}
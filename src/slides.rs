@@ -0,0 +1,193 @@
+use crate::clip::{format_timestamp, Clip};
+use crate::ffmpeg::ffmpeg;
+use crate::pipeline::Video;
+use crate::transcript::Transcript;
+use crate::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::process::Command;
+
+/// How often, in seconds, frames are sampled to look for slides.
+pub const DEFAULT_SLIDE_INTERVAL: f64 = 10.0;
+
+/// The share of words two frames must have in common to be taken for the same slide, allowing
+/// for OCR noise and bullets revealed one by one.
+const SAME_SLIDE_SIMILARITY: f64 = 0.6;
+
+/// Frames with fewer words than this are taken to show no slide, e.g. the speaker's camera.
+const MIN_SLIDE_WORDS: usize = 3;
+
+/// A slide shown in the video from `start` to `end`, in seconds, with the text OCR found on it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Slide {
+    pub start: f64,
+    pub end: f64,
+    pub title: Option<String>,
+    pub text: String,
+}
+
+/// Finds the slides shown in `clip` of the video file at `video`, sampling a frame every
+/// `interval` seconds into `dir` and reading it with `tesseract`, which must be installed.
+pub async fn extract_slides(
+    video: &Path,
+    clip: &Clip,
+    interval: f64,
+    dir: &Path,
+) -> Result<Vec<Slide>> {
+    let frames_dir = dir.join("frames");
+    tokio::fs::create_dir_all(&frames_dir).await?;
+
+    let mut args = vec![];
+    if let Some(start) = clip.start {
+        args.extend(["-ss".to_string(), start.to_string()]);
+    }
+    args.extend(["-i".to_string(), video.to_string_lossy().into_owned()]);
+    if let Some(end) = clip.end {
+        args.extend(["-t".to_string(), (end - clip.offset()).to_string()]);
+    }
+    args.extend([
+        "-vf".to_string(),
+        format!("fps=1/{}", interval),
+        frames_dir
+            .join("frame-%05d.png")
+            .to_string_lossy()
+            .into_owned(),
+    ]);
+    ffmpeg(args).await?;
+
+    let mut names = std::fs::read_dir(&frames_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    names.sort();
+    let mut frames = vec![];
+    for (i, frame) in names.iter().enumerate() {
+        let time = clip.offset() + i as f64 * interval;
+        frames.push((time, ocr(frame).await?));
+    }
+    let end = clip.offset() + names.len() as f64 * interval;
+    Ok(group_slides(frames, end))
+}
+
+/// Reads the text of the image at `path` with `tesseract`, one trimmed line per line of text.
+async fn ocr(path: &Path) -> Result<String> {
+    let output = Command::new("tesseract")
+        .arg(path)
+        .args(["stdout", "--psm", "3", "quiet"])
+        .output()
+        .await
+        .map_err(|err| format!("Failed to run tesseract: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "tesseract failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Groups the OCR text of consecutive frames, each taken at a time in seconds, into slides,
+/// keeping the fullest text of each in case its bullets were revealed one by one. The last
+/// slide ends at `end`.
+fn group_slides(frames: Vec<(f64, String)>, end: f64) -> Vec<Slide> {
+    let mut slides: Vec<(Slide, HashSet<String>)> = vec![];
+    let mut previous_slide = false;
+    for (time, text) in frames {
+        let frame_words = words(&text);
+        if let Some((slide, _)) = slides.last_mut().filter(|_| previous_slide) {
+            slide.end = time;
+        }
+        if frame_words.len() < MIN_SLIDE_WORDS {
+            previous_slide = false;
+            continue;
+        }
+
+        let same = slides
+            .last()
+            .filter(|_| previous_slide)
+            .is_some_and(|(_, words)| {
+                let common = words.intersection(&frame_words).count() as f64;
+                common / words.len().min(frame_words.len()) as f64 >= SAME_SLIDE_SIMILARITY
+            });
+        match slides.last_mut() {
+            Some((slide, words)) if same => {
+                if frame_words.len() > words.len() {
+                    slide.text = text;
+                    slide.title = title(&slide.text);
+                    *words = frame_words;
+                }
+            }
+            _ => slides.push((
+                Slide {
+                    start: time,
+                    end: time,
+                    title: title(&text),
+                    text,
+                },
+                frame_words,
+            )),
+        }
+        previous_slide = true;
+    }
+    if let Some((slide, _)) = slides.last_mut().filter(|_| previous_slide) {
+        slide.end = end;
+    }
+    slides.into_iter().map(|(slide, _)| slide).collect()
+}
+
+/// The first line of a slide with a couple of letters in it, which is usually its title.
+fn title(text: &str) -> Option<String> {
+    text.lines()
+        .find(|line| line.chars().filter(|c| c.is_alphabetic()).count() >= 2)
+        .map(str::to_string)
+}
+
+/// Writes Markdown lecture notes interleaving each slide, headed by its title and timestamp, with
+/// the transcript of what was said while it was shown.
+pub fn lecture_notes(video: &Video, transcript: &Transcript, slides: &[Slide]) -> String {
+    let mut notes = format!("# {}\n\n{}\n", video.title, video.url);
+    let mut segments = transcript.segments.iter().peekable();
+    let mut paragraph = |notes: &mut String, until: f64| {
+        let mut text = vec![];
+        while let Some(segment) = segments.next_if(|segment| segment.start < until) {
+            text.push(segment.text.trim());
+        }
+        if !text.is_empty() {
+            notes.push_str(&format!("\n{}\n", text.join(" ")));
+        }
+    };
+
+    for (i, slide) in slides.iter().enumerate() {
+        paragraph(&mut notes, slide.start);
+        notes.push_str(&format!(
+            "\n## [{}] {}\n\n",
+            format_timestamp(slide.start.floor()),
+            slide
+                .title
+                .as_deref()
+                .unwrap_or(&format!("Slide {}", i + 1))
+        ));
+        for line in slide.text.lines() {
+            notes.push_str(&format!("> {}\n", line));
+        }
+    }
+    paragraph(&mut notes, f64::INFINITY);
+    notes
+}
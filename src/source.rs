@@ -0,0 +1,240 @@
+use crate::archive::{
+    fetch_archive_item, fetch_archive_video, parse_item_url, ArchiveFile, ArchiveItem,
+};
+use crate::clip::format_timestamp;
+use crate::direct::{fetch_direct_video, is_media_url};
+use crate::live::{fetch_live_stream, LiveStream};
+use crate::p2p::{fetch_p2p_video, P2pSource};
+use crate::pipeline::{fetch_video, local_video, SourceOptions, Video};
+use crate::Result;
+use expanduser::expanduser;
+use reqwest::Client;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Where the audio to transcribe comes from, as given on the command line. Every source
+/// resolves to a [`Video`] whose audio is ready to be read, or to a [`LiveStream`] when
+/// transcribing live, so that what happens to the audio after that does not depend on where it
+/// came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// A video or audio file online: an archive.org item, a magnet link or IPFS file, a link
+    /// straight to a media file, or a page yt-dlp can read.
+    RemoteVideo(String),
+    /// An audio or video file on this machine.
+    LocalFile(PathBuf),
+    /// Audio or video piped in, given as `-`.
+    Stdin,
+    /// A microphone, given as `mic` for the default one or `mic:<device>` for another, which is
+    /// recorded until Enter is pressed, or transcribed as it goes when live.
+    Microphone(Option<String>),
+    /// A live stream, transcribed as it is broadcast.
+    LiveStream(String),
+}
+
+impl Source {
+    /// Parses a source given on the command line, which is a live stream if `live` is set,
+    /// unless it is the microphone.
+    pub fn parse(input: &str, live: bool) -> Self {
+        if input == "-" {
+            return Source::Stdin;
+        }
+        if input == "mic" {
+            return Source::Microphone(None);
+        }
+        if let Some(device) = input.strip_prefix("mic:") {
+            return Source::Microphone(Some(device.to_string()));
+        }
+        if live {
+            return Source::LiveStream(input.to_string());
+        }
+        match expanduser(input).ok().filter(|path| path.is_file()) {
+            Some(path) => Source::LocalFile(path),
+            None => Source::RemoteVideo(input.to_string()),
+        }
+    }
+
+    /// Whether the source can only be read once, so that nothing may be read from stdin
+    /// afterwards, such as the answer to whether to go ahead.
+    pub fn consumes_stdin(&self) -> bool {
+        matches!(self, Source::Stdin)
+    }
+
+    /// Fetches the metadata of the source and its audio, or records it, calling `on_progress`
+    /// with what is under way. For archive.org items with more than one audio file,
+    /// `pick_file` chooses the one to transcribe, and `None` is returned if it chooses none.
+    pub async fn resolve(
+        &self,
+        client: &Client,
+        options: &SourceOptions,
+        pick_file: impl FnOnce(&ArchiveItem) -> Option<&ArchiveFile>,
+        mut on_progress: impl FnMut(&str),
+    ) -> Result<Option<Video>> {
+        let temp_dir = |name: &str| std::env::temp_dir().join(format!("transcribe-{}", name));
+        // What is piped in or recorded always goes to the same file name, so each run gets a
+        // directory of its own for it.
+        let run_dir = |name: &str| temp_dir(&format!("{}-{}", name, std::process::id()));
+        let video = match self {
+            Source::RemoteVideo(url) => {
+                if let Some(identifier) = parse_item_url(url) {
+                    let item = fetch_archive_item(client, &identifier).await?;
+                    let Some(file) = pick_file(&item) else {
+                        return Ok(None);
+                    };
+                    fetch_archive_video(client, &item, file, &temp_dir("archive")).await?
+                } else if let Some(p2p) = P2pSource::parse(url) {
+                    fetch_p2p_video(client, &p2p, &temp_dir("p2p")).await?
+                } else if is_media_url(url) {
+                    fetch_direct_video(client, url, &temp_dir("direct")).await?
+                } else {
+                    // Without yt-dlp, or on sites it does not support, URLs of audio files
+                    // without an extension, e.g. from podcast hosts, can still be fetched
+                    // directly.
+                    match fetch_video(url, options, &mut on_progress).await {
+                        Ok(video) => video,
+                        Err(err) => fetch_direct_video(client, url, &temp_dir("direct"))
+                            .await
+                            .map_err(|_| err)?,
+                    }
+                }
+            }
+            Source::LocalFile(path) => local_video(path, &temp_dir("local")).await?,
+            Source::Stdin => {
+                let dir = run_dir("stdin");
+                tokio::fs::create_dir_all(&dir).await?;
+                let path = dir.join("stdin");
+                let mut file = tokio::fs::File::create(&path).await?;
+                tokio::io::copy(&mut tokio::io::stdin(), &mut file).await?;
+                file.flush().await?;
+                if tokio::fs::metadata(&path).await?.len() == 0 {
+                    return Err("Nothing was piped in on standard input".into());
+                }
+                local_video(&path, &dir).await?
+            }
+            Source::Microphone(device) => {
+                let dir = run_dir("mic");
+                tokio::fs::create_dir_all(&dir).await?;
+                let path = dir.join("microphone.m4a");
+                record_microphone(device.as_deref(), &path, &mut on_progress).await?;
+                Video {
+                    title: "Microphone recording".to_string(),
+                    ..local_video(&path, &dir).await?
+                }
+            }
+            Source::LiveStream(url) => fetch_video(url, options, &mut on_progress).await?,
+        };
+        Ok(Some(video))
+    }
+
+    /// Like [`Source::resolve`], for when nobody is there to answer: archive.org items are
+    /// transcribed from their best audio file, and standard input, the microphone and live
+    /// streams are refused.
+    pub async fn resolve_unattended(
+        &self,
+        client: &Client,
+        options: &SourceOptions,
+        on_progress: impl FnMut(&str),
+    ) -> Result<Video> {
+        match self {
+            Source::Stdin | Source::Microphone(_) | Source::LiveStream(_) => {
+                Err("Standard input, the microphone and live streams cannot be used here".into())
+            }
+            _ => self
+                .resolve(client, options, |item| Some(item.best_file()), on_progress)
+                .await?
+                .ok_or_else(|| "No audio file to transcribe".into()),
+        }
+    }
+
+    /// Finds the stream to transcribe live, which is the microphone itself or the audio of a
+    /// live stream.
+    pub async fn resolve_live(&self, options: &SourceOptions) -> Result<LiveStream> {
+        match self {
+            Source::LiveStream(url) => fetch_live_stream(url, options).await,
+            Source::Microphone(device) => {
+                let (format, input) = microphone_input(device.as_deref())?;
+                Ok(LiveStream {
+                    id: "microphone".to_string(),
+                    title: "Microphone".to_string(),
+                    stream_url: input,
+                    input_format: Some(format.to_string()),
+                })
+            }
+            _ => Err("Only live streams and the microphone can be transcribed live".into()),
+        }
+    }
+}
+
+/// The ffmpeg input format and input that record `device`, or the default microphone.
+fn microphone_input(device: Option<&str>) -> Result<(&'static str, String)> {
+    if cfg!(target_os = "macos") {
+        return Ok(("avfoundation", format!(":{}", device.unwrap_or("default"))));
+    }
+    if cfg!(windows) {
+        // DirectShow has no default device, so it has to be named, as listed by
+        // `ffmpeg -list_devices true -f dshow -i dummy`.
+        let device = device.ok_or("Name the microphone to record with mic:<device>")?;
+        return Ok(("dshow", format!("audio={}", device)));
+    }
+    Ok(("pulse", device.unwrap_or("default").to_string()))
+}
+
+/// Records `device`, or the default microphone, into the m4a file at `path` until Enter is
+/// pressed, calling `on_progress` with how long it has been recording every second.
+async fn record_microphone(
+    device: Option<&str>,
+    path: &Path,
+    on_progress: &mut impl FnMut(&str),
+) -> Result<()> {
+    let (format, input) = microphone_input(device)?;
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-y",
+            "-f",
+            format,
+            "-i",
+        ])
+        .arg(&input)
+        .args(["-vn", "-ac", "1", "-c:a", "aac", "-b:a", "64k"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("Failed to run ffmpeg: {}", err))?;
+    // Waiting on ffmpeg closes its stdin, which it is told to stop through, so it is held
+    // apart.
+    let mut stdin = ffmpeg.stdin.take().expect("stdin is piped");
+
+    // Enter is read on a thread of its own, as reading stdin blocks, and through the standard
+    // library's buffer, so that anything typed after it is left for later prompts.
+    let mut enter = tokio::task::spawn_blocking(|| std::io::stdin().read_line(&mut String::new()));
+    let started = Instant::now();
+    loop {
+        on_progress(&format!(
+            "recording {}, press Enter to stop",
+            format_timestamp(started.elapsed().as_secs_f64().floor())
+        ));
+        tokio::select! {
+            _ = &mut enter => break,
+            status = ffmpeg.wait() => {
+                return Err(format!("ffmpeg stopped recording with {}", status?).into());
+            }
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+    }
+
+    // ffmpeg finishes the file and exits when sent `q`.
+    stdin.write_all(b"q").await?;
+    drop(stdin);
+    let status = ffmpeg.wait().await?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed to finish the recording with {}", status).into());
+    }
+    Ok(())
+}
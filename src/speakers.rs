@@ -0,0 +1,142 @@
+use crate::preflight::prompt;
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use tokio::process::Command;
+use transcribe::{
+    clip::format_timestamp,
+    transcript::{Segment, Transcript},
+    Result,
+};
+
+/// How many lines of each speaker are shown when asking for their name.
+const SAMPLE_LINES: usize = 2;
+
+/// Parses a `--speakers` mapping, which either names the speakers in order of first
+/// appearance, e.g. `Alice,Bob`, or by their labels, e.g. `SPEAKER_01=Bob,SPEAKER_00=Alice`.
+/// Speakers left out keep their labels.
+pub fn parse_names(spec: &str, speakers: &[&str]) -> Result<BTreeMap<String, String>> {
+    let mut names = BTreeMap::new();
+    for (i, entry) in spec.split(',').map(str::trim).enumerate() {
+        let (speaker, name) = match entry.split_once('=') {
+            Some((speaker, name)) => (speaker.trim(), name.trim()),
+            None => {
+                let speaker = speakers.get(i).ok_or_else(|| {
+                    format!(
+                        "{} names were given, but the transcript has {} speakers",
+                        spec.split(',').count(),
+                        speakers.len()
+                    )
+                })?;
+                (*speaker, entry)
+            }
+        };
+        if !speakers.contains(&speaker) {
+            return Err(format!(
+                "The transcript has no speaker {}, only {}",
+                speaker,
+                speakers.join(", ")
+            )
+            .into());
+        }
+        if !name.is_empty() {
+            names.insert(speaker.to_string(), name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// The longest lines of `speaker`, which are the likeliest to tell who they are, in the order
+/// they were said.
+fn samples<'a>(transcript: &'a Transcript, speaker: &str) -> Vec<&'a Segment> {
+    let mut lines = transcript
+        .segments
+        .iter()
+        .filter(|segment| segment.speaker.as_deref() == Some(speaker))
+        .collect::<Vec<_>>();
+    lines.sort_by(|a, b| f64::total_cmp(&(b.end - b.start), &(a.end - a.start)));
+    lines.truncate(SAMPLE_LINES);
+    lines.sort_by(|a, b| f64::total_cmp(&a.start, &b.start));
+    lines
+}
+
+fn longest<'a>(lines: &[&'a Segment]) -> Option<&'a Segment> {
+    lines
+        .iter()
+        .copied()
+        .max_by(|a, b| f64::total_cmp(&(a.end - a.start), &(b.end - b.start)))
+}
+
+/// Asks for the name of each speaker of `transcript`, showing a few of their lines and
+/// offering to play the longest of them from `audio_url`, with the names in `suggested`, e.g.
+/// of recognized voices, as the defaults. Speakers left unnamed keep their labels.
+pub async fn name_speakers(
+    transcript: &Transcript,
+    audio_url: &str,
+    suggested: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut names = BTreeMap::new();
+    for speaker in transcript.speakers() {
+        let lines = samples(transcript, speaker);
+        println!();
+        println!("{}:", speaker);
+        for line in &lines {
+            println!(
+                "  [{}] {}",
+                format_timestamp(line.start.floor()),
+                line.text.trim()
+            );
+        }
+        let suggestion = suggested.get(speaker);
+        loop {
+            let name = match suggestion {
+                Some(suggestion) => prompt(&format!(
+                    "Name for {} [{}] (p to play a line, - for none): ",
+                    speaker, suggestion
+                )),
+                None => prompt(&format!(
+                    "Name for {} (p to play a line, blank to keep): ",
+                    speaker
+                )),
+            };
+            match name.as_str() {
+                "" => {
+                    if let Some(suggestion) = suggestion {
+                        names.insert(speaker.to_string(), suggestion.clone());
+                    }
+                    break;
+                }
+                "-" => break,
+                "p" => match longest(&lines) {
+                    Some(line) => {
+                        if let Err(err) = play(audio_url, line).await {
+                            eprintln!("{}", err);
+                        }
+                    }
+                    None => eprintln!("{} has no lines to play", speaker),
+                },
+                _ => {
+                    names.insert(speaker.to_string(), name);
+                    break;
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Plays the audio of `segment` from `audio_url` with ffplay.
+async fn play(audio_url: &str, segment: &Segment) -> Result<()> {
+    let status = Command::new("ffplay")
+        .args(["-hide_banner", "-loglevel", "error", "-nodisp", "-autoexit"])
+        .args(["-ss", &segment.start.to_string()])
+        .args(["-t", &(segment.end - segment.start).to_string()])
+        .arg(audio_url)
+        .stdin(Stdio::null())
+        .status()
+        .await
+        .map_err(|err| format!("Failed to run ffplay: {}", err))?;
+    if !status.success() {
+        return Err(format!("ffplay exited with {}", status).into());
+    }
+    Ok(())
+}
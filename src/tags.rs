@@ -0,0 +1,773 @@
+use crate::describe::Chapter;
+use crate::transcript::{Format, Transcript};
+use crate::Result;
+use itertools::Itertools;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use tokio::process::Command;
+
+/// How much of the start of a file to read to find its tags. ID3 tags sit at the very start of
+/// an MP3, and MP4 files written for streaming keep their metadata there too.
+pub const TAG_PROBE_SIZE: u64 = 256 * 1024;
+
+/// The metadata embedded in an audio or video file, from ID3 tags or MP4 metadata atoms.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// The recording or release date as given in the tag, e.g. `2023`, `2023-05-01`.
+    pub date: Option<String>,
+    /// The length in seconds, if the file records it.
+    pub duration: Option<f64>,
+}
+
+impl MediaTags {
+    /// Reads the tags from `head`, the start of a file, or returns `None` if it is neither an
+    /// ID3-tagged file nor an MP4 file with its metadata within `head`.
+    pub fn parse(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"ID3") {
+            return parse_id3(head);
+        }
+        match find_moov(head, 0)? {
+            MoovSearch::Found(start, end) => parse_moov(head.get(start as usize..end as usize)?),
+            MoovSearch::Next(_) => None,
+        }
+    }
+
+    /// Reads an ID3v1 tag from `tail`, the last 128 bytes of a file, which older MP3s carry
+    /// instead of or beside an ID3v2 tag.
+    pub fn parse_id3v1(tail: &[u8]) -> Option<Self> {
+        let tag = tail.strip_prefix(b"TAG")?;
+        let field = |range: std::ops::Range<usize>| {
+            let bytes = tag.get(range)?;
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            let text = latin1(&bytes[..end]).trim().to_string();
+            (!text.is_empty()).then_some(text)
+        };
+        Some(MediaTags {
+            title: field(0..30),
+            artist: field(30..60),
+            album: field(60..90),
+            date: field(90..94),
+            duration: None,
+        })
+    }
+
+    /// The date as YYYYMMDD, the form yt-dlp reports upload dates in, if the tag gives a full
+    /// date.
+    pub fn upload_date(&self) -> Option<String> {
+        let digits = self
+            .date
+            .as_deref()?
+            .chars()
+            .take_while(|c| *c != 'T' && *c != ' ')
+            .filter(char::is_ascii_digit)
+            .collect::<String>();
+        (digits.len() == 8).then_some(digits)
+    }
+
+    /// Fills in whatever `self` lacks from `other`.
+    pub fn or(self, other: MediaTags) -> MediaTags {
+        MediaTags {
+            title: self.title.or(other.title),
+            artist: self.artist.or(other.artist),
+            album: self.album.or(other.album),
+            date: self.date.or(other.date),
+            duration: self.duration.or(other.duration),
+        }
+    }
+}
+
+/// The most read to find the tags of a file, which covers ID3 tags with cover art and the
+/// metadata of MP4 files hours long.
+pub const MAX_TAG_SIZE: u64 = 32 * 1024 * 1024;
+
+/// How many top-level MP4 atoms to skip over looking for `moov`, which is at most a few.
+pub const MAX_ATOM_HOPS: usize = 8;
+
+/// Reads the tags of the audio or video file at `path`, which has none if it is neither
+/// ID3-tagged nor an MP4 file.
+pub fn read_file_tags(path: &Path) -> Result<MediaTags> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut read = |offset: u64, len: u64| -> Result<Vec<u8>> {
+        let mut data = vec![];
+        file.seek(SeekFrom::Start(offset))?;
+        (&mut file).take(len).read_to_end(&mut data)?;
+        Ok(data)
+    };
+
+    let start = read(0, TAG_PROBE_SIZE)?;
+    if let Some(tag_size) = id3_size(&start) {
+        let tag = read(0, tag_size.min(MAX_TAG_SIZE))?;
+        return Ok(MediaTags::parse(&tag).unwrap_or_default());
+    }
+    if let Some(mut search) = find_moov(&start, 0) {
+        for _ in 0..MAX_ATOM_HOPS {
+            match search {
+                MoovSearch::Found(from, to) => {
+                    let moov = read(from, (to - from).min(MAX_TAG_SIZE))?;
+                    return Ok(parse_moov(&moov).unwrap_or_default());
+                }
+                MoovSearch::Next(offset) if offset >= size => break,
+                MoovSearch::Next(offset) => match find_moov(&read(offset, 16)?, offset) {
+                    Some(next) => search = next,
+                    None => break,
+                },
+            }
+        }
+        return Ok(MediaTags::default());
+    }
+    // MP3s without an ID3v2 tag may still have an ID3v1 tag at their end.
+    let tail = read(size.saturating_sub(128), 128)?;
+    Ok(MediaTags::parse_id3v1(&tail).unwrap_or_default())
+}
+
+/// Reads the chapters of the audio or video file at `path` with `ffprobe`, e.g. those of an
+/// M4B audiobook or the ID3 `CHAP` frames of an MP3. Chapters without a title are numbered.
+pub async fn read_chapters(path: &Path) -> Result<Vec<Chapter>> {
+    #[derive(Deserialize)]
+    struct Probe {
+        #[serde(default)]
+        chapters: Vec<ProbeChapter>,
+    }
+    #[derive(Deserialize)]
+    struct ProbeChapter {
+        start_time: String,
+        end_time: String,
+        #[serde(default)]
+        tags: BTreeMap<String, String>,
+    }
+
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_chapters", "-of", "json"])
+        .arg(path)
+        .output()
+        .await
+        .map_err(|err| format!("Failed to run ffprobe: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    let probe = serde_json::from_slice::<Probe>(&output.stdout)?;
+    let chapters = probe
+        .chapters
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, chapter)| {
+            let title = chapter
+                .tags
+                .get("title")
+                .map(|title| title.trim().to_string())
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| format!("Chapter {}", i + 1));
+            Some(Chapter {
+                start_time: chapter.start_time.parse().ok()?,
+                end_time: chapter.end_time.parse().ok()?,
+                title,
+            })
+        })
+        .collect();
+    Ok(chapters)
+}
+
+/// The size of the ID3v2 tag at the start of `head`, counting its header and footer, if it
+/// starts with one.
+pub fn id3_size(head: &[u8]) -> Option<u64> {
+    if !head.starts_with(b"ID3") {
+        return None;
+    }
+    let footer = match head.get(5)? & 0x10 {
+        0 => 0,
+        _ => 10,
+    };
+    Some(10 + syncsafe(head.get(6..10)?) as u64 + footer)
+}
+
+/// Where the walk over the top-level atoms of an MP4 file for its `moov` atom got to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoovSearch {
+    /// The `moov` atom's contents lie at this byte range of the file, which ends at `u64::MAX`
+    /// if the atom runs to the end of the file.
+    Found(u64, u64),
+    /// The next top-level atom starts at this offset, past the data read so far. Files not
+    /// written for streaming keep `moov` after the media data, so it has to be read from there.
+    Next(u64),
+}
+
+/// Walks the top-level atoms in `data`, which starts at offset `base` of the file on an atom
+/// boundary, looking for `moov`. Returns `None` if `data` is the start of something other
+/// than an MP4 file.
+pub fn find_moov(data: &[u8], base: u64) -> Option<MoovSearch> {
+    if base == 0 && data.get(4..8)? != b"ftyp" {
+        return None;
+    }
+    let mut offset = 0u64;
+    loop {
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        // Only the size and type of an atom are needed, and a 64-bit size after them if set.
+        let Some(header) = data
+            .get(start..start.saturating_add(16).min(data.len()))
+            .filter(|h| h.len() >= 8)
+        else {
+            return Some(MoovSearch::Next(base.checked_add(offset)?));
+        };
+        let Some((kind, size, header_size)) = atom_header(header, u64::MAX) else {
+            // A 64-bit size may be cut off by the end of `data`.
+            return match header.len() < 16 {
+                true => Some(MoovSearch::Next(base.checked_add(offset)?)),
+                false => None,
+            };
+        };
+        if kind == *b"moov" {
+            let start = base.checked_add(offset)?;
+            // A size of zero runs to the end of the file, which `u64::MAX` stands in for.
+            let end = match size {
+                u64::MAX => u64::MAX,
+                size => start.checked_add(size)?,
+            };
+            return Some(MoovSearch::Found(start.checked_add(header_size)?, end));
+        }
+        if size == u64::MAX {
+            return None;
+        }
+        offset = offset.checked_add(size)?;
+    }
+}
+
+/// The type, total size, and header size of the atom at the start of `data`, where a size of
+/// zero means the atom runs to the end of its parent of `remaining` bytes.
+fn atom_header(data: &[u8], remaining: u64) -> Option<([u8; 4], u64, u64)> {
+    let size = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) as u64;
+    let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+    match size {
+        0 => Some((kind, remaining, 8)),
+        1 => {
+            let size = u64::from_be_bytes(data.get(8..16)?.try_into().ok()?);
+            (size >= 16).then_some((kind, size, 16))
+        }
+        size if size >= 8 => Some((kind, size, 8)),
+        _ => None,
+    }
+}
+
+/// The atoms directly inside `data`, as their type and contents.
+fn atoms(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    atom_spans(data).map(|(_, kind, body)| (kind, body))
+}
+
+/// Like [`atoms`], but with the offset of each atom's header in `data` as well.
+fn atom_spans(data: &[u8]) -> impl Iterator<Item = (usize, [u8; 4], &[u8])> {
+    let mut offset = 0usize;
+    std::iter::from_fn(move || {
+        let rest = data.get(offset..)?;
+        let (kind, size, header_size) = atom_header(rest, rest.len() as u64)?;
+        let body = rest.get(header_size as usize..size as usize)?;
+        let start = offset;
+        offset += size as usize;
+        Some((start, kind, body))
+    })
+}
+
+/// An atom inside its parent, as the offset of its header, its type, and its contents.
+type AtomSpan<'a> = (usize, [u8; 4], &'a [u8]);
+
+/// Like [`atom_spans`], but failing unless `data` is made up of whole atoms, for rewriting it
+/// without losing whatever follows an atom that cannot be parsed.
+fn parse_atoms(data: &[u8]) -> Result<Vec<AtomSpan<'_>>> {
+    let spans = atom_spans(data).collect::<Vec<_>>();
+    let parsed = spans.last().map_or(0, |(_, _, body)| {
+        body.as_ptr() as usize - data.as_ptr() as usize + body.len()
+    });
+    match parsed == data.len() {
+        true => Ok(spans),
+        false => Err(format!("MP4 atom at byte {} of its parent cannot be parsed", parsed).into()),
+    }
+}
+
+/// Like [`child`], but failing if the atoms before it cannot be parsed.
+fn parse_child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Result<Option<&'a [u8]>> {
+    Ok(parse_atoms(data)?
+        .into_iter()
+        .find(|(_, k, _)| k == kind)
+        .map(|(_, _, body)| body))
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data).find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+/// Reads the duration from `mvhd` and the iTunes-style tags from `udta/meta/ilst`.
+pub fn parse_moov(moov: &[u8]) -> Option<MediaTags> {
+    let mut tags = MediaTags::default();
+    if let Some(mvhd) = child(moov, b"mvhd") {
+        let (timescale, duration) = match mvhd.first()? {
+            1 => (
+                u32::from_be_bytes(mvhd.get(20..24)?.try_into().ok()?) as f64,
+                u64::from_be_bytes(mvhd.get(24..32)?.try_into().ok()?) as f64,
+            ),
+            _ => (
+                u32::from_be_bytes(mvhd.get(12..16)?.try_into().ok()?) as f64,
+                u32::from_be_bytes(mvhd.get(16..20)?.try_into().ok()?) as f64,
+            ),
+        };
+        tags.duration = (timescale > 0.0).then(|| duration / timescale);
+    }
+
+    let Some(meta) = child(moov, b"udta").and_then(|udta| child(udta, b"meta")) else {
+        return Some(tags);
+    };
+    // `meta` is a full atom with a version and flags before its children, except in some
+    // QuickTime files, where its first child follows straight away.
+    let meta = match meta.get(4..8) {
+        Some(b"hdlr") => meta,
+        _ => meta.get(4..)?,
+    };
+    let Some(ilst) = child(meta, b"ilst") else {
+        return Some(tags);
+    };
+    for (kind, item) in atoms(ilst) {
+        // The value follows a type indicator and a locale, each of four bytes.
+        let Some(value) = child(item, b"data").and_then(|data| data.get(8..)) else {
+            continue;
+        };
+        let value = String::from_utf8_lossy(value).trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match &kind {
+            b"\xa9nam" => tags.title = Some(value),
+            b"\xa9ART" => tags.artist = Some(value),
+            b"aART" if tags.artist.is_none() => tags.artist = Some(value),
+            b"\xa9alb" => tags.album = Some(value),
+            b"\xa9day" => tags.date = Some(value),
+            _ => {}
+        }
+    }
+    Some(tags)
+}
+
+/// Reads ID3v2.2, v2.3, and v2.4 text frames.
+fn parse_id3(data: &[u8]) -> Option<MediaTags> {
+    let version = *data.get(3)?;
+    let flags = *data.get(5)?;
+    let size = syncsafe(data.get(6..10)?) as usize;
+    let mut body = data.get(10..(10 + size).min(data.len()))?;
+    // The extended header is of no use here, so it is skipped.
+    if flags & 0x40 != 0 && version >= 3 {
+        let extended = match version {
+            3 => u32::from_be_bytes(body.get(0..4)?.try_into().ok()?) as usize + 4,
+            _ => syncsafe(body.get(0..4)?) as usize,
+        };
+        body = body.get(extended..)?;
+    }
+
+    let (id_size, header_size) = match version {
+        2 => (3, 6),
+        _ => (4, 10),
+    };
+    let mut tags = MediaTags::default();
+    let mut offset = 0;
+    while let Some(header) = body.get(offset..offset + header_size) {
+        if header[0] == 0 {
+            break;
+        }
+        let id = &header[..id_size];
+        let size = match version {
+            2 => u32::from_be_bytes([0, header[3], header[4], header[5]]) as usize,
+            3 => u32::from_be_bytes(header[4..8].try_into().ok()?) as usize,
+            _ => syncsafe(&header[4..8]) as usize,
+        };
+        let Some(frame) = body.get(offset + header_size..offset + header_size + size) else {
+            break;
+        };
+        offset += header_size + size;
+
+        let text = || id3_text(frame).filter(|text| !text.is_empty());
+        match id {
+            b"TIT2" | b"TT2" => tags.title = text(),
+            b"TPE1" | b"TP1" => tags.artist = text(),
+            b"TALB" | b"TAL" => tags.album = text(),
+            b"TDRC" | b"TYER" | b"TYE" if tags.date.is_none() => tags.date = text(),
+            b"TLEN" | b"TLE" => {
+                tags.duration = text()
+                    .and_then(|ms| ms.parse::<f64>().ok())
+                    .map(|ms| ms / 1000.0)
+            }
+            _ => {}
+        }
+    }
+    Some(tags)
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, byte| (size << 7) | (*byte & 0x7f) as u32)
+}
+
+/// Decodes an ID3 text frame, which starts with a byte giving its encoding. Frames may hold
+/// several values separated by nulls, of which the first is kept.
+fn id3_text(frame: &[u8]) -> Option<String> {
+    let (encoding, text) = frame.split_first()?;
+    let text = match encoding {
+        0 => latin1(text.split(|&b| b == 0).next()?),
+        1 | 2 => {
+            let big_endian = match text.get(0..2) {
+                Some([0xff, 0xfe]) => false,
+                Some([0xfe, 0xff]) => true,
+                _ => *encoding == 2,
+            };
+            let units = text
+                .chunks_exact(2)
+                .map(|pair| match big_endian {
+                    true => u16::from_be_bytes([pair[0], pair[1]]),
+                    false => u16::from_le_bytes([pair[0], pair[1]]),
+                })
+                .skip_while(|&unit| unit == 0xfeff)
+                .take_while(|&unit| unit != 0)
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        _ => {
+            let text = text.split(|&b| b == 0).next()?;
+            String::from_utf8_lossy(text).into_owned()
+        }
+    };
+    Some(text.trim().to_string())
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// A hash of the media data of a file, leaving out its ID3 tag or the MP4 atoms around its
+/// media data, so that it stays the same when the file's tags are rewritten.
+pub fn media_hash(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    match (id3_size(data), find_moov(data, 0)) {
+        (Some(size), _) => hasher.update(data.get(size as usize..).unwrap_or_default()),
+        (None, Some(_)) => {
+            for (kind, body) in atoms(data) {
+                if kind == *b"mdat" {
+                    hasher.update(body);
+                }
+            }
+        }
+        (None, None) => hasher.update(data),
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Writes `transcript` into the tags of the MP3 or MP4 file at `path`, as unsynchronized
+/// lyrics, which most players show alongside the audio. With `synced`, MP3s also get
+/// synchronized lyrics timed to each segment, and MP4s, which have no such tag, get the
+/// lyrics in LRC format, which some players follow along with.
+///
+/// Lyrics already in the file are replaced, and the rest of its tags are kept. The file is
+/// rewritten in full and swapped in once written, so that it is never left half-written.
+pub fn embed_lyrics(path: &Path, transcript: &Transcript, synced: bool) -> Result<()> {
+    let data = std::fs::read(path)?;
+    let lines = transcript
+        .segments
+        .iter()
+        .map(|segment| (segment.start, segment.text.trim().to_string()))
+        .filter(|(_, text)| !text.is_empty())
+        .collect::<Vec<_>>();
+    let text = match lines.is_empty() {
+        true => transcript.text.trim().to_string(),
+        false => lines.iter().map(|(_, text)| text.as_str()).join("\n"),
+    };
+
+    let is_mp3 = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"));
+    let tagged = if data.starts_with(b"ID3") || (is_mp3 && find_moov(&data, 0).is_none()) {
+        let synced = Some(&lines[..]).filter(|_| synced);
+        write_id3_lyrics(&data, &text, synced)?
+    } else if find_moov(&data, 0).is_some() {
+        let text = match synced {
+            true => transcript.render(Format::Lrc),
+            false => text,
+        };
+        write_mp4_lyrics(&data, &text)?
+    } else {
+        return Err("Lyrics can only be embedded in MP3 and MP4 files".into());
+    };
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tagging");
+    let temp = path.with_file_name(name);
+    let written = std::fs::write(&temp, tagged).and_then(|_| std::fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    Ok(written?)
+}
+
+/// Replaces the `USLT` and `SYLT` frames of the ID3v2.3 or v2.4 tag at the start of `data`,
+/// or adds an ID3v2.4 tag if there is none. Synchronized lyrics are given as the start of each
+/// line in seconds and its text.
+fn write_id3_lyrics(data: &[u8], text: &str, synced: Option<&[(f64, String)]>) -> Result<Vec<u8>> {
+    let (version, frames, audio) = match id3_size(data) {
+        Some(size) => {
+            let version = data[3];
+            let flags = data[5];
+            if !matches!(version, 3 | 4) {
+                return Err(format!("ID3v2.{} tags cannot be written", version).into());
+            }
+            if flags & 0x80 != 0 {
+                return Err("Unsynchronized ID3 tags cannot be written".into());
+            }
+            let body_end = (10 + syncsafe(&data[6..10]) as usize).min(data.len());
+            let mut body = &data[10..body_end];
+            if flags & 0x40 != 0 {
+                let extended = match version {
+                    3 => {
+                        u32::from_be_bytes(body.get(0..4).ok_or("Truncated ID3 tag")?.try_into()?)
+                            as usize
+                            + 4
+                    }
+                    _ => syncsafe(body.get(0..4).ok_or("Truncated ID3 tag")?) as usize,
+                };
+                body = body.get(extended..).ok_or("Truncated ID3 tag")?;
+            }
+            let audio = data.get(size as usize..).unwrap_or_default();
+            (version, body, audio)
+        }
+        None => (4, &[][..], data),
+    };
+
+    // Frames are copied as they are, other than the lyrics being replaced.
+    let mut tag = vec![];
+    let mut offset = 0;
+    while let Some(header) = frames.get(offset..offset + 10) {
+        if header[0] == 0 {
+            break;
+        }
+        let size = match version {
+            3 => u32::from_be_bytes(header[4..8].try_into()?) as usize,
+            _ => syncsafe(&header[4..8]) as usize,
+        };
+        let frame = frames
+            .get(offset..offset + 10 + size)
+            .ok_or("Truncated ID3 frame")?;
+        if !matches!(&header[..4], b"USLT" | b"SYLT") {
+            tag.extend_from_slice(frame);
+        }
+        offset += 10 + size;
+    }
+
+    let encode = |text: &str| -> Vec<u8> {
+        match version {
+            // ID3v2.3 has no UTF-8, so UTF-16 with a byte order mark is used instead.
+            3 => [0xff, 0xfe]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .chain([0, 0])
+                .collect(),
+            _ => text.bytes().chain([0]).collect(),
+        }
+    };
+    let encoding = match version {
+        3 => 1,
+        _ => 3,
+    };
+    let mut frame = |id: &[u8; 4], body: Vec<u8>| {
+        let size = match version {
+            3 => (body.len() as u32).to_be_bytes(),
+            _ => to_syncsafe(body.len() as u32),
+        };
+        tag.extend_from_slice(id);
+        tag.extend_from_slice(&size);
+        tag.extend_from_slice(&[0, 0]);
+        tag.extend_from_slice(&body);
+    };
+
+    // The language is left undetermined, with an empty description.
+    let mut uslt = vec![encoding];
+    uslt.extend_from_slice(b"und");
+    uslt.extend(encode(""));
+    let mut lyrics = encode(text);
+    lyrics.truncate(lyrics.len() - if version == 3 { 2 } else { 1 });
+    uslt.extend(lyrics);
+    frame(b"USLT", uslt);
+
+    if let Some(lines) = synced {
+        // Timestamps are in milliseconds, and the content is lyrics.
+        let mut sylt = vec![encoding];
+        sylt.extend_from_slice(b"und");
+        sylt.extend_from_slice(&[2, 1]);
+        sylt.extend(encode(""));
+        for (start, line) in lines {
+            sylt.extend(encode(line));
+            sylt.extend_from_slice(&((start * 1000.0).round() as u32).to_be_bytes());
+        }
+        frame(b"SYLT", sylt);
+    }
+
+    let mut tagged = b"ID3".to_vec();
+    tagged.extend_from_slice(&[version, 0, 0]);
+    tagged.extend_from_slice(&to_syncsafe(tag.len() as u32));
+    tagged.extend(tag);
+    tagged.extend_from_slice(audio);
+    Ok(tagged)
+}
+
+fn to_syncsafe(size: u32) -> [u8; 4] {
+    [21, 14, 7, 0].map(|shift| ((size >> shift) & 0x7f) as u8)
+}
+
+/// Replaces the `©lyr` item of the file's iTunes-style metadata in `moov/udta/meta/ilst`,
+/// adding whichever of those atoms are missing. Where `moov` comes before the media data, the
+/// chunk offsets of each track are moved along by however much it grew or shrank.
+fn write_mp4_lyrics(data: &[u8], text: &str) -> Result<Vec<u8>> {
+    let top = parse_atoms(data)?;
+    if top.iter().any(|(_, kind, _)| kind == b"moof") {
+        return Err("Lyrics cannot be embedded in fragmented MP4 files".into());
+    }
+    let (moov_offset, moov) = top
+        .iter()
+        .find(|(_, kind, _)| kind == b"moov")
+        .map(|&(offset, _, body)| (offset, body))
+        .ok_or("MP4 file has no metadata")?;
+    let end = moov.as_ptr() as usize - data.as_ptr() as usize + moov.len();
+    let mut item = 1u32.to_be_bytes().to_vec();
+    item.extend_from_slice(&[0; 4]);
+    item.extend_from_slice(text.as_bytes());
+    let lyrics = atom(b"\xa9lyr", &atom(b"data", &item));
+
+    let moov = replace_child(moov, b"udta", |udta| {
+        replace_child(udta.unwrap_or_default(), b"meta", |meta| {
+            let meta = meta.unwrap_or_default();
+            // The full atom's version and flags, which some QuickTime files leave out.
+            let (prefix, children) = match meta.get(4..8) {
+                Some(b"hdlr") | None => (&[0u8; 4][..], meta),
+                _ => meta.split_at(4),
+            };
+            let mut children = children.to_vec();
+            if parse_child(&children, b"hdlr")?.is_none() {
+                let mut hdlr = vec![0; 8];
+                hdlr.extend_from_slice(b"mdirappl");
+                hdlr.extend_from_slice(&[0; 9]);
+                children = [atom(b"hdlr", &hdlr), children].concat();
+            }
+            let children = replace_child(&children, b"ilst", |ilst| {
+                let mut items = parse_atoms(ilst.unwrap_or_default())?
+                    .into_iter()
+                    .filter(|(_, kind, _)| kind != b"\xa9lyr")
+                    .flat_map(|(_, kind, body)| atom(&kind, body))
+                    .collect::<Vec<_>>();
+                items.extend_from_slice(&lyrics);
+                Ok(items)
+            })?;
+            Ok([prefix, &children].concat())
+        })
+    })?;
+    let mut moov = atom(b"moov", &moov);
+
+    let before_media = top
+        .iter()
+        .any(|(offset, kind, _)| kind == b"mdat" && *offset > moov_offset);
+    if before_media {
+        let delta = moov.len() as i64 - (end - moov_offset) as i64;
+        shift_chunk_offsets(&mut moov, delta)?;
+    }
+    Ok([&data[..moov_offset], &moov, &data[end..]].concat())
+}
+
+/// Serializes an atom, with a 32-bit size.
+fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(body);
+    atom
+}
+
+/// Replaces the first child of kind `kind` in `data` with what `f` makes of its contents, or
+/// adds one if there is none. Fails rather than drop any children that cannot be parsed.
+fn replace_child(
+    data: &[u8],
+    kind: &[u8; 4],
+    f: impl FnOnce(Option<&[u8]>) -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    let mut f = Some(f);
+    let mut children = vec![];
+    for (_, child_kind, body) in parse_atoms(data)? {
+        match f.take_if(|_| child_kind == *kind) {
+            Some(f) => children.extend(atom(kind, &f(Some(body))?)),
+            None => children.extend(atom(&child_kind, body)),
+        }
+    }
+    if let Some(f) = f {
+        children.extend(atom(kind, &f(None)?));
+    }
+    Ok(children)
+}
+
+/// Moves the `stco` and `co64` chunk offsets of every track in the serialized `moov` atom by
+/// `delta` bytes.
+fn shift_chunk_offsets(moov: &mut [u8], delta: i64) -> Result<()> {
+    let mut tables = vec![];
+    let base = moov.as_ptr() as usize;
+    let offset_of = |body: &[u8]| body.as_ptr() as usize - base;
+    for (_, kind, trak) in parse_atoms(&moov[8..])? {
+        if kind != *b"trak" {
+            continue;
+        }
+        let mut stbl = Some(trak);
+        for kind in [b"mdia", b"minf", b"stbl"] {
+            stbl = match stbl {
+                Some(parent) => parse_child(parent, kind)?,
+                None => None,
+            };
+        }
+        let tables_in = match stbl {
+            Some(stbl) => parse_atoms(stbl)?,
+            None => vec![],
+        };
+        for (_, kind, table) in tables_in {
+            match &kind {
+                b"stco" => tables.push((offset_of(table), table.len(), 4)),
+                b"co64" => tables.push((offset_of(table), table.len(), 8)),
+                _ => {}
+            }
+        }
+    }
+
+    for (offset, len, width) in tables {
+        let table = &mut moov[offset..offset + len];
+        let count = u32::from_be_bytes(
+            table
+                .get(4..8)
+                .ok_or("Truncated chunk offsets")?
+                .try_into()?,
+        );
+        for i in 0..count as usize {
+            let entry = table
+                .get_mut(8 + i * width..8 + (i + 1) * width)
+                .ok_or("Truncated chunk offsets")?;
+            match width {
+                4 => {
+                    let value = u32::from_be_bytes(entry[..].try_into()?) as i64 + delta;
+                    let value = u32::try_from(value).map_err(|_| "Chunk offset out of range")?;
+                    entry.copy_from_slice(&value.to_be_bytes());
+                }
+                _ => {
+                    let value = u64::from_be_bytes(entry[..].try_into()?) as i64 + delta;
+                    let value = u64::try_from(value).map_err(|_| "Chunk offset out of range")?;
+                    entry.copy_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok(())
+}
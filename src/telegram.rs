@@ -0,0 +1,251 @@
+use crate::cache::Cache;
+use crate::clip::Clip;
+use crate::download::DownloadStrategy;
+use crate::ffmpeg;
+use crate::pipeline::{
+    check_audio_size, download_audio, transcribe, SourceOptions, TranscriptionOptions,
+};
+use crate::provider::ProviderPool;
+use crate::source::Source;
+use crate::Result;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const API_BASE: &str = "https://api.telegram.org";
+
+/// How long each `getUpdates` long poll waits for new messages, in seconds.
+const POLL_TIMEOUT: u64 = 50;
+
+/// The longest message Telegram accepts, in characters.
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// The largest file the Bot API lets bots download, in bytes.
+const MAX_FILE_SIZE: u64 = 20 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Response<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    message_id: i64,
+    chat: Chat,
+    text: Option<String>,
+    caption: Option<String>,
+    voice: Option<FileRef>,
+    audio: Option<FileRef>,
+    video: Option<FileRef>,
+    video_note: Option<FileRef>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct FileRef {
+    file_id: String,
+    file_size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct File {
+    file_path: Option<String>,
+}
+
+/// What a message asked to have transcribed.
+enum Request {
+    /// A voice note, audio file, or video sent or forwarded to the bot, by its ID and size.
+    File(String, Option<u64>),
+    /// A link to a video, as found in the message text.
+    Url(String),
+}
+
+impl Message {
+    fn request(&self) -> Option<Request> {
+        let file = [&self.voice, &self.audio, &self.video_note, &self.video]
+            .into_iter()
+            .find_map(|file| file.as_ref());
+        if let Some(file) = file {
+            return Some(Request::File(file.file_id.clone(), file.file_size));
+        }
+        let text = self.text.as_deref().or(self.caption.as_deref())?;
+        text.split_whitespace()
+            .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+            .map(|url| Request::Url(url.to_string()))
+    }
+}
+
+/// A Telegram bot that replies to voice notes, audio, videos, and video links with their
+/// transcripts, polling the Bot API for messages.
+pub struct TelegramBot {
+    client: Client,
+    token: String,
+    /// The chats the bot answers, or every chat if empty, which callers should only allow when
+    /// asked to explicitly.
+    allowed_chats: Vec<i64>,
+}
+
+impl TelegramBot {
+    pub fn new(client: Client, token: impl Into<String>, allowed_chats: Vec<i64>) -> Self {
+        TelegramBot {
+            client,
+            token: token.into(),
+            allowed_chats,
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let url = format!("{}/bot{}/{}", API_BASE, self.token, method);
+        let res: Response<T> = self
+            .client
+            .post(url)
+            .json(&params)
+            .timeout(Duration::from_secs(POLL_TIMEOUT + 10))
+            .send()
+            .await?
+            .json()
+            .await?;
+        match (res.ok, res.result) {
+            (true, Some(result)) => Ok(result),
+            _ => Err(format!(
+                "Telegram API error: {}",
+                res.description.as_deref().unwrap_or("unknown error")
+            )
+            .into()),
+        }
+    }
+
+    /// Downloads a file that was sent to the bot, which the Bot API limits to 20 MB.
+    async fn download_file(&self, file_id: &str) -> Result<Vec<u8>> {
+        let file: File = self.call("getFile", json!({ "file_id": file_id })).await?;
+        let path = file.file_path.ok_or("File is too large to download")?;
+        let url = format!("{}/file/bot{}/{}", API_BASE, self.token, path);
+        let res = self.client.get(url).send().await?.error_for_status()?;
+        Ok(res.bytes().await?.to_vec())
+    }
+
+    /// Replies to `message` with `text`, split over several messages if it is too long.
+    async fn reply(&self, message: &Message, text: &str) -> Result<()> {
+        let text = match text.trim() {
+            "" => "(no speech)",
+            text => text,
+        };
+        let chars = text.chars().collect::<Vec<_>>();
+        for part in chars.chunks(MAX_MESSAGE_LENGTH) {
+            let params = json!({
+                "chat_id": message.chat.id,
+                "text": part.iter().collect::<String>(),
+                "reply_parameters": { "message_id": message.message_id },
+            });
+            self.call::<Value>("sendMessage", params).await?;
+        }
+        Ok(())
+    }
+
+    /// Answers messages until an error occurs polling Telegram for them. Failures to transcribe
+    /// are replied to rather than returned, and failures to reply are logged.
+    pub async fn run(
+        &self,
+        pool: &ProviderPool,
+        options: &TranscriptionOptions,
+        source: &SourceOptions,
+    ) -> Result<()> {
+        let client = source.http_client()?;
+        let cache = Cache::open_default().ok();
+        let mut offset = 0;
+        loop {
+            let params = json!({
+                "offset": offset,
+                "timeout": POLL_TIMEOUT,
+                "allowed_updates": ["message"],
+            });
+            let updates: Vec<Update> = self.call("getUpdates", params).await?;
+            for update in updates {
+                offset = update.update_id + 1;
+                let Some(message) = update.message else {
+                    continue;
+                };
+                if !self.allowed_chats.is_empty() && !self.allowed_chats.contains(&message.chat.id)
+                {
+                    continue;
+                }
+                let reply = match message.request() {
+                    Some(request) => self
+                        .transcribe_request(request, pool, options, source, &client, cache.as_ref())
+                        .await
+                        .unwrap_or_else(|err| format!("Sorry, that failed: {}", err)),
+                    None => "Send a voice note, a video, or a link to one.".to_string(),
+                };
+                // A chat that blocked the bot, for one, should not stop it answering the others.
+                if let Err(err) = self.reply(&message, &reply).await {
+                    eprintln!("Failed to reply in chat {}: {}", message.chat.id, err);
+                }
+            }
+        }
+    }
+
+    async fn transcribe_request(
+        &self,
+        request: Request,
+        pool: &ProviderPool,
+        options: &TranscriptionOptions,
+        source: &SourceOptions,
+        client: &Client,
+        cache: Option<&Cache>,
+    ) -> Result<String> {
+        let video = match request {
+            Request::File(_, Some(size)) if size > MAX_FILE_SIZE => {
+                return Err(format!(
+                    "Telegram only lets bots download files of up to {} MB, send a link instead",
+                    MAX_FILE_SIZE / 1024 / 1024
+                )
+                .into());
+            }
+            Request::File(file_id, _) => {
+                let file = self.download_file(&file_id).await?;
+                let output_args = ["-vn", "-c:a", "aac", "-b:a", "64k"];
+                let audio = ffmpeg::transform(&file, &[], &output_args, "m4a").await?;
+                return Ok(transcribe(pool, audio, options).await?.text);
+            }
+            // Whatever is sent is taken as a URL, never as a path on this machine.
+            Request::Url(url) => {
+                Source::RemoteVideo(url)
+                    .resolve_unattended(client, source, |_| {})
+                    .await?
+            }
+        };
+
+        let key = options.cache_key(&video, &Clip::default());
+        if let Some(transcript) = cache.and_then(|cache| cache.transcript(&key)) {
+            return Ok(transcript.text);
+        }
+        check_audio_size(video.audio_file_size)?;
+        let audio = download_audio(
+            client,
+            &video,
+            cache,
+            DownloadStrategy::default(),
+            |_, _| {},
+        )
+        .await?;
+        let transcript = transcribe(pool, audio, options).await?;
+        if let Some(cache) = cache {
+            cache.put_transcript(&key, &transcript)?;
+        }
+        Ok(transcript.text)
+    }
+}
@@ -0,0 +1,351 @@
+use crate::transcript::Transcript;
+use crate::Result;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+/// The API key [`MockOpenAi`] accepts. Requests with any other key are refused, as the API
+/// would.
+pub const MOCK_API_KEY: &str = "mock-key";
+
+/// What [`MockOpenAi`] serves as the audio track of videos, which nothing decodes unless the
+/// audio is cut or probed with ffmpeg.
+pub const MOCK_AUDIO: &[u8] = b"mock audio track";
+
+/// A request [`MockOpenAi`] received at one of its audio endpoints.
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    /// The endpoint, either `/audio/transcriptions` or `/audio/translations`.
+    pub route: String,
+    pub model: String,
+    pub response_format: String,
+    pub language: Option<String>,
+    pub prompt: Option<String>,
+    /// Whether word timings were requested.
+    pub word_timestamps: bool,
+    /// How many bytes of audio were uploaded.
+    pub file_size: usize,
+}
+
+struct MockState {
+    transcript: Transcript,
+    requests: Mutex<Vec<MockRequest>>,
+    /// The statuses to fail the next requests with, in order.
+    failures: Mutex<Vec<StatusCode>>,
+}
+
+/// A local HTTP server emulating the OpenAI audio endpoints, answering every transcription and
+/// translation with the same transcript, and serving [`MOCK_AUDIO`] as the audio track of
+/// videos, so that the whole pipeline can be run without network access or an API key. The
+/// server stops when it is dropped.
+pub struct MockOpenAi {
+    /// The base URL of the emulated API, e.g. `http://127.0.0.1:41234/v1`, for a providers
+    /// file.
+    pub base_url: String,
+    /// The URL of the audio track, for the info JSON of [`FakeYtDlp`].
+    pub audio_url: String,
+    state: Arc<MockState>,
+    server: JoinHandle<()>,
+}
+
+impl MockOpenAi {
+    /// Starts the server on a free local port, answering with `transcript`. Models that only
+    /// return text, i.e. those not asked for `verbose_json`, get its text alone.
+    pub async fn start(transcript: Transcript) -> Result<Self> {
+        let state = Arc::new(MockState {
+            transcript,
+            requests: Mutex::default(),
+            failures: Mutex::default(),
+        });
+        let app = Router::new()
+            .route("/v1/audio/transcriptions", post(transcriptions))
+            .route("/v1/audio/translations", post(translations))
+            .route("/audio.m4a", get(|| async { MOCK_AUDIO }))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+        let address = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        Ok(MockOpenAi {
+            base_url: format!("http://{}/v1", address),
+            audio_url: format!("http://{}/audio.m4a", address),
+            state,
+            server,
+        })
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Fails the next request with `status`, e.g. to exercise retries and failover. Calling
+    /// this again fails as many requests after it.
+    pub fn fail_next(&self, status: u16) {
+        let status = StatusCode::from_u16(status).expect("Invalid status code");
+        self.state.failures.lock().unwrap().push(status);
+    }
+
+    /// Writes a providers file for `--providers` naming the server `mock` into `dir`, reading
+    /// its key from the `MOCK_API_KEY` environment variable, which is to be set to
+    /// [`MOCK_API_KEY`]. Failed requests are retried after 10ms, rather than the usual 5s.
+    pub fn write_providers(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("providers.json");
+        let providers = json!([{
+            "name": "mock",
+            "base_url": self.base_url,
+            "api_key_env": "MOCK_API_KEY",
+            "retry_delay_ms": 10,
+        }]);
+        fs::write(&path, serde_json::to_string_pretty(&providers)?)?;
+        Ok(path)
+    }
+}
+
+impl Drop for MockOpenAi {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn transcriptions(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    answer(&state, "/audio/transcriptions", &headers, &body)
+}
+
+async fn translations(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    answer(&state, "/audio/translations", &headers, &body)
+}
+
+fn answer(state: &MockState, route: &str, headers: &HeaderMap, body: &[u8]) -> Response {
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .is_some_and(|value| value.as_bytes() == format!("Bearer {}", MOCK_API_KEY).as_bytes());
+    if !authorized {
+        return error(StatusCode::UNAUTHORIZED, "Incorrect API key provided");
+    }
+    let Some(fields) = parse_form(headers, body) else {
+        return error(StatusCode::BAD_REQUEST, "Expected a multipart form");
+    };
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| String::from_utf8_lossy(value).into_owned())
+    };
+    let request = MockRequest {
+        route: route.to_string(),
+        model: field("model").unwrap_or_default(),
+        response_format: field("response_format").unwrap_or_else(|| "json".to_string()),
+        language: field("language"),
+        prompt: field("prompt"),
+        word_timestamps: fields
+            .iter()
+            .any(|(name, value)| name == "timestamp_granularities[]" && value == b"word"),
+        file_size: fields
+            .iter()
+            .find(|(name, _)| name == "file")
+            .map_or(0, |(_, file)| file.len()),
+    };
+    state.requests.lock().unwrap().push(request.clone());
+
+    let failure = {
+        let mut failures = state.failures.lock().unwrap();
+        (!failures.is_empty()).then(|| failures.remove(0))
+    };
+    if let Some(status) = failure {
+        return error(status, "The mock server was asked to fail this request");
+    }
+    if request.model.is_empty() || request.file_size == 0 {
+        return error(StatusCode::BAD_REQUEST, "Missing model or file");
+    }
+
+    let transcript = &state.transcript;
+    if request.response_format != "verbose_json" {
+        return Json(json!({ "text": transcript.text })).into_response();
+    }
+    let mut response = json!({
+        "task": route.trim_start_matches("/audio/").trim_end_matches('s'),
+        "text": transcript.text,
+        "language": transcript.language,
+        "duration": transcript.duration,
+        "segments": transcript.segments.iter().enumerate().map(|(i, segment)| json!({
+            "id": i,
+            "start": segment.start,
+            "end": segment.end,
+            "text": segment.text,
+            "avg_logprob": segment.avg_logprob,
+            "compression_ratio": segment.compression_ratio,
+            "no_speech_prob": segment.no_speech_prob,
+        })).collect::<Vec<_>>(),
+    });
+    if request.word_timestamps {
+        let words = transcript
+            .segments
+            .iter()
+            .flat_map(|segment| &segment.words)
+            .collect::<Vec<_>>();
+        response["words"] = json!(words);
+    }
+    Json(response).into_response()
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    let body = json!({ "error": { "message": message, "type": "invalid_request_error" } });
+    (status, Json(body)).into_response()
+}
+
+/// Splits a `multipart/form-data` body into its fields, by name. Enough of the format is
+/// understood for the forms the API client sends, whose boundary never occurs in the audio.
+fn parse_form(headers: &HeaderMap, body: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    let boundary = content_type.split("boundary=").nth(1)?.trim_matches('"');
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut fields = vec![];
+    for part in split(body, &delimiter).into_iter().skip(1) {
+        if part.starts_with(b"--") {
+            break;
+        }
+        let part = part.strip_prefix(b"\r\n").unwrap_or(part);
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        let end = find(part, b"\r\n\r\n")?;
+        let head = String::from_utf8_lossy(&part[..end]);
+        let name = head.split("name=\"").nth(1)?.split('"').next()?.to_string();
+        fields.push((name, part[end + 4..].to_vec()));
+    }
+    Some(fields)
+}
+
+fn split<'a>(haystack: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = vec![];
+    let mut rest = haystack;
+    while let Some(i) = find(rest, delimiter) {
+        parts.push(&rest[..i]);
+        rest = &rest[i + delimiter.len()..];
+    }
+    parts.push(rest);
+    parts
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// The info JSON yt-dlp reports for a video, with the fields the pipeline reads, and a single
+/// m4a audio format at `audio_url`, e.g. [`MockOpenAi::audio_url`].
+pub fn video_info(id: &str, title: &str, duration: f64, audio_url: &str) -> Value {
+    json!({
+        "id": id,
+        "title": title,
+        "webpage_url": format!("https://www.youtube.com/watch?v={}", id),
+        "channel": "Mock Channel",
+        "upload_date": "20240101",
+        "duration": duration,
+        "formats": [{
+            "format_id": "140",
+            "ext": "m4a",
+            "acodec": "mp4a.40.2",
+            "vcodec": "none",
+            "filesize": MOCK_AUDIO.len(),
+            "url": audio_url,
+        }],
+    })
+}
+
+/// A fake `yt-dlp` in a temporary directory, which prints the same info JSON for any URL it
+/// is asked to dump and fails to download anything, logging how it was run. Running the
+/// pipeline with [`FakeYtDlp::path`] as `PATH` runs it instead of the real one. The directory
+/// is removed when it is dropped. The fake is a shell script, so it only runs on Unix.
+pub struct FakeYtDlp {
+    pub dir: PathBuf,
+}
+
+impl FakeYtDlp {
+    pub fn install(info: &Value) -> Result<Self> {
+        let dir = temp_dir("yt-dlp")?;
+        fs::write(dir.join("info.json"), serde_json::to_string(info)?)?;
+        let script = dir.join("yt-dlp");
+        fs::write(
+            &script,
+            "#!/bin/sh\n\
+             dir=$(dirname \"$0\")\n\
+             echo \"$*\" >> \"$dir/calls.log\"\n\
+             case \" $* \" in\n\
+             *\" -J \"*)\n\
+             \x20 echo \"[youtube] Downloading webpage\" >&2\n\
+             \x20 cat \"$dir/info.json\"\n\
+             \x20 exit 0;;\n\
+             esac\n\
+             echo \"ERROR: the fake yt-dlp only dumps info JSON\" >&2\n\
+             exit 1\n",
+        )?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(FakeYtDlp { dir })
+    }
+
+    /// `PATH` with the fake's directory first.
+    pub fn path(&self) -> OsString {
+        let mut paths = vec![self.dir.clone()];
+        if let Some(path) = std::env::var_os("PATH") {
+            paths.extend(std::env::split_paths(&path));
+        }
+        std::env::join_paths(paths).expect("Invalid PATH")
+    }
+
+    /// The arguments of each time the fake was run, in order.
+    pub fn calls(&self) -> Vec<String> {
+        fs::read_to_string(self.dir.join("calls.log"))
+            .map(|log| log.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for FakeYtDlp {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Creates an empty directory under the system's temporary directory that no other test, in
+/// this process or another, uses, e.g. for the output, cache, and configuration directories of
+/// a run.
+pub fn temp_dir(name: &str) -> Result<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "transcribe-{}-{}-{}",
+        name,
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
@@ -0,0 +1,146 @@
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How many of the most recent measurements of each stage are kept.
+const MAX_SAMPLES: usize = 20;
+
+/// Stages quicker than this, e.g. downloads answered from the cache, say little about
+/// throughput and are not recorded.
+const MIN_SAMPLE_DURATION: Duration = Duration::from_secs(1);
+
+/// How fast each stage of past jobs ran on this machine, kept in the user's data directory,
+/// e.g. `~/.local/share/transcribe/throughput.json`, to estimate how long new jobs will take.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ThroughputHistory {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Bytes of audio downloaded per second.
+    #[serde(default)]
+    download: Vec<f64>,
+    /// Seconds of audio cut into clips per second.
+    #[serde(default)]
+    preprocess: Vec<f64>,
+    /// Seconds of audio transcribed per second, by model, e.g. `whisper-1` or the cache name of
+    /// a local model.
+    #[serde(default)]
+    transcribe: BTreeMap<String, Vec<f64>>,
+}
+
+/// How long each stage of a job is expected to take, where there is history to go by.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Estimate {
+    pub download: Option<Duration>,
+    pub preprocess: Option<Duration>,
+    pub transcribe: Option<Duration>,
+}
+
+impl ThroughputHistory {
+    /// Loads the history, which is empty until the first job has finished.
+    pub fn open_default() -> Result<Self> {
+        let root = dirs::data_dir().ok_or("Missing data directory")?;
+        let path = root.join("transcribe").join("throughput.json");
+        let mut history = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<ThroughputHistory>(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(err.into()),
+        };
+        history.path = path;
+        Ok(history)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn record_download(&mut self, bytes: f64, elapsed: Duration) {
+        record(&mut self.download, bytes, elapsed);
+    }
+
+    pub fn record_preprocess(&mut self, audio_seconds: f64, elapsed: Duration) {
+        record(&mut self.preprocess, audio_seconds, elapsed);
+    }
+
+    pub fn record_transcription(&mut self, model: &str, audio_seconds: f64, elapsed: Duration) {
+        let samples = self.transcribe.entry(model.to_string()).or_default();
+        record(samples, audio_seconds, elapsed);
+    }
+
+    /// Estimates how long it takes to download an audio track of `audio_bytes`, cut clips out
+    /// of it if `cut` is set, and transcribe `audio_seconds` of it with `model`, going by the
+    /// median throughput of each stage so far.
+    pub fn estimate(
+        &self,
+        audio_bytes: f64,
+        audio_seconds: f64,
+        model: &str,
+        cut: bool,
+    ) -> Estimate {
+        let time = |samples: Option<&Vec<f64>>, amount: f64| {
+            median(samples?).map(|rate| Duration::from_secs_f64(amount / rate))
+        };
+        Estimate {
+            download: time(Some(&self.download), audio_bytes),
+            preprocess: time(Some(&self.preprocess).filter(|_| cut), audio_seconds),
+            transcribe: time(self.transcribe.get(model), audio_seconds),
+        }
+    }
+}
+
+fn record(samples: &mut Vec<f64>, amount: f64, elapsed: Duration) {
+    if elapsed < MIN_SAMPLE_DURATION || amount <= 0.0 {
+        return;
+    }
+    samples.push(amount / elapsed.as_secs_f64());
+    if samples.len() > MAX_SAMPLES {
+        samples.drain(..samples.len() - MAX_SAMPLES);
+    }
+}
+
+fn median(samples: &[f64]) -> Option<f64> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted.get(sorted.len() / 2).copied()
+}
+
+impl Estimate {
+    pub fn total(&self) -> Option<Duration> {
+        let stages = [self.download, self.preprocess, self.transcribe];
+        stages.into_iter().flatten().reduce(|a, b| a + b)
+    }
+
+    /// Describes the estimate, e.g. `about 2m 10s (download 12s, transcription 1m 58s)`, or
+    /// `None` if there is no history for any stage.
+    pub fn describe(&self) -> Option<String> {
+        let stages = [
+            ("download", self.download),
+            ("preprocessing", self.preprocess),
+            ("transcription", self.transcribe),
+        ];
+        let parts = stages
+            .iter()
+            .filter_map(|(name, time)| Some(format!("{} {}", name, format_eta((*time)?))))
+            .collect::<Vec<_>>();
+        Some(format!(
+            "about {} ({})",
+            format_eta(self.total()?),
+            parts.join(", ")
+        ))
+    }
+}
+
+/// Formats a duration to the second, e.g. `45s`, `2m 10s`, or `1h 5m`.
+pub fn format_eta(duration: Duration) -> String {
+    let secs = duration.as_secs_f64().round() as u64;
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
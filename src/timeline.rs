@@ -0,0 +1,176 @@
+use crate::transcript::Transcript;
+use serde::Serialize;
+
+/// How close two times must be to count as the same, absorbing the rounding of composing
+/// timelines.
+const EPSILON: f64 = 1e-9;
+
+/// How the audio sent to the model maps onto the source it was made from, after it was cut to
+/// a clip, sped up, or had stretches left out, so that the timestamps of a transcript of it can
+/// be moved back onto the source. Transcripts are moved with [`Timeline::apply`] before they
+/// reach any exporter, so every format, sidecar, and sink agrees on where things were said.
+///
+/// Each span maps the audio sent to the model linearly onto the source from where the span
+/// starts until the next one does. Times before the first span belong to it, and the last span
+/// runs on to the end. A timeline without spans maps every time onto itself.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Timeline {
+    spans: Vec<Span>,
+}
+
+/// A stretch of the audio sent to the model, starting at `output` in it and at `source` in the
+/// source, where each of its seconds covers `rate` seconds of the source.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Span {
+    pub output: f64,
+    pub source: f64,
+    #[serde(skip_serializing_if = "is_normal_speed")]
+    pub rate: f64,
+}
+
+fn is_normal_speed(rate: &f64) -> bool {
+    *rate == 1.0
+}
+
+impl Timeline {
+    /// The timeline of a clip of the source starting `start` seconds in.
+    pub fn trimmed(start: f64) -> Self {
+        Timeline {
+            spans: vec![Span {
+                output: 0.0,
+                source: start,
+                rate: 1.0,
+            }],
+        }
+    }
+
+    /// The timeline of the source played `factor` times as fast.
+    pub fn sped_up(factor: f64) -> Self {
+        assert!(factor > 0.0, "Speed-up factor must be positive");
+        Timeline {
+            spans: vec![Span {
+                output: 0.0,
+                source: 0.0,
+                rate: factor,
+            }],
+        }
+    }
+
+    /// The timeline of audio made by joining the stretches `kept` of the source, given as
+    /// `(start, end)` in order, back to back.
+    pub fn from_kept(kept: &[(f64, f64)]) -> Self {
+        let mut output = 0.0;
+        let spans = kept
+            .iter()
+            .filter(|(start, end)| end > start)
+            .map(|&(start, end)| {
+                let span = Span {
+                    output,
+                    source: start,
+                    rate: 1.0,
+                };
+                output += end - start;
+                span
+            })
+            .collect();
+        Timeline { spans }
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Whether the timeline maps every time onto itself.
+    pub fn is_identity(&self) -> bool {
+        self.spans
+            .iter()
+            .all(|span| span.source == span.output && span.rate == 1.0)
+    }
+
+    /// The timeline of audio made by processing the output of this one further, where `next`
+    /// maps the further processed audio onto this one's output, e.g. a clip that then had its
+    /// silences left out is `Timeline::trimmed(start).then(&stripped)`.
+    pub fn then(&self, next: &Timeline) -> Timeline {
+        if self.spans.is_empty() {
+            return next.clone();
+        }
+        if next.spans.is_empty() {
+            return self.clone();
+        }
+        // The composed timeline changes course wherever `next` does, and wherever `next`
+        // reaches a time at which this one does.
+        let mut starts = next
+            .spans
+            .iter()
+            .map(|span| span.output)
+            .collect::<Vec<_>>();
+        for (i, span) in next.spans.iter().enumerate() {
+            let end = next.spans.get(i + 1).map(|next| next.output);
+            for inner in &self.spans {
+                let time = span.output + (inner.output - span.source) / span.rate;
+                if time > span.output + EPSILON && end.is_none_or(|end| time < end - EPSILON) {
+                    starts.push(time);
+                }
+            }
+        }
+        starts.sort_by(f64::total_cmp);
+        starts.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+
+        let spans = starts
+            .into_iter()
+            .map(|output| {
+                let outer = next.span_at(output);
+                let middle = outer.map_or(output, |span| span.map(output));
+                let inner = self.span_at(middle);
+                Span {
+                    output,
+                    source: inner.map_or(middle, |span| span.map(middle)),
+                    rate: outer.map_or(1.0, |span| span.rate) * inner.map_or(1.0, |span| span.rate),
+                }
+            })
+            .collect();
+        Timeline { spans }
+    }
+
+    /// The span `time` in the audio sent to the model falls in. A time at the join of two
+    /// spans belongs to the later one, as that is where speech after a cut starts.
+    fn span_at(&self, time: f64) -> Option<&Span> {
+        self.spans
+            .iter()
+            .rev()
+            .find(|span| span.output <= time + EPSILON)
+            .or(self.spans.first())
+    }
+
+    /// Maps `time` in the audio sent to the model onto the source.
+    pub fn to_source(&self, time: f64) -> f64 {
+        self.span_at(time).map_or(time, |span| span.map(time))
+    }
+
+    /// Moves every segment and word of `transcript`, which was made from the audio sent to the
+    /// model, onto the source's timeline.
+    pub fn apply(&self, transcript: &mut Transcript) {
+        if self.spans.is_empty() {
+            return;
+        }
+        for segment in &mut transcript.segments {
+            segment.start = self.to_source(segment.start);
+            segment.end = self.to_source(segment.end).max(segment.start);
+            for word in &mut segment.words {
+                word.start = self.to_source(word.start);
+                word.end = self.to_source(word.end).max(word.start);
+            }
+        }
+        // The duration is a length rather than a time, covering the stretch of the source the
+        // audio was made from.
+        if let Some(duration) = &mut transcript.duration {
+            *duration = self.to_source(*duration) - self.to_source(0.0);
+        }
+    }
+}
+
+impl Span {
+    fn map(&self, time: f64) -> f64 {
+        self.source + (time - self.output) * self.rate
+    }
+}
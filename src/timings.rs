@@ -0,0 +1,137 @@
+use crate::throughput::format_eta;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::time::{Duration, Instant};
+
+/// A stage of a job, in the order they run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetching the metadata of the video, or recording or reading in the audio.
+    Metadata,
+    /// Downloading the audio track.
+    Download,
+    /// Cutting the clip, probing the audio, and leaving out silence.
+    Preprocess,
+    /// Sending the audio to the API.
+    Upload,
+    /// Waiting for the model to transcribe the audio, or running the local model.
+    Inference,
+    /// Filtering segments and rendering the transcript.
+    Postprocess,
+    /// Writing the transcript and its sidecars, and sending it to databases and other sinks.
+    Export,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::Metadata,
+        Stage::Download,
+        Stage::Preprocess,
+        Stage::Upload,
+        Stage::Inference,
+        Stage::Postprocess,
+        Stage::Export,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Metadata => "metadata",
+            Stage::Download => "download",
+            Stage::Preprocess => "preprocess",
+            Stage::Upload => "upload",
+            Stage::Inference => "inference",
+            Stage::Postprocess => "postprocess",
+            Stage::Export => "export",
+        }
+    }
+}
+
+/// How long each stage of a job took, written to `metadata.json` as seconds per stage along
+/// with the total. Time spent waiting on the user, such as in `--review`, is left out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    durations: [Duration; Stage::ALL.len()],
+}
+
+impl Timings {
+    /// Adds `duration` to the time spent in `stage`.
+    pub fn add(&mut self, stage: Stage, duration: Duration) {
+        self.durations[stage as usize] += duration;
+    }
+
+    /// Adds the time since `started` to the time spent in `stage`, and returns now, for timing
+    /// the stage after it.
+    pub fn since(&mut self, stage: Stage, started: Instant) -> Instant {
+        let now = Instant::now();
+        self.add(stage, now - started);
+        now
+    }
+
+    /// Adds the time since `started`, spent sending audio to the API, of which `upload` went
+    /// on uploading it and the rest on waiting for inference.
+    pub fn add_requests(&mut self, started: Instant, upload: Duration) {
+        self.add(Stage::Upload, upload);
+        self.add(Stage::Inference, started.elapsed().saturating_sub(upload));
+    }
+
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.durations[stage as usize]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+
+    /// Lists the time spent in each stage that took any, with its share of the total,
+    /// marking the stage that took longest.
+    pub fn describe(&self) -> String {
+        let total = self.total().as_secs_f64().max(f64::EPSILON);
+        let slowest = Stage::ALL
+            .into_iter()
+            .max_by_key(|stage| self.get(*stage))
+            .expect("there are stages");
+        let mut lines = vec![];
+        for stage in Stage::ALL {
+            let duration = self.get(stage);
+            if duration.is_zero() {
+                continue;
+            }
+            lines.push(format!(
+                "  {:<12} {:>9} {:>4.0}%{}",
+                stage.as_str(),
+                format_duration(duration),
+                duration.as_secs_f64() / total * 100.0,
+                match stage == slowest {
+                    true => "  <- bottleneck",
+                    false => "",
+                }
+            ));
+        }
+        lines.push(format!(
+            "  {:<12} {:>9}",
+            "total",
+            format_duration(self.total())
+        ));
+        lines.join("\n")
+    }
+}
+
+/// Formats stages under a minute to the millisecond, as most are, and longer ones as
+/// `format_eta` does.
+fn format_duration(duration: Duration) -> String {
+    match duration < Duration::from_secs(60) {
+        true => format!("{:.3}s", duration.as_secs_f64()),
+        false => format_eta(duration),
+    }
+}
+
+impl Serialize for Timings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let seconds = |duration: Duration| (duration.as_secs_f64() * 1000.0).round() / 1000.0;
+        let mut map = serializer.serialize_map(Some(Stage::ALL.len() + 1))?;
+        for stage in Stage::ALL {
+            map.serialize_entry(stage.as_str(), &seconds(self.get(stage)))?;
+        }
+        map.serialize_entry("total", &seconds(self.total()))?;
+        map.end()
+    }
+}
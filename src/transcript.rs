@@ -0,0 +1,857 @@
+use crate::clip::format_timestamp;
+use crate::timeline::Timeline;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A finished transcription, independent of the format it will eventually be rendered in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transcript {
+    pub text: String,
+    pub language: Option<String>,
+    pub duration: Option<f64>,
+    pub segments: Vec<Segment>,
+}
+
+/// A timed span of the transcript, with `start` and `end` in seconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_logprob: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression_ratio: Option<f64>,
+    /// The model's estimate of the probability that the segment contains no speech at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_prob: Option<f64>,
+    /// The speaker of the segment, if the transcript was diarized, e.g. `SPEAKER_00`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    /// The timings of the segment's words, if they were requested.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+}
+
+/// A word of a segment, with `start` and `end` in seconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Word {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Segment {
+    /// Renders the segment as the `index`th cue of an SRT file, starting from 1.
+    pub fn to_srt_cue(&self, index: usize) -> String {
+        srt_cue(index, self.start, self.end, self.text.trim())
+    }
+
+    /// Renders the segment as a WebVTT cue, tagged with its speaker's voice if it has one.
+    pub fn to_vtt_cue(&self) -> String {
+        vtt_cue(&Cue::from(self), None)
+    }
+
+    /// Renders the segment as an LRC line, with its word timings if it has them.
+    pub fn to_lrc_line(&self) -> String {
+        lrc_line(&Cue::from(self))
+    }
+
+    /// Renders the segment as an HTML paragraph, headed by its timestamp and speaker.
+    pub fn to_html_paragraph(&self) -> String {
+        html_paragraph(&Cue::from(self))
+    }
+
+    /// Renders the segment as an ASS dialogue line, in its speaker's style if it has one.
+    pub fn to_ass_dialogue(&self) -> String {
+        ass_dialogue(&Cue::from(self), &SubtitleStyle::default())
+    }
+
+    /// The average per-token probability of the segment's text, between 0 and 1, if the model
+    /// reported it.
+    pub fn confidence(&self) -> Option<f64> {
+        self.avg_logprob
+            .map(|logprob| logprob.exp().clamp(0.0, 1.0))
+    }
+}
+
+/// A run of consecutive segments that can be re-transcribed together.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub start: f64,
+    pub end: f64,
+    confidences: Vec<Option<f64>>,
+}
+
+impl Chunk {
+    /// The mean confidence of the chunk's segments, if the model reported any.
+    pub fn confidence(&self) -> Option<f64> {
+        let known = self.confidences.iter().flatten().collect::<Vec<_>>();
+        match known.is_empty() {
+            true => None,
+            false => Some(known.iter().copied().sum::<f64>() / known.len() as f64),
+        }
+    }
+}
+
+/// Thresholds above which segments are dropped as silence or garbage, with `None` disabling the
+/// corresponding check. Segments the model reported no statistics for are always kept.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct SegmentFilter {
+    /// Drop segments that are more likely than this to contain no speech, e.g. 0.6.
+    pub no_speech_threshold: Option<f64>,
+    /// Drop segments whose text compresses better than this ratio, which catches the repetitive
+    /// loops the model falls into on noise, e.g. 2.4.
+    pub compression_ratio_threshold: Option<f64>,
+}
+
+impl SegmentFilter {
+    pub fn is_empty(&self) -> bool {
+        self.no_speech_threshold.is_none() && self.compression_ratio_threshold.is_none()
+    }
+
+    pub fn keeps(&self, segment: &Segment) -> bool {
+        let exceeds = |value: Option<f64>, threshold: Option<f64>| match (value, threshold) {
+            (Some(value), Some(threshold)) => value > threshold,
+            _ => false,
+        };
+        !exceeds(segment.no_speech_prob, self.no_speech_threshold)
+            && !exceeds(segment.compression_ratio, self.compression_ratio_threshold)
+    }
+}
+
+/// What to do with a transcript less confident than `--min-confidence`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LowConfidence {
+    /// Exit with an error without writing the transcript.
+    #[default]
+    Fail,
+    /// Write the transcript, marking it as low confidence in `manifest.json`.
+    Flag,
+    /// Re-transcribe with the retry model, failing if that is not confident enough either.
+    Retry,
+}
+
+/// Limits on the cues that short segments are merged into for readable subtitles, going by
+/// common subtitling guidelines.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct SegmentMerge {
+    /// The most characters in a cue, about two lines of subtitles.
+    pub max_chars: usize,
+    /// The longest a cue may stay on screen, in seconds.
+    pub max_duration: f64,
+    /// The fastest a cue may need to be read, in characters per second. Cues faster than this
+    /// are merged with the next one even across the end of a sentence.
+    pub max_cps: f64,
+    /// The longest pause between two segments, in seconds, that they are merged across.
+    pub max_gap: f64,
+}
+
+impl Default for SegmentMerge {
+    fn default() -> Self {
+        SegmentMerge {
+            max_chars: 84,
+            max_duration: 7.0,
+            max_cps: 17.0,
+            max_gap: 1.0,
+        }
+    }
+}
+
+/// Cues shorter than this, in seconds, flash by too fast to read, however little they say.
+const MIN_CUE_DURATION: f64 = 1.0;
+
+impl SegmentMerge {
+    /// Whether `next` can be merged into the cue `cue` within the limits. Cues are merged up
+    /// to the end of a sentence, unless they are too short or too fast to read on their own,
+    /// and never across speakers.
+    fn merges(&self, cue: &Segment, next: &Segment) -> bool {
+        let text = cue.text.trim();
+        let chars = text.chars().count() + 1 + next.text.trim().chars().count();
+        let duration = cue.end - cue.start;
+        let fits = next.start - cue.end <= self.max_gap
+            && next.end - cue.start <= self.max_duration
+            && chars <= self.max_chars
+            && cue.speaker == next.speaker;
+        let too_quick =
+            duration < MIN_CUE_DURATION || text.chars().count() as f64 > self.max_cps * duration;
+        fits && (too_quick || !text.ends_with(['.', '?', '!', '…']))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Text,
+    Srt,
+    Vtt,
+    Ass,
+    Json,
+    Html,
+    Lrc,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Srt => "srt",
+            Format::Vtt => "vtt",
+            Format::Ass => "ass",
+            Format::Json => "json",
+            Format::Html => "html",
+            Format::Lrc => "lrc",
+        }
+    }
+
+    /// Whether the format is a subtitle format, which needs segment timestamps.
+    pub fn is_subtitles(&self) -> bool {
+        matches!(self, Format::Srt | Format::Vtt | Format::Ass | Format::Lrc)
+    }
+
+    /// The name of the format, as used in error messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Text => "Text",
+            Format::Srt => "SRT",
+            Format::Vtt => "WebVTT",
+            Format::Ass => "ASS",
+            Format::Json => "JSON",
+            Format::Html => "HTML",
+            Format::Lrc => "LRC",
+        }
+    }
+
+    /// The version of the format's exporter, bumped whenever its output changes, so that
+    /// outputs written by an older exporter can be found and regenerated.
+    pub fn exporter_version(&self) -> u32 {
+        match self {
+            Format::Text
+            | Format::Srt
+            | Format::Vtt
+            | Format::Ass
+            | Format::Json
+            | Format::Html
+            | Format::Lrc => 1,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Text => "text/plain; charset=utf-8",
+            Format::Srt => "application/x-subrip; charset=utf-8",
+            Format::Vtt => "text/vtt; charset=utf-8",
+            Format::Ass => "text/x-ssa; charset=utf-8",
+            Format::Json => "application/json",
+            Format::Html => "text/html; charset=utf-8",
+            Format::Lrc => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+impl Transcript {
+    /// The confidence of the whole transcript, the mean of its segments' weighted by how long
+    /// they are, if the model reported any.
+    pub fn confidence(&self) -> Option<f64> {
+        let (sum, length) = self
+            .segments
+            .iter()
+            .filter_map(|segment| {
+                let length = (segment.end - segment.start).max(0.0);
+                Some((segment.confidence()? * length, length))
+            })
+            .fold((0.0, 0.0), |(sum, total), (weighted, length)| {
+                (sum + weighted, total + length)
+            });
+        (length > 0.0).then(|| sum / length)
+    }
+
+    /// Drops the segments rejected by `filter`, rebuilding the text from the remaining ones.
+    pub fn filter_segments(&mut self, filter: &SegmentFilter) {
+        if filter.is_empty() {
+            return;
+        }
+        let count = self.segments.len();
+        self.segments.retain(|segment| filter.keeps(segment));
+        if self.segments.len() != count {
+            self.text = self
+                .segments
+                .iter()
+                .map(|segment| segment.text.as_str())
+                .collect();
+        }
+    }
+
+    /// Merges runs of short segments into cues within the limits of `merge`, so that subtitles
+    /// read in phrases rather than the fragments the model often splits speech into. The text
+    /// is unchanged. Merged cues keep the words of each segment, the duration-weighted mean of
+    /// their log probabilities, and the worst of their other statistics.
+    pub fn merge_segments(&mut self, merge: &SegmentMerge) {
+        let mut cues: Vec<Segment> = vec![];
+        for segment in std::mem::take(&mut self.segments) {
+            match cues.last_mut() {
+                Some(cue) if merge.merges(cue, &segment) => {
+                    let (a, b) = (cue.end - cue.start, segment.end - segment.start);
+                    cue.avg_logprob = match (cue.avg_logprob, segment.avg_logprob) {
+                        (Some(x), Some(y)) if a + b > 0.0 => Some((x * a + y * b) / (a + b)),
+                        (x, y) => x.or(y),
+                    };
+                    cue.compression_ratio = match (cue.compression_ratio, segment.compression_ratio)
+                    {
+                        (Some(x), Some(y)) => Some(x.max(y)),
+                        (x, y) => x.or(y),
+                    };
+                    cue.no_speech_prob = match (cue.no_speech_prob, segment.no_speech_prob) {
+                        (Some(x), Some(y)) => Some(x.max(y)),
+                        (x, y) => x.or(y),
+                    };
+                    if !cue.text.ends_with(char::is_whitespace)
+                        && !segment.text.starts_with(char::is_whitespace)
+                    {
+                        cue.text.push(' ');
+                    }
+                    cue.text.push_str(&segment.text);
+                    cue.end = segment.end;
+                    cue.words.extend(segment.words);
+                }
+                _ => cues.push(segment),
+            }
+        }
+        self.segments = cues;
+    }
+
+    /// Groups the segments into consecutive chunks of about `length` seconds, each ending with
+    /// the first segment that reaches past its target end.
+    pub fn chunks(&self, length: f64) -> Vec<Chunk> {
+        let mut chunks: Vec<Chunk> = vec![];
+        for segment in &self.segments {
+            match chunks.last_mut() {
+                Some(chunk) if chunk.end < chunk.start + length => {
+                    chunk.end = segment.end;
+                    chunk.confidences.push(segment.confidence());
+                }
+                _ => chunks.push(Chunk {
+                    start: segment.start,
+                    end: segment.end,
+                    confidences: vec![segment.confidence()],
+                }),
+            }
+        }
+        chunks
+    }
+
+    /// Replaces the segments that start between `start` and `end` with those of `replacement`,
+    /// which must already be offset onto the same timeline, rebuilding the text.
+    pub fn splice(&mut self, start: f64, end: f64, replacement: Transcript) {
+        self.segments
+            .retain(|segment| segment.start < start || segment.start >= end);
+        self.segments.extend(replacement.segments);
+        self.segments
+            .sort_by(|a, b| f64::total_cmp(&a.start, &b.start));
+        self.text = self
+            .segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect();
+    }
+
+    /// Shifts every segment by `seconds`, e.g. to map a clip's timestamps back onto the video.
+    pub fn offset(&mut self, seconds: f64) {
+        Timeline::trimmed(seconds).apply(self);
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        self.render_styled(format, &SubtitleStyle::default())
+    }
+
+    /// Renders the transcript, styling subtitle formats with `style`.
+    pub fn render_styled(&self, format: Format, style: &SubtitleStyle) -> String {
+        let cues = self.segments.iter().map(Cue::from);
+        match format {
+            Format::Text => self.text.trim().to_string(),
+            Format::Srt => to_srt(cues),
+            Format::Vtt => to_vtt(cues, &self.speakers(), style),
+            Format::Ass => to_ass(cues, &self.speakers(), style),
+            Format::Json => {
+                serde_json::to_string_pretty(self).expect("Failed to serialize transcript")
+            }
+            Format::Html => to_html(cues, &self.text),
+            Format::Lrc => to_lrc(cues),
+        }
+    }
+
+    /// Renames the speakers of the segments as `names` maps them, e.g. from `SPEAKER_00` to
+    /// `Alice`, leaving speakers without a name as they are.
+    pub fn rename_speakers(&mut self, names: &BTreeMap<String, String>) {
+        for segment in &mut self.segments {
+            if let Some(name) = segment.speaker.as_ref().and_then(|s| names.get(s)) {
+                segment.speaker = Some(name.clone());
+            }
+        }
+    }
+
+    /// The distinct speakers of the segments, in order of first appearance.
+    pub fn speakers(&self) -> Vec<&str> {
+        let mut speakers = vec![];
+        for speaker in self.segments.iter().filter_map(|s| s.speaker.as_deref()) {
+            if !speakers.contains(&speaker) {
+                speakers.push(speaker);
+            }
+        }
+        speakers
+    }
+}
+
+/// A transcript in the original language paired with its English translation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bilingual {
+    pub transcript: Transcript,
+    pub translation: Transcript,
+}
+
+impl Bilingual {
+    /// Renders both versions, with subtitle formats interleaving them so that each cue shows the
+    /// original line above its translation.
+    pub fn render(&self, format: Format) -> String {
+        self.render_styled(format, &SubtitleStyle::default())
+    }
+
+    /// Renders both versions, styling subtitle formats with `style`, except for karaoke timing.
+    pub fn render_styled(&self, format: Format, style: &SubtitleStyle) -> String {
+        let speakers = self.transcript.speakers();
+        match format {
+            Format::Text => format!(
+                "{}\n\n{}",
+                self.transcript.render(format),
+                self.translation.render(format)
+            ),
+            Format::Srt => to_srt(self.interleaved_cues()),
+            Format::Vtt => to_vtt(self.interleaved_cues(), &speakers, style),
+            Format::Ass => to_ass(self.interleaved_cues(), &speakers, style),
+            Format::Json => {
+                serde_json::to_string_pretty(self).expect("Failed to serialize transcript")
+            }
+            Format::Html => to_html(self.interleaved_cues(), &self.render(Format::Text)),
+            Format::Lrc => to_lrc(self.interleaved_cues()),
+        }
+    }
+
+    /// The two versions are transcribed separately, so their segments rarely line up exactly.
+    /// Each translated segment is attached to the original segment it overlaps the most.
+    fn interleaved_cues(&self) -> impl Iterator<Item = Cue<'_>> {
+        let segments = &self.transcript.segments;
+        let mut translations = vec![Vec::new(); segments.len()];
+        for translated in &self.translation.segments {
+            let overlap = |segment: &Segment| {
+                segment.end.min(translated.end) - segment.start.max(translated.start)
+            };
+            let closest = segments
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| overlap(a).total_cmp(&overlap(b)));
+            if let Some((i, _)) = closest {
+                translations[i].push(translated.text.trim());
+            }
+        }
+
+        segments
+            .iter()
+            .zip(translations)
+            .map(|(segment, translation)| {
+                let text = match translation.join(" ") {
+                    translation if translation.is_empty() => segment.text.trim().to_string(),
+                    translation => format!("{}\n{}", segment.text.trim(), translation),
+                };
+                Cue {
+                    text,
+                    words: &[],
+                    ..Cue::from(segment)
+                }
+            })
+    }
+}
+
+/// A subtitle cue, with `start` and `end` in seconds.
+struct Cue<'a> {
+    start: f64,
+    end: f64,
+    text: String,
+    speaker: Option<&'a str>,
+    words: &'a [Word],
+}
+
+impl<'a> From<&'a Segment> for Cue<'a> {
+    fn from(segment: &'a Segment) -> Self {
+        Cue {
+            start: segment.start,
+            end: segment.end,
+            text: segment.text.trim().to_string(),
+            speaker: segment.speaker.as_deref(),
+            words: &segment.words,
+        }
+    }
+}
+
+/// An RGB color, parsed from `#RRGGBB`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+
+    fn to_css(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// The color in the `&HAABBGGRR` notation of ASS, fully opaque.
+    fn to_ass(self) -> String {
+        format!("&H00{:02X}{:02X}{:02X}", self.b, self.g, self.r)
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color { r, g, b }),
+            _ => Err(format!("Expected a color as #RRGGBB, got '{}'", s)),
+        }
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_css()
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Colors that speakers' captions are styled with, in order of first appearance, unless
+/// [`SubtitleStyle::speaker_colors`] are given.
+const SPEAKER_COLORS: &[Color] = &[
+    Color::rgb(0xff, 0xff, 0x00),
+    Color::rgb(0x00, 0xff, 0xff),
+    Color::rgb(0x00, 0xff, 0x00),
+    Color::rgb(0xff, 0x00, 0xff),
+    Color::rgb(0xff, 0xff, 0xff),
+    Color::rgb(0xff, 0xa5, 0x00),
+];
+
+/// How WebVTT and ASS subtitles are styled.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubtitleStyle {
+    /// WebVTT cue settings added to every cue, e.g. `line:85% align:center`.
+    pub vtt_settings: Option<String>,
+    /// The ASS font name, Arial by default.
+    pub font: Option<String>,
+    /// The ASS font size, relative to a 1080 pixel high video, 64 by default.
+    pub font_size: Option<u32>,
+    /// The colors of each speaker's captions, in order of first appearance.
+    pub speaker_colors: Vec<Color>,
+    /// Whether ASS cues highlight each word as it is spoken, for segments with word timings.
+    pub karaoke: bool,
+}
+
+impl SubtitleStyle {
+    /// The colors of `speakers`, cycling through the configured or default colors.
+    fn colors<'a>(&'a self, speakers: &'a [&'a str]) -> impl Iterator<Item = (&'a str, Color)> {
+        let colors = match self.speaker_colors.is_empty() {
+            true => SPEAKER_COLORS,
+            false => &self.speaker_colors,
+        };
+        speakers.iter().copied().zip(colors.iter().copied().cycle())
+    }
+}
+
+fn to_srt<'a>(cues: impl Iterator<Item = Cue<'a>>) -> String {
+    cues.enumerate()
+        .map(|(i, cue)| srt_cue(i + 1, cue.start, cue.end, &cue.text))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders `cues` as WebVTT, tagging diarized cues with their speaker's voice, e.g.
+/// `<v SPEAKER_00>`, and styling each speaker in a different color.
+fn to_vtt<'a>(
+    cues: impl Iterator<Item = Cue<'a>>,
+    speakers: &[&str],
+    style: &SubtitleStyle,
+) -> String {
+    let mut vtt = "WEBVTT\n\n".to_string();
+    if !speakers.is_empty() {
+        vtt.push_str("STYLE\n");
+        for (speaker, color) in style.colors(speakers) {
+            let name = speaker.replace('\\', "\\\\").replace('"', "\\\"");
+            vtt.push_str(&format!(
+                "::cue(v[voice=\"{}\"]) {{ color: {}; }}\n",
+                name,
+                color.to_css()
+            ));
+        }
+        vtt.push('\n');
+    }
+    for cue in cues {
+        vtt.push_str(&vtt_cue(&cue, style.vtt_settings.as_deref()));
+        vtt.push('\n');
+    }
+    vtt
+}
+
+fn vtt_cue(cue: &Cue, settings: Option<&str>) -> String {
+    let text = escape_vtt(&cue.text);
+    format!(
+        "{} --> {}{}\n{}\n",
+        vtt_timestamp(cue.start),
+        vtt_timestamp(cue.end),
+        settings.map(|s| format!(" {}", s)).unwrap_or_default(),
+        match cue.speaker {
+            Some(speaker) => format!("<v {}>{}", escape_vtt(speaker), text),
+            None => text,
+        }
+    )
+}
+
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Renders `cues` as a standalone HTML page, with a paragraph per cue headed by its timestamp
+/// and speaker, or `text` as a single paragraph if there are no cues.
+fn to_html<'a>(cues: impl Iterator<Item = Cue<'a>>, text: &str) -> String {
+    let mut html = html_header();
+    let mut empty = true;
+    for cue in cues {
+        empty = false;
+        html.push_str(&html_paragraph(&cue));
+    }
+    if empty {
+        for paragraph in text.split("\n\n").filter(|p| !p.trim().is_empty()) {
+            html.push_str(&format!("<p>{}</p>\n", escape_html(paragraph.trim())));
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// The start of an HTML transcript page, up to the opening of its body. Browsers close the
+/// body and page themselves, so a transcript being appended to can be viewed as it grows.
+pub fn html_header() -> String {
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Transcript</title>\n<style>\n\
+     body { font-family: sans-serif; max-width: 48em; margin: 2em auto; line-height: 1.5; }\n\
+     .time { color: #888; font-variant-numeric: tabular-nums; margin-right: 0.5em; }\n\
+     .speaker { font-weight: bold; margin-right: 0.5em; }\n</style>\n</head>\n<body>\n"
+        .to_string()
+}
+
+fn html_paragraph(cue: &Cue) -> String {
+    let start = cue.start.floor();
+    let speaker = cue
+        .speaker
+        .map(|speaker| format!("<span class=\"speaker\">{}</span>", escape_html(speaker)))
+        .unwrap_or_default();
+    format!(
+        "<p id=\"t{}\"><span class=\"time\">{}</span>{}{}</p>\n",
+        start,
+        format_timestamp(start),
+        speaker,
+        escape_html(&cue.text).replace('\n', "<br>\n")
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    escape_vtt(text).replace('"', "&quot;")
+}
+
+/// Renders `cues` as an ASS script, with a style per speaker so that each is shown in a
+/// different color, and with karaoke tags timing each word if `style.karaoke` is set.
+fn to_ass<'a>(
+    cues: impl Iterator<Item = Cue<'a>>,
+    speakers: &[&str],
+    style: &SubtitleStyle,
+) -> String {
+    let mut ass = ass_header(speakers, style);
+    for cue in cues {
+        ass.push_str(&ass_dialogue(&cue, style));
+    }
+    ass
+}
+
+/// The script info, the styles of `speakers`, and the header of the events section.
+pub fn ass_header(speakers: &[&str], style: &SubtitleStyle) -> String {
+    let font = style.font.as_deref().unwrap_or("Arial");
+    let font_size = style.font_size.unwrap_or(64);
+    // Karaoke tags fade each word from the secondary to the primary color.
+    let ass_style = |name: &str, color: Color| {
+        format!(
+            "Style: {},{},{},{},&H00A0A0A0,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,3,1,2,60,60,50,1\n",
+            ass_style_name(name),
+            font,
+            font_size,
+            color.to_ass()
+        )
+    };
+
+    let mut ass = "[Script Info]\nScriptType: v4.00+\nPlayResX: 1920\nPlayResY: 1080\n\
+                   WrapStyle: 0\nScaledBorderAndShadow: yes\n\n"
+        .to_string();
+    ass.push_str(
+        "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, \
+                  OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, \
+                  Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, \
+                  MarginV, Encoding\n",
+    );
+    ass.push_str(&ass_style("Default", Color::rgb(0xff, 0xff, 0xff)));
+    for (speaker, color) in style.colors(speakers) {
+        ass.push_str(&ass_style(speaker, color));
+    }
+
+    ass.push_str(
+        "\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, \
+                  Effect, Text\n",
+    );
+    ass
+}
+
+fn ass_dialogue(cue: &Cue, style: &SubtitleStyle) -> String {
+    let text = match (style.karaoke, cue.words) {
+        (true, [first, ..]) => {
+            let mut text = String::new();
+            let mut at = cue.start.min(first.start);
+            for word in cue.words {
+                let centiseconds = ((word.end - at) * 100.0).round().max(0.0);
+                text.push_str(&format!(
+                    "{{\\k{}}}{} ",
+                    centiseconds,
+                    escape_ass(word.word.trim())
+                ));
+                at = word.end;
+            }
+            text.trim_end().to_string()
+        }
+        _ => escape_ass(&cue.text),
+    };
+    format!(
+        "Dialogue: 0,{},{},{},{},0,0,0,,{}\n",
+        ass_timestamp(cue.start),
+        ass_timestamp(cue.end),
+        cue.speaker.map_or("Default".to_string(), ass_style_name),
+        cue.speaker.map(ass_style_name).unwrap_or_default(),
+        text
+    )
+}
+
+/// Speaker names as ASS style names, which cannot contain the fields' comma separators.
+fn ass_style_name(name: &str) -> String {
+    name.replace(',', " ")
+}
+
+/// Escapes override blocks and turns newlines into ASS line breaks.
+fn escape_ass(text: &str) -> String {
+    text.replace('{', "(")
+        .replace('}', ")")
+        .replace('\n', "\\N")
+}
+
+fn ass_timestamp(seconds: f64) -> String {
+    let centis = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+fn srt_cue(index: usize, start: f64, end: f64, text: &str) -> String {
+    format!(
+        "{}\n{} --> {}\n{}\n",
+        index,
+        srt_timestamp(start),
+        srt_timestamp(end),
+        text
+    )
+}
+
+/// Renders `cues` as LRC lyrics, one timed line per cue, with enhanced LRC word timings for cues
+/// that have them. An empty line clears the lyrics during pauses of over a second, e.g.
+/// instrumental breaks.
+fn to_lrc<'a>(cues: impl Iterator<Item = Cue<'a>>) -> String {
+    let mut lrc = String::new();
+    let mut cues = cues.peekable();
+    while let Some(cue) = cues.next() {
+        lrc.push_str(&lrc_line(&cue));
+        match cues.peek() {
+            Some(next) if next.start - cue.end <= 1.0 => {}
+            _ => lrc.push_str(&format!("[{}]\n", lrc_timestamp(cue.end))),
+        }
+    }
+    lrc
+}
+
+fn lrc_line(cue: &Cue) -> String {
+    let text = match cue.words {
+        [] => cue
+            .text
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" / "),
+        words => words
+            .iter()
+            .map(|word| format!("<{}>{}", lrc_timestamp(word.start), word.word.trim()))
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    format!("[{}]{}\n", lrc_timestamp(cue.start), text)
+}
+
+/// Formats `seconds` as `mm:ss.xx`, with minutes counting past the hour as LRC expects.
+fn lrc_timestamp(seconds: f64) -> String {
+    let centis = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centis / 6000,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    srt_timestamp(seconds).replace(',', ".")
+}
+
+fn srt_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
@@ -0,0 +1,275 @@
+use crate::llm::{chunk_for_llm, LlmChunk};
+use crate::openai::create_embeddings;
+use crate::pipeline::Video;
+use crate::provider::ProviderPool;
+use crate::sql::{psql, run_sql};
+use crate::transcript::Transcript;
+use crate::Result;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// The size in tokens of the chunks that are embedded, and how much consecutive chunks overlap.
+pub const CHUNK_TOKENS: usize = 512;
+const CHUNK_OVERLAP: usize = 64;
+
+/// How many chunks are embedded per request.
+const EMBEDDING_BATCH: usize = 64;
+
+/// The table chunks are stored in when a `postgres://` URL does not name one.
+const DEFAULT_TABLE: &str = "transcript_chunks";
+
+/// Where transcript chunks and their embeddings are upserted for retrieval.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VectorStore {
+    /// A Qdrant collection, reached over its REST API.
+    Qdrant {
+        base_url: String,
+        collection: String,
+        api_key: Option<String>,
+    },
+    /// A Postgres table with a pgvector column, written through `psql`.
+    Pgvector { conninfo: String, table: String },
+}
+
+impl VectorStore {
+    /// Parses `qdrant://host:6333/<collection>` (or `qdrants://` for HTTPS), reading the API key
+    /// from `QDRANT_API_KEY`, or `postgres://user@host/db?table=<table>`.
+    pub fn parse(url: &str) -> Result<Self> {
+        if let Some((scheme, rest)) = url
+            .strip_prefix("qdrant://")
+            .map(|rest| ("http", rest))
+            .or_else(|| url.strip_prefix("qdrants://").map(|rest| ("https", rest)))
+        {
+            let (host, collection) = rest
+                .split_once('/')
+                .filter(|(_, collection)| !collection.is_empty())
+                .ok_or("Missing Qdrant collection, e.g. qdrant://localhost:6333/transcripts")?;
+            return Ok(VectorStore::Qdrant {
+                base_url: format!("{}://{}", scheme, host),
+                collection: collection.trim_end_matches('/').to_string(),
+                api_key: std::env::var("QDRANT_API_KEY").ok(),
+            });
+        }
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            let (conninfo, query) = url.split_once('?').unwrap_or((url, ""));
+            let mut table = DEFAULT_TABLE.to_string();
+            let mut params = vec![];
+            for param in query.split('&').filter(|param| !param.is_empty()) {
+                match param.strip_prefix("table=") {
+                    Some(name) => table = name.to_string(),
+                    None => params.push(param),
+                }
+            }
+            if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("Invalid table name '{}'", table).into());
+            }
+            let conninfo = match params.is_empty() {
+                true => conninfo.to_string(),
+                false => format!("{}?{}", conninfo, params.join("&")),
+            };
+            return Ok(VectorStore::Pgvector { conninfo, table });
+        }
+        Err("Expected a qdrant:// or postgres:// URL".into())
+    }
+
+    /// Chunks `transcript`, embeds the chunks with the providers in `pool`, and upserts them with the video's
+    /// metadata, returning how many chunks were stored. The video's previously stored chunks are
+    /// deleted first, so re-ingesting a video replaces its chunks rather than duplicating them.
+    pub async fn ingest(
+        &self,
+        pool: &ProviderPool,
+        video: &Video,
+        transcript: &Transcript,
+    ) -> Result<usize> {
+        let chunks = chunk_for_llm(transcript, CHUNK_TOKENS, CHUNK_OVERLAP);
+        let mut embeddings = vec![];
+        for batch in chunks.chunks(EMBEDDING_BATCH) {
+            let inputs = batch.iter().map(|c| c.text.clone()).collect::<Vec<_>>();
+            let (batch, _) = pool
+                .run(|provider| {
+                    let inputs = inputs.clone();
+                    async move { create_embeddings(&provider.endpoint, &inputs).await }
+                })
+                .await?;
+            embeddings.extend(batch);
+        }
+        let Some(dimensions) = embeddings.first().map(Vec::len) else {
+            return Ok(0);
+        };
+
+        match self {
+            VectorStore::Qdrant {
+                base_url,
+                collection,
+                api_key,
+            } => {
+                let qdrant = Qdrant {
+                    client: Client::new(),
+                    base_url: format!("{}/collections/{}", base_url, collection),
+                    api_key: api_key.as_deref(),
+                };
+                qdrant.ensure_collection(dimensions).await?;
+                qdrant.delete_video(&video.id).await?;
+                qdrant.upsert(video, &chunks, embeddings).await?;
+            }
+            VectorStore::Pgvector { conninfo, table } => {
+                let sql = pgvector_sql(table, dimensions, video, &chunks, &embeddings);
+                run_psql(conninfo, &sql)?;
+            }
+        }
+        Ok(chunks.len())
+    }
+}
+
+/// A deterministic UUID for the `index`th chunk of the video with ID `video_id`.
+fn chunk_id(video_id: &str, index: usize) -> String {
+    let hash = format!("{:x}", Sha256::digest(format!("{}:{}", video_id, index)));
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hash[..8],
+        &hash[8..12],
+        &hash[12..16],
+        &hash[16..20],
+        &hash[20..32]
+    )
+}
+
+fn payload(video: &Video, chunk: &LlmChunk) -> Value {
+    json!({
+        "video_id": video.id,
+        "title": video.title,
+        "url": video.url,
+        "channel": video.channel,
+        "upload_date": video.upload_date,
+        "chunk": chunk.index,
+        "start": chunk.start,
+        "end": chunk.end,
+        "text": chunk.text,
+    })
+}
+
+struct Qdrant<'a> {
+    client: Client,
+    /// The URL of the collection.
+    base_url: String,
+    api_key: Option<&'a str>,
+}
+
+impl Qdrant<'_> {
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path));
+        match self.api_key {
+            Some(key) => request.header("api-key", key),
+            None => request,
+        }
+    }
+
+    /// Creates the collection for cosine similarity over vectors of `dimensions`, unless it
+    /// already exists.
+    async fn ensure_collection(&self, dimensions: usize) -> Result<()> {
+        let res = self.request(reqwest::Method::GET, "").send().await?;
+        if res.status() != StatusCode::NOT_FOUND {
+            res.error_for_status()?;
+            return Ok(());
+        }
+        let body = json!({ "vectors": { "size": dimensions, "distance": "Cosine" } });
+        let res = self
+            .request(reqwest::Method::PUT, "")
+            .json(&body)
+            .send()
+            .await?;
+        res.error_for_status()?;
+        Ok(())
+    }
+
+    async fn delete_video(&self, video_id: &str) -> Result<()> {
+        let filter = json!({ "must": [{ "key": "video_id", "match": { "value": video_id } }] });
+        let res = self
+            .request(reqwest::Method::POST, "/points/delete?wait=true")
+            .json(&json!({ "filter": filter }))
+            .send()
+            .await?;
+        res.error_for_status()?;
+        Ok(())
+    }
+
+    async fn upsert(
+        &self,
+        video: &Video,
+        chunks: &[LlmChunk],
+        embeddings: Vec<Vec<f32>>,
+    ) -> Result<()> {
+        let points = chunks
+            .iter()
+            .zip(embeddings)
+            .map(|(chunk, vector)| {
+                json!({
+                    "id": chunk_id(&video.id, chunk.index),
+                    "vector": vector,
+                    "payload": payload(video, chunk),
+                })
+            })
+            .collect::<Vec<_>>();
+        let res = self
+            .request(reqwest::Method::PUT, "/points?wait=true")
+            .json(&json!({ "points": points }))
+            .send()
+            .await?;
+        res.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// The SQL that creates the pgvector table if needed and upserts `chunks` into it.
+fn pgvector_sql(
+    table: &str,
+    dimensions: usize,
+    video: &Video,
+    chunks: &[LlmChunk],
+    embeddings: &[Vec<f32>],
+) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let optional = |s: &Option<String>| s.as_deref().map_or("NULL".to_string(), quote);
+    let number = |n: Option<f64>| n.map_or("NULL".to_string(), |n| n.to_string());
+
+    let mut sql = format!(
+        "\\set ON_ERROR_STOP on\nBEGIN;\nCREATE EXTENSION IF NOT EXISTS vector;\n\
+         CREATE TABLE IF NOT EXISTS {table} (\n  id uuid PRIMARY KEY,\n  video_id text NOT NULL,\n  \
+         title text,\n  url text,\n  channel text,\n  upload_date text,\n  chunk integer,\n  \
+         start_time double precision,\n  end_time double precision,\n  text text,\n  \
+         embedding vector({dimensions})\n);\nDELETE FROM {table} WHERE video_id = {video_id};\n",
+        video_id = quote(&video.id)
+    );
+    for (chunk, embedding) in chunks.iter().zip(embeddings) {
+        let vector = embedding
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        sql.push_str(&format!(
+            "INSERT INTO {} VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, '[{}]');\n",
+            table,
+            quote(&chunk_id(&video.id, chunk.index)),
+            quote(&video.id),
+            quote(&video.title),
+            quote(&video.url),
+            optional(&video.channel),
+            optional(&video.upload_date),
+            chunk.index,
+            number(chunk.start),
+            number(chunk.end),
+            quote(&chunk.text),
+            vector
+        ));
+    }
+    sql.push_str("COMMIT;\n");
+    sql
+}
+
+/// Runs `sql` with `psql`, which must be installed, against the database at `conninfo`.
+fn run_psql(conninfo: &str, sql: &str) -> Result<()> {
+    run_sql(&mut psql(conninfo, &[]), sql)
+}
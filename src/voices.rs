@@ -0,0 +1,181 @@
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The sidecar of an output directory that the voices of its diarized speakers are kept in.
+pub const VOICES_FILE: &str = "voices.json";
+
+/// The similarity of two voices' embeddings, between -1 and 1, above which they are taken to
+/// be the same person by default.
+pub const DEFAULT_VOICE_THRESHOLD: f32 = 0.75;
+
+/// The voice of a speaker of a diarized transcript, as `voices.json` records it by the label
+/// the diarizer gave the speaker, e.g. `SPEAKER_00`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Voice {
+    /// The name the speaker was given, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The speaker embedding the diarizer computed for the speaker.
+    pub embedding: Vec<f32>,
+}
+
+/// A named voice heard in the archive, averaged over the videos it was named in.
+#[derive(Clone, Debug)]
+pub struct KnownVoice {
+    pub name: String,
+    pub embedding: Vec<f32>,
+    /// How many videos the voice was named in.
+    pub videos: usize,
+}
+
+/// A speaker recognized as a known voice.
+#[derive(Clone, Debug)]
+pub struct VoiceMatch {
+    pub name: String,
+    pub similarity: f32,
+}
+
+/// Reads the speaker embeddings of a diarized transcript, by label, from the
+/// `speaker_embeddings` WhisperX writes alongside its segments. Transcripts without them, and
+/// files that are not JSON, have none.
+pub fn parse_embeddings(contents: &str) -> BTreeMap<String, Vec<f32>> {
+    let contents = contents.trim_start_matches('\u{feff}');
+    let Ok(value) = serde_json::from_str::<Value>(contents) else {
+        return BTreeMap::new();
+    };
+    let Some(embeddings) = value.get("speaker_embeddings").and_then(Value::as_object) else {
+        return BTreeMap::new();
+    };
+    embeddings
+        .iter()
+        .filter_map(|(label, embedding)| {
+            let embedding = embedding
+                .as_array()?
+                .iter()
+                .map(|x| x.as_f64().map(|x| x as f32))
+                .collect::<Option<Vec<_>>>()?;
+            Some((label.clone(), embedding))
+        })
+        .collect()
+}
+
+/// Reads the voices recorded in the output directory `dir`, by label.
+pub fn read_voices(dir: &Path) -> Result<BTreeMap<String, Voice>> {
+    match fs::read(dir.join(VOICES_FILE)) {
+        Ok(json) => Ok(serde_json::from_slice(&json)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn write_voices(dir: &Path, voices: &BTreeMap<String, Voice>) -> Result<()> {
+    fs::write(dir.join(VOICES_FILE), serde_json::to_string_pretty(voices)?)?;
+    Ok(())
+}
+
+/// Collects the named voices of every output directory in the archive at `root`, except
+/// `skip`, e.g. the one being written, averaging the embeddings of each name.
+pub fn known_voices(root: &Path, skip: &Path) -> Result<Vec<KnownVoice>> {
+    let mut known: BTreeMap<String, KnownVoice> = BTreeMap::new();
+    let Ok(entries) = fs::read_dir(root) else {
+        return Ok(vec![]);
+    };
+    for entry in entries {
+        let dir = entry?.path();
+        if !dir.is_dir() || dir == skip {
+            continue;
+        }
+        let Ok(voices) = read_voices(&dir) else {
+            continue;
+        };
+        for voice in voices.into_values() {
+            let Some(name) = voice.name else {
+                continue;
+            };
+            let embedding = normalize(voice.embedding);
+            match known.get_mut(&name) {
+                Some(known) if known.embedding.len() == embedding.len() => {
+                    for (sum, x) in known.embedding.iter_mut().zip(&embedding) {
+                        *sum += x;
+                    }
+                    known.videos += 1;
+                }
+                Some(_) => {}
+                None => {
+                    known.insert(
+                        name.clone(),
+                        KnownVoice {
+                            name,
+                            embedding,
+                            videos: 1,
+                        },
+                    );
+                }
+            }
+        }
+    }
+    Ok(known
+        .into_values()
+        .map(|voice| KnownVoice {
+            embedding: normalize(voice.embedding),
+            ..voice
+        })
+        .collect())
+}
+
+/// Matches the speakers of a video, by their embeddings, to the known voices at least
+/// `threshold` similar to them. The most similar pairs are matched first, so that no two
+/// speakers of the video are given the same name.
+pub fn match_voices(
+    embeddings: &BTreeMap<String, Vec<f32>>,
+    known: &[KnownVoice],
+    threshold: f32,
+) -> BTreeMap<String, VoiceMatch> {
+    let mut pairs = vec![];
+    for (label, embedding) in embeddings {
+        let embedding = normalize(embedding.clone());
+        for voice in known {
+            if voice.embedding.len() != embedding.len() {
+                continue;
+            }
+            let similarity = dot(&embedding, &voice.embedding);
+            if similarity >= threshold {
+                pairs.push((similarity, label, voice));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| f32::total_cmp(&b.0, &a.0));
+
+    let mut matches = BTreeMap::new();
+    for (similarity, label, voice) in pairs {
+        let named = matches
+            .values()
+            .any(|other: &VoiceMatch| other.name == voice.name);
+        if matches.contains_key(label) || named {
+            continue;
+        }
+        let name = voice.name.clone();
+        matches.insert(label.clone(), VoiceMatch { name, similarity });
+    }
+    matches
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Scales `embedding` to unit length, which makes the dot product of two embeddings their
+/// cosine similarity.
+fn normalize(mut embedding: Vec<f32>) -> Vec<f32> {
+    let length = dot(&embedding, &embedding).sqrt();
+    if length > 0.0 {
+        for x in &mut embedding {
+            *x /= length;
+        }
+    }
+    embedding
+}
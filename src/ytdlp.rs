@@ -0,0 +1,186 @@
+use crate::download::format_progress;
+use crate::Result;
+use serde_json::Value;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+
+/// Errors yt-dlp reports for videos that will not become available by trying again, which are
+/// not retried.
+const PERMANENT_ERRORS: [&str; 8] = [
+    "Unsupported URL",
+    "is not a valid URL",
+    "Video unavailable",
+    "Private video",
+    "This video has been removed",
+    "members-only",
+    "Sign in to confirm your age",
+    "HTTP Error 404",
+];
+
+/// How yt-dlp is asked to report download progress, one line per update, which no other line
+/// it prints starts with.
+const PROGRESS_TEMPLATE: &str = "download:transcribe-progress %(progress.downloaded_bytes)s %(progress.total_bytes)s %(progress.total_bytes_estimate)s";
+
+/// How often and how patiently yt-dlp is retried when it fails.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retries {
+    /// How many times to retry after the first attempt.
+    pub attempts: u32,
+    /// How long to wait before the first retry, doubling before each one after it.
+    pub delay: Duration,
+}
+
+/// Runs `yt-dlp -J` with `args` and returns the info JSON it prints, retrying failures that
+/// may be transient as `retries` allows. `on_progress` is called with each line yt-dlp logs
+/// while it works, e.g. `[youtube] dQw4w9WgXcQ: Downloading webpage`, and with a note before
+/// each retry.
+pub async fn dump_json(
+    args: &[String],
+    retries: Retries,
+    mut on_progress: impl FnMut(&str),
+) -> Result<Value> {
+    let mut delay = retries.delay;
+    let mut attempt = 0;
+    loop {
+        let error = match run(args, &mut on_progress).await? {
+            Ok(info) => return Ok(info),
+            Err(error) => error,
+        };
+        if attempt >= retries.attempts || PERMANENT_ERRORS.iter().any(|e| error.contains(e)) {
+            return Err(error.into());
+        }
+        attempt += 1;
+        let reason = error.lines().last().unwrap_or_default();
+        on_progress(&format!(
+            "retrying in {}s, attempt {} of {}: {}",
+            delay.as_secs_f64(),
+            attempt + 1,
+            retries.attempts + 1,
+            reason.trim_start_matches("ERROR: ")
+        ));
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+/// Runs yt-dlp once, failing outright if it cannot be started, and returning the error it
+/// reported if it ran and failed.
+async fn run(
+    args: &[String],
+    on_progress: &mut impl FnMut(&str),
+) -> Result<std::result::Result<Value, String>> {
+    // `-J` keeps yt-dlp quiet, and `--verbose` has it log its progress to stderr regardless,
+    // among debug lines that are left out.
+    let mut child = Command::new("yt-dlp")
+        .args(args)
+        .args(["--verbose", "-J"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("Failed to run yt-dlp: {}", err))?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let mut output = vec![];
+    let mut errors = vec![];
+    let read_stdout = stdout.read_to_end(&mut output);
+    let read_stderr = async {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Some(line) = lines.next_line().await? {
+            let line = line.trim();
+            if line.starts_with("ERROR:") {
+                errors.push(line.to_string());
+            } else if line.starts_with('[') && !line.starts_with("[debug]") {
+                on_progress(line);
+            }
+        }
+        std::io::Result::Ok(())
+    };
+    let (read_stdout, read_stderr) = tokio::join!(read_stdout, read_stderr);
+    read_stdout?;
+    read_stderr?;
+    let status = child.wait().await?;
+
+    if !status.success() {
+        let error = match errors.is_empty() {
+            true => format!("yt-dlp exited with {}", status),
+            false => errors.join("\n"),
+        };
+        return Ok(Err(error));
+    }
+    Ok(Ok(serde_json::from_slice(&output)?))
+}
+
+/// Downloads with yt-dlp into `dir`, running it with `args`, which end with the URL, and calling
+/// `on_progress` with how far each stream has got, e.g. `42% of 120.5 MB`, and with the other
+/// steps yt-dlp logs, such as merging the streams it downloaded.
+///
+/// yt-dlp downloads the streams it picks one after the other, including ones split into DASH
+/// or HLS fragments, so the progress starts over for each of them.
+pub async fn download(
+    args: &[String],
+    dir: &Path,
+    mut on_progress: impl FnMut(&str),
+) -> Result<()> {
+    let mut child = Command::new("yt-dlp")
+        .args(["--newline", "--progress-template", PROGRESS_TEMPLATE, "-P"])
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("Failed to run yt-dlp: {}", err))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    let read_stdout = async {
+        let mut lines = BufReader::new(stdout).lines();
+        let mut streams = 0;
+        while let Some(line) = lines.next_line().await? {
+            let line = line.trim();
+            if let Some(progress) = line.strip_prefix("transcribe-progress ") {
+                // Missing values are `NA`, and estimates may have fractions of a byte.
+                let mut values = progress
+                    .split_whitespace()
+                    .map(|value| value.parse::<f64>().ok().map(|value| value as u64));
+                let received = values.next().flatten().unwrap_or_default();
+                let total = values.next().flatten().or(values.next().flatten());
+                let progress = format_progress(received, total);
+                match streams {
+                    0 | 1 => on_progress(&progress),
+                    n => on_progress(&format!("stream {}: {}", n, progress)),
+                }
+            } else if line.starts_with("[download] Destination:") {
+                streams += 1;
+            } else if line.starts_with('[') && !line.starts_with("[download]") {
+                on_progress(line);
+            }
+        }
+        std::io::Result::Ok(())
+    };
+    let mut error_output = String::new();
+    let read_stderr = stderr.read_to_string(&mut error_output);
+    let (read_stdout, read_stderr) = tokio::join!(read_stdout, read_stderr);
+    read_stdout?;
+    read_stderr?;
+    let status = child.wait().await?;
+    if !status.success() {
+        let errors = error_output
+            .lines()
+            .filter(|line| line.starts_with("ERROR:"))
+            .collect::<Vec<_>>();
+        return Err(match errors.is_empty() {
+            true => format!("yt-dlp exited with {}", status),
+            false => errors.join("\n"),
+        }
+        .into());
+    }
+    Ok(())
+}
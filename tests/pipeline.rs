@@ -0,0 +1,127 @@
+#![cfg(all(feature = "test-support", unix))]
+
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use transcribe::testing::{temp_dir, video_info, FakeYtDlp, MockOpenAi, MOCK_API_KEY};
+use transcribe::transcript::Transcript;
+
+const VIDEO_ID: &str = "mockvideo01";
+
+/// A transcript of two segments, the model being much surer of the first.
+fn transcript() -> Transcript {
+    serde_json::from_value(json!({
+        "text": " Hello there. General Kenobi.",
+        "language": "english",
+        "duration": 8.0,
+        "segments": [
+            { "start": 0.0, "end": 4.0, "text": " Hello there.", "avg_logprob": -0.05, "no_speech_prob": 0.01 },
+            { "start": 4.0, "end": 8.0, "text": " General Kenobi.", "avg_logprob": -0.6, "no_speech_prob": 0.01 }
+        ]
+    }))
+    .unwrap()
+}
+
+/// Runs `transcribe` on the mock video with `args`, against `server` and with `yt_dlp` on the
+/// `PATH`, keeping its configuration and caches in `home`, and answering its prompts with yes.
+async fn run(server: &MockOpenAi, yt_dlp: &FakeYtDlp, home: &Path, args: &[&str]) -> Output {
+    let providers = server.write_providers(home).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_transcribe"))
+        .arg(format!("https://www.youtube.com/watch?v={}", VIDEO_ID))
+        .arg("--providers")
+        .arg(&providers)
+        .args(args)
+        .env("PATH", yt_dlp.path())
+        .env("MOCK_API_KEY", MOCK_API_KEY)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let _ = stdin.write_all(b"y\n").await;
+    drop(stdin);
+    child.wait_with_output().await.unwrap()
+}
+
+async fn setup() -> (MockOpenAi, FakeYtDlp, std::path::PathBuf) {
+    let server = MockOpenAi::start(transcript()).await.unwrap();
+    let info = video_info(VIDEO_ID, "Mock video", 8.0, &server.audio_url);
+    let yt_dlp = FakeYtDlp::install(&info).unwrap();
+    (server, yt_dlp, temp_dir("pipeline").unwrap())
+}
+
+#[tokio::test]
+async fn transcribes_a_video_end_to_end() {
+    let (server, yt_dlp, home) = setup().await;
+    let out = home.join("out");
+    let output = run(
+        &server,
+        &yt_dlp,
+        &home,
+        &["-f", "srt", "-d", out.to_str().unwrap()],
+    )
+    .await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("00:00:04,000 --> 00:00:08,000\nGeneral Kenobi."));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].route, "/audio/transcriptions");
+    assert_eq!(requests[0].model, "whisper-1");
+    assert_eq!(requests[0].response_format, "verbose_json");
+    assert!(yt_dlp.calls().iter().any(|call| call.contains(VIDEO_ID)));
+
+    let dir = out.join(VIDEO_ID);
+    let srt = fs::read_to_string(dir.join("transcript.srt")).unwrap();
+    assert!(srt.contains("Hello there."));
+    let manifest: Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("manifest.json")).unwrap()).unwrap();
+    assert_eq!(manifest["video_id"], VIDEO_ID);
+    assert_eq!(manifest["provider"], "mock");
+    fs::remove_dir_all(home).unwrap();
+}
+
+#[tokio::test]
+async fn refuses_transcripts_below_the_minimum_confidence() {
+    let (server, yt_dlp, home) = setup().await;
+    let out = home.join("out");
+    let output = run(
+        &server,
+        &yt_dlp,
+        &home,
+        &["-d", out.to_str().unwrap(), "--min-confidence", "0.85"],
+    )
+    .await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("75% confident"));
+    assert!(!out.join(VIDEO_ID).join("manifest.json").exists());
+    fs::remove_dir_all(home).unwrap();
+}
+
+#[tokio::test]
+async fn fails_over_when_the_api_errors() {
+    let (server, yt_dlp, home) = setup().await;
+    server.fail_next(500);
+    let output = run(&server, &yt_dlp, &home, &[]).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(server.requests().len(), 2);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello there."));
+    fs::remove_dir_all(home).unwrap();
+}
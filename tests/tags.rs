@@ -0,0 +1,292 @@
+use serde_json::json;
+use std::path::PathBuf;
+use transcribe::tags::{embed_lyrics, find_moov, read_file_tags, MoovSearch};
+use transcribe::transcript::Transcript;
+
+fn transcript() -> Transcript {
+    serde_json::from_value(json!({
+        "text": " Hello there. General Kenobi.",
+        "language": "english",
+        "duration": 8.0,
+        "segments": [
+            { "start": 1.0, "end": 4.0, "text": " Hello there." },
+            { "start": 6.0, "end": 7.5, "text": " General Kenobi." }
+        ]
+    }))
+    .unwrap()
+}
+
+/// A path in the temporary directory for a test to write `name` to, unique to this process.
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("transcribe-tags-{}-{}", std::process::id(), name))
+}
+
+/// Writes `data` to a temporary file named `name`, embeds the transcript's lyrics in it, and
+/// returns what the file then holds.
+fn embed(name: &str, data: &[u8], synced: bool) -> transcribe::Result<Vec<u8>> {
+    let path = temp_file(name);
+    std::fs::write(&path, data).unwrap();
+    let embedded =
+        embed_lyrics(&path, &transcript(), synced).map(|_| std::fs::read(&path).unwrap());
+    let kept = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    if embedded.is_err() {
+        assert_eq!(kept, data, "a file that could not be tagged was changed");
+    }
+    embedded
+}
+
+fn syncsafe(size: usize) -> [u8; 4] {
+    [21, 14, 7, 0].map(|shift| ((size >> shift) & 0x7f) as u8)
+}
+
+fn unsyncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |size, &b| (size << 7) | b as usize)
+}
+
+fn id3_size(version: u8, size: usize) -> [u8; 4] {
+    match version {
+        3 => (size as u32).to_be_bytes(),
+        _ => syncsafe(size),
+    }
+}
+
+fn id3_frame(version: u8, id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    [&id[..], &id3_size(version, body.len()), &[0, 0], body].concat()
+}
+
+/// An MP3 with an ID3 tag of `version` that has a title and old lyrics, followed by its audio.
+fn mp3(version: u8) -> Vec<u8> {
+    let tag = [
+        id3_frame(version, b"TIT2", b"\x00Episode 1"),
+        id3_frame(version, b"USLT", b"\x00undOld lyrics"),
+        vec![0; 16],
+    ]
+    .concat();
+    [
+        &b"ID3"[..],
+        &[version, 0, 0],
+        &syncsafe(tag.len()),
+        &tag,
+        AUDIO,
+    ]
+    .concat()
+}
+
+const AUDIO: &[u8] = b"\xff\xfbAUDIO FRAMES";
+
+/// The frames of the ID3 tag at the start of `data`, as their ID and contents.
+fn id3_frames(data: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    let version = data[3];
+    let size = unsyncsafe(&data[6..10]);
+    let body = &data[10..10 + size];
+    let mut frames = vec![];
+    let mut offset = 0;
+    while let Some(header) = body.get(offset..offset + 10).filter(|h| h[0] != 0) {
+        let size = match version {
+            3 => u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize,
+            _ => unsyncsafe(&header[4..8]),
+        };
+        let id = header[..4].try_into().unwrap();
+        frames.push((id, body[offset + 10..offset + 10 + size].to_vec()));
+        offset += 10 + size;
+    }
+    frames
+}
+
+fn assert_id3_round_trip(version: u8, lyrics: &[u8]) {
+    let tagged = embed(&format!("v2{}.mp3", version), &mp3(version), true).unwrap();
+    assert_eq!(&tagged[..4], &[b'I', b'D', b'3', version]);
+    assert!(tagged.ends_with(AUDIO));
+    let frames = id3_frames(&tagged);
+    let ids = frames.iter().map(|(id, _)| id).collect::<Vec<_>>();
+    assert_eq!(ids, [b"TIT2", b"USLT", b"SYLT"]);
+    assert!(frames[1].1.ends_with(lyrics));
+
+    let path = temp_file(&format!("v2{}-read.mp3", version));
+    std::fs::write(&path, &tagged).unwrap();
+    let tags = read_file_tags(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(tags.title.as_deref(), Some("Episode 1"));
+}
+
+#[test]
+fn replaces_the_lyrics_of_an_id3v2_3_tag() {
+    let lyrics = "Hello there.\nGeneral Kenobi."
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+    assert_id3_round_trip(3, &lyrics);
+}
+
+#[test]
+fn replaces_the_lyrics_of_an_id3v2_4_tag() {
+    assert_id3_round_trip(4, b"Hello there.\nGeneral Kenobi.");
+}
+
+#[test]
+fn refuses_an_id3_tag_with_a_truncated_frame() {
+    let mut data = mp3(4);
+    // The title frame claims to run past the end of the tag.
+    data[10 + 4..10 + 8].copy_from_slice(&syncsafe(1000));
+    assert!(embed("truncated.mp3", &data, false).is_err());
+}
+
+fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    [&((body.len() + 8) as u32).to_be_bytes()[..], kind, body].concat()
+}
+
+/// A chunk offset table of `kind` (`stco` or `co64`) pointing at `offsets`.
+fn chunk_offsets(kind: &[u8; 4], offsets: &[u64]) -> Vec<u8> {
+    let mut table = vec![0; 4];
+    table.extend_from_slice(&(offsets.len() as u32).to_be_bytes());
+    for &offset in offsets {
+        match kind {
+            b"stco" => table.extend_from_slice(&(offset as u32).to_be_bytes()),
+            _ => table.extend_from_slice(&offset.to_be_bytes()),
+        }
+    }
+    atom(kind, &table)
+}
+
+fn trak(table: Vec<u8>) -> Vec<u8> {
+    atom(
+        b"trak",
+        &atom(b"mdia", &atom(b"minf", &atom(b"stbl", &table))),
+    )
+}
+
+/// A faststart MP4, with `moov` before `mdat`, whose two tracks point at the chunks `AAAA` and
+/// `BBBB` in `mdat` with a 32-bit and a 64-bit chunk offset table, plus `extra` in `moov`.
+fn faststart_mp4(extra: &[u8]) -> Vec<u8> {
+    let ftyp = atom(b"ftyp", b"M4A \x00\x00\x00\x00M4A isom");
+    let moov = |offsets: [u64; 2]| {
+        let body = [
+            trak(chunk_offsets(b"stco", &offsets[..1])),
+            trak(chunk_offsets(b"co64", &offsets[1..])),
+            extra.to_vec(),
+        ]
+        .concat();
+        atom(b"moov", &body)
+    };
+    let mdat_start = (ftyp.len() + moov([0, 0]).len()) as u64;
+    let mdat = atom(b"mdat", b"AAAABBBB");
+    [ftyp, moov([mdat_start + 8, mdat_start + 12]), mdat].concat()
+}
+
+/// The contents of the atom at `path` below `data`, which is a list of atoms.
+fn find_atom<'a>(mut data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    for kind in path {
+        let mut offset = 0;
+        data = loop {
+            let header = data.get(offset..offset + 8)?;
+            let size = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+            if &header[4..8] == *kind {
+                break &data[offset + 8..offset + size];
+            }
+            offset += size;
+        };
+    }
+    Some(data)
+}
+
+/// The chunk offsets in the table of `kind` in the `trak` of `moov` that has one.
+fn read_chunk_offsets(moov: &[u8], kind: &[u8; 4]) -> Vec<u64> {
+    let mut offset = 0;
+    while let Some(header) = moov.get(offset..offset + 8) {
+        let size = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let trak = &moov[offset + 8..offset + size];
+        offset += size;
+        let Some(table) = find_atom(trak, &[b"mdia", b"minf", b"stbl", kind]) else {
+            continue;
+        };
+        let width = if kind == b"stco" { 4 } else { 8 };
+        return table[8..]
+            .chunks(width)
+            .map(|entry| entry.iter().fold(0, |value, &b| (value << 8) | b as u64))
+            .collect();
+    }
+    vec![]
+}
+
+#[test]
+fn shifts_the_chunk_offsets_of_a_faststart_mp4() {
+    let data = faststart_mp4(&[]);
+    let tagged = embed("faststart.m4a", &data, false).unwrap();
+    assert!(tagged.len() > data.len());
+
+    let Some(MoovSearch::Found(start, end)) = find_moov(&tagged, 0) else {
+        panic!("no moov in the tagged file");
+    };
+    let moov = &tagged[start as usize..end as usize];
+    let lyrics = find_atom(moov, &[b"udta", b"meta"])
+        .and_then(|meta| find_atom(&meta[4..], &[b"ilst", b"\xa9lyr", b"data"]))
+        .unwrap();
+    assert_eq!(&lyrics[8..], b"Hello there.\nGeneral Kenobi.");
+    for (kind, chunk) in [(b"stco", b"AAAA"), (b"co64", b"BBBB")] {
+        let offsets = read_chunk_offsets(moov, kind);
+        assert_eq!(offsets.len(), 1);
+        let offset = offsets[0] as usize;
+        assert_eq!(&tagged[offset..offset + 4], chunk, "{:?} not shifted", kind);
+    }
+}
+
+#[test]
+fn refuses_an_mp4_with_an_atom_that_does_not_parse() {
+    // An atom claiming to be smaller than its own header, after which nothing can be read.
+    let data = faststart_mp4(&[0, 0, 0, 3, b'f', b'r', b'e', b'e']);
+    assert!(embed("malformed.m4a", &data, false).is_err());
+}
+
+/// The header of an atom with a 64-bit size.
+fn large_atom_header(kind: &[u8; 4], size: u64) -> Vec<u8> {
+    [&1u32.to_be_bytes()[..], kind, &size.to_be_bytes()].concat()
+}
+
+#[test]
+fn finds_moov_after_an_atom_with_a_64_bit_size() {
+    let ftyp = atom(b"ftyp", b"M4A \x00\x00\x00\x00");
+    let mdat = [large_atom_header(b"mdat", 24), b"AAAABBBB".to_vec()].concat();
+    let moov = atom(b"moov", b"contents");
+    let data = [ftyp.clone(), mdat.clone(), moov].concat();
+    let start = (ftyp.len() + mdat.len()) as u64;
+    assert_eq!(
+        find_moov(&data, 0),
+        Some(MoovSearch::Found(start + 8, start + 16))
+    );
+}
+
+#[test]
+fn finds_a_moov_with_a_64_bit_size() {
+    let moov = [large_atom_header(b"moov", 24), b"contents".to_vec()].concat();
+    assert_eq!(find_moov(&moov, 1000), Some(MoovSearch::Found(1016, 1024)));
+}
+
+#[test]
+fn asks_for_more_of_a_64_bit_size_cut_off_by_the_end_of_the_data() {
+    let header = large_atom_header(b"mdat", 1 << 40);
+    assert_eq!(find_moov(&header[..12], 500), Some(MoovSearch::Next(500)));
+}
+
+#[test]
+fn finds_a_moov_running_to_the_end_of_the_file() {
+    let moov = [&0u32.to_be_bytes()[..], b"moov", b"contents"].concat();
+    assert_eq!(
+        find_moov(&moov, 1 << 20),
+        Some(MoovSearch::Found((1 << 20) + 8, u64::MAX))
+    );
+}
+
+#[test]
+fn stops_at_an_atom_running_to_the_end_of_the_file() {
+    let mdat = [&0u32.to_be_bytes()[..], b"mdat", b"AAAA"].concat();
+    assert_eq!(find_moov(&mdat, 1 << 20), None);
+}
+
+#[test]
+fn refuses_atoms_whose_size_overflows() {
+    let mdat = large_atom_header(b"mdat", u64::MAX - 4);
+    assert_eq!(find_moov(&mdat, 100), None);
+    let moov = large_atom_header(b"moov", u64::MAX - 4);
+    assert_eq!(find_moov(&moov, 100), None);
+}
@@ -0,0 +1,171 @@
+use serde_json::json;
+use transcribe::timeline::Timeline;
+use transcribe::transcript::{Format, Transcript};
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
+
+/// A transcript of audio sent to the model, with a segment on either side of 5 seconds in.
+fn transcript() -> Transcript {
+    serde_json::from_value(json!({
+        "text": " Hello there. General Kenobi.",
+        "language": "english",
+        "duration": 8.0,
+        "segments": [
+            {
+                "start": 1.0,
+                "end": 4.0,
+                "text": " Hello there.",
+                "words": [
+                    { "word": "Hello", "start": 1.0, "end": 2.0 },
+                    { "word": "there.", "start": 2.5, "end": 4.0 }
+                ]
+            },
+            { "start": 6.0, "end": 7.5, "text": " General Kenobi." }
+        ]
+    }))
+    .unwrap()
+}
+
+/// A clip starting 10 minutes in, with the 30 seconds of silence after its first 10 seconds
+/// left out, sent to the model at double speed.
+fn preprocessed() -> Timeline {
+    let stripped = Timeline::from_kept(&[(0.0, 10.0), (40.0, 60.0)]);
+    Timeline::trimmed(600.0)
+        .then(&stripped)
+        .then(&Timeline::sped_up(2.0))
+}
+
+#[test]
+fn untouched_audio_keeps_its_timestamps() {
+    let timeline = Timeline::default();
+    assert!(timeline.is_identity());
+    assert_close(timeline.to_source(12.5), 12.5);
+    assert!(Timeline::trimmed(0.0).is_identity());
+}
+
+#[test]
+fn trimming_offsets_by_the_clip_start() {
+    let timeline = Timeline::trimmed(90.0);
+    assert_close(timeline.to_source(0.0), 90.0);
+    assert_close(timeline.to_source(12.5), 102.5);
+}
+
+#[test]
+fn speed_up_stretches_back_to_the_source() {
+    let timeline = Timeline::sped_up(1.5);
+    assert_close(timeline.to_source(10.0), 15.0);
+    assert_close(
+        Timeline::trimmed(60.0).then(&timeline).to_source(10.0),
+        75.0,
+    );
+}
+
+#[test]
+fn silence_removal_skips_over_the_cuts() {
+    let timeline = Timeline::from_kept(&[(0.0, 3.25), (7.75, 12.25), (19.75, 30.0)]);
+    assert_close(timeline.to_source(1.0), 1.0);
+    assert_close(timeline.to_source(3.6), 8.1);
+    assert_close(timeline.to_source(8.0), 20.0);
+    // A time at a join belongs to the speech after the cut.
+    assert_close(timeline.to_source(3.25), 7.75);
+    // Audio that starts with a cut starts later in the source.
+    let timeline = Timeline::from_kept(&[(0.0, 0.0), (5.0, 10.0)]);
+    assert_close(timeline.to_source(0.0), 5.0);
+}
+
+#[test]
+fn composed_preprocessing_maps_back_to_the_source() {
+    let timeline = preprocessed();
+    assert_close(timeline.to_source(0.0), 600.0);
+    assert_close(timeline.to_source(2.0), 604.0);
+    assert_close(timeline.to_source(5.0), 640.0);
+    assert_close(timeline.to_source(6.0), 642.0);
+    assert_close(timeline.to_source(15.0), 660.0);
+}
+
+#[test]
+fn composition_is_associative() {
+    let trimmed = Timeline::trimmed(600.0);
+    let stripped = Timeline::from_kept(&[(0.0, 10.0), (40.0, 60.0)]);
+    let sped_up = Timeline::sped_up(2.0);
+    let nested = trimmed.then(&stripped.then(&sped_up));
+    let chained = trimmed.then(&stripped).then(&sped_up);
+    for time in [0.0, 1.0, 4.99, 5.0, 5.01, 9.0, 30.0] {
+        assert_close(nested.to_source(time), chained.to_source(time));
+    }
+}
+
+#[test]
+fn apply_maps_segments_and_words() {
+    let mut transcript = transcript();
+    preprocessed().apply(&mut transcript);
+    let first = &transcript.segments[0];
+    assert_close(first.start, 602.0);
+    assert_close(first.end, 608.0);
+    assert_close(first.words[1].start, 605.0);
+    assert_close(first.words[1].end, 608.0);
+    let second = &transcript.segments[1];
+    assert_close(second.start, 642.0);
+    assert_close(second.end, 645.0);
+    // The duration covers the stretch of the clip the audio was made from.
+    assert_close(transcript.duration.unwrap(), 46.0);
+}
+
+#[test]
+fn offset_matches_a_trimmed_timeline() {
+    let mut offset = transcript();
+    offset.offset(600.0);
+    let mut trimmed = transcript();
+    Timeline::trimmed(600.0).apply(&mut trimmed);
+    for format in [Format::Srt, Format::Vtt, Format::Json, Format::Lrc] {
+        assert_eq!(offset.render(format), trimmed.render(format));
+    }
+}
+
+#[test]
+fn every_exporter_emits_source_timestamps() {
+    let mut transcript = transcript();
+    preprocessed().apply(&mut transcript);
+    let expected = [
+        (
+            Format::Srt,
+            [
+                "00:10:02,000 --> 00:10:08,000",
+                "00:10:42,000 --> 00:10:45,000",
+            ],
+        ),
+        (
+            Format::Vtt,
+            [
+                "00:10:02.000 --> 00:10:08.000",
+                "00:10:42.000 --> 00:10:45.000",
+            ],
+        ),
+        (
+            Format::Ass,
+            ["0:10:02.00,0:10:08.00", "0:10:42.00,0:10:45.00"],
+        ),
+        (Format::Lrc, ["[10:02.00]", "[10:42.00]"]),
+        (Format::Json, ["\"start\":602.0", "\"start\":642.0"]),
+    ];
+    for (format, timestamps) in expected {
+        let rendered = transcript.render(format).replace([' ', '\n'], "");
+        for timestamp in timestamps {
+            let timestamp = timestamp.replace(' ', "");
+            assert!(
+                rendered.contains(&timestamp),
+                "{:?} output lacks {}: {}",
+                format,
+                timestamp,
+                rendered
+            );
+        }
+    }
+}